@@ -0,0 +1,167 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Paying several recipients with one transaction, from a paste of "address, amount" lines
+//! like Electrum's "pay to many". The amounts are in BTC. ldk-node only sends to a single
+//! address, so the on-chain wallet of the seed is loaded into bdk to build the transaction.
+
+use crate::error::WalletError;
+use crate::format::Denomination;
+use crate::i18n::{tr, trf};
+use crate::input_eval::parse_satoshis;
+use crate::recovery;
+
+use bdk::{
+    bitcoin::{Address, Network, Txid},
+    FeeRate, SignOptions,
+};
+use ldk_node::bip39::Mnemonic;
+use std::str::FromStr;
+
+/// Parse the lines into the recipients, ignoring the empty ones
+pub fn parse(input: &str, network: Network) -> Result<Vec<(Address, u64)>, String> {
+    let mut recipients = Vec::new();
+    for (nr, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (addr, amount) = line
+            .rsplit_once(',')
+            .ok_or_else(|| trf("Line {}: expected an address and an amount", &[&(nr + 1)]))?;
+        let addr = Address::from_str(addr.trim())
+            .map_err(|e| trf("Line {}: invalid address: {}", &[&(nr + 1), &e]))?
+            .require_network(network)
+            .map_err(|_| trf("Line {}: the address is not for {}", &[&(nr + 1), &network]))?;
+        let sats = parse_satoshis(amount.trim()).map_err(|e| e.to_string())?;
+        if sats == 0 {
+            return Err(trf("Line {}: the amount is missing", &[&(nr + 1)]));
+        }
+        recipients.push((addr, sats));
+    }
+    if recipients.is_empty() {
+        return Err(tr("No recipients to pay"));
+    }
+    total(&recipients)?;
+    Ok(recipients)
+}
+
+/// The lines the recipients were parsed from, for showing them in the address field again
+pub fn format(recipients: &[(Address, u64)]) -> String {
+    recipients
+        .iter()
+        .map(|(addr, sats)| format!("{}, {}", addr, Denomination::Btc.format_sats(*sats)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The sum of the amounts, an error if it overflows
+pub fn total(recipients: &[(Address, u64)]) -> Result<u64, String> {
+    recipients
+        .iter()
+        .try_fold(0u64, |sum, (_, sats)| sum.checked_add(*sats))
+        .ok_or_else(|| tr("The amounts add up to more than there can be"))
+}
+
+pub async fn send(
+    recipients: &[(Address, u64)],
+    sat_per_vbyte: f64,
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<Txid, WalletError> {
+    let (wallet, blockchain) = recovery::seed_wallet(mnemonic, network).await?;
+
+    let mut builder = wallet.build_tx();
+    for (addr, sats) in recipients {
+        builder.add_recipient(addr.script_pubkey(), *sats);
+    }
+    builder
+        .fee_rate(FeeRate::from_sat_per_vb(sat_per_vbyte as f32))
+        .enable_rbf();
+    let (mut psbt, _) = builder
+        .finish()
+        .map_err(|e| WalletError::Node(trf("Failed to build the transaction: {}", &[&e])))?;
+    let finalized = wallet
+        .sign(&mut psbt, SignOptions::default())
+        .map_err(|e| WalletError::Node(trf("Failed to sign the transaction: {}", &[&e])))?;
+    if !finalized {
+        return Err(WalletError::Node(tr("Failed to sign all the inputs")));
+    }
+    let tx = psbt.extract_tx();
+    blockchain
+        .broadcast(&tx)
+        .await
+        .map_err(|e| WalletError::Network(trf("Failed to broadcast the transaction: {}", &[&e])))?;
+    log_info!(
+        "batch payment to {} recipients sent: {}",
+        recipients.len(),
+        tx.txid()
+    );
+    Ok(tx.txid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let input = "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.001\n\n  3M5f673Ler6iJbatJNvex7EYANRsydSQXE,0.0002  \n";
+        let recipients = parse(input, Network::Bitcoin).unwrap();
+        assert_eq!(recipients.len(), 2);
+        assert_eq!(
+            recipients[0].0.to_string(),
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa"
+        );
+        assert_eq!(recipients[0].1, 100_000);
+        assert_eq!(recipients[1].1, 20_000);
+        assert_eq!(total(&recipients), Ok(120_000));
+        assert_eq!(
+            parse(&format(&recipients), Network::Bitcoin),
+            Ok(recipients)
+        );
+
+        assert!(parse("", Network::Bitcoin).is_err());
+        assert!(parse(
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+            Network::Bitcoin
+        )
+        .is_err());
+        assert!(parse(
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0",
+            Network::Bitcoin
+        )
+        .is_err());
+        assert!(parse(
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.1",
+            Network::Testnet
+        )
+        .is_err());
+        assert!(parse("notanaddress, 0.1", Network::Bitcoin).is_err());
+    }
+
+    #[test]
+    fn test_total_overflow() {
+        let addr = Address::from_str("bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa")
+            .unwrap()
+            .assume_checked();
+        assert!(total(&[(addr.clone(), u64::MAX), (addr.clone(), 1)]).is_err());
+        assert_eq!(
+            total(&[(addr.clone(), u64::MAX - 1), (addr, 1)]),
+            Ok(u64::MAX)
+        );
+    }
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The command line client, to script the wallet over ssh on the phone or to use it on the
+//! desktop. It runs the node of the active wallet, so the GUI has to be closed meanwhile.
+
+#[macro_use]
+extern crate utwallet_core;
+
+use utwallet_core::constants::{CLI_PAYMENT_TIMEOUT_SECS, EVENT_POLL_SECS};
+use utwallet_core::error::WalletError;
+use utwallet_core::input_eval::{InputEval, InputNetwork};
+use utwallet_core::payments::{self, Status};
+use utwallet_core::wallet::BdkWallet;
+use utwallet_core::{demo, logging, pin, profiles, proxy};
+
+use std::{
+    env, io, process, thread,
+    time::{Duration, Instant},
+};
+
+const USAGE: &str = "usage: utwallet-cli [-v] <command>
+
+  balance                         the spendable on-chain and the lightning balance in sats
+  newaddress                      a new on-chain address
+  invoice <sats> [description]    a lightning invoice, 0 sats for any amount
+  pay <invoice|offer|address> [sats]
+                                  pay, the amount is needed for addresses and open invoices
+  channels                        the channels with peer, capacity and funding outpoint
+
+  -v                              print the log as well";
+
+fn main() {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let verbose = args.first().map(|a| a == "-v").unwrap_or(false);
+    if verbose {
+        args.remove(0);
+    }
+    logging::set_console(verbose);
+    logging::set_verbose(verbose);
+    if let Err(e) = profiles::check_override() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+    demo::init();
+
+    let command = match args.first() {
+        Some(command) if command != "help" && command != "--help" => command.clone(),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = proxy::apply_settings() {
+        log_error!("{}", e);
+    }
+
+    let res = BdkWallet::init_node(|_| {}).and_then(|_| run(&command, &args[1..]));
+    if let Err(e) = BdkWallet::shutdown() {
+        log_error!("{}", e);
+    }
+    match res {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    }
+}
+
+fn run(command: &str, args: &[String]) -> Result<String, WalletError> {
+    match command {
+        "balance" => {
+            let (onchain, lightning) = BdkWallet::get_balance()?;
+            Ok(format!(
+                "onchain: {} sats\nlightning: {} sats",
+                onchain, lightning
+            ))
+        }
+        "newaddress" => Ok(BdkWallet::get_address()?.to_string()),
+        "invoice" => {
+            let sats = sats_arg(args.first())?.unwrap_or(0);
+            let description = args.get(1..).unwrap_or_default().join(" ");
+            BdkWallet::create_invoice((sats > 0).then_some(sats), &description)
+        }
+        "pay" => {
+            let recipient = args.first().ok_or(WalletError::InvalidInput(
+                "pay needs an invoice, an offer or an address".to_string(),
+            ))?;
+            pay(recipient, sats_arg(args.get(1))?)
+        }
+        "channels" => Ok(BdkWallet::channel_peers()?
+            .iter()
+            .map(|(peer, address, capacity, funding)| {
+                format!("{}@{} {} sats {}", peer, address, capacity, funding)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        _ => Err(WalletError::InvalidInput(format!(
+            "unknown command {}\n\n{}",
+            command, USAGE
+        ))),
+    }
+}
+
+fn sats_arg(arg: Option<&String>) -> Result<Option<u64>, WalletError> {
+    arg.map(|a| {
+        a.parse::<u64>()
+            .map_err(|e| WalletError::InvalidAmount(format!("Invalid amount {}: {}", a, e)))
+    })
+    .transpose()
+}
+
+fn pay(recipient: &str, satoshis: Option<u64>) -> Result<String, WalletError> {
+    if pin::is_set().map_err(WalletError::Unauthorized)? {
+        let entered = rpassword::prompt_password("PIN: ")
+            .map_err(|e| WalletError::Other(format!("Failed to read the PIN: {}", e)))?;
+        pin::verify(entered.trim()).map_err(WalletError::Unauthorized)?;
+    }
+    let bitcoins = satoshis
+        .map(|sats| format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000))
+        .unwrap_or_default();
+    match InputEval::evaluate(recipient, &bitcoins, "")?.network {
+        InputNetwork::Mainnet(addr) => {
+            let satoshis = satoshis.ok_or(WalletError::InvalidAmount(
+                "Sending to an address needs an amount".to_string(),
+            ))?;
+            Ok(BdkWallet::payto(addr, satoshis)?.to_string())
+        }
+        InputNetwork::Payjoin(addr, pj) => {
+            let satoshis = satoshis.ok_or(WalletError::InvalidAmount(
+                "Sending to an address needs an amount".to_string(),
+            ))?;
+            Ok(BdkWallet::payjoin(&addr, satoshis, &pj)?.to_string())
+        }
+        InputNetwork::SilentPayment(code) => {
+            let satoshis = satoshis.ok_or(WalletError::InvalidAmount(
+                "Sending to a silent payment code needs an amount".to_string(),
+            ))?;
+            Ok(BdkWallet::pay_silent(&code, satoshis)?.to_string())
+        }
+        InputNetwork::Lightning(invoice) => {
+            wait_for_payment(&BdkWallet::pay_invoice(&invoice, satoshis)?)
+        }
+        InputNetwork::LightningOffer(offer) => {
+            wait_for_payment(&BdkWallet::pay_offer(&offer, satoshis, "")?)
+        }
+        InputNetwork::Psbt(psbt) => BdkWallet::sign_psbt(*psbt, confirm_psbt),
+        InputNetwork::Batch(recipients) => Ok(BdkWallet::payto_many(&recipients)?.to_string()),
+        _ => Err(WalletError::InvalidInput(
+            "Only invoices, offers and addresses can be paid from the command line".to_string(),
+        )),
+    }
+}
+
+/// Ask on the terminal whether the PSBT may be signed, a pasted PSBT isn't checked by anyone
+/// else before it's broadcast
+fn confirm_psbt(amount: u64, fee: u64) -> bool {
+    eprint!(
+        "The PSBT spends {} sats of this wallet, including a fee of {} sats. Sign it? [y/N] ",
+        amount, fee
+    );
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Process the events of the node until the lightning payment succeeded or failed, the node
+/// must not be stopped while it is in flight
+fn wait_for_payment(payment_id: &str) -> Result<String, WalletError> {
+    let handle = payments::start();
+    payments::dispatched(handle, payment_id);
+    let started = Instant::now();
+    loop {
+        while BdkWallet::handle_ldk_event()?.is_some() {}
+        match payments::status(handle) {
+            Some(Status::Succeeded) => return Ok(payment_id.to_string()),
+            Some(Status::Failed(reason)) => {
+                return Err(WalletError::Node(format!("The payment failed: {}", reason)))
+            }
+            _ => {}
+        }
+        if started.elapsed() > Duration::from_secs(CLI_PAYMENT_TIMEOUT_SECS) {
+            return Err(WalletError::Node(format!(
+                "The payment {} is still in flight, its outcome shows in the history once the wallet runs again",
+                payment_id
+            )));
+        }
+        thread::sleep(Duration::from_secs(EVENT_POLL_SECS));
+    }
+}
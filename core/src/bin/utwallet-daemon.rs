@@ -0,0 +1,52 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Runs the node of the active wallet without the GUI, see utwallet-daemon.service
+
+#[macro_use]
+extern crate utwallet_core;
+
+use utwallet_core::settings::Settings;
+use utwallet_core::{daemon, i18n, logging, profiles, proxy};
+
+use std::{env, process};
+
+fn main() {
+    // the catalogs are installed next to the binary, like for the GUI
+    if let Some(dir) = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|d| d.to_path_buf()))
+    {
+        if let Err(e) = i18n::init(&dir.join("share/locale")) {
+            log_error!("{}", e);
+        }
+    }
+    if let Err(e) = profiles::check_override() {
+        log_error!("{}", e);
+        process::exit(1);
+    }
+    match Settings::load() {
+        Ok(settings) => logging::set_verbose(settings.verbose_logging),
+        Err(e) => log_error!("{}", e),
+    }
+    if let Err(e) = proxy::apply_settings() {
+        log_error!("{}", e);
+    }
+    if let Err(e) = daemon::run() {
+        log_error!("{}", e);
+        process::exit(1);
+    }
+}
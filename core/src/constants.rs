@@ -0,0 +1,96 @@
+/// the name of the app, which is also the name of its data directory
+pub const APP_NAME: &str = "utlnwallet.ulrichard";
+
+/// the esplora servers the settings start out with
+pub const ESPLORA_SERVERS: &[&str] = &[
+    "https://blockstream.info/api/",
+    "https://ax101.blockeng.ch/",
+    "https://ax102.blockeng.ch/",
+];
+
+/// the esplora servers the settings start out with on the test networks
+pub const TESTNET_ESPLORA_SERVERS: &[&str] = &["https://blockstream.info/testnet/api/"];
+pub const SIGNET_ESPLORA_SERVERS: &[&str] = &["https://mempool.space/signet/api/"];
+/// e.g. from `nigiri start --ln`
+pub const REGTEST_ESPLORA_SERVERS: &[&str] = &["http://127.0.0.1:3000/"];
+
+pub const RAPID_GOSSIP_SYNC_URL: &str = "https://rapidsync.lightningdevkit.org/snapshot";
+pub const TESTNET_RAPID_GOSSIP_SYNC_URL: &str =
+    "https://rapidsync.lightningdevkit.org/testnet/snapshot";
+/// fall back to the gossip of the peers when there was no new snapshot for this long
+pub const RGS_FALLBACK_SECS: u64 = 2 * 24 * 60 * 60;
+/// how long to stay with the gossip of the peers before trying the snapshots again
+pub const RGS_RETRY_SECS: u64 = 24 * 60 * 60;
+
+/// the ldk-node release this is built against, keep in sync with Cargo.toml
+pub const LDK_NODE_VERSION: &str = "0.3";
+
+/// how often the exchange rate is fetched in the background
+pub const RATE_REFRESH_SECS: u64 = 600;
+
+/// how often the on-chain fee estimates are fetched in the background
+pub const FEE_REFRESH_SECS: u64 = 600;
+
+/// how often the esplora servers are checked in the background
+pub const ESPLORA_CHECK_SECS: u64 = 300;
+
+/// how long an esplora server has to answer the health check
+pub const ESPLORA_CHECK_TIMEOUT_SECS: u64 = 10;
+
+/// how often the events of the node are processed
+pub const EVENT_POLL_SECS: u64 = 2;
+
+/// how long the command line client waits for the outcome of a lightning payment
+pub const CLI_PAYMENT_TIMEOUT_SECS: u64 = 300;
+
+/// how often the wallets are synced in the background by default
+pub const SYNC_REFRESH_SECS: u64 = 120;
+
+/// how many events are kept in the log by default
+pub const EVENT_LOG_LENGTH: u32 = 50;
+
+/// how often the network connection is checked for changes
+pub const NETWORK_POLL_SECS: u64 = 5;
+
+/// the syncs and fee estimates are this many times less frequent while saving data
+pub const LOW_DATA_FACTOR: u32 = 4;
+
+/// how long the wallet calls wait for a node that is being started
+pub const NODE_WAIT_SECS: u64 = 30;
+
+/// how long to wait before the second attempt to start a stopped node again
+pub const NODE_RESTART_RETRY_SECS: u64 = 3;
+
+/// the size of a spend with a few inputs, to keep the fee of a send from the seed out of
+/// the reserve of the node
+pub const SEED_SPEND_VBYTES: u64 = 250;
+
+/// a pasted PSBT with a higher fee is refused, whatever it sends
+pub const PSBT_MAX_FEE_SATS: u64 = 100_000;
+
+/// a pasted PSBT whose fee is more than this share of its outputs is refused
+pub const PSBT_MAX_FEE_PERCENT: u64 = 10;
+
+/// how long the address input has to stay unchanged before it is resolved
+pub const EVALUATION_DEBOUNCE_MS: u64 = 400;
+
+/// the default port of the REST API on localhost
+pub const REST_PORT: u16 = 8421;
+
+/// the delay before the first retry of a failed webhook call, it doubles with every attempt
+pub const WEBHOOK_RETRY_SECS: u64 = 30;
+
+/// how often the payments to the lightning address are moved from the provider to the node
+pub const LNADDRESS_SWEEP_SECS: u64 = 60;
+
+/// smaller balances at the provider of the lightning address are left for later
+pub const LNADDRESS_SWEEP_MIN_SATS: u64 = 100;
+
+/// the largest payment the lightning address accepts
+pub const LNADDRESS_MAX_SATS: u64 = 1_000_000;
+
+/// how often the daemon checks whether the GUI gave the node back
+pub const DAEMON_RECLAIM_SECS: u64 = 5;
+
+pub const LN_ULR: &str =
+    "03a46be38d068c2bc5af3fc13da840790ed5643f3d6d27e5e34d67ed2aec16ce67@77.74.80.179:9735";
@@ -0,0 +1,234 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The node can run without the GUI, e.g. started by a systemd user service, to keep the
+//! channels alive and receive payments. When the GUI opens, the daemon hands the node over
+//! with a lease, and takes it back once the GUI gives the lease back or leaves the bus, e.g.
+//! by crashing. Taking the node over needs the D-Bus token of the settings.
+
+use crate::constants::DAEMON_RECLAIM_SECS;
+use crate::dbus;
+use crate::eventlog::{self, Severity};
+use crate::notify;
+use crate::rest;
+use crate::scheduler::{self, Update};
+use crate::wallet::BdkWallet;
+
+use std::{
+    process,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+use zbus::{
+    blocking::{fdo::DBusProxy, Connection, ConnectionBuilder, Proxy},
+    dbus_interface, fdo,
+    names::BusName,
+    MessageHeader,
+};
+
+const BUS_NAME: &str = "utlnwallet.ulrichard.Daemon";
+const OBJECT_PATH: &str = "/utlnwallet/Daemon";
+const INTERFACE: &str = "utlnwallet.ulrichard.Daemon";
+
+/// the GUI runs the node at the moment
+static HANDED_OVER: AtomicBool = AtomicBool::new(false);
+
+/// the unique bus name of the GUI holding the lease on the node
+static LEASE: Mutex<Option<String>> = Mutex::new(None);
+
+/// the connection of the GUI, its unique name holds the lease as long as it is open
+static LEASE_CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+
+struct Daemon;
+
+fn sender(header: &MessageHeader<'_>) -> fdo::Result<String> {
+    header
+        .sender()
+        .ok()
+        .flatten()
+        .map(|name| name.to_string())
+        .ok_or_else(|| fdo::Error::AccessDenied("No sender".to_string()))
+}
+
+#[dbus_interface(name = "utlnwallet.ulrichard.Daemon")]
+impl Daemon {
+    /// Stop the node, so that the GUI can start it. The caller holds the lease until it
+    /// gives the node back or leaves the bus.
+    fn hand_over(&self, token: &str, #[zbus(header)] header: MessageHeader<'_>) -> fdo::Result<()> {
+        dbus::check_token(token)?;
+        let sender = sender(&header)?;
+        let mut lease = LEASE
+            .lock()
+            .map_err(|e| fdo::Error::Failed(format!("Unable to get the lease: {:?}", e)))?;
+        if !HANDED_OVER.load(Ordering::SeqCst) {
+            log_info!("handing the node over to the GUI");
+            scheduler::pause(true);
+            rest::stop();
+            BdkWallet::shutdown().map_err(|e| fdo::Error::Failed(e.to_string()))?;
+            HANDED_OVER.store(true, Ordering::SeqCst);
+        }
+        *lease = Some(sender);
+        Ok(())
+    }
+
+    /// The GUI stopped the node, the daemon may take it back
+    fn give_back(&self, token: &str, #[zbus(header)] header: MessageHeader<'_>) -> fdo::Result<()> {
+        dbus::check_token(token)?;
+        let sender = sender(&header)?;
+        let mut lease = LEASE
+            .lock()
+            .map_err(|e| fdo::Error::Failed(format!("Unable to get the lease: {:?}", e)))?;
+        if lease.as_deref() == Some(sender.as_str()) {
+            log_info!("the GUI gave the node back");
+            *lease = None;
+        }
+        Ok(())
+    }
+
+    #[dbus_interface(property)]
+    fn pid(&self) -> u32 {
+        process::id()
+    }
+
+    #[dbus_interface(property)]
+    fn running_node(&self) -> bool {
+        !HANDED_OVER.load(Ordering::SeqCst)
+    }
+}
+
+/// Whether the GUI holding the lease left the bus without giving the node back
+fn holder_gone(connection: &Connection, holder: &str) -> bool {
+    let gone = BusName::try_from(holder)
+        .ok()
+        .and_then(|name| {
+            DBusProxy::new(connection)
+                .and_then(|dbus| dbus.name_has_owner(name))
+                .ok()
+        })
+        .map(|present| !present)
+        .unwrap_or(false);
+    if gone {
+        log_info!("the GUI left without giving the node back");
+    }
+    gone
+}
+
+/// Run the node until the process is stopped
+pub fn run() -> Result<(), String> {
+    BdkWallet::init_node(|stage| log_info!("daemon: {}", stage.name()))?;
+    let connection = ConnectionBuilder::session()
+        .and_then(|b| b.name(BUS_NAME))
+        .and_then(|b| b.serve_at(OBJECT_PATH, Daemon))
+        .and_then(|b| b.build())
+        .map_err(|e| format!("Failed to offer the daemon on D-Bus: {}", e))?;
+    scheduler::start(|update| match update {
+        Update::Event(event) => {
+            log_info!("{}", event.describe());
+            eventlog::push(Severity::Info, &event.describe(), event.reference());
+            notify::event(&event);
+        }
+        Update::Failed(task, e) => log_error!("{:?} failed: {}", task, e),
+        _ => {}
+    });
+    if let Err(e) = rest::start() {
+        log_error!("{}", e);
+    }
+    log_info!("the daemon is running");
+
+    loop {
+        thread::sleep(Duration::from_secs(DAEMON_RECLAIM_SECS));
+        if !HANDED_OVER.load(Ordering::SeqCst) {
+            continue;
+        }
+        let holder = match LEASE.lock() {
+            Ok(lease) => lease.clone(),
+            Err(_) => continue,
+        };
+        // the bus is asked without the mutex, the D-Bus calls are served on the same connection
+        if let Some(holder) = &holder {
+            if !holder_gone(&connection, holder) {
+                continue;
+            }
+        }
+        // a GUI asking for the node meanwhile waits until it was taken back
+        let mut lease = match LEASE.lock() {
+            Ok(lease) => lease,
+            Err(_) => continue,
+        };
+        if *lease != holder {
+            continue;
+        }
+        *lease = None;
+        log_info!("taking the node back");
+        match BdkWallet::init_node(|_| {}) {
+            Ok(()) => {
+                HANDED_OVER.store(false, Ordering::SeqCst);
+                scheduler::pause(false);
+                if let Err(e) = rest::start() {
+                    log_error!("{}", e);
+                }
+            }
+            Err(e) => {
+                log_error!("Failed to take the node back: {}", e);
+                notify::stopped(&e.to_string());
+            }
+        }
+    }
+}
+
+/// Ask a running daemon to hand the node over, false if there is none. The lease lasts until
+/// give_back is called or the process ends.
+pub fn take_over() -> Result<bool, String> {
+    let mut lease = LEASE_CONNECTION
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the daemon lease: {:?}", e))?;
+    let conn = match lease.take() {
+        Some(conn) => conn,
+        None => Connection::session().map_err(|e| format!("Failed to connect to D-Bus: {}", e))?,
+    };
+    let name = BusName::try_from(BUS_NAME).map_err(|e| e.to_string())?;
+    let running = DBusProxy::new(&conn)
+        .and_then(|dbus| dbus.name_has_owner(name))
+        .map_err(|e| format!("Failed to look for the daemon: {}", e))?;
+    if !running {
+        return Ok(false);
+    }
+    let token = dbus::token()?;
+    Proxy::new(&conn, BUS_NAME, OBJECT_PATH, INTERFACE)
+        .and_then(|proxy| proxy.call_method("HandOver", &(token.as_str(),)))
+        .map_err(|e| format!("The daemon didn't hand the node over: {}", e))?;
+    *lease = Some(conn);
+    Ok(true)
+}
+
+/// Let the daemon take the node back, after it was stopped here
+pub fn give_back() -> Result<(), String> {
+    let conn = match LEASE_CONNECTION
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the daemon lease: {:?}", e))?
+        .take()
+    {
+        Some(conn) => conn,
+        None => return Ok(()),
+    };
+    let token = dbus::token()?;
+    Proxy::new(&conn, BUS_NAME, OBJECT_PATH, INTERFACE)
+        .and_then(|proxy| proxy.call_method("GiveBack", &(token.as_str(),)))
+        .map_err(|e| format!("Failed to give the node back to the daemon: {}", e))?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! A small D-Bus API for the other apps on the phone: the balance, new invoices, and
+//! payment requests. Any process on the session bus can call it, so the balance and the
+//! invoices need the token from the settings. A requested payment is only sent after the
+//! user confirmed it in the GUI, the outcome is announced with the PaymentFinished signal.
+
+use crate::error::WalletError;
+use crate::input_eval::{InputEval, InputNetwork};
+use crate::secrets;
+use crate::wallet::BdkWallet;
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Mutex,
+};
+use zbus::{blocking::Connection, blocking::ConnectionBuilder, dbus_interface, fdo, SignalContext};
+
+const BUS_NAME: &str = "utlnwallet.ulrichard";
+const OBJECT_PATH: &str = "/utlnwallet/Wallet";
+
+pub const DBUS_TOKEN: &str = "dbus_token";
+
+static CONNECTION: Mutex<Option<Connection>> = Mutex::new(None);
+
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+
+/// An invoice another app wants paid
+pub struct PaymentRequest {
+    pub id: u32,
+    pub invoice: String,
+}
+
+struct Wallet {
+    /// hands the requests to the GUI for confirmation
+    notify: Box<dyn Fn(PaymentRequest) + Send + Sync>,
+}
+
+fn failed(e: WalletError) -> fdo::Error {
+    log_error!("D-Bus call failed: {}", e);
+    fdo::Error::Failed(e.to_string())
+}
+
+/// The token the other apps have to present, created on first use
+pub fn token() -> Result<String, String> {
+    secrets::token(DBUS_TOKEN)
+}
+
+/// Replace the token, so that the apps using the old one lose access
+pub fn reset_token() -> Result<String, String> {
+    secrets::new_token(DBUS_TOKEN)
+}
+
+pub(crate) fn check_token(presented: &str) -> fdo::Result<()> {
+    let token = token().map_err(|e| failed(WalletError::Storage(e)))?;
+    if secrets::same_token(presented, &token) {
+        Ok(())
+    } else {
+        log_error!("D-Bus call with a wrong token");
+        Err(fdo::Error::AccessDenied("Wrong token".to_string()))
+    }
+}
+
+#[dbus_interface(name = "utlnwallet.ulrichard.Wallet")]
+impl Wallet {
+    /// The spendable on-chain and the lightning balance in sats
+    fn get_balance(&self, token: &str) -> fdo::Result<(u64, u64)> {
+        check_token(token)?;
+        BdkWallet::get_balance().map_err(failed)
+    }
+
+    /// A lightning invoice, 0 sats for an invoice without amount
+    fn create_invoice(
+        &self,
+        token: &str,
+        amount_sats: u64,
+        description: &str,
+    ) -> fdo::Result<String> {
+        check_token(token)?;
+        let amount = (amount_sats > 0).then_some(amount_sats);
+        BdkWallet::create_invoice(amount, description).map_err(failed)
+    }
+
+    /// Ask the user to pay the invoice, and return the id of the request
+    fn request_payment(&self, invoice: &str) -> fdo::Result<u32> {
+        match InputEval::evaluate(invoice, "", "")
+            .map_err(failed)?
+            .network
+        {
+            InputNetwork::Lightning(inv) if inv.amount_milli_satoshis().is_some() => {}
+            InputNetwork::Lightning(_) => {
+                return Err(fdo::Error::InvalidArgs(
+                    "Only invoices with an amount can be requested".to_string(),
+                ))
+            }
+            _ => {
+                return Err(fdo::Error::InvalidArgs(
+                    "Not a lightning invoice".to_string(),
+                ))
+            }
+        }
+        let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+        log_info!("payment request {} over D-Bus for {}", id, invoice);
+        (self.notify)(PaymentRequest {
+            id,
+            invoice: invoice.to_string(),
+        });
+        Ok(id)
+    }
+
+    /// status is paid, rejected or failed, detail is the payment hash or the error
+    #[dbus_interface(signal)]
+    async fn payment_finished(
+        ctxt: &SignalContext<'_>,
+        id: u32,
+        status: &str,
+        detail: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Claim the bus name and serve the requests, the connection lives as long as the app
+pub fn start(notify: impl Fn(PaymentRequest) + Send + Sync + 'static) -> Result<(), String> {
+    let mut conn = CONNECTION
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the D-Bus connection: {:?}", e))?;
+    if conn.is_some() {
+        return Ok(());
+    }
+    let wallet = Wallet {
+        notify: Box::new(notify),
+    };
+    let connection = ConnectionBuilder::session()
+        .and_then(|b| b.name(BUS_NAME))
+        .and_then(|b| b.serve_at(OBJECT_PATH, wallet))
+        .and_then(|b| b.build())
+        .map_err(|e| format!("Failed to offer the D-Bus interface: {}", e))?;
+    *conn = Some(connection);
+    Ok(())
+}
+
+fn emit(id: u32, status: &str, detail: &str) {
+    let res = CONNECTION
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the D-Bus connection: {:?}", e))
+        .and_then(|conn| {
+            let conn = conn.as_ref().ok_or("No D-Bus connection")?;
+            let iface = conn
+                .object_server()
+                .interface::<_, Wallet>(OBJECT_PATH)
+                .map_err(|e| e.to_string())?;
+            zbus::block_on(Wallet::payment_finished(
+                iface.signal_context(),
+                id,
+                status,
+                detail,
+            ))
+            .map_err(|e| e.to_string())
+        });
+    if let Err(e) = res {
+        log_error!(
+            "Failed to announce the outcome of payment request {}: {}",
+            id,
+            e
+        );
+    }
+}
+
+/// Announce the outcome of a confirmed payment request
+pub fn finish(id: u32, res: &Result<String, WalletError>) {
+    match res {
+        Ok(msg) => emit(id, "paid", msg),
+        Err(e) => emit(id, "failed", &e.to_string()),
+    }
+}
+
+/// The user declined the payment request
+pub fn reject(id: u32) {
+    emit(id, "rejected", "")
+}
@@ -0,0 +1,395 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! A wallet with canned data instead of the node, for developing the GUI and taking
+//! screenshots in the emulator. It is enabled with UTWALLET_DEMO=1, keeps its files in a
+//! separate directory and never starts the node.
+
+use crate::constants::LDK_NODE_VERSION;
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::payments;
+use crate::storage::to_hex;
+use crate::wallet::{NodeInfo, WalletEvent};
+
+use chrono::Utc;
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::hashes::{sha256, Hash};
+use ldk_node::bitcoin::secp256k1::{PublicKey, Secp256k1, SecretKey};
+use ldk_node::bitcoin::{Address, Network, Txid};
+use ldk_node::lightning::ln::{PaymentHash, PaymentPreimage, PaymentSecret};
+use ldk_node::lightning_invoice::{Currency, InvoiceBuilder};
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentId, PaymentKind, PaymentStatus};
+use rand_core::{OsRng, RngCore};
+use std::{
+    collections::VecDeque,
+    env,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+};
+
+/// a well known test seed, nobody should ever send real funds to it
+const DEMO_MNEMONIC: &str =
+    "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+const DEMO_ADDRESS: &str = "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu";
+const DEMO_NODE_SECRET: [u8; 32] = [0x42; 32];
+const DEMO_PEER: &str = "03864ef025fde8fb587d989186ce6a4a186895ee44a926bfc370e2c366597a3f8f";
+const DEMO_PEER_ADDRESS: &str = "3.33.236.230:9735";
+const DEMO_CHANNEL_SATS: u64 = 500_000;
+const DEMO_BLOCK_HEIGHT: u32 = 850_000;
+/// the amount that "arrives" for an invoice without an amount
+const DEMO_RECEIVED_SATS: u64 = 21_000;
+const DEMO_FEE_MSAT: u64 = 1_000;
+
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<Option<Demo>> = Mutex::new(None);
+
+struct Demo {
+    onchain_sats: u64,
+    lightning_sats: u64,
+    channel_open: bool,
+    payments: Vec<PaymentDetails>,
+    events: VecDeque<WalletEvent>,
+}
+
+impl Demo {
+    fn canned() -> Self {
+        let mut demo = Self {
+            onchain_sats: 150_000,
+            lightning_sats: 0,
+            channel_open: true,
+            payments: Vec::new(),
+            events: VecDeque::new(),
+        };
+        demo.record(PaymentKind::Onchain, 250_000, PaymentDirection::Inbound);
+        demo.record(bolt11([1; 32]), 80_000, PaymentDirection::Inbound);
+        demo.record(bolt11([2; 32]), 12_500, PaymentDirection::Outbound);
+        demo.record(bolt11([3; 32]), 4_200, PaymentDirection::Outbound);
+        demo.lightning_sats = 80_000 - 12_500 - 4_200;
+        demo
+    }
+
+    fn record(&mut self, kind: PaymentKind, sats: u64, direction: PaymentDirection) -> PaymentId {
+        let id = match &kind {
+            PaymentKind::Bolt11 { hash, .. } => PaymentId(hash.0),
+            _ => PaymentId(random_bytes()),
+        };
+        self.payments.push(PaymentDetails {
+            id,
+            kind,
+            amount_msat: Some(sats * 1_000),
+            direction,
+            status: PaymentStatus::Succeeded,
+        });
+        id
+    }
+
+    fn spend_lightning(&mut self, sats: u64) -> Result<String, WalletError> {
+        if !self.channel_open || sats > self.lightning_sats {
+            return Err(WalletError::Node(tr(
+                "Not enough outbound capacity in the demo channel",
+            )));
+        }
+        self.lightning_sats -= sats;
+        let id = self.record(bolt11(random_bytes()), sats, PaymentDirection::Outbound);
+        let hash = to_hex(&id.0);
+        self.events.push_back(WalletEvent::PaymentSuccessful {
+            payment_hash: hash.clone(),
+            fee_paid_msat: Some(DEMO_FEE_MSAT),
+        });
+        Ok(hash)
+    }
+}
+
+fn bolt11(preimage: [u8; 32]) -> PaymentKind {
+    PaymentKind::Bolt11 {
+        hash: PaymentHash(sha256::Hash::hash(&preimage).to_byte_array()),
+        preimage: Some(PaymentPreimage(preimage)),
+        secret: None,
+    }
+}
+
+fn random_bytes() -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes
+}
+
+fn with_state<T>(f: impl FnOnce(&mut Demo) -> Result<T, WalletError>) -> Result<T, WalletError> {
+    let mut state = STATE.lock().map_err(WalletError::poisoned)?;
+    f(state.get_or_insert_with(Demo::canned))
+}
+
+/// Switch to the demo wallet if the environment asks for it, before anything else runs
+pub fn init() {
+    if env::var("UTWALLET_DEMO").map_or(false, |v| v == "1") {
+        log_info!("running the demo wallet with canned data");
+        ACTIVE.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn is_active() -> bool {
+    ACTIVE.load(Ordering::SeqCst)
+}
+
+pub fn balance() -> Result<(u64, u64), WalletError> {
+    with_state(|demo| Ok((demo.onchain_sats, demo.lightning_sats)))
+}
+
+pub fn address() -> Result<Address, WalletError> {
+    Address::from_str(DEMO_ADDRESS)
+        .and_then(|a| a.require_network(Network::Bitcoin))
+        .map_err(|e| WalletError::Other(e.to_string()))
+}
+
+pub fn can_pay_lightning(amount_msat: Option<u64>) -> Result<bool, WalletError> {
+    with_state(|demo| {
+        Ok(demo.channel_open && demo.lightning_sats * 1_000 >= amount_msat.unwrap_or(1))
+    })
+}
+
+pub fn list_payments() -> Result<Vec<PaymentDetails>, WalletError> {
+    with_state(|demo| Ok(demo.payments.clone()))
+}
+
+pub fn payment(id: &PaymentId) -> Result<Option<PaymentDetails>, WalletError> {
+    with_state(|demo| Ok(demo.payments.iter().find(|p| p.id == *id).cloned()))
+}
+
+pub fn send_onchain(sats: u64) -> Result<Txid, WalletError> {
+    with_state(|demo| {
+        if sats > demo.onchain_sats {
+            return Err(WalletError::Node(tr(
+                "Insufficient funds in the demo wallet",
+            )));
+        }
+        demo.onchain_sats -= sats;
+        demo.record(PaymentKind::Onchain, sats, PaymentDirection::Outbound);
+        Ok(Txid::from_byte_array(random_bytes()))
+    })
+}
+
+/// Pretend to pay over the demo channel, returns the payment hash
+pub fn send_lightning(sats: u64) -> Result<String, WalletError> {
+    with_state(|demo| demo.spend_lightning(sats))
+}
+
+/// Create a real looking invoice, which gets "paid" with the next events
+pub fn create_invoice(amount: Option<u64>, desc: &str) -> Result<String, WalletError> {
+    let preimage = random_bytes();
+    let secp = Secp256k1::new();
+    let key = SecretKey::from_slice(&DEMO_NODE_SECRET).map_err(|e| e.to_string())?;
+    let mut builder = InvoiceBuilder::new(Currency::Bitcoin)
+        .description(desc.to_string())
+        .payment_hash(sha256::Hash::hash(&preimage))
+        .payment_secret(PaymentSecret(random_bytes()))
+        .current_timestamp()
+        .min_final_cltv_expiry_delta(144);
+    if let Some(sats) = amount {
+        builder = builder.amount_milli_satoshis(sats * 1_000);
+    }
+    let invoice = builder
+        .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &key))
+        .map_err(|e| WalletError::Other(trf("Failed to create the demo invoice: {}", &[&e])))?;
+
+    let sats = amount.unwrap_or(DEMO_RECEIVED_SATS);
+    with_state(|demo| {
+        let id = demo.record(bolt11(preimage), sats, PaymentDirection::Inbound);
+        if let Some(payment) = demo.payments.iter_mut().find(|p| p.id == id) {
+            payment.status = PaymentStatus::Pending;
+        }
+        demo.events.push_back(WalletEvent::PaymentReceived {
+            payment_hash: to_hex(&id.0),
+            amount_msat: sats * 1_000,
+        });
+        Ok(())
+    })?;
+    Ok(invoice.to_string())
+}
+
+/// The next queued event, applied to the balances as it is handed out
+pub fn next_event() -> Result<Option<WalletEvent>, WalletError> {
+    let event = with_state(|demo| {
+        let event = demo.events.pop_front();
+        match &event {
+            Some(WalletEvent::PaymentReceived {
+                payment_hash,
+                amount_msat,
+            }) => {
+                demo.lightning_sats += amount_msat / 1_000;
+                if let Some(payment) = demo
+                    .payments
+                    .iter_mut()
+                    .find(|p| to_hex(&p.id.0) == *payment_hash)
+                {
+                    payment.status = PaymentStatus::Succeeded;
+                }
+            }
+            Some(WalletEvent::ChannelReady { .. }) => demo.channel_open = true,
+            Some(WalletEvent::ChannelClosed { .. }) => {
+                demo.channel_open = false;
+                demo.onchain_sats += demo.lightning_sats;
+                demo.lightning_sats = 0;
+            }
+            _ => {}
+        }
+        Ok(event)
+    })?;
+    if let Some(WalletEvent::PaymentSuccessful { payment_hash, .. }) = &event {
+        payments::settle(payment_hash, Ok(()));
+    }
+    Ok(event)
+}
+
+pub fn channel_open(sats: u64) -> Result<(), WalletError> {
+    with_state(|demo| {
+        if demo.channel_open {
+            return Err(WalletError::Node(tr(
+                "The demo wallet has a channel already",
+            )));
+        }
+        if sats > demo.onchain_sats {
+            return Err(WalletError::Node(tr(
+                "Insufficient funds in the demo wallet",
+            )));
+        }
+        demo.onchain_sats -= sats;
+        demo.lightning_sats += sats;
+        let channel_id = to_hex(&random_bytes());
+        demo.events.push_back(WalletEvent::ChannelPending {
+            channel_id: channel_id.clone(),
+            peer: DEMO_PEER.to_string(),
+            inbound: false,
+        });
+        demo.events.push_back(WalletEvent::ChannelReady {
+            channel_id,
+            peer: DEMO_PEER.to_string(),
+        });
+        Ok(())
+    })
+}
+
+pub fn channel_close() -> Result<(), WalletError> {
+    with_state(|demo| {
+        if !demo.channel_open {
+            return Err(WalletError::Node(tr("The demo wallet has no channel")));
+        }
+        demo.events.push_back(WalletEvent::ChannelClosed {
+            channel_id: to_hex(&random_bytes()),
+            reason: "closed by us".to_string(),
+        });
+        Ok(())
+    })
+}
+
+/// Our share of the demo channel, like BdkWallet::get_channel_status
+pub fn channel_status() -> Result<String, WalletError> {
+    with_state(|demo| {
+        Ok(if demo.channel_open {
+            format!(
+                "{}",
+                demo.lightning_sats as f32 / DEMO_CHANNEL_SATS.max(demo.lightning_sats) as f32
+            )
+        } else {
+            String::new()
+        })
+    })
+}
+
+pub fn channel_peers() -> Result<Vec<(String, String, u64, String)>, WalletError> {
+    with_state(|demo| {
+        Ok(if demo.channel_open {
+            vec![(
+                DEMO_PEER.to_string(),
+                DEMO_PEER_ADDRESS.to_string(),
+                DEMO_CHANNEL_SATS,
+                format!("{}:0", to_hex(&[7; 32])),
+            )]
+        } else {
+            Vec::new()
+        })
+    })
+}
+
+pub fn node_id() -> Result<String, WalletError> {
+    let key = SecretKey::from_slice(&DEMO_NODE_SECRET).map_err(|e| e.to_string())?;
+    Ok(PublicKey::from_secret_key(&Secp256k1::new(), &key).to_string())
+}
+
+pub fn node_info() -> Result<NodeInfo, WalletError> {
+    let channels = with_state(|demo| Ok(demo.channel_open as usize))?;
+    Ok(NodeInfo {
+        node_id: node_id()?,
+        listening_addresses: Vec::new(),
+        peers: 1,
+        connected_peers: 1,
+        channels,
+        usable_channels: channels,
+        block_height: DEMO_BLOCK_HEIGHT,
+        network: "demo".to_string(),
+        ldk_node_version: LDK_NODE_VERSION.to_string(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+    })
+}
+
+pub fn sync_status() -> (u32, Option<u64>) {
+    (DEMO_BLOCK_HEIGHT, Some(Utc::now().timestamp() as u64))
+}
+
+pub fn mnemonic() -> Result<Mnemonic, WalletError> {
+    Mnemonic::parse(DEMO_MNEMONIC)
+        .map_err(|e| WalletError::InvalidInput(trf("Failed to parse mnemonic: {}", &[&e])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_canned_balance() {
+        let demo = Demo::canned();
+        let received: u64 = demo
+            .payments
+            .iter()
+            .filter(|p| !matches!(p.kind, PaymentKind::Onchain))
+            .map(|p| match p.direction {
+                PaymentDirection::Inbound => p.amount_msat.unwrap_or(0) as i64,
+                PaymentDirection::Outbound => -(p.amount_msat.unwrap_or(0) as i64),
+            })
+            .sum::<i64>() as u64;
+        assert_eq!(received / 1_000, demo.lightning_sats);
+    }
+
+    #[test]
+    fn test_spend_lightning() {
+        let mut demo = Demo::canned();
+        let before = demo.lightning_sats;
+        let hash = demo.spend_lightning(1_000).unwrap();
+        assert_eq!(demo.lightning_sats, before - 1_000);
+        assert_eq!(
+            demo.events.front().map(|e| e.reference()),
+            Some(hash.as_str())
+        );
+        assert!(demo.spend_lightning(before).is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        assert!(address().is_ok());
+        assert!(mnemonic().is_ok());
+    }
+}
@@ -0,0 +1,145 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! An Electrum server, e.g. an electrs at home, as the source of the fee estimates and of the
+//! chain tip in the settings. It is not a chain source: ldk-node 0.3 can only sync from
+//! esplora, so the node and the wallets built from the seed keep using the esplora servers.
+
+use crate::proxy;
+use crate::settings::Settings;
+
+use electrum_client::{Client, ConfigBuilder, ElectrumApi, Socks5Config};
+
+const SCHEMES: &[&str] = &["ssl://", "tcp://"];
+
+/// seconds to wait for an answer
+const TIMEOUT_SECS: u8 = 10;
+
+const FEE_TARGET_BLOCKS: usize = 6;
+
+/// Check the url for plausibility. Without a scheme, ssl is assumed.
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    let url = if SCHEMES.iter().any(|s| url.starts_with(s)) {
+        url.to_string()
+    } else if url.contains("://") {
+        return Err(format!(
+            "The Electrum url has to start with ssl:// or tcp:// : {}",
+            url
+        ));
+    } else {
+        format!("ssl://{}", url)
+    };
+    let host_port = &url[SCHEMES[0].len()..];
+    match host_port.rsplit_once(':') {
+        Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(url),
+        _ => Err(format!(
+            "The Electrum url needs a host and a port, e.g. ssl://electrs.local:50002 : {}",
+            url
+        )),
+    }
+}
+
+/// Servers at home usually have a self-signed certificate, which can't be validated
+fn is_private_host(url: &str) -> bool {
+    let host = url
+        .split_once("://")
+        .map(|(_, rest)| rest)
+        .unwrap_or(url)
+        .rsplit_once(':')
+        .map(|(host, _)| host)
+        .unwrap_or_default();
+    host == "localhost"
+        || host.ends_with(".local")
+        || host.ends_with(".lan")
+        || host.ends_with(".onion")
+        || host.starts_with("127.")
+        || host.starts_with("10.")
+        || host.starts_with("192.168.")
+        || (16..32).any(|n| host.starts_with(&format!("172.{}.", n)))
+}
+
+/// The Electrum server from the settings, None to take the fee estimates from esplora
+pub fn configured() -> Option<String> {
+    Settings::load()
+        .ok()
+        .map(|s| s.electrum_url)
+        .filter(|url| !url.is_empty())
+}
+
+fn client(url: &str) -> Result<Client, String> {
+    proxy::check_online()?;
+    let proxy = Settings::load()?.proxy;
+    let socks5 = proxy
+        .strip_prefix("socks5h://")
+        .or_else(|| proxy.strip_prefix("socks5://"))
+        .map(Socks5Config::new);
+    let config = ConfigBuilder::new()
+        .timeout(Some(TIMEOUT_SECS))
+        .validate_domain(!is_private_host(url))
+        .socks5(socks5)
+        .build();
+    Client::from_config(url, config)
+        .map_err(|e| format!("Failed to connect to the Electrum server {}: {}", url, e))
+}
+
+/// The on-chain fee rate in sat/vB
+pub fn fee_rate(url: &str) -> Result<f64, String> {
+    let btc_per_kvb = client(url)?
+        .estimate_fee(FEE_TARGET_BLOCKS)
+        .map_err(|e| format!("No fee estimate from the Electrum server: {}", e))?;
+    if btc_per_kvb <= 0.0 {
+        return Err("The Electrum server has no fee estimate".to_string());
+    }
+    Ok(btc_per_kvb * 100_000.0)
+}
+
+/// The height of the chain tip
+pub fn tip_height(url: &str) -> Result<u64, String> {
+    client(url)?
+        .block_headers_subscribe()
+        .map(|header| header.height as u64)
+        .map_err(|e| format!("No chain tip from the Electrum server: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            normalize_url("electrs.local:50002").unwrap(),
+            "ssl://electrs.local:50002"
+        );
+        assert_eq!(
+            normalize_url(" tcp://192.168.1.5:50001/ ").unwrap(),
+            "tcp://192.168.1.5:50001"
+        );
+        assert!(normalize_url("https://electrs.local:50002").is_err());
+        assert!(normalize_url("ssl://electrs.local").is_err());
+        assert!(normalize_url("ssl://:50002").is_err());
+    }
+
+    #[test]
+    fn test_private_hosts() {
+        assert!(is_private_host("ssl://electrs.local:50002"));
+        assert!(is_private_host("ssl://192.168.1.5:50002"));
+        assert!(is_private_host("tcp://172.20.0.2:50001"));
+        assert!(!is_private_host("ssl://electrum.blockstream.info:50002"));
+        assert!(!is_private_host("ssl://172.32.0.2:50002"));
+    }
+}
@@ -0,0 +1,140 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::i18n::tr;
+
+use std::{fmt, sync::PoisonError};
+
+/// The errors of the wallet operations. The kind tells the GUI how to present an error and
+/// what the user can do about it, the message carries the details for the log.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WalletError {
+    /// the node isn't running, e.g. for a watch-only wallet
+    NotInitialized,
+    /// another thread panicked while holding the node
+    Poisoned(String),
+    /// the PIN has to be entered first
+    Unauthorized(String),
+    /// the address, invoice or key couldn't be understood
+    InvalidInput(String),
+    /// the amount is missing, out of the allowed range or doesn't match the invoice
+    InvalidAmount(String),
+    /// a server couldn't be reached, or the wallet is offline
+    Network(String),
+    /// the lightning node or the on-chain wallet refused the operation
+    Node(String),
+    /// reading or writing the files in the data directory failed
+    Storage(String),
+    /// a newer request superseded this one, nothing to report
+    Cancelled(String),
+    Other(String),
+}
+
+impl WalletError {
+    pub fn poisoned<T>(e: PoisonError<T>) -> Self {
+        Self::Poisoned(format!("Unable to get the mutex for the wallet: {:?}", e))
+    }
+
+    /// A stable identifier, for the GUI to pick a translated message
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotInitialized => "not_initialized",
+            Self::Poisoned(_) => "poisoned",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::InvalidAmount(_) => "invalid_amount",
+            Self::Network(_) => "network",
+            Self::Node(_) => "node",
+            Self::Storage(_) => "storage",
+            Self::Cancelled(_) => "cancelled",
+            Self::Other(_) => "other",
+        }
+    }
+
+    /// Whether the user can fix it and try again, as opposed to restarting the app
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(
+            self,
+            Self::NotInitialized | Self::Poisoned(_) | Self::Storage(_)
+        )
+    }
+
+    /// What the user can do about it
+    pub fn action(&self) -> &'static str {
+        match self {
+            Self::NotInitialized | Self::Poisoned(_) => "restart",
+            Self::Unauthorized(_) => "enter_pin",
+            Self::InvalidInput(_) | Self::InvalidAmount(_) => "edit_input",
+            Self::Network(_) | Self::Node(_) => "retry",
+            Self::Storage(_) | Self::Cancelled(_) | Self::Other(_) => "",
+        }
+    }
+}
+
+impl fmt::Display for WalletError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotInitialized => write!(f, "{}", tr("The wallet was not initialized")),
+            Self::Poisoned(msg)
+            | Self::Unauthorized(msg)
+            | Self::InvalidInput(msg)
+            | Self::InvalidAmount(msg)
+            | Self::Network(msg)
+            | Self::Node(msg)
+            | Self::Storage(msg)
+            | Self::Cancelled(msg)
+            | Self::Other(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// The rest of the code base reports errors as plain messages
+impl From<WalletError> for String {
+    fn from(e: WalletError) -> Self {
+        e.to_string()
+    }
+}
+
+impl From<String> for WalletError {
+    fn from(msg: String) -> Self {
+        Self::Other(msg)
+    }
+}
+
+impl From<&str> for WalletError {
+    fn from(msg: &str) -> Self {
+        Self::Other(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wallet_error() {
+        let e = WalletError::InvalidAmount("No amount to pay the invoice!".to_string());
+        assert_eq!(e.code(), "invalid_amount");
+        assert!(e.is_recoverable());
+        assert_eq!(e.action(), "edit_input");
+        assert_eq!(String::from(e), "No amount to pay the invoice!");
+        assert!(!WalletError::NotInitialized.is_recoverable());
+        assert_eq!(
+            WalletError::from("offline".to_string()),
+            WalletError::Other("offline".to_string())
+        );
+    }
+}
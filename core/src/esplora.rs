@@ -0,0 +1,222 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The esplora servers the wallets sync with. The user can configure the list, the servers
+//! are checked concurrently and the wallet fails over to another one when the current
+//! server stops answering. The last working server is remembered across restarts.
+
+use crate::constants::ESPLORA_CHECK_TIMEOUT_SECS;
+use crate::proxy;
+use crate::rates::get_json;
+use crate::settings::Settings;
+use crate::storage::{load_json, save_json};
+
+use bdk::blockchain::{
+    esplora::EsploraBlockchainConfig, ConfigurableBlockchain, EsploraBlockchain,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::mpsc::channel,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+const ESPLORA_STATE_FILE: &str = "esplora.json";
+
+/// The server in use, loaded from the state file on first use
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct EsploraState {
+    /// the server that answered last
+    current: String,
+}
+
+/// Check the url for plausibility and bring it into the form the clients expect
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!(
+            "The esplora url must start with https:// : {}",
+            url
+        ));
+    }
+    if url.len() <= "https://".len() || url.contains(char::is_whitespace) {
+        return Err(format!("Invalid esplora url: {}", url));
+    }
+    Ok(if url.ends_with('/') {
+        url.to_string()
+    } else {
+        format!("{}/", url)
+    })
+}
+
+/// The servers from the settings
+pub fn configured() -> Vec<String> {
+    Settings::load()
+        .unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Settings::default()
+        })
+        .esplora_servers
+}
+
+/// A bdk chain source for the server, through the proxy of the settings
+pub fn blockchain(url: &str) -> Result<EsploraBlockchain, String> {
+    EsploraBlockchain::from_config(&EsploraBlockchainConfig {
+        base_url: url.to_string(),
+        proxy: proxy::configured()?,
+        concurrency: None,
+        stop_gap: 20,
+        timeout: None,
+    })
+    .map_err(|e| format!("Failed to set up the esplora client: {}", e))
+}
+
+/// The server to use right now
+pub fn current() -> String {
+    let servers = configured();
+    let mut cur = match CURRENT.lock() {
+        Ok(cur) => cur,
+        Err(e) => {
+            log_error!("Unable to get the mutex for the esplora server: {:?}", e);
+            return servers.first().cloned().unwrap_or_default();
+        }
+    };
+    if cur.is_none() {
+        let state: EsploraState = load_json(ESPLORA_STATE_FILE).unwrap_or_else(|e| {
+            log_error!("{}", e);
+            EsploraState::default()
+        });
+        *cur = Some(state.current);
+    }
+    match cur.as_ref() {
+        Some(url) if servers.contains(url) => url.clone(),
+        _ => servers.first().cloned().unwrap_or_default(),
+    }
+}
+
+fn remember(url: &str) {
+    if let Ok(mut cur) = CURRENT.lock() {
+        *cur = Some(url.to_string());
+    }
+    let state = EsploraState {
+        current: url.to_string(),
+    };
+    if let Err(e) = save_json(ESPLORA_STATE_FILE, &state) {
+        log_error!("{}", e);
+    }
+}
+
+/// Whether the server answers with the height of the chain tip
+fn check(url: &str) -> bool {
+    match get_json(&format!("{}blocks/tip/height", url)) {
+        Ok(height) => height.as_u64().is_some(),
+        Err(e) => {
+            log_error!("esplora server {} is unavailable: {}", url, e);
+            false
+        }
+    }
+}
+
+/// Check all the servers at once. The ones that don't answer in time count as unavailable.
+pub fn check_all(servers: &[String]) -> Vec<(String, bool)> {
+    let (sender, receiver) = channel();
+    for (idx, url) in servers.iter().enumerate() {
+        let sender = sender.clone();
+        let url = url.clone();
+        thread::spawn(move || {
+            let _ = sender.send((idx, check(&url)));
+        });
+    }
+    drop(sender);
+
+    let mut health: Vec<(String, bool)> = servers.iter().map(|s| (s.clone(), false)).collect();
+    let deadline = Instant::now() + Duration::from_secs(ESPLORA_CHECK_TIMEOUT_SECS);
+    while let Some(timeout) = deadline.checked_duration_since(Instant::now()) {
+        match receiver.recv_timeout(timeout) {
+            Ok((idx, ok)) => health[idx].1 = ok,
+            Err(_) => break,
+        }
+    }
+    health
+}
+
+/// Stay with the current server while it works, otherwise take the first one that does
+fn pick(current: &str, health: &[(String, bool)]) -> Option<String> {
+    if health.iter().any(|(url, ok)| *ok && url == current) {
+        return Some(current.to_string());
+    }
+    health
+        .iter()
+        .find(|(_, ok)| *ok)
+        .map(|(url, _)| url.clone())
+}
+
+/// Check the servers and fail over if the current one is down. Returns the server to use,
+/// and whether it changed.
+pub fn select() -> (String, bool) {
+    let current = current();
+    if proxy::check_online().is_err() {
+        return (current, false);
+    }
+    let health = check_all(&configured());
+    match pick(&current, &health) {
+        Some(url) => {
+            remember(&url);
+            let changed = url != current;
+            if changed {
+                log_info!("switching the esplora server from {} to {}", current, url);
+            }
+            (url, changed)
+        }
+        None => {
+            log_error!("none of the esplora servers is available");
+            (current, false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        assert_eq!(
+            normalize_url(" https://blockstream.info/api ").unwrap(),
+            "https://blockstream.info/api/"
+        );
+        assert_eq!(
+            normalize_url("http://192.168.1.5:3002/").unwrap(),
+            "http://192.168.1.5:3002/"
+        );
+        assert!(normalize_url("blockstream.info/api").is_err());
+        assert!(normalize_url("https://").is_err());
+        assert!(normalize_url("https://a b/").is_err());
+    }
+
+    #[test]
+    fn test_failover() {
+        let health = |a, b| vec![("a/".to_string(), a), ("b/".to_string(), b)];
+        assert_eq!(pick("b/", &health(true, true)), Some("b/".to_string()));
+        assert_eq!(pick("b/", &health(true, false)), Some("a/".to_string()));
+        assert_eq!(pick("c/", &health(false, true)), Some("b/".to_string()));
+        assert_eq!(pick("a/", &health(false, false)), None);
+    }
+}
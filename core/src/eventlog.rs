@@ -0,0 +1,120 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The events and errors shown on the main page. They are redacted and kept across restarts,
+//! up to the configured number of entries.
+
+use crate::redact::redact;
+use crate::settings::Settings;
+use crate::storage::{load_json, save_json};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const EVENTLOG_FILE: &str = "eventlog.json";
+
+static LISTENERS: Mutex<Vec<Box<dyn Fn(LogEntry) + Send>>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    #[default]
+    Info,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// unix timestamp
+    pub timestamp: i64,
+    pub severity: Severity,
+    pub message: String,
+    /// the payment hash or the channel id the entry is about, empty if none
+    #[serde(default)]
+    pub reference: String,
+}
+
+/// The entries, newest first
+pub fn load() -> Result<Vec<LogEntry>, String> {
+    load_json(EVENTLOG_FILE)
+}
+
+fn prepend(entries: &mut Vec<LogEntry>, entry: LogEntry, length: usize) {
+    entries.insert(0, entry);
+    entries.truncate(length);
+}
+
+/// Record an entry and hand it to the listeners
+pub fn push(severity: Severity, message: &str, reference: &str) {
+    let entry = LogEntry {
+        timestamp: Utc::now().timestamp(),
+        severity,
+        message: redact(message),
+        reference: reference.to_string(),
+    };
+    let length = Settings::load().unwrap_or_default().event_log_length as usize;
+    let res = load().and_then(|mut entries| {
+        prepend(&mut entries, entry.clone(), length);
+        save_json(EVENTLOG_FILE, &entries)
+    });
+    if let Err(e) = res {
+        log_error!("{}", e);
+    }
+    if let Ok(listeners) = LISTENERS.lock() {
+        for listener in listeners.iter() {
+            listener(entry.clone());
+        }
+    }
+}
+
+pub fn clear() -> Result<(), String> {
+    save_json(EVENTLOG_FILE, &Vec::<LogEntry>::new())
+}
+
+/// Register a listener for the new entries
+pub fn subscribe(listener: Box<dyn Fn(LogEntry) + Send>) {
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push(listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepend() {
+        let entry = |message: &str| LogEntry {
+            message: message.to_string(),
+            ..Default::default()
+        };
+        let mut entries = vec![entry("b"), entry("a")];
+        prepend(&mut entries, entry("c"), 2);
+        assert_eq!(entries, vec![entry("c"), entry("b")]);
+        prepend(&mut entries, entry("d"), 0);
+        assert!(entries.is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history::HistoryEntry;
+
+use chrono::{TimeZone, Utc};
+
+/// Generate a CSV in the Koinly universal format
+/// https://support.koinly.io/en/articles/9489976-how-to-create-a-custom-csv-file-with-your-data
+pub fn koinly_csv(entries: &[HistoryEntry]) -> String {
+    let header = "Date,Sent Amount,Sent Currency,Received Amount,Received Currency,Fee Amount,Fee Currency,Net Worth Amount,Net Worth Currency,Label,Description,TxHash";
+    exportable(entries)
+        .map(|e| {
+            let btc = btc(e.amount_msat);
+            let (sent, received) = if e.direction == "out" {
+                (btc.as_str(), "")
+            } else {
+                ("", btc.as_str())
+            };
+            let (sent_cur, received_cur) = if e.direction == "out" {
+                ("BTC", "")
+            } else {
+                ("", "BTC")
+            };
+            let fee = e.fee_msat.map(btc_str).unwrap_or_default();
+            let fee_cur = if fee.is_empty() { "" } else { "BTC" };
+            format!(
+                "{},{},{},{},{},{},{},{},{},,{},{}",
+                date(e.timestamp, "%Y-%m-%d %H:%M UTC"),
+                sent,
+                sent_cur,
+                received,
+                received_cur,
+                fee,
+                fee_cur,
+                fiat(e),
+                e.currency,
+                e.kind,
+                e.id
+            )
+        })
+        .fold(header.to_string(), |acc, line| acc + "\n" + &line)
+}
+
+/// Generate a CSV in the CoinTracking generic import format
+/// https://cointracking.info/import/import_csv/
+pub fn cointracking_csv(entries: &[HistoryEntry]) -> String {
+    let header = r#""Type","Buy Amount","Buy Currency","Sell Amount","Sell Currency","Fee","Fee Currency","Exchange","Trade-Group","Comment","Date""#;
+    exportable(entries)
+        .map(|e| {
+            let btc = btc(e.amount_msat);
+            let (typ, buy, buy_cur, sell, sell_cur) = if e.direction == "out" {
+                ("Withdrawal", "", "", btc.as_str(), "BTC")
+            } else {
+                ("Deposit", btc.as_str(), "BTC", "", "")
+            };
+            let fee = e.fee_msat.map(btc_str).unwrap_or_default();
+            let fee_cur = if fee.is_empty() { "" } else { "BTC" };
+            let comment = match fiat(e).as_str() {
+                "" => e.kind.clone(),
+                f => format!("{} {} {}", e.kind, f, e.currency),
+            };
+            format!(
+                r#""{}","{}","{}","{}","{}","{}","{}","utwallet","","{}","{}""#,
+                typ,
+                buy,
+                buy_cur,
+                sell,
+                sell_cur,
+                fee,
+                fee_cur,
+                comment,
+                date(e.timestamp, "%d.%m.%Y %H:%M:%S")
+            )
+        })
+        .fold(header.to_string(), |acc, line| acc + "\n" + &line)
+}
+
+/// Only completed payments are relevant for accounting. Channel operations move funds
+/// between our own wallets.
+fn exportable(entries: &[HistoryEntry]) -> impl Iterator<Item = &HistoryEntry> {
+    entries
+        .iter()
+        .filter(|e| e.status == "succeeded" && !e.is_channel_event())
+}
+
+fn btc(amount_msat: Option<u64>) -> String {
+    amount_msat.map(btc_str).unwrap_or_default()
+}
+
+fn btc_str(msat: u64) -> String {
+    format!("{:.8}", msat as f64 / 100_000_000_000.0)
+}
+
+fn fiat(e: &HistoryEntry) -> String {
+    match (e.amount_msat, e.fiat_rate) {
+        (Some(msat), Some(rate)) => format!("{:.2}", msat as f64 / 100_000_000_000.0 * rate),
+        _ => "".to_string(),
+    }
+}
+
+fn date(timestamp: i64, fmt: &str) -> String {
+    match Utc.timestamp_opt(timestamp, 0).single() {
+        Some(dt) if timestamp > 0 => dt.format(fmt).to_string(),
+        _ => "".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<HistoryEntry> {
+        vec![
+            HistoryEntry {
+                id: "ab01".to_string(),
+                kind: "bolt11".to_string(),
+                direction: "out".to_string(),
+                status: "succeeded".to_string(),
+                amount_msat: Some(100_000_000),
+                timestamp: 1_700_000_000,
+                fee_msat: Some(2_000),
+                fiat_rate: Some(30_000.0),
+                currency: "CHF".to_string(),
+                ..Default::default()
+            },
+            HistoryEntry {
+                id: "cd02".to_string(),
+                kind: "onchain".to_string(),
+                direction: "in".to_string(),
+                status: "succeeded".to_string(),
+                amount_msat: Some(50_000_000),
+                timestamp: 1_700_000_600,
+                ..Default::default()
+            },
+            HistoryEntry {
+                id: "open-ef03".to_string(),
+                kind: "channel_open".to_string(),
+                direction: "out".to_string(),
+                status: "pending".to_string(),
+                ..Default::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn test_koinly() {
+        let csv = koinly_csv(&entries());
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            "2023-11-14 22:13 UTC,0.00100000,BTC,,,0.00000002,BTC,30.00,CHF,,bolt11,ab01"
+        );
+        assert_eq!(
+            lines[2],
+            "2023-11-14 22:23 UTC,,,0.00050000,BTC,,,,,,onchain,cd02"
+        );
+    }
+
+    #[test]
+    fn test_cointracking() {
+        let csv = cointracking_csv(&entries());
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            lines[1],
+            r#""Withdrawal","","","0.00100000","BTC","0.00000002","BTC","utwallet","","bolt11 30.00 CHF","14.11.2023 22:13:20""#
+        );
+        assert_eq!(
+            lines[2],
+            r#""Deposit","0.00050000","BTC","","","","","utwallet","","onchain","14.11.2023 22:23:20""#
+        );
+    }
+}
@@ -0,0 +1,189 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use serde::{Deserialize, Serialize};
+use std::{env, str::FromStr};
+
+/// The unit bitcoin amounts are shown and entered in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Denomination {
+    #[default]
+    Btc,
+    Sats,
+}
+
+impl Denomination {
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Self::Btc => "BTC",
+            Self::Sats => "sat",
+        }
+    }
+
+    pub fn format_sats(&self, sats: u64) -> String {
+        match self {
+            Self::Btc => format!("{}", sats as f64 / 100_000_000.0),
+            Self::Sats => format!("{}", sats),
+        }
+    }
+
+    /// Lightning amounts can have fractions of a satoshi
+    pub fn format_msat(&self, msat: u64) -> String {
+        match self {
+            Self::Btc => format!("{}", msat as f64 / 100_000_000_000.0),
+            Self::Sats if msat % 1_000 == 0 => format!("{}", msat / 1_000),
+            Self::Sats => format!("{:.3}", msat as f64 / 1_000.0),
+        }
+    }
+
+    /// Convert an amount entered in this denomination to a BTC string
+    pub fn to_btc(&self, amount: &str) -> Result<String, String> {
+        match self {
+            Self::Btc => Ok(amount.to_string()),
+            Self::Sats if amount.is_empty() => Ok("".to_string()),
+            Self::Sats => u64::from_str(amount.trim())
+                .map(|sats| format!("{:.8}", sats as f64 / 100_000_000.0))
+                .map_err(|e| format!("Failed to parse the satoshis from {:?} : {}", amount, e)),
+        }
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "btc" => Ok(Self::Btc),
+            "sat" | "sats" => Ok(Self::Sats),
+            _ => Err(format!("Unknown denomination {}", s)),
+        }
+    }
+}
+
+/// Thousands and decimal separators of a locale
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NumberFormat {
+    pub group: &'static str,
+    pub decimal: &'static str,
+}
+
+impl NumberFormat {
+    /// The separators for a locale name such as de_CH.UTF-8
+    pub fn from_locale(locale: &str) -> Self {
+        let name = locale.split('.').next().unwrap_or_default();
+        let lang = name.split('_').next().unwrap_or_default();
+        let (group, decimal) = match (lang, name) {
+            (_, "de_CH") | (_, "de_LI") | (_, "it_CH") => ("'", "."),
+            ("de", _)
+            | ("nl", _)
+            | ("it", _)
+            | ("es", _)
+            | ("pt", _)
+            | ("da", _)
+            | ("id", _)
+            | ("tr", _) => (".", ","),
+            ("fr", _)
+            | ("ru", _)
+            | ("pl", _)
+            | ("cs", _)
+            | ("sv", _)
+            | ("fi", _)
+            | ("nb", _)
+            | ("uk", _) => ("\u{a0}", ","),
+            ("C", _) | ("POSIX", _) | ("", _) => ("", "."),
+            _ => (",", "."),
+        };
+        Self { group, decimal }
+    }
+
+    /// The separators of the locale the app runs in
+    pub fn system() -> Self {
+        let locale = ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|v| env::var(v).ok())
+            .find(|v| !v.is_empty())
+            .unwrap_or_default();
+        Self::from_locale(&locale)
+    }
+
+    /// Apply the separators to a number formatted with a decimal point and no grouping
+    pub fn localize(&self, plain: &str) -> String {
+        let (sign, digits) = match plain.strip_prefix('-') {
+            Some(d) => ("-", d),
+            None => ("", plain),
+        };
+        let (int, frac) = match digits.split_once('.') {
+            Some((i, f)) => (i, Some(f)),
+            None => (digits, None),
+        };
+        let mut grouped = String::new();
+        for (i, c) in int.chars().enumerate() {
+            if i > 0 && (int.len() - i) % 3 == 0 {
+                grouped.push_str(self.group);
+            }
+            grouped.push(c);
+        }
+        match frac {
+            Some(f) => format!("{}{}{}{}", sign, grouped, self.decimal, f),
+            None => format!("{}{}", sign, grouped),
+        }
+    }
+
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        self.localize(&format!("{:.*}", decimals, value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        assert_eq!(Denomination::Btc.format_sats(12_345), "0.00012345");
+        assert_eq!(Denomination::Sats.format_sats(12_345), "12345");
+        assert_eq!(Denomination::Btc.format_msat(100_000_000), "0.001");
+        assert_eq!(Denomination::Sats.format_msat(100_000_000), "100000");
+        assert_eq!(Denomination::Sats.format_msat(1_500), "1.500");
+    }
+
+    #[test]
+    fn test_localize() {
+        let ch = NumberFormat::from_locale("de_CH.UTF-8");
+        assert_eq!(ch.localize("1234567.891"), "1'234'567.891");
+        let de = NumberFormat::from_locale("de_DE.UTF-8");
+        assert_eq!(de.number(-1234.5, 2), "-1.234,50");
+        let en = NumberFormat::from_locale("en_US.UTF-8");
+        assert_eq!(en.localize("123"), "123");
+        assert_eq!(en.localize("0.00012345"), "0.00012345");
+        assert_eq!(en.localize("123456"), "123,456");
+        let fr = NumberFormat::from_locale("fr_FR");
+        assert_eq!(fr.number(1000.0, 0), "1\u{a0}000");
+        assert_eq!(NumberFormat::from_locale("C").localize("1000.5"), "1000.5");
+    }
+
+    #[test]
+    fn test_to_btc() {
+        assert_eq!(Denomination::Btc.to_btc("0.1"), Ok("0.1".to_string()));
+        assert_eq!(
+            Denomination::Sats.to_btc("2100"),
+            Ok("0.00002100".to_string())
+        );
+        assert_eq!(Denomination::Sats.to_btc(""), Ok("".to_string()));
+        assert!(Denomination::Sats.to_btc("0.5").is_err());
+    }
+}
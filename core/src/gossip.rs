@@ -0,0 +1,256 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Where the node learns the channel graph from. Rapid gossip sync snapshots are quick on a
+//! phone, but come from a single server. When that server has nothing new for too long, the
+//! node falls back to the gossip of its peers, and tries the snapshots again later.
+
+use crate::constants::{
+    RAPID_GOSSIP_SYNC_URL, RGS_FALLBACK_SECS, RGS_RETRY_SECS, TESTNET_RAPID_GOSSIP_SYNC_URL,
+};
+use crate::i18n::{tr, trf};
+use crate::profiles;
+use crate::proxy;
+use crate::settings::Settings;
+use crate::storage::{load_json, save_json};
+
+use chrono::Utc;
+use ldk_node::bitcoin::Network;
+use ldk_node::Builder;
+use serde::{Deserialize, Serialize};
+
+const GOSSIP_STATE_FILE: &str = "gossip.json";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct GossipState {
+    /// unix timestamp of the newest snapshot the node applied
+    last_snapshot: i64,
+    /// when the node started with the snapshot server, so a new server gets its chance
+    rgs_since: i64,
+    /// when the node fell back to the gossip of its peers, 0 while it uses the snapshots
+    p2p_since: i64,
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Keep,
+    /// the snapshots are too old, switch to the gossip of the peers
+    Fallback,
+    /// time to check whether the snapshot server is back
+    Retry,
+}
+
+/// Check the url for plausibility, an empty url stands for the default server
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    if url.is_empty() {
+        return Ok(String::new());
+    }
+    if !url.starts_with("https://") {
+        return Err(trf(
+            "The rapid gossip sync url must start with https:// : {}",
+            &[&url],
+        ));
+    }
+    if url.len() <= "https://".len() || url.contains(char::is_whitespace) {
+        return Err(trf("Invalid rapid gossip sync url: {}", &[&url]));
+    }
+    Ok(url.to_string())
+}
+
+/// The snapshot server of the settings, or the default one of the network
+pub fn rgs_url(configured: &str, network: Network) -> Option<String> {
+    if !configured.is_empty() {
+        return Some(configured.to_string());
+    }
+    // there are no public snapshots for signet and regtest
+    match network {
+        Network::Bitcoin => Some(RAPID_GOSSIP_SYNC_URL.to_string()),
+        Network::Testnet => Some(TESTNET_RAPID_GOSSIP_SYNC_URL.to_string()),
+        _ => None,
+    }
+}
+
+fn configured_url() -> Option<String> {
+    let settings = Settings::load().unwrap_or_else(|e| {
+        log_error!("{}", e);
+        Settings::default()
+    });
+    rgs_url(&settings.rgs_url, profiles::network())
+}
+
+/// Set the gossip source for the node that is about to be built
+pub fn configure(builder: &mut Builder) {
+    let mut state: GossipState = load_json(GOSSIP_STATE_FILE).unwrap_or_else(|e| {
+        log_error!("{}", e);
+        GossipState::default()
+    });
+    match configured_url() {
+        Some(url) if state.p2p_since == 0 => {
+            log_info!("rapid gossip sync from {}", url);
+            builder.set_gossip_source_rgs(url);
+            // the node was off in between, so the age of the last snapshot says nothing yet
+            state.rgs_since = Utc::now().timestamp();
+            if let Err(e) = save_json(GOSSIP_STATE_FILE, &state) {
+                log_error!("{}", e);
+            }
+        }
+        _ => {
+            log_info!("gossip from the peers");
+            builder.set_gossip_source_p2p();
+        }
+    };
+}
+
+/// Give the snapshot server a fresh start, e.g. after the url changed
+pub fn reset() -> Result<(), String> {
+    save_json(GOSSIP_STATE_FILE, &GossipState::default())
+}
+
+fn decide(state: &mut GossipState, snapshot: Option<u32>, now: i64) -> Action {
+    if state.p2p_since > 0 {
+        return if now - state.p2p_since >= RGS_RETRY_SECS as i64 {
+            Action::Retry
+        } else {
+            Action::Keep
+        };
+    }
+    if state.rgs_since == 0 {
+        state.rgs_since = now;
+    }
+    if let Some(snapshot) = snapshot {
+        state.last_snapshot = state.last_snapshot.max(snapshot as i64);
+    }
+    if now - state.last_snapshot.max(state.rgs_since) > RGS_FALLBACK_SECS as i64 {
+        state.p2p_since = now;
+        Action::Fallback
+    } else {
+        Action::Keep
+    }
+}
+
+/// Follow the snapshots the node applied, and return whether the node has to be restarted
+/// with the other gossip source
+pub fn check(snapshot: Option<u32>) -> Result<bool, String> {
+    let url = match configured_url() {
+        Some(url) => url,
+        None => return Ok(false),
+    };
+    let mut state: GossipState = load_json(GOSSIP_STATE_FILE)?;
+    let restart = match decide(&mut state, snapshot, Utc::now().timestamp()) {
+        Action::Keep => false,
+        Action::Fallback => {
+            log_error!(
+                "no new gossip snapshot from {} since {}, falling back to the gossip of the peers",
+                url,
+                state.last_snapshot
+            );
+            true
+        }
+        Action::Retry => match probe(&url) {
+            Ok(()) => {
+                log_info!("the gossip snapshots from {} are available again", url);
+                state = GossipState::default();
+                true
+            }
+            Err(e) => {
+                log_error!("{}", e);
+                state.p2p_since = Utc::now().timestamp();
+                false
+            }
+        },
+    };
+    save_json(GOSSIP_STATE_FILE, &state)?;
+    Ok(restart)
+}
+
+/// Fetch the changes of the last day, to see whether the server serves snapshots
+pub fn probe(url: &str) -> Result<(), String> {
+    proxy::check_online()?;
+    let since = Utc::now().timestamp() - 24 * 60 * 60;
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+    rt.block_on(async {
+        let resp = proxy::http_client()?
+            .get(format!("{}/{}", url, since))
+            .send()
+            .await
+            .map_err(|e| trf("The gossip snapshot server can't be reached: {}", &[&e]))?;
+        if !resp.status().is_success() {
+            return Err(trf(
+                "The gossip snapshot server answered with status {}",
+                &[&resp.status()],
+            ));
+        }
+        match resp.bytes().await {
+            Ok(body) if !body.is_empty() => Ok(()),
+            _ => Err(tr("The gossip snapshot server sent no snapshot")),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(normalize_url(" "), Ok(String::new()));
+        assert_eq!(
+            normalize_url("https://rgs.example.com/snapshot/"),
+            Ok("https://rgs.example.com/snapshot".to_string())
+        );
+        assert!(normalize_url("rgs.example.com").is_err());
+        assert!(normalize_url("http://rgs.example.com/snapshot").is_err());
+        assert!(normalize_url("https://").is_err());
+    }
+
+    #[test]
+    fn test_rgs_url() {
+        assert_eq!(
+            rgs_url("", Network::Bitcoin),
+            Some(RAPID_GOSSIP_SYNC_URL.to_string())
+        );
+        assert_eq!(rgs_url("", Network::Signet), None);
+        assert_eq!(
+            rgs_url("http://127.0.0.1:8011/snapshot", Network::Regtest),
+            Some("http://127.0.0.1:8011/snapshot".to_string())
+        );
+    }
+
+    #[test]
+    fn test_fallback_and_retry() {
+        let day = 24 * 60 * 60;
+        let mut state = GossipState::default();
+        assert_eq!(decide(&mut state, None, 1_000), Action::Keep);
+        assert_eq!(state.rgs_since, 1_000);
+        assert_eq!(decide(&mut state, Some(1_500), 2_000), Action::Keep);
+        assert_eq!(state.last_snapshot, 1_500);
+        // the snapshots stopped coming
+        let late = 1_500 + RGS_FALLBACK_SECS as i64 + 1;
+        assert_eq!(decide(&mut state, Some(1_500), late), Action::Fallback);
+        assert_eq!(state.p2p_since, late);
+        assert_eq!(decide(&mut state, None, late + 10), Action::Keep);
+        assert_eq!(
+            decide(&mut state, None, late + RGS_RETRY_SECS as i64),
+            Action::Retry
+        );
+        // a new server starts with a clean slate
+        let mut state = GossipState::default();
+        assert_eq!(decide(&mut state, None, 10 * day), Action::Keep);
+    }
+}
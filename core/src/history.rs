@@ -0,0 +1,369 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::retention;
+use crate::storage::{load_json, save_json, to_hex};
+use crate::wallet::BdkWallet;
+
+use chrono::Utc;
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentId, PaymentKind, PaymentStatus};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+};
+
+enum HistoryUpdate {
+    Payment(PaymentId),
+    Record(HistoryEntry),
+}
+
+/// Changes since the model last looked, filled from the ldk event handler
+static HISTORY_UPDATES: Mutex<Vec<HistoryUpdate>> = Mutex::new(Vec::new());
+
+/// Only the GUI has a history model, without it nobody would take the updates
+static SUBSCRIBED: AtomicBool = AtomicBool::new(false);
+
+/// The latest known exchange rate and its currency
+static EXCHANGE_RATE: Mutex<Option<(f64, String)>> = Mutex::new(None);
+
+const RECORDS_FILE: &str = "history.json";
+const META_FILE: &str = "payment_meta.json";
+const ARCHIVED_FILE: &str = "archived.json";
+/// Payments folded into the monthly totals. They stay in the payment store of the node,
+/// which guards against paying an invoice twice.
+const PRUNED_FILE: &str = "pruned.json";
+
+/// Queue a payment to be inserted or updated in the history model
+pub fn notify_payment(id: PaymentId) {
+    queue(HistoryUpdate::Payment(id));
+}
+
+fn queue(update: HistoryUpdate) {
+    if !SUBSCRIBED.load(Ordering::SeqCst) {
+        return;
+    }
+    if let Ok(mut updates) = HISTORY_UPDATES.lock() {
+        updates.push(update);
+    }
+}
+
+/// The entries that changed since the last call, for the history model
+pub fn take_updates() -> Result<Vec<HistoryEntry>, String> {
+    let updates = HISTORY_UPDATES
+        .lock()
+        .map(|mut updates| std::mem::take(&mut *updates))
+        .map_err(|e| format!("Unable to get the mutex for the history updates: {:?}", e))?;
+    let archived: HashSet<String> = load_json(ARCHIVED_FILE).unwrap_or_default();
+    let pruned: HashSet<String> = load_json(PRUNED_FILE).unwrap_or_default();
+    let mut entries = Vec::new();
+    for update in updates {
+        match update {
+            HistoryUpdate::Payment(id) => match BdkWallet::payment(&id) {
+                Ok(Some(payment)) if !pruned.contains(&to_hex(&payment.id.0)) => {
+                    let metas: HashMap<String, PaymentMeta> =
+                        load_json(META_FILE).unwrap_or_default();
+                    let entry = HistoryEntry::from(&payment);
+                    let meta = metas.get(&entry.id);
+                    let mut entry = entry.with_meta(meta).with_alias(&mut HashMap::new());
+                    entry.archived = archived.contains(&entry.id);
+                    entries.push(entry);
+                }
+                Ok(_) => {}
+                Err(e) => log_error!("{}", e),
+            },
+            HistoryUpdate::Record(entry) => entries.push(entry.with_alias(&mut HashMap::new())),
+        }
+    }
+    Ok(entries)
+}
+
+/// Start queueing the changes for the history model, after a full load that already
+/// contains everything queued so far
+pub fn subscribe_updates() {
+    SUBSCRIBED.store(true, Ordering::SeqCst);
+    if let Ok(mut updates) = HISTORY_UPDATES.lock() {
+        updates.clear();
+    }
+}
+
+/// Persist an entry that is not part of the ldk payment store, such as a channel operation,
+/// and queue it for the history model
+pub fn add_record(entry: HistoryEntry) -> Result<(), String> {
+    let mut records = load_records()?;
+    records.push(entry.clone());
+    save_json(RECORDS_FILE, &records)?;
+    queue(HistoryUpdate::Record(entry));
+    Ok(())
+}
+
+/// Read the records that were persisted by add_record
+pub fn load_records() -> Result<Vec<HistoryEntry>, String> {
+    load_json(RECORDS_FILE)
+}
+
+/// Remember when a payment happened, what it cost in fees, what it was worth in fiat
+/// and why it failed if it did
+pub fn record_payment_meta(
+    id: &PaymentId,
+    fee_msat: Option<u64>,
+    failure_reason: Option<&str>,
+) -> Result<(), String> {
+    let mut metas: HashMap<String, PaymentMeta> = load_json(META_FILE)?;
+    let meta = metas.entry(to_hex(&id.0)).or_default();
+    if meta.timestamp == 0 {
+        meta.timestamp = Utc::now().timestamp();
+        if let Some((rate, currency)) = EXCHANGE_RATE.lock().ok().and_then(|r| r.clone()) {
+            meta.fiat_rate = Some(rate);
+            meta.currency = currency;
+        }
+    }
+    if fee_msat.is_some() {
+        meta.fee_msat = fee_msat;
+    }
+    if let Some(reason) = failure_reason {
+        meta.failure_reason = reason.to_string();
+    }
+    save_json(META_FILE, &metas)?;
+    notify_payment(*id);
+    Ok(())
+}
+
+/// Remember who we paid, as the ldk payment store doesn't keep the payee of an invoice
+pub fn record_payment_peer(id: &PaymentId, peer: &str) -> Result<(), String> {
+    let mut metas: HashMap<String, PaymentMeta> = load_json(META_FILE)?;
+    metas.entry(to_hex(&id.0)).or_default().peer = peer.to_string();
+    save_json(META_FILE, &metas)
+}
+
+/// Hide or show an entry in the default view of the history
+pub fn set_archived(id: &str, archived: bool) -> Result<(), String> {
+    let mut ids: HashSet<String> = load_json(ARCHIVED_FILE)?;
+    if archived {
+        ids.insert(id.to_string());
+    } else {
+        ids.remove(id);
+    }
+    save_json(ARCHIVED_FILE, &ids)
+}
+
+/// The latest exchange rate and its currency, if one was fetched already
+pub fn exchange_rate() -> Option<(f64, String)> {
+    EXCHANGE_RATE.lock().ok().and_then(|r| r.clone())
+}
+
+/// Keep track of the latest exchange rate, so that it can be stored along with new payments
+pub fn set_exchange_rate(rate: f64, currency: &str) {
+    if let Ok(mut r) = EXCHANGE_RATE.lock() {
+        *r = Some((rate, currency.to_string()));
+    }
+}
+
+/// Drop the details of payments that are no longer in the payment store of the node,
+/// and return how many were dropped
+pub fn revalidate() -> Result<usize, String> {
+    let ids = BdkWallet::list_payments()?
+        .iter()
+        .map(|p| to_hex(&p.id.0))
+        .collect::<HashSet<_>>();
+    let mut metas: HashMap<String, PaymentMeta> = load_json(META_FILE)?;
+    let before = metas.len();
+    metas.retain(|id, _| ids.contains(id));
+    let dropped = before - metas.len();
+    if dropped > 0 {
+        save_json(META_FILE, &metas)?;
+    }
+    Ok(dropped)
+}
+
+/// Remove the completed payments older than the retention period from the history and the
+/// side files, optionally keeping monthly totals. Returns how many entries were removed.
+pub fn prune(retention_days: u32, keep_totals: bool) -> Result<usize, String> {
+    let cutoff = Utc::now().timestamp() - retention_days as i64 * 24 * 3600;
+    let compaction = retention::compact(&all_entries()?, cutoff, keep_totals);
+    if compaction.removed.is_empty() {
+        return Ok(0);
+    }
+    let removed = compaction.removed.iter().cloned().collect::<HashSet<_>>();
+
+    let mut pruned: HashSet<String> = load_json(PRUNED_FILE)?;
+    pruned.extend(removed.iter().cloned());
+    save_json(PRUNED_FILE, &pruned)?;
+    let mut metas: HashMap<String, PaymentMeta> = load_json(META_FILE)?;
+    metas.retain(|id, _| !removed.contains(id));
+    save_json(META_FILE, &metas)?;
+    let mut records = load_records()?;
+    records.retain(|r| !removed.contains(&r.id));
+    records.extend(compaction.aggregates);
+    save_json(RECORDS_FILE, &records)?;
+    let mut archived: HashSet<String> = load_json(ARCHIVED_FILE)?;
+    archived.retain(|id| !removed.contains(id));
+    save_json(ARCHIVED_FILE, &archived)?;
+
+    Ok(removed.len())
+}
+
+/// All payments and records, newest first
+pub fn all_entries() -> Result<Vec<HistoryEntry>, String> {
+    let metas: HashMap<String, PaymentMeta> = load_json(META_FILE)?;
+    let archived: HashSet<String> = load_json(ARCHIVED_FILE)?;
+    let pruned: HashSet<String> = load_json(PRUNED_FILE)?;
+    let mut aliases = HashMap::new();
+    let mut entries = BdkWallet::list_payments()?
+        .iter()
+        .filter(|p| !pruned.contains(&to_hex(&p.id.0)))
+        .map(|p| HistoryEntry::from(p).with_meta(metas.get(&to_hex(&p.id.0))))
+        .chain(load_records()?)
+        .map(|e| e.with_alias(&mut aliases))
+        .map(|mut e| {
+            e.archived = archived.contains(&e.id);
+            e
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+    Ok(entries)
+}
+
+/// Details about a payment that the ldk payment store doesn't keep
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PaymentMeta {
+    /// unix timestamp of when the payment was first seen
+    pub timestamp: i64,
+    pub fee_msat: Option<u64>,
+    /// the price of one BTC at the time of the payment
+    pub fiat_rate: Option<f64>,
+    pub currency: String,
+    #[serde(default)]
+    pub failure_reason: String,
+    /// the node id of the recipient of an outgoing lightning payment
+    #[serde(default)]
+    pub peer: String,
+}
+
+/// One row of the transaction history
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    /// onchain, bolt11, bolt12, spontaneous, channel_open, channel_ready, channel_close,
+    /// force_close or aggregate
+    pub kind: String,
+    pub direction: String,
+    pub status: String,
+    pub amount_msat: Option<u64>,
+    /// the counterparty node id for channel operations
+    #[serde(default)]
+    pub peer: String,
+    #[serde(default)]
+    pub channel_id: String,
+    /// unix timestamp, 0 if unknown
+    #[serde(default)]
+    pub timestamp: i64,
+    #[serde(default)]
+    pub fee_msat: Option<u64>,
+    #[serde(default)]
+    pub fiat_rate: Option<f64>,
+    #[serde(default)]
+    pub currency: String,
+    /// why the payment failed, empty if it didn't
+    #[serde(default)]
+    pub failure_reason: String,
+    /// the alias of the peer as announced in the network graph
+    #[serde(skip)]
+    pub alias: String,
+    /// the color of the peer as announced in the network graph
+    #[serde(skip)]
+    pub color: String,
+    /// archived entries are hidden by default
+    #[serde(skip)]
+    pub archived: bool,
+    /// why a channel was closed
+    #[serde(default)]
+    pub reason: String,
+    /// who initiated a channel operation: us or peer
+    #[serde(default)]
+    pub initiator: String,
+    /// how many pruned payments an aggregate stands for
+    #[serde(default)]
+    pub merged: u32,
+}
+
+impl HistoryEntry {
+    /// Channel operations as opposed to payments
+    pub fn is_channel_event(&self) -> bool {
+        self.kind.starts_with("channel_") || self.kind == "force_close"
+    }
+
+    fn with_meta(mut self, meta: Option<&PaymentMeta>) -> Self {
+        if let Some(meta) = meta {
+            self.timestamp = meta.timestamp;
+            self.fee_msat = meta.fee_msat;
+            self.fiat_rate = meta.fiat_rate;
+            self.currency = meta.currency.clone();
+            self.failure_reason = meta.failure_reason.clone();
+            if !meta.peer.is_empty() {
+                self.peer = meta.peer.clone();
+            }
+        }
+        self
+    }
+
+    /// Look up the alias and color of the peer in the gossip data
+    fn with_alias(mut self, cache: &mut HashMap<String, Option<(String, String)>>) -> Self {
+        if self.peer.is_empty() {
+            return self;
+        }
+        let resolved = cache.entry(self.peer.clone()).or_insert_with(|| {
+            BdkWallet::node_alias(&self.peer).unwrap_or_else(|e| {
+                log_error!("{}", e);
+                None
+            })
+        });
+        if let Some((alias, color)) = resolved {
+            self.alias = alias.clone();
+            self.color = color.clone();
+        }
+        self
+    }
+}
+
+impl From<&PaymentDetails> for HistoryEntry {
+    fn from(payment: &PaymentDetails) -> Self {
+        let kind = match payment.kind {
+            PaymentKind::Onchain => "onchain",
+            PaymentKind::Bolt11 { .. } | PaymentKind::Bolt11Jit { .. } => "bolt11",
+            PaymentKind::Bolt12Offer { .. } | PaymentKind::Bolt12Refund { .. } => "bolt12",
+            PaymentKind::Spontaneous { .. } => "spontaneous",
+        };
+        let direction = match payment.direction {
+            PaymentDirection::Inbound => "in",
+            PaymentDirection::Outbound => "out",
+        };
+        let status = match payment.status {
+            PaymentStatus::Pending => "pending",
+            PaymentStatus::Succeeded => "succeeded",
+            PaymentStatus::Failed => "failed",
+        };
+        Self {
+            id: to_hex(&payment.id.0),
+            kind: kind.to_string(),
+            direction: direction.to_string(),
+            status: status.to_string(),
+            amount_msat: payment.amount_msat,
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,87 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Translation of the messages the user gets to see, from the same gettext catalogs as the
+//! QML. The build extracts the strings passed to tr() and trf() from the Rust sources.
+//! Without a catalog for the locale, the messages stay in English.
+
+use crate::constants::APP_NAME;
+
+use gettextrs::{bindtextdomain, gettext, textdomain};
+use std::{fmt::Display, path::Path};
+
+/// Look up the messages in the catalogs below the locale directory
+pub fn init(locale_dir: &Path) -> Result<(), String> {
+    textdomain(APP_NAME).map_err(|e| format!("Failed to set the gettext domain: {}", e))?;
+    bindtextdomain(APP_NAME, locale_dir)
+        .map_err(|e| format!("Failed to bind the gettext domain: {}", e))?;
+    Ok(())
+}
+
+/// Translate a message
+pub fn tr(msgid: &str) -> String {
+    gettext(msgid)
+}
+
+/// Translate a message, then replace its {} placeholders by the arguments in order.
+/// The translated message has to keep the same number of placeholders.
+pub fn trf(msgid: &str, args: &[&dyn Display]) -> String {
+    fill(&gettext(msgid), args)
+}
+
+/// Mark a message for the catalog that is stored in English and translated when it is shown
+pub const fn noop(msgid: &'static str) -> &'static str {
+    msgid
+}
+
+fn fill(template: &str, args: &[&dyn Display]) -> String {
+    let mut parts = template.split("{}");
+    let mut out = parts.next().unwrap_or_default().to_string();
+    let mut args = args.iter();
+    for part in parts {
+        match args.next() {
+            Some(arg) => out.push_str(&arg.to_string()),
+            None => out.push_str("{}"),
+        }
+        out.push_str(part);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(fill("received {} sats", &[&2100]), "received 2100 sats");
+        assert_eq!(
+            fill("payment {} is above {}", &[&"5", &4]),
+            "payment 5 is above 4"
+        );
+        assert_eq!(fill("no placeholder", &[&1]), "no placeholder");
+        assert_eq!(fill("{} and {}", &[&1]), "1 and {}");
+    }
+
+    #[test]
+    fn test_untranslated() {
+        assert_eq!(tr("The invoice expired"), "The invoice expired");
+        assert_eq!(
+            trf("channel with {} ready", &[&"ACINQ"]),
+            "channel with ACINQ ready"
+        );
+    }
+}
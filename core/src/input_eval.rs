@@ -0,0 +1,1415 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::batch;
+use crate::constants::EVALUATION_DEBOUNCE_MS;
+use crate::error::WalletError;
+use crate::format::Denomination;
+use crate::i18n::{tr, trf};
+use crate::profiles;
+use crate::proxy;
+use crate::psbt::{self, PSBT_PREFIX};
+use crate::silentpayment::SilentPaymentCode;
+use crate::wallet::BdkWallet;
+
+use ldk_node::bitcoin::{
+    bip32::ExtendedPrivKey, blockdata::constants::ChainHash, psbt::PartiallySignedTransaction,
+    secp256k1::PublicKey, Address, PrivateKey,
+};
+use ldk_node::lightning::ln::msgs::SocketAddress;
+use ldk_node::lightning::offers::offer::{Amount, Offer};
+use ldk_node::lightning::offers::refund::Refund;
+use ldk_node::lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescription};
+use libelectrum2descriptors::ElectrumExtendedPrivKey;
+use lnurl::{api::LnUrlResponse, lightning_address::LightningAddress, lnurl::LnUrl};
+use miniscript::Descriptor;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, NON_ALPHANUMERIC};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// On-chain addresses of mainnet, testnet, signet and regtest: bech32(m) for segwit v0 and
+/// taproot, base58 for the legacy ones
+pub const BTC_ADDRESS_PATTERN: &str =
+    r"(?:(?:bc1|tb1|bcrt1)[ac-hj-np-zAC-HJ-NP-Z02-9]{8,87}|[123mn][a-km-zA-HJ-NP-Z1-9]{25,34})";
+
+/// the human readable parts of the bech32 strings that QR codes may carry in uppercase
+const BECH32_PREFIXES: &[&str] = &[
+    "BC1", "TB1", "BCRT1", "SP1", "TSP1", "LNBC", "LNTB", "LNO1", "LNR1",
+];
+
+/// QR codes in alphanumeric mode carry the bech32 strings in uppercase. Bring the schemes,
+/// addresses and invoices into the lowercase form the patterns expect. Base58 addresses and
+/// LNURLs are left as they are.
+pub fn normalize_case(input: &str) -> String {
+    let (scheme, rest) = match input.split_once(':') {
+        Some((scheme, rest))
+            if scheme.eq_ignore_ascii_case("bitcoin")
+                || scheme.eq_ignore_ascii_case("lightning") =>
+        {
+            (format!("{}:", scheme.to_lowercase()), rest)
+        }
+        _ => (String::new(), input),
+    };
+    let (body, query) = match rest.split_once('?') {
+        Some((body, query)) => (body, Some(query)),
+        None => (rest, None),
+    };
+    let mut normalized = scheme + &lowercase_bech32(body);
+    if let Some(query) = query {
+        let params = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) if key.eq_ignore_ascii_case("lightning") => {
+                    format!("lightning={}", lowercase_bech32(value))
+                }
+                _ => param.to_string(),
+            })
+            .collect::<Vec<_>>();
+        normalized = format!("{}?{}", normalized, params.join("&"));
+    }
+    normalized
+}
+
+/// bech32 strings are either all lowercase or all uppercase
+fn lowercase_bech32(s: &str) -> String {
+    if !s.chars().any(|c| c.is_ascii_lowercase())
+        && BECH32_PREFIXES.iter().any(|p| s.starts_with(p))
+    {
+        s.to_lowercase()
+    } else {
+        s.to_string()
+    }
+}
+
+/// The percent decoded parameters of a BIP21 uri. Unknown parameters are ignored, unless
+/// they are marked as required with the req- prefix.
+fn bip21_params(uri: &str) -> Result<HashMap<String, String>, WalletError> {
+    let mut props = HashMap::new();
+    let query = match uri.split_once('?') {
+        Some((_, query)) => query,
+        None => return Ok(props),
+    };
+    for param in query.split('&') {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let key = key.to_lowercase();
+        if key.starts_with("req-") {
+            return Err(WalletError::InvalidInput(trf(
+                "The payment request requires {}, which is not supported",
+                &[&key],
+            )));
+        }
+        // BIP21 follows RFC 3986, so a + stays a +
+        let value = percent_decode_str(value).decode_utf8_lossy();
+        if !value.is_empty() {
+            props.insert(key, value.to_string());
+        }
+    }
+    Ok(props)
+}
+
+/// the id of the newest evaluation, the older ones are dropped
+static LATEST_REQUEST: AtomicU64 = AtomicU64::new(0);
+
+pub struct InputEval {
+    pub network: InputNetwork,
+    pub satoshis: Option<u64>,
+    pub description: String,
+    /// the longest comment the LNURL-pay service accepts, 0 if it takes none
+    pub comment_max: u32,
+    /// the range in msat an LNURL-pay service accepts when the payer picks the amount
+    pub sendable_msat: Option<(u64, u64)>,
+}
+
+pub enum PrivateKeys {
+    Pk(PrivateKey),
+    Epk(ExtendedPrivKey),
+    Desc(Descriptor<String>),
+}
+
+impl PrivateKeys {
+    pub fn to_string(&self) -> String {
+        match self {
+            Self::Pk(pk) => pk.to_wif(),
+            Self::Epk(epk) => epk.to_string(),
+            Self::Desc(desc) => desc.to_string(),
+        }
+    }
+}
+
+pub enum InputNetwork {
+    Mainnet(Address),
+    Lightning(Bolt11Invoice),
+    LightningOffer(Offer),
+    PrivKey(PrivateKeys),
+    LnWithdraw(String),
+    /// an LNURL-channel service offering to open a channel to us
+    LnChannel(String),
+    /// an LNURL-pay service waiting for the payer to pick an amount
+    LnPay(String),
+    /// a BIP352 silent payment code
+    SilentPayment(SilentPaymentCode),
+    /// an address whose receiver offers a BIP78 payjoin at the endpoint
+    Payjoin(Address, String),
+    /// a BOLT12 refund, which pays us once we answer with an invoice
+    LightningRefund(Refund),
+    /// a partially signed transaction for us to sign and broadcast
+    Psbt(Box<PartiallySignedTransaction>),
+    /// several addresses to pay with one transaction
+    Batch(Vec<(Address, u64)>),
+}
+
+impl InputEval {
+    pub fn evaluate(
+        recipient: &str,
+        bitcoins: &str,
+        description: &str,
+    ) -> Result<Self, WalletError> {
+        Self::evaluate_with(None, recipient, bitcoins, description)
+    }
+
+    /// Evaluate the input for a request from new_request() once the input settled.
+    /// Gives up with WalletError::Cancelled as soon as a newer request was made.
+    pub fn evaluate_request(
+        request: u64,
+        requested_at: Instant,
+        recipient: &str,
+        bitcoins: &str,
+        description: &str,
+    ) -> Result<Self, WalletError> {
+        check_request(Some(request))?;
+        let settle = Duration::from_millis(EVALUATION_DEBOUNCE_MS);
+        if let Some(remaining) = settle.checked_sub(requested_at.elapsed()) {
+            thread::sleep(remaining);
+        }
+        Self::evaluate_with(Some(request), recipient, bitcoins, description)
+    }
+
+    fn evaluate_with(
+        request: Option<u64>,
+        recipient: &str,
+        bitcoins: &str,
+        description: &str,
+    ) -> Result<Self, WalletError> {
+        check_request(request)?;
+        // base64 is case sensitive
+        if recipient.trim_start().starts_with(PSBT_PREFIX) {
+            return Self::psbt(recipient);
+        }
+        if recipient.trim().contains('\n') {
+            return Self::batch(recipient);
+        }
+        let recipient = &normalize_case(recipient);
+        let descr = description.to_string();
+        let satoshis = if bitcoins.is_empty() {
+            None
+        } else {
+            Some(parse_satoshis(bitcoins)?)
+        };
+
+        let re = Regex::new(&format!("^{}$", BTC_ADDRESS_PATTERN)).map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            return Self::mainnet(recipient, satoshis, descr);
+        }
+
+        // https://developer.bitcoin.org/devguide/payment_processing.html
+        let re = Regex::new(&format!(
+            "^bitcoin:({})([?&][A-Za-z0-9_-]+=[^&]*)*$",
+            BTC_ADDRESS_PATTERN
+        ))
+        .map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            let caps = re.captures(recipient).unwrap();
+            let addr = caps.get(1).unwrap().as_str();
+
+            let props = bip21_params(recipient)?;
+            let satoshis = if let Some(sats) = props.get("amount") {
+                Some(parse_satoshis(sats)?)
+            } else {
+                satoshis
+            };
+            let descr = match (props.get("label"), props.get("message")) {
+                (Some(label), _) => label.clone(),
+                (None, Some(message)) => message.clone(),
+                (None, None) => descr,
+            };
+
+            // unified QR codes carry an invoice along with the address
+            if let Some(invoice) = props.get("lightning") {
+                match Self::lightning(invoice, satoshis, descr.clone()) {
+                    Ok(eval) if Self::can_pay_lightning(&eval) => return Ok(eval),
+                    Ok(_) => log_info!("no usable channel for the invoice, paying on-chain"),
+                    Err(e) => log_error!("ignoring the invoice of the unified QR code: {}", e),
+                }
+            }
+
+            let eval = Self::mainnet(&addr, satoshis, descr)?;
+            return Ok(match (eval.network, props.get("pj")) {
+                (InputNetwork::Mainnet(addr), Some(pj)) => Self {
+                    network: InputNetwork::Payjoin(addr, pj.clone()),
+                    ..eval
+                },
+                (network, _) => Self { network, ..eval },
+            });
+        }
+
+        // https://github.com/bitcoin/bips/blob/master/bip-0352.mediawiki
+        if recipient.starts_with("sp1") || recipient.starts_with("tsp1") {
+            let code = SilentPaymentCode::parse(recipient, profiles::network())
+                .map_err(WalletError::InvalidInput)?;
+            return Ok(Self {
+                network: InputNetwork::SilentPayment(code),
+                satoshis,
+                description: descr,
+                comment_max: 0,
+                sendable_msat: None,
+            });
+        }
+
+        // private key
+        if let Ok(pk) = PrivateKey::from_wif(&recipient) {
+            return Ok(Self {
+                network: InputNetwork::PrivKey(PrivateKeys::Pk(pk)),
+                satoshis: None,
+                description: "sweep private key".to_string(),
+                comment_max: 0,
+                sendable_msat: None,
+            });
+        }
+
+        // extended private key
+        if let Ok(xprv) = ExtendedPrivKey::from_str(&recipient) {
+            return Ok(Self {
+                network: InputNetwork::PrivKey(PrivateKeys::Epk(xprv)),
+                satoshis: None,
+                description: "sweep private keys".to_string(),
+                comment_max: 0,
+                sendable_msat: None,
+            });
+        }
+
+        // electrum extended private key
+        if let Ok(exprv) = ElectrumExtendedPrivKey::from_str(recipient) {
+            return Ok(Self {
+                network: InputNetwork::PrivKey(PrivateKeys::Epk(*exprv.xprv())),
+                satoshis: None,
+                description: "sweep private keys".to_string(),
+                comment_max: 0,
+                sendable_msat: None,
+            });
+        }
+
+        // miniscript descriptor
+        if let Ok(desc) = Descriptor::<String>::from_str(&recipient) {
+            desc.sanity_check().map_err(|e| {
+                WalletError::InvalidInput(trf("Descriptor failed sanity check: {}", &[&e]))
+            })?;
+            return Ok(Self {
+                network: InputNetwork::PrivKey(PrivateKeys::Desc(desc)),
+                satoshis: None,
+                description: "sweep private keys".to_string(),
+                comment_max: 0,
+                sendable_msat: None,
+            });
+        }
+
+        // https://www.bolt11.org/
+        let rgx_bolt11 = r#"^(?i)(LIGHTNING:)?ln(bc|tb|tbs|bcrt)[a-z0-9]{100,700}$"#;
+        let re = Regex::new(&rgx_bolt11).map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            let recipient = recipient
+                .replace("LIGHTNING:", "")
+                .replace("lightning:", "");
+            let invoice = str::parse::<Bolt11Invoice>(&recipient)
+                .map_err(|e| WalletError::InvalidInput(e.to_string()))?;
+            let satoshis = if let Some(msat) = invoice.amount_milli_satoshis() {
+                Some(msat / 1_000)
+            } else {
+                satoshis
+            };
+            return Self::lightning(&recipient, satoshis, descr);
+        }
+
+        // https://bolt12.org/
+        let rgx_bolt12 = r#"^lno1[a-z0-9]{55,150}$"#;
+        let re = Regex::new(&rgx_bolt12).map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            let offer = str::parse::<Offer>(&recipient).map_err(|e| {
+                WalletError::InvalidInput(trf(
+                    "Failed to parse BOLT12 offer: {}",
+                    &[&format!("{:?}", e)],
+                ))
+            })?;
+            let satoshis = match offer.amount() {
+                Some(Amount::Bitcoin { amount_msats }) => Some(amount_msats / 1_000),
+                Some(Amount::Currency { .. }) => {
+                    return Err(WalletError::InvalidInput(tr(
+                        "For BOLT12 we only support BTC at the moment",
+                    )));
+                }
+                None => satoshis,
+            };
+            return Self::lightning_offer(&recipient, satoshis, descr);
+        }
+
+        // the invoice_request of a BOLT12 refund
+        let rgx_refund = r#"^lnr1[a-z0-9]{55,1000}$"#;
+        let re = Regex::new(&rgx_refund).map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            return Self::lightning_refund(recipient);
+        }
+
+        // LNURL https://github.com/lnurl/luds
+        if recipient.starts_with("LNURL")
+            || recipient.starts_with("lightning:LNURL")
+            || recipient.starts_with("LIGHTNING:LNURL")
+        {
+            let recipient = recipient
+                .replace("LIGHTNING:", "")
+                .replace("lightning:", "");
+            let lnu = LnUrl::from_str(&recipient)
+                .map_err(|e| WalletError::InvalidInput(e.to_string()))?;
+            let url = lnu.url.as_str();
+            return Self::ln_url(&url, satoshis, descr, request);
+        }
+
+        // lnurlw
+        if recipient.starts_with("lnurlw://") || recipient.contains("api.swiss-bitcoin-pay.ch/card")
+        {
+            let recipient = recipient.replace("lnurlw://", "https://");
+            return Self::ln_url(&recipient, satoshis, descr, request);
+        }
+
+        // LNURL https://github.com/lnurl/luds
+        if recipient.starts_with("https://") {
+            return Self::ln_url(&recipient, satoshis, descr, request);
+        }
+
+        // https://coincharge.io/lnurl/
+        let rgx_lnaddr = r#"^[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,6}$"#;
+        let re = Regex::new(&rgx_lnaddr).map_err(|e| e.to_string())?;
+        if re.is_match(recipient) {
+            let lnaddr = LightningAddress::from_str(&recipient)
+                .map_err(|e| WalletError::InvalidInput(e.to_string()))?;
+            let url = lnaddr.lnurlp_url().as_str().to_string();
+            return Self::ln_url(&url, satoshis, descr, request);
+        }
+
+        Err(WalletError::InvalidInput(tr("Unknown input format")))
+    }
+
+    /// Whether a usable channel has the outbound capacity for the evaluated invoice
+    fn can_pay_lightning(eval: &Self) -> bool {
+        BdkWallet::can_pay_lightning(eval.satoshis.map(|sats| sats.saturating_mul(1_000)))
+            .unwrap_or_else(|e| {
+                log_debug!("{}", e);
+                false
+            })
+    }
+
+    fn mainnet(
+        addr: &str,
+        satoshis: Option<u64>,
+        description: String,
+    ) -> Result<Self, WalletError> {
+        let addr = Address::from_str(addr).map_err(|e| {
+            WalletError::InvalidInput(trf("Failed to parse address {} : {}", &[&addr, &e]))
+        })?;
+        let network = profiles::network();
+        let addr = addr.require_network(network).map_err(|e| {
+            format!(
+                "The onchain address doesn't look like it is for {}: {}",
+                network, e
+            )
+        })?;
+        Ok(Self {
+            network: InputNetwork::Mainnet(addr),
+            satoshis,
+            description,
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn lightning(
+        invoice: &str,
+        satoshis: Option<u64>,
+        description: String,
+    ) -> Result<Self, WalletError> {
+        let invoice = Bolt11Invoice::from_str(invoice).map_err(|e| {
+            WalletError::InvalidInput(trf(
+                "Failed to construct the invoice {} : {}",
+                &[&invoice, &e],
+            ))
+        })?;
+        let network = profiles::network();
+        if invoice.network() != network {
+            return Err(WalletError::InvalidInput(trf(
+                "The invoice is for {}, but the wallet runs on {}",
+                &[&invoice.network(), &network],
+            )));
+        }
+        let satoshis = if let Some(msats) = invoice.amount_milli_satoshis() {
+            Some(msats / 1_000)
+        } else {
+            satoshis
+        };
+        let description = if let Bolt11InvoiceDescription::Direct(desc) = invoice.description() {
+            desc.clone().into_inner().to_string()
+        } else {
+            description
+        };
+        Ok(Self {
+            network: InputNetwork::Lightning(invoice),
+            satoshis,
+            description,
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn batch(input: &str) -> Result<Self, WalletError> {
+        let recipients =
+            batch::parse(input, profiles::network()).map_err(WalletError::InvalidInput)?;
+        Ok(Self {
+            satoshis: Some(batch::total(&recipients).map_err(WalletError::InvalidAmount)?),
+            description: trf("pay {} recipients", &[&recipients.len()]),
+            network: InputNetwork::Batch(recipients),
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn psbt(input: &str) -> Result<Self, WalletError> {
+        let psbt = psbt::parse(input).map_err(WalletError::InvalidInput)?;
+        let outputs = psbt.unsigned_tx.output.len();
+        Ok(Self {
+            satoshis: Some(psbt.unsigned_tx.output.iter().map(|o| o.value).sum()),
+            description: trf("PSBT with {} outputs", &[&outputs]),
+            network: InputNetwork::Psbt(Box::new(psbt)),
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn lightning_refund(refund: &str) -> Result<Self, WalletError> {
+        let refund = str::parse::<Refund>(refund).map_err(|e| {
+            WalletError::InvalidInput(trf(
+                "Failed to parse BOLT12 refund: {}",
+                &[&format!("{:?}", e)],
+            ))
+        })?;
+        let network = profiles::network();
+        if refund.chain() != ChainHash::using_genesis_block(network) {
+            return Err(WalletError::InvalidInput(trf(
+                "The refund is not for {}",
+                &[&network],
+            )));
+        }
+        if refund.is_expired() {
+            return Err(WalletError::InvalidInput(tr("The refund expired")));
+        }
+        Ok(Self {
+            satoshis: Some(refund.amount_msats() / 1_000),
+            description: refund.description().to_string(),
+            network: InputNetwork::LightningRefund(refund),
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn lightning_offer(
+        offer: &str,
+        satoshis: Option<u64>,
+        description: String,
+    ) -> Result<Self, WalletError> {
+        let offer = str::parse::<Offer>(&offer).map_err(|e| {
+            WalletError::InvalidInput(trf(
+                "Failed to parse BOLT12 offer: {}",
+                &[&format!("{:?}", e)],
+            ))
+        })?;
+        let network = profiles::network();
+        if !offer.supports_chain(ChainHash::using_genesis_block(network)) {
+            return Err(WalletError::InvalidInput(trf(
+                "The offer can't be paid on {}",
+                &[&network],
+            )));
+        }
+
+        let satoshis = match offer.amount() {
+            Some(Amount::Bitcoin { amount_msats }) => Some(amount_msats / 1_000),
+            Some(Amount::Currency { .. }) => {
+                return Err(WalletError::InvalidInput(tr(
+                    "For BOLT12 we only support BTC at the moment",
+                )));
+            }
+            None => satoshis,
+        };
+
+        let description = if let Some(desc) = offer.description() {
+            format!("{}", desc)
+        } else {
+            description
+        };
+
+        Ok(Self {
+            network: InputNetwork::LightningOffer(offer),
+            satoshis,
+            description,
+            comment_max: 0,
+            sendable_msat: None,
+        })
+    }
+
+    fn ln_url(
+        url: &str,
+        satoshis: Option<u64>,
+        description: String,
+        request: Option<u64>,
+    ) -> Result<Self, WalletError> {
+        let client = proxy::lnurl_client().map_err(WalletError::Network)?;
+        check_request(request)?;
+        let resp = client
+            .make_request(url)
+            .map_err(|e| WalletError::Network(trf("Failed to query lnurl: {}", &[&e])))?;
+        match resp {
+            LnUrlResponse::LnUrlPayResponse(pay) => {
+                let msats = if let Some(sats) = satoshis {
+                    let msats = sats_to_msat(sats)?;
+                    if msats < pay.min_sendable || msats > pay.max_sendable {
+                        return Err(WalletError::InvalidAmount(trf(
+                            "payment {} is not between {} and {}",
+                            &[&msats, &pay.min_sendable, &pay.max_sendable],
+                        )));
+                    }
+                    msats
+                } else if pay.min_sendable == pay.max_sendable {
+                    pay.min_sendable
+                } else {
+                    return Ok(Self {
+                        network: InputNetwork::LnPay(url.to_string()),
+                        satoshis: None,
+                        description,
+                        comment_max: pay.comment_allowed.unwrap_or(0),
+                        sendable_msat: Some((pay.min_sendable, pay.max_sendable)),
+                    });
+                };
+                check_request(request)?;
+                let comment = lnurl_comment(&description, pay.comment_allowed);
+                let resp = client
+                    .get_invoice(&pay, msats, None, comment.as_deref())
+                    .map_err(|e| WalletError::Network(e.to_string()))?;
+                let invoice = resp.invoice();
+                let mut eval =
+                    Self::lightning(&invoice.to_string(), Some(msats / 1_000), description)?;
+                eval.comment_max = pay.comment_allowed.unwrap_or(0);
+                Ok(eval)
+            }
+            LnUrlResponse::LnUrlWithdrawResponse(lnurlw) => {
+                let msats = if let Some(sats) = satoshis {
+                    let msats = sats_to_msat(sats)?;
+                    if msats > lnurlw.max_withdrawable {
+                        return Err(WalletError::InvalidAmount(trf(
+                            "payment {} is above {}",
+                            &[&msats, &lnurlw.max_withdrawable],
+                        )));
+                    }
+                    if let Some(minw) = lnurlw.min_withdrawable {
+                        if msats < minw {
+                            return Err(WalletError::InvalidAmount(trf(
+                                "payment {} is below {}",
+                                &[&msats, &minw],
+                            )));
+                        }
+                    }
+                    msats
+                } else {
+                    lnurlw.max_withdrawable
+                };
+
+                Ok(Self {
+                    network: InputNetwork::LnWithdraw(url.to_string()),
+                    satoshis: Some(msats / 1_000),
+                    description: lnurlw.default_description,
+                    comment_max: 0,
+                    sendable_msat: None,
+                })
+            }
+            LnUrlResponse::LnUrlChannelResponse(channel) => Ok(Self {
+                network: InputNetwork::LnChannel(url.to_string()),
+                satoshis: None,
+                description: trf("incoming channel from {}", &[&channel.uri]),
+                comment_max: 0,
+                sendable_msat: None,
+            }),
+        }
+    }
+
+    /// generate a comma separated value string to pass to the QML GUI
+    pub fn gui_csv(&self) -> Result<String, WalletError> {
+        self.gui_csv_in(Denomination::Btc)
+    }
+
+    /// The recipient, amount and description separated by semicolons, for filling the GUI fields
+    pub fn gui_csv_in(&self, denomination: Denomination) -> Result<String, WalletError> {
+        let recipient = match &self.network {
+            InputNetwork::Mainnet(addr) => addr.to_string(),
+            InputNetwork::Lightning(invoice) => invoice.to_string(),
+            InputNetwork::LightningOffer(offer) => offer.to_string(),
+            InputNetwork::LightningRefund(refund) => refund.to_string(),
+            InputNetwork::LnWithdraw(ss) => ss.to_string(),
+            InputNetwork::LnChannel(ss) => ss.to_string(),
+            InputNetwork::LnPay(ss) => ss.to_string(),
+            InputNetwork::SilentPayment(code) => code.to_string(),
+            // the endpoint has to survive the evaluation before sending
+            InputNetwork::Payjoin(addr, pj) => format!(
+                "bitcoin:{}?pj={}",
+                addr,
+                utf8_percent_encode(pj, NON_ALPHANUMERIC)
+            ),
+            InputNetwork::PrivKey(ss) => ss.to_string(),
+            InputNetwork::Psbt(psbt) => psbt.to_string(),
+            InputNetwork::Batch(recipients) => batch::format(recipients),
+        };
+        let sats = match (self.satoshis, denomination) {
+            (Some(s), Denomination::Btc) => format!("{}", s as f32 / 100_000_000.0),
+            (Some(s), d) => d.format_sats(s),
+            (None, _) => "".to_string(),
+        };
+        Ok(format!("{};{};{}", recipient, sats, self.description))
+    }
+}
+
+/// The msat range of an LNURL-pay service in whole sats, rounded inwards
+pub fn sendable_sats((min, max): (u64, u64)) -> (u64, u64) {
+    ((min + 999) / 1_000, max / 1_000)
+}
+
+/// The error for an LNURL-pay service that still waits for an amount
+pub fn pick_amount(sendable_msat: Option<(u64, u64)>) -> WalletError {
+    match sendable_msat.map(sendable_sats) {
+        Some((min, max)) => {
+            WalletError::InvalidAmount(trf("Pick an amount between {} and {} sats", &[&min, &max]))
+        }
+        None => WalletError::InvalidAmount(tr("Amount field needs to be filled!")),
+    }
+}
+
+/// The description as LUD-12 comment, cut to the length the service allows
+fn lnurl_comment(description: &str, allowed: Option<u32>) -> Option<String> {
+    match allowed {
+        Some(allowed) if allowed > 0 && !description.trim().is_empty() => {
+            Some(description.trim().chars().take(allowed as usize).collect())
+        }
+        _ => None,
+    }
+}
+
+/// Start a new evaluation, which supersedes all the ones before
+pub fn new_request() -> u64 {
+    LATEST_REQUEST.fetch_add(1, Ordering::SeqCst) + 1
+}
+
+/// Fail if a newer evaluation was started in the meantime
+fn check_request(request: Option<u64>) -> Result<(), WalletError> {
+    match request {
+        Some(id) if id != LATEST_REQUEST.load(Ordering::SeqCst) => Err(WalletError::Cancelled(
+            trf("Evaluation {} was superseded", &[&id]),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Convert a string with a value in Bitcoin to Satoshis
+pub fn parse_satoshis(amount: &str) -> Result<u64, WalletError> {
+    if amount.is_empty() {
+        return Ok(0);
+    }
+    let amount = f64::from_str(amount).map_err(|e| {
+        WalletError::InvalidInput(trf(
+            "Failed to parse the satoshis from {} : {}",
+            &[&format!("{:?}", amount), &e],
+        ))
+    })?;
+    Ok((amount * 100_000_000.0) as u64)
+}
+
+/// The amount in msat, an amount too large for that comes from a bogus input
+pub fn sats_to_msat(sats: u64) -> Result<u64, WalletError> {
+    sats.checked_mul(1_000)
+        .ok_or_else(|| WalletError::InvalidAmount(trf("The amount {} is too large", &[&sats])))
+}
+
+/// Parses an amount in fiat such as "5chf", "5.50 EUR" or "USD 3". Returns the value and the
+/// upper case currency code, or None if the amount has no currency.
+pub fn parse_fiat(amount: &str) -> Option<(f64, String)> {
+    let re = Regex::new(r"^\s*(?:([a-zA-Z]{3})\s*)?(\d+(?:\.\d*)?)\s*([a-zA-Z]{3})?\s*$").ok()?;
+    let caps = re.captures(amount)?;
+    let currency = match (caps.get(1), caps.get(3)) {
+        (Some(c), None) | (None, Some(c)) => c.as_str().to_uppercase(),
+        _ => return None,
+    };
+    if currency == "BTC" || currency == "SAT" {
+        return None;
+    }
+    let value = f64::from_str(&caps[2]).ok()?;
+    Some((value, currency))
+}
+
+/// Checks if the input looks like a nodeid that could be used to open a channel
+pub fn is_node_id(input: &str) -> bool {
+    let id_addr = input.split("@").collect::<Vec<_>>();
+    if id_addr.len() != 2 {
+        return false;
+    }
+    if PublicKey::from_str(id_addr[0]).is_err() {
+        return false;
+    }
+    if SocketAddress::from_str(id_addr[1]).is_err() {
+        return false;
+    }
+
+    return true;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sats_to_msat() {
+        assert_eq!(sats_to_msat(21), Ok(21_000));
+        assert!(sats_to_msat(u64::MAX / 1_000 + 1).is_err());
+    }
+
+    #[test]
+    fn test_parse_fiat() {
+        assert_eq!(parse_fiat("5chf"), Some((5.0, "CHF".to_string())));
+        assert_eq!(parse_fiat("5.50 EUR"), Some((5.5, "EUR".to_string())));
+        assert_eq!(parse_fiat("USD 3"), Some((3.0, "USD".to_string())));
+        assert_eq!(parse_fiat("0.001"), None);
+        assert_eq!(parse_fiat("0.001 btc"), None);
+        assert_eq!(parse_fiat("chf"), None);
+        assert_eq!(parse_fiat("CHF 5 EUR"), None);
+    }
+
+    #[test]
+    fn test_superseded_request() {
+        let first = new_request();
+        assert_eq!(check_request(Some(first)), Ok(()));
+        let second = new_request();
+        assert!(matches!(
+            check_request(Some(first)),
+            Err(WalletError::Cancelled(_))
+        ));
+        assert_eq!(check_request(Some(second)), Ok(()));
+        assert_eq!(check_request(None), Ok(()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown input format")]
+    fn test_empty() {
+        let inp = "";
+        let _resp = InputEval::evaluate(inp, "", "").unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown input format")]
+    fn test_short_numeric() {
+        let inp = "1234567890";
+        let _resp = InputEval::evaluate(inp, "", "").unwrap();
+    }
+
+    #[test]
+    fn test_legacy_address() {
+        let inp = "3M5f673Ler6iJbatJNvex7EYANRsydSQXE";
+        let resp = InputEval::evaluate(inp, "1", "d").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(inp, addr.to_string());
+        } else {
+            panic!("not recognized as regular mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(100_000_000));
+        assert_eq!(resp.description, "d");
+        assert_eq!(
+            resp.gui_csv().unwrap(),
+            "3M5f673Ler6iJbatJNvex7EYANRsydSQXE;1;d"
+        );
+    }
+
+    #[test]
+    fn test_beech_address() {
+        let inp = "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa";
+        let resp = InputEval::evaluate(inp, "0.0000001", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(inp, addr.to_string());
+        } else {
+            panic!("not recognized as regular mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(10));
+        assert_eq!(resp.description, "");
+        assert_eq!(
+            resp.gui_csv().unwrap(),
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa;0.0000001;"
+        );
+    }
+
+    #[test]
+    fn test_taproot_address() {
+        let inp = "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(inp, addr.to_string());
+        } else {
+            panic!("not recognized as taproot address");
+        }
+        assert_eq!(resp.satoshis, None);
+        assert_eq!(resp.gui_csv().unwrap(), format!("{};;", inp));
+    }
+
+    #[test]
+    fn test_p2wsh_address() {
+        let inp = "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(inp, addr.to_string());
+        } else {
+            panic!("not recognized as segwit script address");
+        }
+    }
+
+    #[test]
+    fn test_uri_taproot() {
+        let inp = "bitcoin:bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr?amount=0.001&label=taproot";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(
+                "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr",
+                addr.to_string()
+            );
+        } else {
+            panic!("not recognized as taproot address");
+        }
+        assert_eq!(resp.satoshis, Some(100_000));
+        assert_eq!(resp.description, "taproot");
+    }
+
+    #[test]
+    fn test_normalize_case() {
+        assert_eq!(
+            normalize_case(
+                "BITCOIN:BC1QA8DN66XN2YQ4FCAEE4F0GWKKR6E6EM643CM8FA?amount=0.1&label=Shop"
+            ),
+            "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?amount=0.1&label=Shop"
+        );
+        assert_eq!(
+            normalize_case("BITCOIN:BC1QA8DN66XN2YQ4FCAEE4F0GWKKR6E6EM643CM8FA?LIGHTNING=LNBC1ABC"),
+            "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?lightning=lnbc1abc"
+        );
+        assert_eq!(normalize_case("LIGHTNING:LNBC1ABC"), "lightning:lnbc1abc");
+        // base58 and LNURL are case sensitive or expected in uppercase
+        assert_eq!(
+            normalize_case("3M5f673Ler6iJbatJNvex7EYANRsydSQXE"),
+            "3M5f673Ler6iJbatJNvex7EYANRsydSQXE"
+        );
+        assert_eq!(
+            normalize_case("LIGHTNING:LNURL1DP68"),
+            "lightning:LNURL1DP68"
+        );
+        assert_eq!(normalize_case("satoshi@example.com"), "satoshi@example.com");
+    }
+
+    #[test]
+    fn test_uppercase_address() {
+        let inp = "BC1P5CYXNUXMEUWUVKWFEM96LQZSZD02N6XDCJRS20CAC6YQJJWUDPXQKEDRCR";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(inp.to_lowercase(), addr.to_string());
+        } else {
+            panic!("not recognized as mainnet address");
+        }
+    }
+
+    #[test]
+    fn test_uppercase_uri() {
+        let inp = "BITCOIN:BC1QA8DN66XN2YQ4FCAEE4F0GWKKR6E6EM643CM8FA?AMOUNT=0.001";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(
+                "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+                addr.to_string()
+            );
+        } else {
+            panic!("not recognized as mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(100_000));
+    }
+
+    #[test]
+    fn test_uri_encoded_label() {
+        let inp = "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?label=Coffee%20Shop&message=table%204";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        assert_eq!(resp.description, "Coffee Shop");
+
+        let inp =
+            "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?message=table%204+5%20%E2%98%95";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        assert_eq!(resp.description, "table 4+5 ☕");
+    }
+
+    #[test]
+    fn test_batch() {
+        let inp = "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.001\n3M5f673Ler6iJbatJNvex7EYANRsydSQXE, 0.0002";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        match &resp.network {
+            InputNetwork::Batch(recipients) => assert_eq!(recipients.len(), 2),
+            _ => panic!("not recognized as batch"),
+        }
+        assert_eq!(resp.satoshis, Some(120_000));
+        let csv = resp.gui_csv_in(Denomination::Btc).unwrap();
+        assert_eq!(csv.split(';').next().unwrap(), inp);
+
+        let inp = "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.001\nlnbc1garbage";
+        assert!(InputEval::evaluate(inp, "", "").is_err());
+    }
+
+    #[test]
+    fn test_psbt() {
+        let inp = "cHNidP8BAFICAAAAARaeHoPpMIUzkbxvNfYFxnVM/q1Xz4OHY507QJbFTxj0AQAAAAD9////AVDDAAAAAAAAFgAU6ds9aNNRAVTjuc1S9DrWHrOs71UAAAAAAAAA";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        match &resp.network {
+            InputNetwork::Psbt(psbt) => assert_eq!(psbt.unsigned_tx.output.len(), 1),
+            _ => panic!("not recognized as PSBT"),
+        }
+        assert_eq!(resp.satoshis, Some(50_000));
+        let csv = resp.gui_csv_in(Denomination::Btc).unwrap();
+        assert_eq!(csv.split(';').next().unwrap(), inp);
+
+        assert!(InputEval::evaluate("cHNidP8BAFICAAAAARae", "", "").is_err());
+    }
+
+    #[test]
+    fn test_payjoin() {
+        let inp = "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?amount=0.001&pj=https%3A%2F%2Fbtcpay.example.com%2FBTC%2Fpj";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        match &resp.network {
+            InputNetwork::Payjoin(addr, pj) => {
+                assert_eq!(
+                    addr.to_string(),
+                    "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa"
+                );
+                assert_eq!(pj, "https://btcpay.example.com/BTC/pj");
+            }
+            _ => panic!("not recognized as payjoin"),
+        }
+        assert_eq!(resp.satoshis, Some(100_000));
+
+        // the address field keeps the endpoint
+        let csv = resp.gui_csv_in(Denomination::Btc).unwrap();
+        let recipient = csv.split(';').next().unwrap();
+        let again = InputEval::evaluate(recipient, "", "").unwrap();
+        match again.network {
+            InputNetwork::Payjoin(_, pj) => assert_eq!(pj, "https://btcpay.example.com/BTC/pj"),
+            _ => panic!("the endpoint got lost"),
+        }
+    }
+
+    #[test]
+    fn test_sendable_sats() {
+        assert_eq!(sendable_sats((1_000, 100_000_000)), (1, 100_000));
+        assert_eq!(sendable_sats((1_500, 2_500)), (2, 2));
+        match pick_amount(Some((1_000, 5_000_000))) {
+            WalletError::InvalidAmount(msg) => {
+                assert_eq!(msg, "Pick an amount between 1 and 5000 sats")
+            }
+            _ => panic!("expected an invalid amount"),
+        }
+    }
+
+    #[test]
+    fn test_lnurl_comment() {
+        assert_eq!(lnurl_comment("for the coffee", None), None);
+        assert_eq!(lnurl_comment("for the coffee", Some(0)), None);
+        assert_eq!(lnurl_comment(" ", Some(100)), None);
+        assert_eq!(
+            lnurl_comment("for the coffee", Some(100)),
+            Some("for the coffee".to_string())
+        );
+        assert_eq!(
+            lnurl_comment("für Kaffee", Some(3)),
+            Some("für".to_string())
+        );
+    }
+
+    #[test]
+    fn test_uri_amount() {
+        let inp = "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?amount=100";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(
+                "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+                addr.to_string()
+            );
+        } else {
+            panic!("not recognized as regular mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(10_000_000_000));
+        assert_eq!(resp.description, "");
+        assert_eq!(
+            resp.gui_csv().unwrap(),
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa;100;"
+        );
+    }
+
+    #[test]
+    fn test_uri_label_amount() {
+        let inp = "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?label=test&amount=100";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(
+                "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+                addr.to_string()
+            );
+        } else {
+            panic!("not recognized as regular mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(10_000_000_000));
+        assert_eq!(resp.description, "test");
+        assert_eq!(
+            resp.gui_csv().unwrap(),
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa;100;test"
+        );
+    }
+
+    #[test]
+    fn test_uri_unified() {
+        // without a usable channel, the address is paid
+        let inp = "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?amount=0.0001&label=test&lightning=lnbc1pjzg3y4sp5t5pqc4w2re6duurq9smwhd78688rwmg2hwxhypxn0vqgu9vgjxnspp5z7p6kn5fpnr8zefvhdw90gascnae5a9s2flrwjp45a6tf53gwrrqdq9u2d2zxqr3jscqpjrzjqvp62xyytkuen9rc8asxue3fuuzultc89ewwnfxch70zf80yl0gpjzxypyqqxhqqqqqqqqqqqqqqqzqq9q9qx3qysgqcnwt6hdzlz3r5k3vqlwcyjrgmyyxrcq7rv304w32q8s6zqe4r7vjvvqxq8rk0g8j9udljtr9dw908ye7608z945gpa3h0avudrqtcpsp7zd4mp";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Mainnet(ref addr) = resp.network {
+            assert_eq!(
+                "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+                addr.to_string()
+            );
+        } else {
+            panic!("not recognized as regular mainnet address");
+        }
+        assert_eq!(resp.satoshis, Some(10_000));
+        assert_eq!(resp.description, "test");
+    }
+
+    #[test]
+    fn test_bip21_params() {
+        let props = bip21_params(
+            "bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?Amount=1&foo=bar&message=",
+        )
+        .unwrap();
+        assert_eq!(props.get("amount"), Some(&"1".to_string()));
+        assert_eq!(props.get("foo"), Some(&"bar".to_string()));
+        assert_eq!(props.get("message"), None);
+        assert!(
+            bip21_params("bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?req-pop=x").is_err()
+        );
+        assert!(
+            bip21_params("bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa")
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_priv_key() {
+        let inp = "KxWvpvpY9C5weJGWpUMQqHt88Xktt7nZDZPHbpJjEuUaDgeMHJuw";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::PrivKey(ref key) = resp.network {
+            assert_eq!(
+                "KxWvpvpY9C5weJGWpUMQqHt88Xktt7nZDZPHbpJjEuUaDgeMHJuw",
+                key.to_string()
+            );
+        } else {
+            panic!("not recognized as private key address");
+        }
+    }
+
+    #[test]
+    fn test_xprv() {
+        let inp = "xprv9z1Nt86QQeoGXTjrvKgbFT924JeV1qmo2QV6m8YYTWkaVVWNc3nmeTTKsoq2PKVMfQLUKchQbazkT5FqLo4BUC2P2rVFmDnE46QBNjiAsLP";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::PrivKey(ref key) = resp.network {
+            assert_eq!(
+                "xprv9z1Nt86QQeoGXTjrvKgbFT924JeV1qmo2QV6m8YYTWkaVVWNc3nmeTTKsoq2PKVMfQLUKchQbazkT5FqLo4BUC2P2rVFmDnE46QBNjiAsLP",
+                key.to_string()
+            );
+        } else {
+            panic!("not recognized as private key address");
+        }
+    }
+
+    #[test]
+    fn test_zprv() {
+        let inp = "zprvAZLoT7yPmyP5qVdL4pdmE2tFeGx1BWSbMwkMwXwPE6z3E1qrYCY4HsZPkRXHnziAB8uSpMzjiDM3jbkQsnWWDAkVtsMo1L9sES5xeJMq3YV";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::PrivKey(ref key) = resp.network {
+            assert_eq!(
+                "xprv9ugGqndZUcJ88uF6Q74WorhFJLf7JGTbXihvNk9cU6EH7pDQ2tCw3kF7i1c7oBQKMrfqKQocntdwy2XHSPgUchPJABxwqWWtgyxfsAdXcKZ",
+                key.to_string()
+            );
+        } else {
+            panic!("not recognized as private key address");
+        }
+    }
+
+    #[test]
+    fn test_desc() {
+        let inp = "pkh(xprv9z1Nt86QQeoGXTjrvKgbFT924JeV1qmo2QV6m8YYTWkaVVWNc3nmeTTKsoq2PKVMfQLUKchQbazkT5FqLo4BUC2P2rVFmDnE46QBNjiAsLP)";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::PrivKey(ref desc) = resp.network {
+            assert_eq!(inp.to_string() + "#smfvl5ay", desc.to_string());
+        } else {
+            panic!("not recognized as miniscript descriptor");
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "sanity check")]
+    fn test_desc_invalid() {
+        let inp = "pkh(pkh(xprv9z1Nt86QQeoGXTjrvKgbFT924JeV1qmE46QBNjiAsLP))";
+        InputEval::evaluate(inp, "", "").unwrap();
+    }
+
+    #[test]
+    fn test_bolt11_short() {
+        let inp = "lnbc1pjzg3y4sp5t5pqc4w2re6duurq9smwhd78688rwmg2hwxhypxn0vqgu9vgjxnspp5z7p6kn5fpnr8zefvhdw90gascnae5a9s2flrwjp45a6tf53gwrrqdq9u2d2zxqr3jscqpjrzjqvp62xyytkuen9rc8asxue3fuuzultc89ewwnfxch70zf80yl0gpjzxypyqqxhqqqqqqqqqqqqqqqzqq9q9qx3qysgqcnwt6hdzlz3r5k3vqlwcyjrgmyyxrcq7rv304w32q8s6zqe4r7vjvvqxq8rk0g8j9udljtr9dw908ye7608z945gpa3h0avudrqtcpsp7zd4mp";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(ref invoice) = resp.network {
+            assert_eq!(inp, invoice.to_string());
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, None);
+        assert_eq!(resp.description, "⚡");
+        assert_eq!(resp.gui_csv().unwrap(), "lnbc1pjzg3y4sp5t5pqc4w2re6duurq9smwhd78688rwmg2hwxhypxn0vqgu9vgjxnspp5z7p6kn5fpnr8zefvhdw90gascnae5a9s2flrwjp45a6tf53gwrrqdq9u2d2zxqr3jscqpjrzjqvp62xyytkuen9rc8asxue3fuuzultc89ewwnfxch70zf80yl0gpjzxypyqqxhqqqqqqqqqqqqqqqzqq9q9qx3qysgqcnwt6hdzlz3r5k3vqlwcyjrgmyyxrcq7rv304w32q8s6zqe4r7vjvvqxq8rk0g8j9udljtr9dw908ye7608z945gpa3h0avudrqtcpsp7zd4mp;;⚡");
+    }
+
+    #[test]
+    fn test_bolt11_long() {
+        let inp = "lnbc3518772650p1pjzg3x2sp59yemkg0cfmsxmugaesm304av4cx4mrp8q7zl65sses7dya7v725spp52ezaxjly2cvdvzlnyakgrq8v3gpnc58rtjepwch74gwgx05snvvqd2qw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqw3jhxapqxqr3jscqpjrzjq032f2wvt88a4lpgxa3nlxuuzd6xmm5azq8np92afzqnsfvv09qk6za0p5qqjdgqqqqqqqqqqqqqqqqqyu9qx3qysgq8v099gx9mlh9fvs3l0n0qlgka7kt0en8kca659maxy3kuww9y4l3utddc3yrx24hs2jwfyx8h0w2t6xltetqzd4a0mlpqwjz2mp5stsqvat45l";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(ref invoice) = resp.network {
+            assert_eq!(inp, invoice.to_string());
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(351877));
+        let desc = "test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test test ";
+        assert_eq!(resp.description, desc);
+        let exp = format!("{};{};{}", inp, 0.00351877, desc);
+        assert_eq!(resp.gui_csv().unwrap(), exp);
+    }
+
+    #[test]
+    fn test_bolt11_timecatcher() {
+        let inp = "lnbc21u1pjgj7azpp5w9kue4qeexcjv8j7jjpvxhfsut25d07e6lxz9xq5x3ftdjrv8spqdpydpv5z6zndf44jm6zg9xnsarz2dmkww2p2dgqcqzrrxqyp2xqsp5mf6qel6ymkeuue833vnscdwdkyrl5gef225z9f776gn0pgmehsqq9qyyssqfn28qncnutmp9y3wvqxze4xtewqkxv4jtqvndhk4hqwhqr4fl5j80zy6jcwvud85r0v0vpdwqd0d93n53jcnv43ee3dxjww3tcvgc9sph6jczf";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(2100));
+        assert_eq!(resp.description, "hYAhSjkYoBAM8tbSwg9ASP");
+    }
+
+    #[test]
+    fn test_bolt11_ulrichard() {
+        let inp = "LIGHTNING:LNBC33327780P1PJF2SS6SP50PNHS5H63S0XVJLAJUJM68M6JYQQDLHW0FA4A2HCUTAVRR2N7U4SPP5WVLRGLGX53R5R2FV8DAFK88Q6WXKNN4PPC7S0QCCHHYNMMXDXM5QDQ9U2D2ZXQR3JSCQPJRZJQTZXVFSUXE4L92PF97TT4RCGPY2XALKMLWEXH899WQXF83L8NWV4XZMCSQQQTLQQQYQQQQLGQQQQQWGQVS9QX3QYSGQQZGVXP2RHFQ32DC3RQH2AE2QSMLZJGE9YC2JWQWZ3MDPZFULHPXPXWEVW0QAZN4MDF8593UZFXARP3CTMTGE6W6TEENQW5R7TSE5JHCQK7ZNKT";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(3332));
+        assert_eq!(resp.description, "⚡");
+    }
+
+    #[test]
+    fn test_bolt12_short() {
+        let inp = "lno1pgqpvggr53478rgx3s4uttelcy76ssrepm2kg0ead5n7tc6dvlkj4mqkeens";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::LightningOffer(offer) = resp.network {
+            assert_eq!(inp, offer.to_string());
+            assert_eq!(offer.amount(), None);
+            assert_eq!(offer.description().unwrap().to_string(), "");
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+    }
+
+    #[test]
+    fn test_bolt12_long() {
+        let inp = "lno1pqpzwrc2936x2um5yp6x2um5yp6x2um5yp6x2um5yp6x2um5yp6x2um5yp6x2um5yp6x2um5yp6x2um5zcss8frtuwxsdrptckhnlsfa4pq8jrk4vsln6mf8uh356eld9tkpdnn8";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::LightningOffer(offer) = resp.network {
+            assert_eq!(inp, offer.to_string());
+            assert_eq!(
+                offer.amount(),
+                Some(Amount::Bitcoin { amount_msats: 9999 }).as_ref()
+            );
+            assert_eq!(
+                offer.description().unwrap().to_string(),
+                "test test test test test test test test test"
+            );
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+    }
+
+    #[test]
+    fn test_lnurl_https() {
+        let inp = "https://opreturnbot.com/.well-known/lnurlp/ben";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(1));
+        assert_eq!(resp.description, "");
+    }
+
+    #[test]
+    fn test_lnurl() {
+        let inp = "LNURL1DP68GURN8GHJ7MR9VAJKUEPWD3HXY6T5WVHXXMMD9AKXUATJD3JX2ANFVDJJ7CTSDYHHVV30D3H82UNV9AF5ZMJEWFV82CJ3D4R8G42STP2N272V23K550MSD9HR6VFJYESK6MM4DE6R6VPWX5NXGATJV96XJMMW85CNQVPSV48PVT";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert!(
+            resp.satoshis.unwrap() > 1_000 && resp.satoshis.unwrap() < 3_000,
+            "satoshis: {}",
+            resp.satoshis.unwrap()
+        );
+        assert_eq!(resp.description, "");
+    }
+
+    #[test]
+    fn test_lnurl_prefix() {
+        let inp = "lightning:LNURL1DP68GURN8GHJ7MR9VAJKUEPWD3HXY6T5WVHXXMMD9AKXUATJD3CZ7CTSDYHHVVF0D3H82UNV9UUNGWPCMUCDQF";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(2100));
+        assert_eq!(resp.description, "");
+    }
+
+    #[test]
+    fn test_lightning_address_ben() {
+        let inp = "ben@opreturnbot.com";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(1));
+        assert_eq!(resp.description, "");
+    }
+
+    #[test]
+    fn test_lightning_address_ulrichard() {
+        let inp = "ulrichard@sbpc.ch";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, Some(1));
+        assert_eq!(resp.description, "Lightning Address Top-up");
+    }
+
+    // I didn't want to dox my real card id, as otherwise anybody could block it.
+    #[test]
+    #[should_panic(expected = "HttpResponse(500)")]
+    fn test_lightning_address() {
+        let inp = "2iwc-vo3m-lsks-zt0z@swiss-bitcoin-pay.ch";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::Lightning(invoice) = resp.network {
+            assert_eq!(*"lnbc", invoice.to_string()[..4]);
+        } else {
+            panic!("not recognized as lightning invoice");
+        }
+        assert_eq!(resp.satoshis, None);
+        assert_eq!(resp.description, "");
+    }
+
+    #[test]
+    fn test_nodeid_ulrichard() {
+        let inp = crate::constants::LN_ULR;
+        assert!(is_node_id(inp));
+    }
+
+    #[test]
+    fn test_nodeid_tor() {
+        let inp = "02fb0ba685e8f5be6eb39e5f1f2481b16673aa1019852a727b3140f5b0716cf48a@rquqr26p26lwgnanyjrr4mo33ri76y3a55xge57w52n5qlwp6sixzhad.onion:9735";
+        assert!(is_node_id(inp));
+    }
+
+    #[test]
+    fn test_nodeid_localhost() {
+        let inp =
+            "02fb0ba685e8f5be6eb39e5f1f2481b16673aa1019852a727b3140f5b0716cf48a@127.0.0.1:9735";
+        assert!(is_node_id(inp));
+    }
+
+    #[test]
+    fn test_nodeid_invalid_pubkey() {
+        let inp = "02fb0ba85e8f5beeb39e5f1f2481b1673aa1019852727b3140f5b0716cf48a@127.0.0.1:9735";
+        assert!(!is_node_id(inp));
+    }
+
+    #[test]
+    fn test_nodeid_empty() {
+        let inp = "";
+        assert!(!is_node_id(inp));
+    }
+
+    // I didn't want to dox my real card id, as otherwise anybody could withdraw from it.
+    #[test]
+    fn test_lnurlw() {
+        let inp = "lnurlw://api.swiss-bitcoin-pay.ch/card/AbCdEfGhIjKlMnOpQr?p=123456789ABCDEF&c=123456789ABCDEF";
+        let resp = InputEval::evaluate(inp, "", "").unwrap();
+        if let InputNetwork::LnWithdraw(invoice) = resp.network {
+            assert_eq!(inp.replace("lnurlw://", "https://"), invoice);
+        } else {
+            panic!("not recognized as lightning withdrawal");
+        }
+        assert_eq!(resp.satoshis, Some(21000000000));
+        assert_eq!(resp.description, "🇨🇭 Swiss Bitcoin Pay Card");
+    }
+}
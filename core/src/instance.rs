@@ -0,0 +1,155 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Only one process may run the node of a wallet, two nodes with the same channel state
+//! would lose funds. The process running it holds an exclusive lock on a file in the data
+//! directory for as long as the node runs, the kernel drops it when the process dies. The
+//! pid in the file only serves the error message.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    os::unix::io::AsRawFd,
+    path::{Path, PathBuf},
+    process,
+    sync::Mutex,
+};
+
+const LOCK_FILE: &str = "node.pid";
+
+/// The locked file of the directory this process runs the node of
+static HELD: Mutex<Option<(PathBuf, File)>> = Mutex::new(None);
+
+fn open(dir: &Path) -> Result<File, String> {
+    let file = dir.join(LOCK_FILE);
+    fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&file)
+        .map_err(|e| format!("Failed to open {:?}: {}", file, e))
+}
+
+/// Take the exclusive lock without waiting, false if another open file holds it.
+/// flock rather than File::try_lock, which needs a newer toolchain than the click builds use.
+fn try_lock(file: &File) -> io::Result<bool> {
+    // SAFETY: the descriptor stays open for as long as the file is borrowed
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EWOULDBLOCK) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+fn unlock(file: &File) {
+    // SAFETY: as in try_lock
+    unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_UN) };
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut content = String::new();
+    file.read_to_string(&mut content).ok()?;
+    content.trim().parse().ok()
+}
+
+/// The process that runs the node in the directory, None while it is free
+pub fn holder(dir: &Path) -> Option<u32> {
+    if let Ok(held) = HELD.lock() {
+        if matches!(&*held, Some((held_dir, _)) if held_dir == dir) {
+            return Some(process::id());
+        }
+    }
+    let mut file = open(dir).ok()?;
+    match try_lock(&file) {
+        Ok(true) => None,
+        Ok(false) => Some(read_pid(&mut file).unwrap_or_default()),
+        Err(e) => {
+            log_error!("Failed to check the lock of {:?}: {}", dir, e);
+            None
+        }
+    }
+}
+
+/// Claim the node in the directory for this process, until it is released or the process ends
+pub fn acquire(dir: &Path) -> Result<(), String> {
+    let mut held = HELD
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the node lock: {:?}", e))?;
+    if let Some((held_dir, _)) = &*held {
+        if held_dir == dir {
+            return Ok(());
+        }
+    }
+    let mut file = open(dir)?;
+    match try_lock(&file) {
+        Ok(true) => {}
+        Ok(false) => {
+            return Err(match read_pid(&mut file) {
+                Some(pid) => format!("The wallet is in use by process {}, close it first", pid),
+                None => "The wallet is in use by another process, close it first".to_string(),
+            });
+        }
+        Err(e) => return Err(format!("Failed to lock {:?}: {}", dir.join(LOCK_FILE), e)),
+    }
+    file.set_len(0)
+        .and_then(|_| file.rewind())
+        .and_then(|_| file.write_all(process::id().to_string().as_bytes()))
+        .map_err(|e| format!("Failed to write {:?}: {}", dir.join(LOCK_FILE), e))?;
+    // a single lock is held, the one of a previous profile is dropped here
+    *held = Some((dir.to_path_buf(), file));
+    Ok(())
+}
+
+/// Give the node in the directory free, if this process holds it.
+/// The file stays, removing it would let two processes lock different files.
+pub fn release(dir: &Path) {
+    if let Ok(mut held) = HELD.lock() {
+        if matches!(&*held, Some((held_dir, _)) if held_dir == dir) {
+            if let Some((_, file)) = held.take() {
+                let _ = file.set_len(0);
+                unlock(&file);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock() {
+        let dir = std::env::temp_dir().join(format!("utwallet-lock-test-{}", process::id()));
+        assert_eq!(holder(&dir), None);
+        acquire(&dir).unwrap();
+        assert_eq!(holder(&dir), Some(process::id()));
+        // another open file can't get the lock, as another process wouldn't
+        let mut other = open(&dir).unwrap();
+        assert!(!try_lock(&other).unwrap());
+        assert_eq!(read_pid(&mut other), Some(process::id()));
+        // acquiring again is fine for the holder
+        acquire(&dir).unwrap();
+        release(&dir);
+        assert_eq!(holder(&dir), None);
+        assert!(try_lock(&other).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
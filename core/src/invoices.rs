@@ -0,0 +1,125 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::storage::{load_json, save_json, to_hex};
+use crate::wallet::BdkWallet;
+
+use chrono::Utc;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::payment::{PaymentKind, PaymentStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+const INVOICES_FILE: &str = "invoices.json";
+
+/// An invoice that we created for receiving a payment
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct InvoiceRecord {
+    pub invoice: String,
+    pub payment_hash: String,
+    pub amount_msat: Option<u64>,
+    pub description: String,
+    /// unix timestamp of the creation
+    pub created: i64,
+    pub expiry_secs: u32,
+    /// the LNURL-withdraw service that was asked to pay this invoice
+    #[serde(default)]
+    pub withdraw_url: String,
+    /// what the withdraw service answered to the callback
+    #[serde(default)]
+    pub withdraw_response: String,
+}
+
+impl InvoiceRecord {
+    pub fn expires_at(&self) -> i64 {
+        self.created + self.expiry_secs as i64
+    }
+}
+
+/// Remember a freshly created invoice
+pub fn add_invoice(invoice: &Bolt11Invoice, description: &str) -> Result<(), String> {
+    let mut invoices: Vec<InvoiceRecord> = load_json(INVOICES_FILE)?;
+    invoices.push(InvoiceRecord {
+        invoice: invoice.to_string(),
+        payment_hash: invoice.payment_hash().to_string(),
+        amount_msat: invoice.amount_milli_satoshis(),
+        description: description.to_string(),
+        created: Utc::now().timestamp(),
+        expiry_secs: invoice.expiry_time().as_secs() as u32,
+    });
+    save_json(INVOICES_FILE, &invoices)
+}
+
+/// The invoice we created with this payment hash
+pub fn find(payment_hash: &str) -> Result<Option<InvoiceRecord>, String> {
+    let invoices: Vec<InvoiceRecord> = load_json(INVOICES_FILE)?;
+    Ok(invoices
+        .into_iter()
+        .find(|i| i.payment_hash == payment_hash))
+}
+
+/// Remember that an invoice was handed to an LNURL-withdraw service, and what it answered
+pub fn mark_withdraw(invoice: &str, url: &str, response: &str) -> Result<(), String> {
+    let mut invoices: Vec<InvoiceRecord> = load_json(INVOICES_FILE)?;
+    let inv = invoices
+        .iter_mut()
+        .find(|i| i.invoice == invoice)
+        .ok_or(format!("Unknown withdraw invoice {}", invoice))?;
+    inv.withdraw_url = url.to_string();
+    inv.withdraw_response = response.to_string();
+    save_json(INVOICES_FILE, &invoices)
+}
+
+/// The payment hashes of all inbound lightning payments that succeeded
+fn paid_hashes() -> Result<HashSet<String>, String> {
+    Ok(BdkWallet::list_payments()?
+        .iter()
+        .filter(|p| p.status == PaymentStatus::Succeeded)
+        .filter_map(|p| match p.kind {
+            PaymentKind::Bolt11 { hash, .. } | PaymentKind::Bolt11Jit { hash, .. } => {
+                Some(to_hex(&hash.0))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// For invoices handed to a withdraw service: did the service pay?
+pub fn withdraw_status(inv: &InvoiceRecord, paid: bool) -> &'static str {
+    if inv.withdraw_url.is_empty() {
+        ""
+    } else if paid {
+        "paid"
+    } else if inv.expires_at() < Utc::now().timestamp() {
+        "never paid"
+    } else {
+        "waiting"
+    }
+}
+
+/// The invoices we created, newest first, along with whether they were paid
+pub fn load_invoices() -> Result<Vec<(InvoiceRecord, bool)>, String> {
+    let invoices: Vec<InvoiceRecord> = load_json(INVOICES_FILE)?;
+    let paid = paid_hashes()?;
+    Ok(invoices
+        .into_iter()
+        .rev()
+        .map(|inv| {
+            let is_paid = paid.contains(&inv.payment_hash);
+            (inv, is_paid)
+        })
+        .collect())
+}
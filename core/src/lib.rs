@@ -0,0 +1,74 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The wallet logic without any Qt dependencies. The QML frontend, the command line client and
+//! the daemon are built on it.
+
+#[macro_use]
+pub mod redact;
+
+pub mod batch;
+pub mod constants;
+pub mod daemon;
+pub mod dbus;
+pub mod demo;
+pub mod electrum;
+pub mod error;
+pub mod esplora;
+pub mod eventlog;
+pub mod export;
+pub mod format;
+pub mod gossip;
+pub mod history;
+pub mod i18n;
+pub mod input_eval;
+pub mod instance;
+pub mod invoices;
+pub mod lifecycle;
+pub mod lnaddress;
+pub mod logging;
+pub mod message;
+pub mod netwatch;
+pub mod notify;
+pub mod pairing;
+pub mod payjoin;
+pub mod payments;
+pub mod pin;
+pub mod pos;
+pub mod preview;
+pub mod price_history;
+pub mod profiles;
+pub mod proxy;
+pub mod psbt;
+pub mod qr;
+pub mod rates;
+pub mod receipt;
+pub mod recovery;
+pub mod report;
+pub mod rest;
+pub mod retention;
+pub mod scheduler;
+pub mod secrets;
+pub mod settings;
+pub mod share;
+pub mod silentpayment;
+pub mod storage;
+pub mod sweeper;
+pub mod ticker;
+pub mod validate;
+pub mod wallet;
+pub mod watchonly;
+pub mod webhook;
@@ -0,0 +1,65 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The OS suspends the app when it goes to the background, and may kill it without notice
+//! while it is suspended. The node is stopped cleanly before, so that its storage can't be
+//! left half written, and started again when the app becomes active.
+
+use crate::daemon;
+use crate::error::WalletError;
+use crate::scheduler;
+use crate::wallet::BdkWallet;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static SUSPENDED: AtomicBool = AtomicBool::new(false);
+
+/// Pause the background tasks and stop the node
+pub fn suspend() -> Result<(), WalletError> {
+    if SUSPENDED.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    log_info!("suspending, stopping the node");
+    scheduler::pause(true);
+    BdkWallet::shutdown()?;
+    // the daemon keeps the channels alive while the app is in the background
+    if let Err(e) = daemon::give_back() {
+        log_error!("{}", e);
+    }
+    Ok(())
+}
+
+/// Start the node again and resume the background tasks
+pub fn resume() -> Result<(), WalletError> {
+    if !SUSPENDED.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    log_info!("resuming, starting the node");
+    // the daemon may have taken the node while the app was suspended
+    if let Err(e) = daemon::take_over() {
+        log_error!("{}", e);
+    }
+    if let Err(e) = BdkWallet::init_node(|_| {}) {
+        SUSPENDED.store(true, Ordering::SeqCst);
+        return Err(e);
+    }
+    scheduler::pause(false);
+    Ok(())
+}
+
+pub fn is_suspended() -> bool {
+    SUSPENDED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,302 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! A personal lightning address like `me@domain`, served by the LNURLp extension of an
+//! LNbits instance. The pay link of the address is created in an LNbits wallet, which
+//! answers the payers with its own invoices. So the payments arrive there even while the
+//! phone is off, and the wallet moves them over to its node with an invoice of its own
+//! whenever it runs. Until then the provider holds the funds.
+//!
+//! The calls, all with the admin key of the LNbits wallet as `X-Api-Key`:
+//! * `POST {provider}/lnurlp/api/v1/links` creates the pay link for the username
+//! * `DELETE {provider}/lnurlp/api/v1/links/{id}` gives the address up
+//! * `GET {provider}/api/v1/wallet` answers the balance in msat
+//! * `POST {provider}/api/v1/payments` with `{"out": true, "bolt11": ...}` pays the node
+
+use crate::constants::{LNADDRESS_MAX_SATS, LNADDRESS_SWEEP_MIN_SATS};
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::proxy;
+use crate::secrets;
+use crate::settings::Settings;
+use crate::wallet::BdkWallet;
+
+use reqwest::Method;
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::{json, Value};
+
+pub const LNADDRESS_KEY: &str = "lnaddress_key";
+
+/// LNbits keeps this much of a payment for the routing fees, at least 2 sat or 1%
+const FEE_RESERVE_MIN_MSAT: u64 = 2_000;
+const FEE_RESERVE_PERCENT: u64 = 1;
+
+#[derive(Debug, Deserialize)]
+struct PayLink {
+    id: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct WalletInfo {
+    balance: u64,
+}
+
+/// Check the url of the LNbits instance, only https is accepted
+pub fn normalize_url(url: &str) -> Result<String, String> {
+    let url = url.trim().trim_end_matches('/');
+    if !url.starts_with("https://") || url.len() <= "https://".len() {
+        return Err(trf(
+            "The lightning address provider must be an https url: {}",
+            &[&url],
+        ));
+    }
+    if url.contains(char::is_whitespace) {
+        return Err(trf("Invalid lightning address provider: {}", &[&url]));
+    }
+    Ok(url.to_string())
+}
+
+/// The name in front of the @, as LUD-16 allows it
+pub fn normalize_username(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    let allowed = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "-_.".contains(c);
+    if name.is_empty() || name.len() > 64 || !name.chars().all(allowed) {
+        return Err(trf("Invalid name for a lightning address: {}", &[&name]));
+    }
+    Ok(name)
+}
+
+/// The address LNbits serves the pay link of the username under
+fn address(provider: &str, username: &str) -> String {
+    let host = provider.trim_start_matches("https://");
+    let host = host.split('/').next().unwrap_or(host);
+    format!("{}@{}", username, host)
+}
+
+/// How many sats can be paid out of the balance, with the fee reserve of LNbits left behind
+fn sweepable_sats(balance_msat: u64) -> u64 {
+    let reserve = (balance_msat * FEE_RESERVE_PERCENT / 100).max(FEE_RESERVE_MIN_MSAT);
+    balance_msat.saturating_sub(reserve) / 1_000
+}
+
+/// Call the LNbits api, and parse the answer if there is one
+fn call<T: DeserializeOwned>(
+    method: Method,
+    url: &str,
+    key: &str,
+    body: Option<Value>,
+) -> Result<Option<T>, String> {
+    proxy::check_online()?;
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+    rt.block_on(async {
+        let mut req = proxy::http_client()?
+            .request(method, url)
+            .header("X-Api-Key", key);
+        if let Some(body) = body {
+            req = req.json(&body);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| trf("The lightning address provider failed: {}", &[&e]))?;
+        if !resp.status().is_success() {
+            return Err(trf(
+                "The lightning address provider answered with {}",
+                &[&resp.status()],
+            ));
+        }
+        let text = resp
+            .text()
+            .await
+            .map_err(|e| trf("The lightning address provider failed: {}", &[&e]))?;
+        if text.trim().is_empty() {
+            return Ok(None);
+        }
+        serde_json::from_str(&text)
+            .map(Some)
+            .map_err(|e| format!("Unexpected answer from the provider: {}", e))
+    })
+}
+
+fn key() -> Result<String, String> {
+    match secrets::get(LNADDRESS_KEY)? {
+        Some(key) if !key.is_empty() => Ok(key),
+        _ => Err(tr("No lightning address claimed")),
+    }
+}
+
+/// Create the pay link for the name in the LNbits wallet of the admin key, and return the address
+pub fn claim(provider: &str, admin_key: &str, username: &str) -> Result<String, String> {
+    let provider = normalize_url(provider)?;
+    let username = normalize_username(username)?;
+    let admin_key = admin_key.trim();
+    if admin_key.is_empty() {
+        return Err(tr("The admin key of the LNbits wallet is missing"));
+    }
+    let address = address(&provider, &username);
+    let body = json!({
+        "description": trf("Payment to {}", &[&address]),
+        "min": 1,
+        "max": LNADDRESS_MAX_SATS,
+        "comment_chars": 0,
+        "username": username,
+    });
+    let link: PayLink = call(
+        Method::POST,
+        &format!("{}/lnurlp/api/v1/links", provider),
+        admin_key,
+        Some(body),
+    )?
+    .ok_or_else(|| tr("The lightning address provider sent no pay link"))?;
+    let link_id = match link.id {
+        Value::String(id) => id,
+        id => id.to_string(),
+    };
+    secrets::set(LNADDRESS_KEY, admin_key)?;
+    let mut settings = Settings::load()?;
+    settings.lnaddress_provider = provider;
+    settings.lnaddress_link = link_id;
+    settings.lnaddress = address.clone();
+    settings.save()?;
+    log_info!("claimed the lightning address {}", address);
+    Ok(address)
+}
+
+/// Give the address up, it is forgotten even if the provider can't be reached.
+/// Whatever was not moved to the node yet stays in the LNbits wallet.
+pub fn release() -> Result<(), String> {
+    let mut settings = Settings::load()?;
+    if settings.lnaddress.is_empty() {
+        return Ok(());
+    }
+    let res = key().and_then(|key| {
+        call::<Value>(
+            Method::DELETE,
+            &format!(
+                "{}/lnurlp/api/v1/links/{}",
+                settings.lnaddress_provider, settings.lnaddress_link
+            ),
+            &key,
+            None,
+        )
+    });
+    if let Err(e) = &res {
+        log_error!("Failed to release {}: {}", settings.lnaddress, e);
+    }
+    secrets::set(LNADDRESS_KEY, "")?;
+    settings.lnaddress.clear();
+    settings.lnaddress_link.clear();
+    settings.save()?;
+    res.map(|_| ())
+}
+
+/// Move what was paid to the address from the LNbits wallet to the node, and return the sats
+pub fn sweep(settings: &Settings) -> Result<u64, WalletError> {
+    if settings.lnaddress.is_empty() {
+        return Ok(0);
+    }
+    let key = key().map_err(WalletError::Storage)?;
+    let provider = &settings.lnaddress_provider;
+    let wallet: WalletInfo = call(
+        Method::GET,
+        &format!("{}/api/v1/wallet", provider),
+        &key,
+        None,
+    )
+    .map_err(WalletError::Network)?
+    .ok_or_else(|| WalletError::Network(tr("The lightning address provider sent no balance")))?;
+    let sats = sweepable_sats(wallet.balance);
+    if sats < LNADDRESS_SWEEP_MIN_SATS {
+        return Ok(0);
+    }
+    let desc = trf("Received to {}", &[&settings.lnaddress]);
+    let invoice = BdkWallet::create_invoice(Some(sats), &desc)?;
+    call::<Value>(
+        Method::POST,
+        &format!("{}/api/v1/payments", provider),
+        &key,
+        Some(json!({ "out": true, "bolt11": invoice })),
+    )
+    .map_err(WalletError::Network)?;
+    log_info!(
+        "moved {} sats from {} to the node",
+        sats,
+        settings.lnaddress
+    );
+    Ok(sats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(
+            normalize_url(" https://lnbits.example.com/ ").unwrap(),
+            "https://lnbits.example.com"
+        );
+        assert!(normalize_url("http://lnbits.example.com").is_err());
+        assert!(normalize_url("https://").is_err());
+        assert!(normalize_url("https://lnbits example.com").is_err());
+    }
+
+    #[test]
+    fn test_normalize_username() {
+        assert_eq!(normalize_username(" Richi.U ").unwrap(), "richi.u");
+        assert_eq!(normalize_username("pay-me_2").unwrap(), "pay-me_2");
+        assert!(normalize_username("").is_err());
+        assert!(normalize_username("me@example.com").is_err());
+        assert!(normalize_username("zürich").is_err());
+        assert!(normalize_username(&"a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            address("https://lnbits.example.com", "richi"),
+            "richi@lnbits.example.com"
+        );
+        assert_eq!(
+            address("https://example.com/lnbits", "richi"),
+            "richi@example.com"
+        );
+    }
+
+    #[test]
+    fn test_sweepable_sats() {
+        assert_eq!(sweepable_sats(0), 0);
+        assert_eq!(sweepable_sats(1_500), 0);
+        // the minimal reserve of 2 sat
+        assert_eq!(sweepable_sats(100_000), 98);
+        // 1% above 200 sat
+        assert_eq!(sweepable_sats(1_000_000), 990);
+        assert_eq!(sweepable_sats(1_000_999), 990);
+    }
+
+    #[test]
+    fn test_parse_answers() {
+        let link: PayLink =
+            serde_json::from_str(r#"{"id": "aBc12", "username": "richi"}"#).unwrap();
+        assert_eq!(link.id, Value::String("aBc12".to_string()));
+        let link: PayLink = serde_json::from_str(r#"{"id": 7}"#).unwrap();
+        assert_eq!(link.id.to_string(), "7");
+        let wallet: WalletInfo =
+            serde_json::from_str(r#"{"name": "utwallet", "balance": 21000}"#).unwrap();
+        assert_eq!(wallet.balance, 21_000);
+    }
+}
@@ -0,0 +1,197 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The log goes to the system journal as before, and to a rotating file in the app data
+//! directory that can be exported for bug reports. The debug messages are only written in
+//! verbose mode.
+
+use crate::profiles;
+
+use chrono::{DateTime, Utc};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+};
+
+/// shared by all profiles, in the top level directory
+pub const LOG_DIR: &str = "logs";
+const LOG_FILE: &str = "utwallet.log";
+const EXPORT_FILE: &str = "utwallet-log.txt";
+/// the file is rotated once it grows beyond this
+const MAX_LOG_BYTES: u64 = 512 * 1024;
+/// how many rotated files are kept
+const KEEP_ROTATED: usize = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Info,
+    Debug,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Error => "ERROR",
+            Self::Info => "INFO",
+            Self::Debug => "DEBUG",
+        }
+    }
+}
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// print to stdout and stderr besides writing the file
+static CONSOLE: AtomicBool = AtomicBool::new(true);
+
+/// The open log file and its size
+static LOG: Mutex<Option<(File, u64)>> = Mutex::new(None);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::SeqCst);
+}
+
+/// The command line client keeps its output free of the log
+pub fn set_console(console: bool) {
+    CONSOLE.store(console, Ordering::SeqCst);
+}
+
+pub fn dir() -> PathBuf {
+    profiles::root_dir().join(LOG_DIR)
+}
+
+/// Print the message and append it to the log file. It has to be redacted already.
+pub fn write(level: Level, msg: &str) {
+    if level == Level::Debug && !VERBOSE.load(Ordering::SeqCst) {
+        return;
+    }
+    if CONSOLE.load(Ordering::SeqCst) {
+        match level {
+            Level::Error => eprintln!("{}", msg),
+            _ => println!("{}", msg),
+        }
+    }
+    // the log can't report its own failures
+    let _ = append(&dir(), &format_line(Utc::now(), level, msg));
+}
+
+fn format_line(time: DateTime<Utc>, level: Level, msg: &str) -> String {
+    format!(
+        "{} {:5} {}\n",
+        time.format("%Y-%m-%dT%H:%M:%SZ"),
+        level.name(),
+        msg
+    )
+}
+
+fn rotated(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("{}.{}", LOG_FILE, n))
+}
+
+/// Shift the rotated files by one, dropping the oldest, and start a new file
+fn rotate(dir: &Path) -> std::io::Result<()> {
+    for n in (1..KEEP_ROTATED).rev() {
+        let from = rotated(dir, n);
+        if from.exists() {
+            fs::rename(&from, rotated(dir, n + 1))?;
+        }
+    }
+    fs::rename(dir.join(LOG_FILE), rotated(dir, 1))
+}
+
+fn open(dir: &Path) -> std::io::Result<(File, u64)> {
+    fs::create_dir_all(dir)?;
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(LOG_FILE))?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+fn append(dir: &Path, line: &str) -> std::io::Result<()> {
+    let mut log = LOG
+        .lock()
+        .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "log mutex poisoned"))?;
+    if log.is_none() {
+        *log = Some(open(dir)?);
+    }
+    if let Some((_, size)) = log.as_ref() {
+        if *size > 0 && size + line.len() as u64 > MAX_LOG_BYTES {
+            *log = None;
+            rotate(dir)?;
+            *log = Some(open(dir)?);
+        }
+    }
+    if let Some((file, size)) = log.as_mut() {
+        file.write_all(line.as_bytes())?;
+        *size += line.len() as u64;
+    }
+    Ok(())
+}
+
+/// The log from the oldest rotated file to the current one
+fn collect(dir: &Path) -> String {
+    (1..=KEEP_ROTATED)
+        .rev()
+        .map(|n| rotated(dir, n))
+        .chain(std::iter::once(dir.join(LOG_FILE)))
+        .filter_map(|file| fs::read_to_string(file).ok())
+        .collect()
+}
+
+/// Write the whole log into one file, to attach it to an issue, and return its path
+pub fn export() -> Result<PathBuf, String> {
+    let dir = dir();
+    if let Ok(mut log) = LOG.lock() {
+        if let Some((file, _)) = log.as_mut() {
+            let _ = file.flush();
+        }
+    }
+    let file = dir.join(EXPORT_FILE);
+    fs::write(&file, collect(&dir)).map_err(|e| format!("Failed to export the log: {}", e))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_line() {
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        assert_eq!(
+            format_line(time, Level::Info, "getting balances"),
+            "2023-11-14T22:13:20Z INFO  getting balances\n"
+        );
+    }
+
+    #[test]
+    fn test_rotate() {
+        let dir = std::env::temp_dir().join(format!("utwallet-log-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for content in ["first\n", "second\n", "third\n"] {
+            fs::write(dir.join(LOG_FILE), content).unwrap();
+            rotate(&dir).unwrap();
+        }
+        fs::write(dir.join(LOG_FILE), "current\n").unwrap();
+        assert_eq!(collect(&dir), "second\nthird\ncurrent\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
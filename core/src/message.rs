@@ -0,0 +1,180 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Signing messages with the keys of the on-chain addresses, to prove that an address
+//! belongs to the wallet. The signatures are compact and base64 encoded, with the header
+//! byte of BIP137, as Electrum and Sparrow produce and check them.
+
+use crate::i18n::{tr, trf};
+use crate::recovery;
+
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::{
+    base64,
+    bip32::{ChildNumber, DerivationPath, ExtendedPrivKey},
+    secp256k1::{
+        ecdsa::{RecoverableSignature, RecoveryId},
+        Message, Secp256k1, SecretKey,
+    },
+    sign_message::signed_msg_hash,
+    Address, Network, PublicKey,
+};
+use std::str::FromStr;
+
+/// how many addresses of each chain are searched for the one to sign with
+const ADDRESS_SEARCH_LIMIT: u32 = 1_000;
+/// BIP137 header of a signature by a native segwit key, plus the recovery id
+const P2WPKH_HEADER: u8 = 39;
+
+/// The key of one of our addresses
+fn address_key(
+    mnemonic: &Mnemonic,
+    network: Network,
+    address: &Address,
+) -> Result<SecretKey, String> {
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
+        .map_err(|e| format!("Failed to derive the master key: {}", e))?;
+    let path = DerivationPath::from_str(&recovery::account_path(network))
+        .map_err(|e| format!("Invalid derivation path: {}", e))?;
+    let account = master
+        .derive_priv(&secp, &path)
+        .map_err(|e| format!("Failed to derive the account key: {}", e))?;
+    for chain in 0..2 {
+        let chain = account
+            .derive_priv(&secp, &[ChildNumber::Normal { index: chain }])
+            .map_err(|e| format!("Failed to derive the chain key: {}", e))?;
+        for index in 0..ADDRESS_SEARCH_LIMIT {
+            let key = chain
+                .derive_priv(&secp, &[ChildNumber::Normal { index }])
+                .map_err(|e| format!("Failed to derive the address key: {}", e))?
+                .private_key;
+            let pubkey = PublicKey::new(key.public_key(&secp));
+            if Address::p2wpkh(&pubkey, network).ok().as_ref() == Some(address) {
+                return Ok(key);
+            }
+        }
+    }
+    Err(trf(
+        "The address {} doesn't belong to this wallet",
+        &[&address],
+    ))
+}
+
+fn parse_address(address: &str, network: Network) -> Result<Address, String> {
+    Address::from_str(address.trim())
+        .map_err(|e| trf("Invalid address: {}", &[&e]))?
+        .require_network(network)
+        .map_err(|_| trf("The address doesn't look like it is for {}", &[&network]))
+}
+
+/// Sign the text with the key of one of our addresses, and return the signature in base64
+pub fn sign(
+    mnemonic: &Mnemonic,
+    network: Network,
+    address: &str,
+    text: &str,
+) -> Result<String, String> {
+    let address = parse_address(address, network)?;
+    let key = address_key(mnemonic, network, &address)?;
+    let msg = Message::from_slice(signed_msg_hash(text).as_ref())
+        .map_err(|e| format!("Failed to hash the message: {}", e))?;
+    let (recid, sig) = Secp256k1::new()
+        .sign_ecdsa_recoverable(&msg, &key)
+        .serialize_compact();
+    let mut bytes = vec![P2WPKH_HEADER + recid.to_i32() as u8];
+    bytes.extend_from_slice(&sig);
+    Ok(base64::encode(bytes))
+}
+
+/// Check that the signature of the text was made with the key of the address. Legacy,
+/// nested and native segwit addresses are accepted, whatever the header says.
+pub fn verify(
+    network: Network,
+    address: &str,
+    text: &str,
+    signature: &str,
+) -> Result<bool, String> {
+    let address = parse_address(address, network)?;
+    let bytes =
+        base64::decode(signature.trim()).map_err(|e| trf("Invalid signature: {}", &[&e]))?;
+    if bytes.len() != 65 || !(27..=42).contains(&bytes[0]) {
+        return Err(tr("The signature has the wrong format"));
+    }
+    let header = bytes[0] - 27;
+    let recid = RecoveryId::from_i32((header % 4) as i32)
+        .map_err(|e| trf("Invalid signature: {}", &[&e]))?;
+    let sig = RecoverableSignature::from_compact(&bytes[1..], recid)
+        .map_err(|e| trf("Invalid signature: {}", &[&e]))?;
+    let msg = Message::from_slice(signed_msg_hash(text).as_ref())
+        .map_err(|e| format!("Failed to hash the message: {}", e))?;
+    let recovered = match Secp256k1::new().recover_ecdsa(&msg, &sig) {
+        Ok(key) => key,
+        Err(_) => return Ok(false),
+    };
+    // the headers below 31 are for uncompressed keys
+    let pubkey = if header < 4 {
+        PublicKey::new_uncompressed(recovered)
+    } else {
+        PublicKey::new(recovered)
+    };
+    let candidates = [
+        Some(Address::p2pkh(&pubkey, network)),
+        Address::p2wpkh(&pubkey, network).ok(),
+        Address::p2shwpkh(&pubkey, network).ok(),
+    ];
+    Ok(candidates.iter().flatten().any(|a| *a == address))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mnemonic() -> Mnemonic {
+        Mnemonic::from_str(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        // the first receive address of the mnemonic, BIP84 test vector
+        let address = "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu";
+        let signature = sign(&mnemonic(), Network::Bitcoin, address, "utwallet").unwrap();
+        assert!(verify(Network::Bitcoin, address, "utwallet", &signature).unwrap());
+        assert!(!verify(Network::Bitcoin, address, "utwallet!", &signature).unwrap());
+        assert!(!verify(
+            Network::Bitcoin,
+            "bc1qnjg0jd8228aq7egyzacy8cys3knf9xvrerkf9g",
+            "utwallet",
+            &signature
+        )
+        .unwrap());
+        assert!(verify(Network::Bitcoin, address, "utwallet", "garbage").is_err());
+    }
+
+    #[test]
+    fn test_foreign_address() {
+        let res = sign(
+            &mnemonic(),
+            Network::Bitcoin,
+            "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa",
+            "utwallet",
+        );
+        assert!(res.is_err());
+    }
+}
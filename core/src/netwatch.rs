@@ -0,0 +1,162 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Notices when the device switches between WiFi, mobile data and offline, by looking at the
+//! interface that carries the default route. Mobile data is treated as metered.
+
+use serde::{Deserialize, Serialize};
+use std::{fs, str::FromStr, sync::Mutex};
+
+const ROUTE_FILE: &str = "/proc/net/route";
+
+/// The interface names of the mobile data connections on the supported devices
+const MOBILE_PREFIXES: &[&str] = &["rmnet", "ccmni", "wwan", "ppp", "usb"];
+
+/// what the last check found
+static LAST: Mutex<Option<Connection>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Connection {
+    Offline,
+    Wifi,
+    Mobile,
+    /// ethernet, vpn or anything else we don't know
+    Other,
+}
+
+impl Connection {
+    pub fn name(self) -> &'static str {
+        match self {
+            Connection::Offline => "offline",
+            Connection::Wifi => "wifi",
+            Connection::Mobile => "mobile",
+            Connection::Other => "other",
+        }
+    }
+}
+
+/// When to save data by syncing less often and deferring the optional requests
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LowData {
+    #[default]
+    Off,
+    /// while on mobile data
+    Mobile,
+    Always,
+}
+
+impl LowData {
+    pub fn name(self) -> &'static str {
+        match self {
+            LowData::Off => "off",
+            LowData::Mobile => "mobile",
+            LowData::Always => "always",
+        }
+    }
+
+    /// Whether to save data on the connection
+    pub fn applies(self, connection: Connection) -> bool {
+        match self {
+            LowData::Off => false,
+            LowData::Mobile => connection == Connection::Mobile,
+            LowData::Always => true,
+        }
+    }
+}
+
+impl FromStr for LowData {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(Self::Off),
+            "mobile" => Ok(Self::Mobile),
+            "always" => Ok(Self::Always),
+            _ => Err(format!("Unknown low data mode {}", s)),
+        }
+    }
+}
+
+fn interface_kind(name: &str) -> Connection {
+    if name.starts_with("wl") {
+        Connection::Wifi
+    } else if MOBILE_PREFIXES.iter().any(|p| name.starts_with(p)) {
+        Connection::Mobile
+    } else {
+        Connection::Other
+    }
+}
+
+/// The interface of the default route, from a routing table in the format of /proc/net/route
+fn default_interface(routes: &str) -> Option<&str> {
+    routes.lines().skip(1).find_map(|line| {
+        let mut columns = line.split_whitespace();
+        let interface = columns.next()?;
+        (columns.next()? == "00000000").then_some(interface)
+    })
+}
+
+/// How the device is connected at the moment
+pub fn current() -> Connection {
+    fs::read_to_string(ROUTE_FILE)
+        .ok()
+        .as_deref()
+        .and_then(default_interface)
+        .map(interface_kind)
+        .unwrap_or(Connection::Offline)
+}
+
+/// The new connection if it changed since the last call. The first call only records it.
+pub fn changed() -> Option<Connection> {
+    let now = current();
+    let mut last = LAST.lock().ok()?;
+    let previous = last.replace(now);
+    previous.filter(|p| *p != now).map(|_| now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_interface() {
+        let routes = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+            wlan0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+            wlan0\t00000000\t0101A8C0\t0003\t0\t0\t600\t00000000\n";
+        assert_eq!(default_interface(routes), Some("wlan0"));
+        assert_eq!(default_interface("Iface\tDestination\n"), None);
+    }
+
+    #[test]
+    fn test_low_data() {
+        assert!(!LowData::Off.applies(Connection::Mobile));
+        assert!(LowData::Mobile.applies(Connection::Mobile));
+        assert!(!LowData::Mobile.applies(Connection::Wifi));
+        assert!(LowData::Always.applies(Connection::Wifi));
+        assert_eq!("mobile".parse::<LowData>(), Ok(LowData::Mobile));
+        assert!("sometimes".parse::<LowData>().is_err());
+    }
+
+    #[test]
+    fn test_interface_kind() {
+        assert_eq!(interface_kind("wlan0"), Connection::Wifi);
+        assert_eq!(interface_kind("wlp2s0"), Connection::Wifi);
+        assert_eq!(interface_kind("rmnet_data0"), Connection::Mobile);
+        assert_eq!(interface_kind("ccmni1"), Connection::Mobile);
+        assert_eq!(interface_kind("eth0"), Connection::Other);
+    }
+}
@@ -0,0 +1,105 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Desktop notifications about the events the daemon handles while the GUI is closed. They
+//! go through org.freedesktop.Notifications, which Lomiri shows like the ones of the apps.
+
+use crate::i18n::{tr, trf};
+use crate::settings::Settings;
+use crate::wallet::WalletEvent;
+
+use std::collections::HashMap;
+use zbus::{
+    blocking::{Connection, Proxy},
+    zvariant::Value,
+};
+
+const BUS_NAME: &str = "org.freedesktop.Notifications";
+const OBJECT_PATH: &str = "/org/freedesktop/Notifications";
+const INTERFACE: &str = "org.freedesktop.Notifications";
+const APP_NAME: &str = "utlnwallet";
+const ICON: &str = "utlnwallet.ulrichard";
+
+/// The headline of the notification for an event
+fn summary(event: &WalletEvent) -> String {
+    match event {
+        WalletEvent::PaymentReceived { .. } => tr("Payment received"),
+        WalletEvent::PaymentSuccessful { .. } => tr("Payment sent"),
+        WalletEvent::PaymentFailed { .. } => tr("Payment failed"),
+        WalletEvent::ChannelPending { .. } => tr("Channel pending"),
+        WalletEvent::ChannelReady { .. } => tr("Channel ready"),
+        WalletEvent::ChannelClosed { .. } => tr("Channel closed"),
+    }
+}
+
+/// Show a notification, and return its id
+pub fn post(summary: &str, body: &str) -> Result<u32, String> {
+    let conn = Connection::session().map_err(|e| format!("Failed to connect to D-Bus: {}", e))?;
+    let hints: HashMap<&str, Value> = HashMap::new();
+    Proxy::new(&conn, BUS_NAME, OBJECT_PATH, INTERFACE)
+        .and_then(|proxy| {
+            proxy.call(
+                "Notify",
+                &(
+                    APP_NAME,
+                    0u32,
+                    ICON,
+                    summary,
+                    body,
+                    Vec::<&str>::new(),
+                    hints,
+                    -1i32,
+                ),
+            )
+        })
+        .map_err(|e| format!("Failed to post a notification: {}", e))
+}
+
+/// Notify about the event, unless the user turned the notifications off
+pub fn event(event: &WalletEvent) {
+    let enabled = Settings::load().map(|s| s.notifications).unwrap_or(true);
+    if !enabled {
+        return;
+    }
+    if let Err(e) = post(&summary(event), &event.describe()) {
+        log_error!("{}", e);
+    }
+}
+
+/// Notify that the daemon stopped receiving, e.g. because the node failed
+pub fn stopped(reason: &str) {
+    if let Err(e) = post(
+        &tr("Not receiving payments"),
+        &trf("The background node stopped: {}", &[&reason]),
+    ) {
+        log_error!("{}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let event = WalletEvent::PaymentReceived {
+            payment_hash: "00".to_string(),
+            amount_msat: 21_000,
+        };
+        assert_eq!(summary(&event), "Payment received");
+        assert_eq!(event.describe(), "received 21 sats");
+    }
+}
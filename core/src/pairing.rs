@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::profiles;
+use crate::qr;
+use crate::recovery;
+use crate::secrets;
+use crate::storage::data_file;
+use crate::wallet::BdkWallet;
+use crate::watchonly;
+
+use serde::{Deserialize, Serialize};
+
+pub const PAIRING_TOKEN: &str = "pairing_token";
+
+/// What a desktop companion needs to show the balances and the history, but not to spend
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Pairing {
+    #[serde(rename = "type")]
+    pub typ: String,
+    pub version: u32,
+    pub network: String,
+    /// the name of the profile
+    pub wallet: String,
+    /// the public receive and change descriptors
+    pub descriptors: Vec<String>,
+    pub node_id: Option<String>,
+    /// grants read-only access to the REST API
+    pub token: String,
+}
+
+/// Whether a companion presented the current token, none is current once it was revoked
+pub fn verify_token(presented: &str) -> bool {
+    match secrets::get(PAIRING_TOKEN) {
+        Ok(Some(token)) => secrets::same_token(presented, &token),
+        _ => false,
+    }
+}
+
+/// Forget the token, so that paired companions lose access
+pub fn revoke() -> Result<(), String> {
+    secrets::set(PAIRING_TOKEN, "")
+}
+
+pub fn pairing() -> Result<Pairing, String> {
+    let (descriptors, node_id) = match watchonly::load()? {
+        Some(w) => (
+            [Some(w.descriptor), w.change_descriptor]
+                .into_iter()
+                .flatten()
+                .collect(),
+            None,
+        ),
+        None => {
+            let (receive, change) =
+                recovery::descriptors(&BdkWallet::mnemonic()?, profiles::network())?;
+            (vec![receive, change], Some(BdkWallet::node_id()?))
+        }
+    };
+    Ok(payload(
+        descriptors,
+        node_id,
+        secrets::token(PAIRING_TOKEN)?,
+    ))
+}
+
+fn payload(descriptors: Vec<String>, node_id: Option<String>, token: String) -> Pairing {
+    Pairing {
+        typ: "utwallet-pairing".to_string(),
+        version: 1,
+        network: profiles::network().to_string(),
+        wallet: profiles::active(),
+        descriptors,
+        node_id,
+        token,
+    }
+}
+
+/// Write the pairing payload as json, and return it as a QR code in a data url
+pub fn export() -> Result<String, String> {
+    let json = serde_json::to_string(&pairing()?)
+        .map_err(|e| format!("Failed to serialize the pairing: {}", e))?;
+    std::fs::write(data_file("pairing.json"), &json)
+        .map_err(|e| format!("Failed to write the pairing: {}", e))?;
+    qr::data_url(&json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_payload() {
+        let pairing = payload(
+            vec!["wpkh([73c5da0a/84'/0'/0']xpub/0/*)".to_string()],
+            Some("02abc".to_string()),
+            "abc123".to_string(),
+        );
+        let json: Value = serde_json::to_value(&pairing).unwrap();
+        assert_eq!(json["type"], "utwallet-pairing");
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["network"], profiles::network().to_string());
+        assert_eq!(json["wallet"], profiles::active());
+        assert_eq!(json["node_id"], "02abc");
+        assert_eq!(json["token"], "abc123");
+        assert_eq!(serde_json::from_value::<Pairing>(json).unwrap(), pairing);
+    }
+}
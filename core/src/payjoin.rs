@@ -0,0 +1,498 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! BIP78 payjoin for BIP21 uris with a `pj=` endpoint. The original transaction is built
+//! and signed from the seed, the receiver adds an input of its own, and the proposal is
+//! checked, signed and broadcast. Whenever the negotiation or the broadcast of the proposal
+//! fails, the original transaction is broadcast instead, which is a normal payment to the
+//! address.
+
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::proxy;
+use crate::psbt;
+use crate::recovery;
+
+use bdk::{
+    bitcoin::{psbt::PartiallySignedTransaction as Psbt, Address, Network, Script, Txid},
+    database::MemoryDatabase,
+    FeeRate, SignOptions, Wallet,
+};
+use ldk_node::bip39::Mnemonic;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::Duration,
+};
+
+/// how long the receiver gets to answer
+const TIMEOUT_SECS: u64 = 60;
+/// the size of the P2WPKH input the receiver adds, which we may help to pay for
+const INPUT_VBYTES: f64 = 68.0;
+
+/// Only https or a Tor hidden service, as the original transaction is sent along
+pub fn check_endpoint(endpoint: &str) -> Result<(), String> {
+    let onion = endpoint.strip_prefix("http://").map_or(false, |rest| {
+        rest.split(['/', ':'])
+            .next()
+            .map_or(false, |host| host.ends_with(".onion"))
+    });
+    if endpoint.starts_with("https://") || onion {
+        Ok(())
+    } else {
+        Err(trf(
+            "The payjoin endpoint has to be https or onion: {}",
+            &[&endpoint],
+        ))
+    }
+}
+
+/// The endpoint with the parameters of the sender
+fn request_url(endpoint: &str, sat_per_vbyte: f64, fee_output: Option<(usize, u64)>) -> String {
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    let mut url = format!(
+        "{}{}v=1&disableoutputsubstitution=true&minfeerate={}",
+        endpoint, separator, sat_per_vbyte
+    );
+    if let Some((index, max_sats)) = fee_output {
+        url.push_str(&format!(
+            "&additionalfeeoutputindex={}&maxadditionalfeecontribution={}",
+            index, max_sats
+        ));
+    }
+    url
+}
+
+fn is_mine(wallet: &Wallet<MemoryDatabase>, script: &Script) -> Result<bool, String> {
+    wallet
+        .is_mine(script)
+        .map_err(|e| format!("Failed to look up a script: {}", e))
+}
+
+/// What the transaction costs us: our inputs minus the outputs that come back to us
+fn net_spend(wallet: &Wallet<MemoryDatabase>, psbt: &Psbt) -> Result<i64, String> {
+    let mut spend = 0i64;
+    for input in &psbt.inputs {
+        let utxo = input
+            .witness_utxo
+            .as_ref()
+            .ok_or_else(|| tr("An input of the payjoin lacks its amount"))?;
+        if is_mine(wallet, &utxo.script_pubkey)? {
+            spend += utxo.value as i64;
+        }
+    }
+    for output in &psbt.unsigned_tx.output {
+        if is_mine(wallet, &output.script_pubkey)? {
+            spend -= output.value as i64;
+        }
+    }
+    Ok(spend)
+}
+
+/// Check the proposal of the receiver against the unsigned original, and bring back the data
+/// of our inputs, which the receiver drops. The virtual size is the one of the signed original.
+fn check_proposal(
+    wallet: &Wallet<MemoryDatabase>,
+    original: &Psbt,
+    original_vsize: f64,
+    proposal: &mut Psbt,
+    max_contribution: u64,
+) -> Result<(), String> {
+    let (otx, ptx) = (&original.unsigned_tx, &proposal.unsigned_tx);
+    if otx.version != ptx.version || otx.lock_time != ptx.lock_time {
+        return Err(tr(
+            "The payjoin proposal changed the transaction version or lock time",
+        ));
+    }
+    if ptx.input.len() != proposal.inputs.len() {
+        return Err(tr("The payjoin proposal is malformed"));
+    }
+    let outpoints: HashSet<_> = ptx.input.iter().map(|txin| txin.previous_output).collect();
+    if outpoints.len() != ptx.input.len() {
+        return Err(tr("The payjoin proposal spends an input twice"));
+    }
+    let ours: HashMap<_, _> = otx
+        .input
+        .iter()
+        .zip(&original.inputs)
+        .map(|(txin, input)| (txin.previous_output, (txin.sequence, input)))
+        .collect();
+    let mut found = 0;
+    for (txin, input) in ptx.input.iter().zip(proposal.inputs.iter_mut()) {
+        match ours.get(&txin.previous_output) {
+            Some((sequence, original_input)) => {
+                if txin.sequence != *sequence {
+                    return Err(tr("The payjoin proposal changed the sequence of our input"));
+                }
+                *input = (*original_input).clone();
+                found += 1;
+            }
+            None => {
+                if input.final_script_witness.is_none() && input.final_script_sig.is_none() {
+                    return Err(tr("The payjoin receiver didn't sign its input"));
+                }
+                let utxo = input
+                    .witness_utxo
+                    .as_ref()
+                    .ok_or_else(|| tr("An input of the payjoin lacks its amount"))?;
+                if is_mine(wallet, &utxo.script_pubkey)? {
+                    return Err(tr("The payjoin receiver added an input of ours"));
+                }
+            }
+        }
+    }
+    if found != ours.len() {
+        return Err(tr("The payjoin proposal dropped one of our inputs"));
+    }
+    for output in &otx.output {
+        if !ptx
+            .output
+            .iter()
+            .any(|o| o.script_pubkey == output.script_pubkey)
+        {
+            return Err(tr("The payjoin proposal dropped one of our outputs"));
+        }
+    }
+    if net_spend(wallet, proposal)? > net_spend(wallet, original)? + max_contribution as i64 {
+        return Err(tr("The payjoin proposal costs us more than allowed"));
+    }
+    // BIP78: the receiver pays for its inputs rather than lowering the fee rate
+    let fee = |tx: &Psbt| -> Result<u64, String> {
+        psbt::fee(tx)?.ok_or_else(|| tr("An input of the payjoin lacks its amount"))
+    };
+    let added = proposal.unsigned_tx.input.len() - original.unsigned_tx.input.len();
+    let proposal_vsize = original_vsize + INPUT_VBYTES * added as f64;
+    if (fee(proposal)? as f64) / proposal_vsize < fee(original)? as f64 / original_vsize {
+        return Err(tr("The payjoin proposal lowers the fee rate"));
+    }
+    Ok(())
+}
+
+/// Send the signed original to the receiver, and return its proposal signed
+async fn negotiate(
+    wallet: &Wallet<MemoryDatabase>,
+    original: &Psbt,
+    signed: &Psbt,
+    endpoint: &str,
+    sat_per_vbyte: f64,
+) -> Result<Psbt, String> {
+    check_endpoint(endpoint)?;
+    let mut change = None;
+    for (index, output) in original.unsigned_tx.output.iter().enumerate() {
+        if is_mine(wallet, &output.script_pubkey)? {
+            change = Some(index);
+        }
+    }
+    // without change, there is nothing to pay a higher fee from
+    let max_contribution = change.map_or(0, |_| (INPUT_VBYTES * sat_per_vbyte).ceil() as u64);
+    let url = request_url(
+        endpoint,
+        sat_per_vbyte,
+        change.map(|index| (index, max_contribution)),
+    );
+    let resp = proxy::http_client()?
+        .post(&url)
+        .header("Content-Type", "text/plain")
+        .timeout(Duration::from_secs(TIMEOUT_SECS))
+        .body(signed.to_string())
+        .send()
+        .await
+        .map_err(|e| trf("The payjoin receiver can't be reached: {}", &[&e]))?;
+    if !resp.status().is_success() {
+        return Err(trf(
+            "The payjoin receiver answered with {}",
+            &[&resp.status()],
+        ));
+    }
+    let body = resp
+        .text()
+        .await
+        .map_err(|e| trf("The payjoin receiver can't be reached: {}", &[&e]))?;
+    let mut proposal = Psbt::from_str(body.trim())
+        .map_err(|e| trf("The payjoin proposal is malformed: {}", &[&e]))?;
+    let original_vsize = signed.clone().extract_tx().vsize() as f64;
+    check_proposal(
+        wallet,
+        original,
+        original_vsize,
+        &mut proposal,
+        max_contribution,
+    )?;
+    let sign_options = SignOptions {
+        trust_witness_utxo: true,
+        ..Default::default()
+    };
+    let finalized = wallet
+        .sign(&mut proposal, sign_options)
+        .map_err(|e| trf("Failed to sign the payjoin: {}", &[&e]))?;
+    if !finalized {
+        return Err(tr("Failed to sign all the inputs of the payjoin"));
+    }
+    Ok(proposal)
+}
+
+/// Pay the address with a payjoin, or with the original transaction if that fails
+pub async fn send(
+    recipient: &Address,
+    amount: u64,
+    endpoint: &str,
+    sat_per_vbyte: f64,
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<Txid, WalletError> {
+    let (wallet, blockchain) = recovery::seed_wallet(mnemonic, network).await?;
+
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(recipient.script_pubkey(), amount)
+        .fee_rate(FeeRate::from_sat_per_vb(sat_per_vbyte as f32))
+        .enable_rbf();
+    let (original, _) = builder
+        .finish()
+        .map_err(|e| WalletError::Node(trf("Failed to build the transaction: {}", &[&e])))?;
+    let mut signed = original.clone();
+    let finalized = wallet
+        .sign(&mut signed, SignOptions::default())
+        .map_err(|e| WalletError::Node(trf("Failed to sign the transaction: {}", &[&e])))?;
+    if !finalized {
+        return Err(WalletError::Node(tr("Failed to sign all the inputs")));
+    }
+
+    let tx = match negotiate(&wallet, &original, &signed, endpoint, sat_per_vbyte).await {
+        Ok(proposal) => {
+            log_info!("payjoin negotiated with {}", endpoint);
+            let tx = proposal.extract_tx();
+            match blockchain.broadcast(&tx).await {
+                Ok(()) => {
+                    log_info!("payjoin sent: {}", tx.txid());
+                    return Ok(tx.txid());
+                }
+                Err(e) => {
+                    log_error!(
+                        "Failed to broadcast the payjoin: {}, sending without payjoin",
+                        e
+                    );
+                    signed.extract_tx()
+                }
+            }
+        }
+        Err(e) => {
+            log_error!("{}, sending without payjoin", e);
+            signed.extract_tx()
+        }
+    };
+    blockchain
+        .broadcast(&tx)
+        .await
+        .map_err(|e| WalletError::Network(trf("Failed to broadcast the transaction: {}", &[&e])))?;
+    log_info!("on-chain payment sent: {}", tx.txid());
+    Ok(tx.txid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::{
+        bitcoin::{
+            absolute::LockTime, psbt::Input, OutPoint, ScriptBuf, Sequence, Transaction, TxIn,
+            TxOut, Witness,
+        },
+        wallet::AddressIndex,
+    };
+
+    const OUR_OUTPOINT: &str = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:0";
+    const THEIR_OUTPOINT: &str =
+        "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9:1";
+    /// the virtual size of the signed original, one P2WPKH input and two outputs
+    const ORIGINAL_VSIZE: f64 = 141.0;
+
+    fn wallet() -> Wallet<MemoryDatabase> {
+        let mnemonic = Mnemonic::from_str(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let (desc, change_desc) =
+            recovery::private_descriptors(&mnemonic, Network::Bitcoin).unwrap();
+        Wallet::new(
+            &desc,
+            Some(&change_desc),
+            Network::Bitcoin,
+            MemoryDatabase::default(),
+        )
+        .unwrap()
+    }
+
+    fn recipient() -> ScriptBuf {
+        Address::from_str("bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa")
+            .unwrap()
+            .assume_checked()
+            .script_pubkey()
+    }
+
+    fn txin(outpoint: &str) -> TxIn {
+        TxIn {
+            previous_output: OutPoint::from_str(outpoint).unwrap(),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }
+    }
+
+    fn psbt(inputs: Vec<(TxIn, Input)>, outputs: Vec<(ScriptBuf, u64)>) -> Psbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: inputs.iter().map(|(txin, _)| txin.clone()).collect(),
+            output: outputs
+                .into_iter()
+                .map(|(script_pubkey, value)| TxOut {
+                    value,
+                    script_pubkey,
+                })
+                .collect(),
+        };
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        psbt.inputs = inputs.into_iter().map(|(_, input)| input).collect();
+        psbt
+    }
+
+    /// Pays 50k from a 100k coin of ours with 1000 sats fee, and the receiver's proposal that
+    /// adds a 20k coin and takes 482 sats from our change for the fee of its input
+    fn example(wallet: &Wallet<MemoryDatabase>) -> (Psbt, Psbt) {
+        let ours = wallet
+            .get_address(AddressIndex::New)
+            .unwrap()
+            .script_pubkey();
+        let change = wallet
+            .get_internal_address(AddressIndex::New)
+            .unwrap()
+            .script_pubkey();
+        let our_input = Input {
+            witness_utxo: Some(TxOut {
+                value: 100_000,
+                script_pubkey: ours,
+            }),
+            ..Default::default()
+        };
+        let their_input = Input {
+            witness_utxo: Some(TxOut {
+                value: 20_000,
+                script_pubkey: Address::from_str("3M5f673Ler6iJbatJNvex7EYANRsydSQXE")
+                    .unwrap()
+                    .assume_checked()
+                    .script_pubkey(),
+            }),
+            final_script_witness: Some(Witness::from_slice(&[vec![1u8; 72], vec![2u8; 33]])),
+            ..Default::default()
+        };
+        let original = psbt(
+            vec![(txin(OUR_OUTPOINT), our_input)],
+            vec![(recipient(), 50_000), (change.clone(), 49_000)],
+        );
+        // the receiver drops the data of our input
+        let proposal = psbt(
+            vec![
+                (txin(OUR_OUTPOINT), Input::default()),
+                (txin(THEIR_OUTPOINT), their_input),
+            ],
+            vec![(recipient(), 69_990), (change, 48_518)],
+        );
+        (original, proposal)
+    }
+
+    fn checked(
+        wallet: &Wallet<MemoryDatabase>,
+        original: &Psbt,
+        proposal: &Psbt,
+        max_contribution: u64,
+    ) -> Result<Psbt, String> {
+        let mut proposal = proposal.clone();
+        check_proposal(
+            wallet,
+            original,
+            ORIGINAL_VSIZE,
+            &mut proposal,
+            max_contribution,
+        )
+        .map(|_| proposal)
+    }
+
+    #[test]
+    fn test_check_proposal() {
+        let wallet = wallet();
+        let (original, proposal) = example(&wallet);
+        let check = |proposal: &Psbt, max_contribution| {
+            checked(&wallet, &original, proposal, max_contribution)
+        };
+        let accepted = check(&proposal, 500).unwrap();
+        assert_eq!(accepted.inputs[0], original.inputs[0]);
+
+        // a dropped input of ours
+        let mut dropped = proposal.clone();
+        dropped.unsigned_tx.input.remove(0);
+        dropped.inputs.remove(0);
+        assert!(check(&dropped, 500).is_err());
+
+        // a changed sequence of our input
+        let mut sequence = proposal.clone();
+        sequence.unsigned_tx.input[0].sequence = Sequence::MAX;
+        assert!(check(&sequence, 500).is_err());
+
+        // the receiver's input isn't signed
+        let mut unsigned = proposal.clone();
+        unsigned.inputs[1].final_script_witness = None;
+        assert!(check(&unsigned, 500).is_err());
+
+        // more than we are willing to contribute
+        assert!(check(&proposal, 400).is_err());
+
+        // an input spent twice
+        let mut duplicated = proposal.clone();
+        duplicated
+            .unsigned_tx
+            .input
+            .push(duplicated.unsigned_tx.input[1].clone());
+        duplicated.inputs.push(duplicated.inputs[1].clone());
+        assert!(check(&duplicated, 500).is_err());
+
+        // the receiver's input is paid by lowering the fee rate
+        let mut cheaper = proposal.clone();
+        cheaper.unsigned_tx.output[0].value = 70_482;
+        assert!(check(&cheaper, 500).is_err());
+    }
+
+    #[test]
+    fn test_check_endpoint() {
+        assert!(check_endpoint("https://btcpay.example.com/BTC/pj").is_ok());
+        assert!(check_endpoint("http://abcdefgh.onion/pj").is_ok());
+        assert!(check_endpoint("http://abcdefgh.onion:8080/pj").is_ok());
+        assert!(check_endpoint("http://btcpay.example.com/pj").is_err());
+        assert!(check_endpoint("http://example.com/x.onion").is_err());
+    }
+
+    #[test]
+    fn test_request_url() {
+        assert_eq!(
+            request_url("https://example.com/pj", 2.0, None),
+            "https://example.com/pj?v=1&disableoutputsubstitution=true&minfeerate=2"
+        );
+        assert_eq!(
+            request_url("https://example.com/pj?id=7", 1.5, Some((1, 102))),
+            "https://example.com/pj?id=7&v=1&disableoutputsubstitution=true&minfeerate=1.5\
+             &additionalfeeoutputindex=1&maxadditionalfeecontribution=102"
+        );
+    }
+}
@@ -0,0 +1,339 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Handles for the outgoing payments, so the GUI can follow them and abort the ones that
+//! haven't left yet.
+
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+
+use std::sync::{
+    atomic::{AtomicU32, Ordering},
+    Mutex,
+};
+
+/// how many handles are remembered, the oldest finished ones are dropped first
+const KEPT_HANDLES: usize = 20;
+
+pub type Handle = u32;
+
+static NEXT_HANDLE: AtomicU32 = AtomicU32::new(1);
+static PAYMENTS: Mutex<Payments> = Mutex::new(Payments {
+    entries: Vec::new(),
+    settled: Vec::new(),
+});
+static LISTENERS: Mutex<Vec<Box<dyn Fn(Handle, Status) + Send>>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Status {
+    /// waiting for the worker or resolving the recipient, can still be cancelled
+    Pending,
+    /// the HTLCs are on their way, with the hex payment id
+    InFlight(String),
+    Succeeded,
+    Failed(String),
+    Cancelled,
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Pending => "pending",
+            Status::InFlight(_) => "in_flight",
+            Status::Succeeded => "succeeded",
+            Status::Failed(_) => "failed",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, Status::Pending | Status::InFlight(_))
+    }
+}
+
+struct Payments {
+    entries: Vec<(Handle, Status)>,
+    /// outcomes the node reported before the payment was marked as dispatched, by payment id
+    settled: Vec<(String, Result<(), String>)>,
+}
+
+impl Payments {
+    fn insert(&mut self, handle: Handle) {
+        if self.entries.len() >= KEPT_HANDLES {
+            if let Some(pos) = self.entries.iter().position(|(_, s)| s.is_finished()) {
+                self.entries.remove(pos);
+            }
+        }
+        self.entries.push((handle, Status::Pending));
+    }
+
+    fn get(&self, handle: Handle) -> Option<&Status> {
+        self.entries
+            .iter()
+            .find(|(h, _)| *h == handle)
+            .map(|(_, s)| s)
+    }
+
+    /// Change the status, and return it if it changed
+    fn set(&mut self, handle: Handle, status: Status) -> Option<Status> {
+        let entry = self.entries.iter_mut().find(|(h, _)| *h == handle)?;
+        if entry.1 == status {
+            return None;
+        }
+        entry.1 = status.clone();
+        Some(status)
+    }
+
+    /// The outcome of the job that made the payment. A lightning payment stays in flight
+    /// until the node reports the result, a cancelled one stays cancelled unless it was sent.
+    fn finish(&mut self, handle: Handle, res: &Result<String, WalletError>) -> Option<Status> {
+        let status = match (self.get(handle)?, res) {
+            (Status::InFlight(_), Ok(_)) => return None,
+            // the node already reported the outcome
+            (Status::Succeeded | Status::Failed(_), _) => return None,
+            (Status::Cancelled, Err(_)) => return None,
+            (_, Ok(_)) => Status::Succeeded,
+            (_, Err(e)) => Status::Failed(e.to_string()),
+        };
+        self.set(handle, status)
+    }
+
+    /// In flight, or settled right away if the node was quicker to report the outcome
+    fn dispatch(&mut self, handle: Handle, payment_id: &str) -> Option<Status> {
+        let status = match self.settled.iter().position(|(id, _)| id == payment_id) {
+            Some(pos) => settled_status(self.settled.remove(pos).1),
+            None => Status::InFlight(payment_id.to_string()),
+        };
+        self.set(handle, status)
+    }
+
+    /// The outcome of the payment in flight with the id. It is kept for dispatch if there is
+    /// none yet, the node can report it before the sending job got to record the id.
+    fn settle(
+        &mut self,
+        payment_id: &str,
+        outcome: Result<(), String>,
+    ) -> Option<(Handle, Status)> {
+        let handle = self
+            .entries
+            .iter()
+            .find(|(_, s)| matches!(s, Status::InFlight(id) if id == payment_id))
+            .map(|(h, _)| *h);
+        match handle {
+            Some(handle) => self
+                .set(handle, settled_status(outcome))
+                .map(|s| (handle, s)),
+            None => {
+                if self.settled.len() >= KEPT_HANDLES {
+                    self.settled.remove(0);
+                }
+                self.settled.push((payment_id.to_string(), outcome));
+                None
+            }
+        }
+    }
+
+    fn cancel(&mut self, handle: Handle) -> Result<Status, WalletError> {
+        match self.get(handle) {
+            Some(Status::Pending) => Ok(self
+                .set(handle, Status::Cancelled)
+                .unwrap_or(Status::Cancelled)),
+            // ldk-node doesn't expose abandon_payment, the retries time out by themselves
+            Some(Status::InFlight(_)) => Err(WalletError::Node(tr(
+                "The payment is on its way, it fails by itself if no route works out",
+            ))),
+            Some(_) => Err(WalletError::InvalidInput(tr(
+                "The payment is already finished",
+            ))),
+            None => Err(WalletError::InvalidInput(trf(
+                "There is no payment with the id {}",
+                &[&handle],
+            ))),
+        }
+    }
+}
+
+fn settled_status(outcome: Result<(), String>) -> Status {
+    match outcome {
+        Ok(()) => Status::Succeeded,
+        Err(reason) => Status::Failed(reason),
+    }
+}
+
+fn notify(handle: Handle, status: Status) {
+    if let Ok(listeners) = LISTENERS.lock() {
+        for listener in listeners.iter() {
+            listener(handle, status.clone());
+        }
+    }
+}
+
+/// Hand out a handle for a payment that is about to be made
+pub fn start() -> Handle {
+    let handle = NEXT_HANDLE.fetch_add(1, Ordering::SeqCst);
+    match PAYMENTS.lock() {
+        Ok(mut payments) => payments.insert(handle),
+        Err(e) => log_error!("Unable to get the mutex for the payments: {:?}", e),
+    }
+    notify(handle, Status::Pending);
+    handle
+}
+
+pub fn status(handle: Handle) -> Option<Status> {
+    PAYMENTS.lock().ok()?.get(handle).cloned()
+}
+
+/// Fail if the user aborted the payment, to be checked right before it is sent
+pub fn check(handle: Handle) -> Result<(), WalletError> {
+    match status(handle) {
+        Some(Status::Cancelled) => Err(WalletError::Cancelled(tr("The payment was cancelled"))),
+        _ => Ok(()),
+    }
+}
+
+/// The lightning payment left, the node reports the outcome later
+pub fn dispatched(handle: Handle, payment_id: &str) {
+    let changed = PAYMENTS
+        .lock()
+        .ok()
+        .and_then(|mut p| p.dispatch(handle, payment_id));
+    if let Some(status) = changed {
+        notify(handle, status);
+    }
+}
+
+pub fn finish(handle: Handle, res: &Result<String, WalletError>) {
+    let changed = PAYMENTS.lock().ok().and_then(|mut p| p.finish(handle, res));
+    if let Some(status) = changed {
+        notify(handle, status);
+    }
+}
+
+/// The node reported the outcome of a lightning payment
+pub fn settle(payment_id: &str, outcome: Result<(), String>) {
+    let changed = PAYMENTS
+        .lock()
+        .ok()
+        .and_then(|mut p| p.settle(payment_id, outcome));
+    if let Some((handle, status)) = changed {
+        notify(handle, status);
+    }
+}
+
+/// Abort a payment that didn't leave yet
+pub fn cancel(handle: Handle) -> Result<(), WalletError> {
+    let status = PAYMENTS
+        .lock()
+        .map_err(WalletError::poisoned)?
+        .cancel(handle)?;
+    notify(handle, status);
+    Ok(())
+}
+
+/// Register a listener for the status changes
+pub fn subscribe(listener: Box<dyn Fn(Handle, Status) + Send>) {
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push(listener);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payments(count: u32) -> Payments {
+        let mut payments = Payments {
+            entries: Vec::new(),
+            settled: Vec::new(),
+        };
+        for handle in 1..=count {
+            payments.insert(handle);
+        }
+        payments
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut payments = payments(2);
+        assert_eq!(payments.cancel(1), Ok(Status::Cancelled));
+        assert!(payments.cancel(1).is_err());
+        payments.set(2, Status::InFlight("ab".to_string()));
+        assert!(payments.cancel(2).is_err());
+        assert!(payments.cancel(3).is_err());
+    }
+
+    #[test]
+    fn test_finish() {
+        let mut payments = payments(3);
+        assert_eq!(
+            payments.finish(1, &Ok("txid".to_string())),
+            Some(Status::Succeeded)
+        );
+        payments.set(2, Status::InFlight("ab".to_string()));
+        assert_eq!(payments.finish(2, &Ok("ab".to_string())), None);
+        payments.cancel(3).unwrap();
+        let cancelled = Err(WalletError::Other("cancelled".to_string()));
+        assert_eq!(payments.finish(3, &cancelled), None);
+        assert_eq!(payments.get(3), Some(&Status::Cancelled));
+    }
+
+    #[test]
+    fn test_settle() {
+        let mut payments = payments(2);
+        payments.set(1, Status::InFlight("ab".to_string()));
+        payments.set(2, Status::InFlight("cd".to_string()));
+        assert_eq!(
+            payments.settle("cd", Err("no route".to_string())),
+            Some((2, Status::Failed("no route".to_string())))
+        );
+        assert_eq!(payments.settle("ef", Ok(())), None);
+        assert_eq!(payments.settle("ab", Ok(())), Some((1, Status::Succeeded)));
+    }
+
+    #[test]
+    fn test_settle_before_dispatch() {
+        let mut payments = payments(2);
+        // the node reports the outcomes before the sending jobs record the ids
+        assert_eq!(payments.settle("ab", Err("no route".to_string())), None);
+        assert_eq!(payments.settle("cd", Ok(())), None);
+        assert_eq!(
+            payments.dispatch(1, "ab"),
+            Some(Status::Failed("no route".to_string()))
+        );
+        assert_eq!(payments.finish(1, &Ok("ab".to_string())), None);
+        assert_eq!(
+            payments.get(1),
+            Some(&Status::Failed("no route".to_string()))
+        );
+        assert_eq!(payments.dispatch(2, "cd"), Some(Status::Succeeded));
+        assert!(payments.settled.is_empty());
+
+        for i in 0..=KEPT_HANDLES {
+            payments.settle(&i.to_string(), Ok(()));
+        }
+        assert_eq!(payments.settled.len(), KEPT_HANDLES);
+    }
+
+    #[test]
+    fn test_keep_pending() {
+        let mut payments = payments(KEPT_HANDLES as u32);
+        payments.set(5, Status::Succeeded);
+        payments.insert(100);
+        assert_eq!(payments.entries.len(), KEPT_HANDLES);
+        assert_eq!(payments.get(5), None);
+        assert_eq!(payments.get(1), Some(&Status::Pending));
+    }
+}
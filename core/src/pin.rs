@@ -0,0 +1,405 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::i18n::{tr, trf};
+use crate::secrets;
+use crate::settings::Settings;
+use crate::storage;
+
+use chrono::Utc;
+use ldk_node::bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use ldk_node::bitcoin::hashes::{sha256, Hash, HashEngine};
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    sync::Mutex,
+    thread,
+    time::Duration,
+};
+
+const PIN_FILE: &str = "pin.json";
+/// PBKDF2 rounds to slow down guessing, should the file leak
+const ITERATIONS: u32 = 50_000;
+/// Wrong attempts before the lockout kicks in
+const FREE_ATTEMPTS: u32 = 3;
+const MAX_LOCKOUT_SECS: i64 = 3600;
+/// How long a verified PIN authorizes protected actions
+const AUTHORIZATION_SECS: i64 = 60;
+/// How long a typed PIN counts as re-entered for a large payment
+const FRESH_PIN_SECS: i64 = 30;
+/// How often the inactivity timeout is checked
+const LOCK_CHECK_SECS: u64 = 5;
+
+/// Until when protected actions are allowed without asking for the PIN again
+static AUTHORIZED_UNTIL: Mutex<i64> = Mutex::new(0);
+
+/// When the PIN was last typed, consumed by the next large payment
+static PIN_ENTERED_AT: Mutex<i64> = Mutex::new(0);
+
+/// The wallet starts locked, and locks again after the inactivity timeout
+static LOCKED: AtomicBool = AtomicBool::new(true);
+
+static LAST_ACTIVITY: Mutex<i64> = Mutex::new(0);
+
+static LOCK_LISTENERS: Mutex<Vec<Box<dyn Fn(bool) + Send>>> = Mutex::new(Vec::new());
+
+static LOCK_TIMER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// The persisted PIN state. The PIN itself is only kept as a salted hash.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct PinStore {
+    salt: String,
+    hash: String,
+    failures: u32,
+    locked_until: i64,
+}
+
+impl PinStore {
+    fn load() -> Result<Self, String> {
+        storage::load_json(PIN_FILE)
+    }
+
+    /// Written atomically, a truncated file would lock the user out
+    fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize the PIN: {}", e))?;
+        secrets::write_private(&storage::data_file(PIN_FILE), json.as_bytes())
+    }
+
+    fn is_set(&self) -> bool {
+        !self.hash.is_empty()
+    }
+
+    fn matches(&self, pin: &str) -> bool {
+        let hash = storage::to_hex(&pbkdf2(pin, self.salt.as_bytes(), ITERATIONS));
+        // compare without an early exit
+        hash.len() == self.hash.len()
+            && hash
+                .bytes()
+                .zip(self.hash.bytes())
+                .fold(0, |acc, (a, b)| acc | (a ^ b))
+                == 0
+    }
+}
+
+/// HMAC-SHA256
+pub fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(key);
+    engine.input(data);
+    Hmac::<sha256::Hash>::from_engine(engine).to_byte_array()
+}
+
+/// PBKDF2-HMAC-SHA256 with a single output block
+pub fn pbkdf2(password: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut block = hmac(password.as_bytes(), &[salt, &1u32.to_be_bytes()].concat());
+    let mut result = block;
+    for _ in 1..iterations {
+        block = hmac(password.as_bytes(), &block);
+        result.iter_mut().zip(block).for_each(|(r, b)| *r ^= b);
+    }
+    result
+}
+
+/// How long to lock out after this many consecutive wrong attempts
+fn lockout_secs(failures: u32) -> i64 {
+    if failures < FREE_ATTEMPTS {
+        return 0;
+    }
+    let doublings = (failures - FREE_ATTEMPTS).min(7);
+    (30 << doublings).min(MAX_LOCKOUT_SECS)
+}
+
+fn validate(pin: &str) -> Result<(), String> {
+    if pin.len() < 4 || pin.len() > 12 || !pin.chars().all(|c| c.is_ascii_digit()) {
+        return Err(tr("The PIN must consist of 4 to 12 digits"));
+    }
+    Ok(())
+}
+
+/// Fails if the PIN file can't be read, which callers have to treat as set and locked
+pub fn is_set() -> Result<bool, String> {
+    PinStore::load().map(|s| s.is_set())
+}
+
+/// Whether a PIN protects the wallet. An unreadable PIN file counts as set.
+fn is_protected() -> bool {
+    is_set().unwrap_or_else(|e| {
+        log_error!("{}", e);
+        true
+    })
+}
+
+/// Check the PIN, counting failed attempts and enforcing the lockout.
+/// On success, protected actions are authorized for a short while.
+pub fn verify(pin: &str) -> Result<(), String> {
+    let mut store = PinStore::load()?;
+    if !store.is_set() {
+        return Ok(());
+    }
+    let now = Utc::now().timestamp();
+    if store.locked_until > now {
+        return Err(trf(
+            "Too many wrong attempts, try again in {} seconds",
+            &[&(store.locked_until - now)],
+        ));
+    }
+    if store.matches(pin) {
+        store.failures = 0;
+        store.locked_until = 0;
+        store.save()?;
+        *PIN_ENTERED_AT
+            .lock()
+            .map_err(|e| format!("Unable to get the mutex for the PIN: {:?}", e))? = now;
+        return authorize();
+    }
+    store.failures += 1;
+    store.locked_until = now + lockout_secs(store.failures);
+    store.save()?;
+    Err(tr("Wrong PIN"))
+}
+
+/// Accept a successful identification by the system's biometric service in place of the PIN,
+/// if the user enabled that.
+pub fn verify_biometric() -> Result<(), String> {
+    if !Settings::load()?.biometric_unlock {
+        return Err(tr("Biometric unlock is disabled"));
+    }
+    authorize()
+}
+
+/// Unlock the wallet and allow protected actions for a short while
+fn authorize() -> Result<(), String> {
+    *AUTHORIZED_UNTIL
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the PIN: {:?}", e))? =
+        Utc::now().timestamp() + AUTHORIZATION_SECS;
+    touch();
+    if LOCKED.swap(false, Ordering::SeqCst) {
+        notify(false);
+    }
+    Ok(())
+}
+
+/// Set a new PIN. If one is already set, the current one has to be provided.
+pub fn set(current: &str, new: &str) -> Result<(), String> {
+    validate(new)?;
+    if is_set()? {
+        verify(current)?;
+    }
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    let salt = storage::to_hex(&salt);
+    let store = PinStore {
+        hash: storage::to_hex(&pbkdf2(new, salt.as_bytes(), ITERATIONS)),
+        salt,
+        ..Default::default()
+    };
+    store.save()
+}
+
+/// Succeeds if no PIN is set, or if it was verified recently
+pub fn require() -> Result<(), String> {
+    if !is_set()? {
+        return Ok(());
+    }
+    if is_locked() {
+        return Err(tr("The wallet is locked"));
+    }
+    let until = *AUTHORIZED_UNTIL
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the PIN: {:?}", e))?;
+    if until < Utc::now().timestamp() {
+        return Err(tr("Please enter the PIN first"));
+    }
+    Ok(())
+}
+
+/// Whether a payment of this size has to be confirmed by typing the PIN again
+fn exceeds_threshold(sats: u64, threshold_sats: u64) -> bool {
+    threshold_sats > 0 && sats > threshold_sats
+}
+
+/// Whether sending this many sats requires entering the PIN again
+pub fn needs_fresh_pin(sats: u64) -> bool {
+    let threshold = Settings::load()
+        .map(|s| s.pin_threshold_sats)
+        .unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Settings::default().pin_threshold_sats
+        });
+    is_protected() && exceeds_threshold(sats, threshold)
+}
+
+/// Like require(), but above the threshold the PIN must have been typed just now.
+/// The fingerprint doesn't count, and every entry authorizes only one such payment.
+pub fn require_for_amount(sats: u64) -> Result<(), String> {
+    require()?;
+    if !needs_fresh_pin(sats) {
+        return Ok(());
+    }
+    let mut entered = PIN_ENTERED_AT
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the PIN: {:?}", e))?;
+    let fresh = *entered + FRESH_PIN_SECS >= Utc::now().timestamp();
+    *entered = 0;
+    if !fresh {
+        return Err(trf(
+            "Payments above {} sats require entering the PIN again",
+            &[&Settings::load()?.pin_threshold_sats],
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the PIN has to be entered before the wallet can be used
+pub fn is_locked() -> bool {
+    LOCKED.load(Ordering::SeqCst) && is_protected()
+}
+
+/// Lock the wallet and revoke a previous authorization
+pub fn lock() {
+    if let Ok(mut until) = AUTHORIZED_UNTIL.lock() {
+        *until = 0;
+    }
+    if let Ok(mut entered) = PIN_ENTERED_AT.lock() {
+        *entered = 0;
+    }
+    if !LOCKED.swap(true, Ordering::SeqCst) {
+        notify(true);
+    }
+}
+
+/// Record user activity, which postpones the auto-lock
+pub fn touch() {
+    if let Ok(mut last) = LAST_ACTIVITY.lock() {
+        *last = Utc::now().timestamp();
+    }
+}
+
+fn notify(locked: bool) {
+    if let Ok(listeners) = LOCK_LISTENERS.lock() {
+        for listener in listeners.iter() {
+            listener(locked);
+        }
+    }
+}
+
+/// Whether the inactivity timeout elapsed. A timeout of 0 disables the auto-lock.
+fn timed_out(last_activity: i64, now: i64, timeout_secs: u32) -> bool {
+    timeout_secs > 0 && now - last_activity >= timeout_secs as i64
+}
+
+/// Register a listener for the wallet getting locked or unlocked. The first subscription
+/// starts watching for inactivity in a background thread.
+pub fn subscribe(listener: Box<dyn Fn(bool) + Send>) {
+    if let Ok(mut listeners) = LOCK_LISTENERS.lock() {
+        listeners.push(listener);
+    }
+    if LOCK_TIMER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    touch();
+    thread::spawn(|| loop {
+        thread::sleep(Duration::from_secs(LOCK_CHECK_SECS));
+        if is_locked() || !is_protected() {
+            continue;
+        }
+        let timeout = Settings::load()
+            .map(|s| s.auto_lock_secs)
+            .unwrap_or_else(|e| {
+                log_error!("{}", e);
+                Settings::default().auto_lock_secs
+            });
+        let last = LAST_ACTIVITY.lock().map(|l| *l).unwrap_or(0);
+        if timed_out(last, Utc::now().timestamp(), timeout) {
+            lock();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lockout_secs() {
+        assert_eq!(lockout_secs(0), 0);
+        assert_eq!(lockout_secs(2), 0);
+        assert_eq!(lockout_secs(3), 30);
+        assert_eq!(lockout_secs(4), 60);
+        assert_eq!(lockout_secs(20), MAX_LOCKOUT_SECS);
+    }
+
+    #[test]
+    fn test_exceeds_threshold() {
+        assert!(!exceeds_threshold(1_000_000, 0));
+        assert!(!exceeds_threshold(50_000, 50_000));
+        assert!(exceeds_threshold(50_001, 50_000));
+    }
+
+    #[test]
+    fn test_unlock_and_lock() {
+        // the lock screen hides once a verified PIN unlocks the wallet
+        let states = std::sync::Arc::new(Mutex::new(Vec::new()));
+        let seen = states.clone();
+        if let Ok(mut listeners) = LOCK_LISTENERS.lock() {
+            listeners.push(Box::new(move |locked| seen.lock().unwrap().push(locked)));
+        }
+        assert!(LOCKED.load(Ordering::SeqCst));
+        authorize().unwrap();
+        assert!(!LOCKED.load(Ordering::SeqCst));
+        lock();
+        assert!(LOCKED.load(Ordering::SeqCst));
+        assert_eq!(*states.lock().unwrap(), vec![false, true]);
+    }
+
+    #[test]
+    fn test_timed_out() {
+        assert!(!timed_out(100, 200, 0));
+        assert!(!timed_out(100, 200, 300));
+        assert!(timed_out(100, 400, 300));
+    }
+
+    #[test]
+    fn test_pbkdf2() {
+        assert_eq!(
+            storage::to_hex(&pbkdf2("password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            storage::to_hex(&pbkdf2("password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        let store = PinStore {
+            hash: storage::to_hex(&pbkdf2("1234", b"salt", ITERATIONS)),
+            salt: "salt".to_string(),
+            ..Default::default()
+        };
+        assert!(store.is_set());
+        assert!(store.matches("1234"));
+        assert!(!store.matches("4321"));
+        assert!(!PinStore::default().is_set());
+        assert!(validate("12a4").is_err());
+        assert!(validate("123").is_err());
+        assert!(validate("123456").is_ok());
+    }
+}
@@ -0,0 +1,298 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The point of sale mode: the merchant types a fiat amount on a keypad, the customer pays
+//! an invoice for its value in sats, and the sale is recorded for the daily report.
+
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::rates;
+use crate::storage::{load_json, save_json};
+use crate::wallet::BdkWallet;
+
+use chrono::{Local, Utc};
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const SALES_FILE: &str = "sales.json";
+
+/// Informed when a sale was paid, with its payment hash
+static LISTENERS: Mutex<Vec<Box<dyn Fn(String) + Send>>> = Mutex::new(Vec::new());
+
+/// A sale that was charged at the point of sale
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Sale {
+    pub payment_hash: String,
+    pub invoice: String,
+    /// the amount typed on the keypad, without the tip
+    pub fiat_amount: f64,
+    pub tip_fiat: f64,
+    pub currency: String,
+    /// the price of one BTC that was used for the conversion
+    pub rate: f64,
+    /// including the tip
+    pub amount_sats: u64,
+    /// local date of the sale, formatted as YYYY-MM-DD
+    pub day: String,
+    /// unix timestamp of the creation
+    pub created: i64,
+    /// unix timestamp of the payment, 0 while it is open
+    #[serde(default)]
+    pub paid_at: i64,
+}
+
+/// The totals of the paid sales of one day
+#[derive(Debug, Default, PartialEq)]
+pub struct DailyReport {
+    pub day: String,
+    pub count: u32,
+    /// without the tips
+    pub fiat_total: f64,
+    pub tips_fiat: f64,
+    pub sats_total: u64,
+    /// empty if there were no sales, "mixed" if the currency was changed during the day
+    pub currency: String,
+}
+
+impl DailyReport {
+    /// A short summary for the GUI
+    pub fn to_text(&self) -> String {
+        trf(
+            "{}: {} sales\ntotal: {} {}\ntips: {} {}\nreceived: {} sats",
+            &[
+                &self.day,
+                &self.count,
+                &format!("{:.2}", self.fiat_total),
+                &self.currency,
+                &format!("{:.2}", self.tips_fiat),
+                &self.currency,
+                &self.sats_total,
+            ],
+        )
+    }
+}
+
+/// Apply a key of the keypad to the entered amount. The digits are entered in cents,
+/// "<" removes the last digit and "C" clears the amount.
+pub fn keypad(entered: &str, key: &str) -> String {
+    match key {
+        "C" => String::new(),
+        "<" => {
+            let mut entered = entered.to_string();
+            entered.pop();
+            entered
+        }
+        // nobody sells for more than ten millions at a point of sale
+        d if d.len() == 1 && d.chars().all(|c| c.is_ascii_digit()) && entered.len() < 9 => {
+            if entered.is_empty() && d == "0" {
+                String::new()
+            } else {
+                format!("{}{}", entered, d)
+            }
+        }
+        _ => entered.to_string(),
+    }
+}
+
+/// The fiat value of the digits entered on the keypad
+pub fn keypad_value(entered: &str) -> f64 {
+    entered.parse::<u64>().unwrap_or(0) as f64 / 100.0
+}
+
+/// The tip for an amount, rounded to cents
+pub fn tip(fiat_amount: f64, tip_percent: u32) -> f64 {
+    (fiat_amount * tip_percent as f64).round() / 100.0
+}
+
+/// Convert a fiat amount to sats at the given price of one BTC
+pub fn to_sats(fiat_amount: f64, rate: f64) -> u64 {
+    if rate <= 0.0 {
+        return 0;
+    }
+    (fiat_amount / rate * 100_000_000.0).round() as u64
+}
+
+/// Create the invoice for a sale and record it
+pub fn charge(fiat_amount: f64, tip_percent: u32) -> Result<Sale, WalletError> {
+    if fiat_amount <= 0.0 {
+        return Err(WalletError::InvalidAmount(tr(
+            "Enter the amount of the sale",
+        )));
+    }
+    let rate = match rates::latest() {
+        Some(rate) if rate.rate > 0.0 => rate,
+        _ => {
+            return Err(WalletError::Network(tr(
+                "The exchange rate is not known yet",
+            )))
+        }
+    };
+    let tip_fiat = tip(fiat_amount, tip_percent);
+    let amount_sats = to_sats(fiat_amount + tip_fiat, rate.rate);
+    let desc = trf(
+        "Sale of {} {}",
+        &[&format!("{:.2}", fiat_amount + tip_fiat), &rate.currency],
+    );
+    let invoice = BdkWallet::create_invoice(Some(amount_sats), &desc)?;
+    let payment_hash = invoice
+        .parse::<Bolt11Invoice>()
+        .map(|i| i.payment_hash().to_string())
+        .map_err(|e| WalletError::Other(trf("Failed to parse our own invoice: {}", &[&e])))?;
+
+    let sale = Sale {
+        payment_hash,
+        invoice,
+        fiat_amount,
+        tip_fiat,
+        currency: rate.currency,
+        rate: rate.rate,
+        amount_sats,
+        day: Local::now().format("%Y-%m-%d").to_string(),
+        created: Utc::now().timestamp(),
+        paid_at: 0,
+    };
+    let mut sales: Vec<Sale> = load_json(SALES_FILE)?;
+    sales.push(sale.clone());
+    save_json(SALES_FILE, &sales)?;
+    Ok(sale)
+}
+
+/// Mark the sale with this payment hash as paid, if there is one
+pub fn payment_received(payment_hash: &str) {
+    let mut sales: Vec<Sale> = match load_json(SALES_FILE) {
+        Ok(sales) => sales,
+        Err(e) => {
+            log_error!("{}", e);
+            return;
+        }
+    };
+    let sale = match sales.iter_mut().find(|s| s.payment_hash == payment_hash) {
+        Some(sale) => sale,
+        None => return,
+    };
+    sale.paid_at = Utc::now().timestamp();
+    if let Err(e) = save_json(SALES_FILE, &sales) {
+        log_error!("{}", e);
+    }
+    if let Ok(listeners) = LISTENERS.lock() {
+        for listener in listeners.iter() {
+            listener(payment_hash.to_string());
+        }
+    }
+}
+
+/// Register a listener for paid sales
+pub fn subscribe(listener: Box<dyn Fn(String) + Send>) {
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push(listener);
+    }
+}
+
+/// Sum up the paid sales of one day
+pub fn summarize(sales: &[Sale], day: &str) -> DailyReport {
+    let mut report = DailyReport {
+        day: day.to_string(),
+        ..Default::default()
+    };
+    for sale in sales.iter().filter(|s| s.day == day && s.paid_at > 0) {
+        report.count += 1;
+        report.fiat_total += sale.fiat_amount;
+        report.tips_fiat += sale.tip_fiat;
+        report.sats_total += sale.amount_sats;
+        if report.currency.is_empty() {
+            report.currency = sale.currency.clone();
+        } else if report.currency != sale.currency {
+            report.currency = "mixed".to_string();
+        }
+    }
+    report
+}
+
+/// The report of a day, formatted as YYYY-MM-DD. Today if the day is empty.
+pub fn daily_report(day: &str) -> Result<DailyReport, String> {
+    let day = if day.is_empty() {
+        Local::now().format("%Y-%m-%d").to_string()
+    } else {
+        day.to_string()
+    };
+    let sales: Vec<Sale> = load_json(SALES_FILE)?;
+    Ok(summarize(&sales, &day))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sale(day: &str, fiat: f64, tip: f64, sats: u64, paid_at: i64) -> Sale {
+        Sale {
+            fiat_amount: fiat,
+            tip_fiat: tip,
+            currency: "CHF".to_string(),
+            amount_sats: sats,
+            day: day.to_string(),
+            paid_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_keypad() {
+        let mut entered = String::new();
+        for key in ["0", "1", "2", "5", "0"] {
+            entered = keypad(&entered, key);
+        }
+        assert_eq!(entered, "1250");
+        assert_eq!(keypad_value(&entered), 12.5);
+        assert_eq!(keypad(&entered, "<"), "125");
+        assert_eq!(keypad(&entered, "x"), "1250");
+        assert_eq!(keypad(&entered, "C"), "");
+        assert_eq!(keypad_value(""), 0.0);
+        assert_eq!(keypad("123456789", "1"), "123456789");
+    }
+
+    #[test]
+    fn test_conversion() {
+        assert_eq!(tip(12.5, 10), 1.25);
+        assert_eq!(tip(9.99, 15), 1.5);
+        assert_eq!(tip(12.5, 0), 0.0);
+        assert_eq!(to_sats(50.0, 50_000.0), 100_000);
+        assert_eq!(to_sats(1.0, 0.0), 0);
+    }
+
+    #[test]
+    fn test_summarize() {
+        let sales = vec![
+            sale("2024-05-01", 10.0, 1.0, 20_000, 100),
+            sale("2024-05-01", 5.5, 0.0, 10_000, 200),
+            sale("2024-05-01", 7.0, 0.0, 14_000, 0),
+            sale("2024-05-02", 3.0, 0.0, 6_000, 300),
+        ];
+        let report = summarize(&sales, "2024-05-01");
+        assert_eq!(report.count, 2);
+        assert_eq!(report.fiat_total, 15.5);
+        assert_eq!(report.tips_fiat, 1.0);
+        assert_eq!(report.sats_total, 30_000);
+        assert_eq!(report.currency, "CHF");
+        assert_eq!(
+            summarize(&sales, "2024-04-30"),
+            DailyReport {
+                day: "2024-04-30".to_string(),
+                ..Default::default()
+            }
+        );
+    }
+}
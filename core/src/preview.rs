@@ -0,0 +1,259 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::batch;
+use crate::electrum;
+use crate::error::WalletError;
+use crate::esplora;
+use crate::i18n::{tr, trf};
+use crate::input_eval::{pick_amount, InputEval, InputNetwork};
+use crate::profiles;
+use crate::psbt;
+use crate::rates::get_json;
+use crate::wallet::BdkWallet;
+
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// The virtual size of a payment with one segwit input, and an output plus change
+const TYPICAL_TX_VSIZE: u64 = 141;
+/// The virtual size of each further P2WPKH output
+const OUTPUT_VSIZE: u64 = 31;
+/// Confirmation target for the on-chain fee estimate, in blocks
+const FEE_TARGET_BLOCKS: &str = "6";
+
+/// the on-chain fee rate in sat/vB of the last refresh
+static FEE_ESTIMATE: Mutex<Option<f64>> = Mutex::new(None);
+
+/// What a payment is going to do, for the user to confirm before it is sent
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Preview {
+    /// on-chain, batch, lightning, offer, withdraw, sweep or psbt
+    pub kind: String,
+    pub recipient: String,
+    /// the alias the recipient's node announced, if known
+    pub alias: String,
+    pub amount_sats: u64,
+    pub fee_sats: u64,
+    /// the fee is an upper bound rather than an estimate
+    pub fee_is_max: bool,
+}
+
+/// ldk caps the routing fees at 1% plus 50 sats by default
+fn max_routing_fee_sats(amount_sats: u64) -> u64 {
+    amount_sats / 100 + 50
+}
+
+fn onchain_fee_sats(sat_per_vbyte: f64) -> u64 {
+    (sat_per_vbyte * TYPICAL_TX_VSIZE as f64).ceil() as u64
+}
+
+fn batch_fee_sats(sat_per_vbyte: f64, recipients: usize) -> u64 {
+    let vsize = TYPICAL_TX_VSIZE + OUTPUT_VSIZE * recipients.saturating_sub(1) as u64;
+    (sat_per_vbyte * vsize as f64).ceil() as u64
+}
+
+fn parse_fee_estimate(json: &Value) -> Result<f64, String> {
+    json.get(FEE_TARGET_BLOCKS)
+        .and_then(|v| v.as_f64())
+        .ok_or_else(|| format!("No fee estimate for {} blocks", FEE_TARGET_BLOCKS))
+}
+
+/// Fetch the on-chain fee rate and remember it for the previews
+pub fn refresh_fee_estimate() -> Result<f64, WalletError> {
+    let sat_per_vbyte = match electrum::configured() {
+        Some(url) => electrum::fee_rate(&url).map_err(WalletError::Network)?,
+        None => {
+            let json = get_json(&format!("{}fee-estimates", esplora::current()))
+                .map_err(WalletError::Network)?;
+            parse_fee_estimate(&json)?
+        }
+    };
+    *FEE_ESTIMATE.lock().map_err(WalletError::poisoned)? = Some(sat_per_vbyte);
+    Ok(sat_per_vbyte)
+}
+
+/// The remembered fee rate, fetched if there is none yet
+fn fee_estimate() -> Result<f64, WalletError> {
+    let cached = *FEE_ESTIMATE.lock().map_err(WalletError::poisoned)?;
+    match cached {
+        Some(sat_per_vbyte) => Ok(sat_per_vbyte),
+        None => refresh_fee_estimate(),
+    }
+}
+
+fn alias(node_id: &str) -> String {
+    BdkWallet::node_alias(node_id)
+        .ok()
+        .flatten()
+        .map(|(alias, _)| alias)
+        .unwrap_or_default()
+}
+
+/// Estimate the fees of the resolved input, without sending anything
+pub fn prepare(inpeval: &InputEval) -> Result<Preview, WalletError> {
+    let satoshis = inpeval.satoshis;
+    let preview = match &inpeval.network {
+        InputNetwork::Payjoin(addr, _) => {
+            let amount_sats = satoshis.ok_or(WalletError::InvalidAmount(tr(
+                "Amount field needs to be filled!",
+            )))?;
+            Preview {
+                kind: "payjoin".to_string(),
+                recipient: addr.to_string(),
+                amount_sats,
+                fee_sats: onchain_fee_sats(fee_estimate()?),
+                ..Default::default()
+            }
+        }
+        InputNetwork::Mainnet(addr) => {
+            let amount_sats = satoshis.ok_or(WalletError::InvalidAmount(tr(
+                "Amount field needs to be filled!",
+            )))?;
+            Preview {
+                kind: "on-chain".to_string(),
+                recipient: addr.to_string(),
+                amount_sats,
+                fee_sats: onchain_fee_sats(fee_estimate()?),
+                ..Default::default()
+            }
+        }
+        InputNetwork::Batch(recipients) => Preview {
+            kind: "batch".to_string(),
+            recipient: recipients
+                .iter()
+                .map(|(addr, sats)| format!("{}: {}", addr, sats))
+                .collect::<Vec<_>>()
+                .join(", "),
+            amount_sats: batch::total(recipients).map_err(WalletError::InvalidAmount)?,
+            fee_sats: batch_fee_sats(fee_estimate()?, recipients.len()),
+            ..Default::default()
+        },
+        InputNetwork::Lightning(invoice) => {
+            let amount_sats = invoice
+                .amount_milli_satoshis()
+                .map(|msat| msat / 1_000)
+                .or(satoshis)
+                .ok_or(WalletError::InvalidAmount(tr(
+                    "No amount to pay the invoice!",
+                )))?;
+            let payee = invoice.recover_payee_pub_key().to_string();
+            Preview {
+                kind: "lightning".to_string(),
+                alias: alias(&payee),
+                recipient: payee,
+                amount_sats,
+                fee_sats: max_routing_fee_sats(amount_sats),
+                fee_is_max: true,
+            }
+        }
+        InputNetwork::SilentPayment(code) => {
+            let amount_sats = satoshis.ok_or(WalletError::InvalidAmount(tr(
+                "Amount field needs to be filled!",
+            )))?;
+            Preview {
+                kind: "silent payment".to_string(),
+                recipient: code.to_string(),
+                amount_sats,
+                fee_sats: onchain_fee_sats(fee_estimate()?),
+                ..Default::default()
+            }
+        }
+        InputNetwork::LightningOffer(offer) => {
+            let amount_sats = satoshis.unwrap_or(0);
+            let payee = offer.signing_pubkey().to_string();
+            Preview {
+                kind: "offer".to_string(),
+                alias: alias(&payee),
+                recipient: payee,
+                amount_sats,
+                fee_sats: max_routing_fee_sats(amount_sats),
+                fee_is_max: true,
+            }
+        }
+        InputNetwork::LightningRefund(refund) => {
+            let payer = refund.payer_id().to_string();
+            Preview {
+                kind: "refund".to_string(),
+                alias: alias(&payer),
+                recipient: payer,
+                amount_sats: refund.amount_msats() / 1_000,
+                ..Default::default()
+            }
+        }
+        InputNetwork::Psbt(psbt) => {
+            let network = profiles::network();
+            let outputs = psbt::outputs(psbt, network);
+            let ours = psbt::our_outputs(psbt, &BdkWallet::mnemonic()?, network)?;
+            // refused PSBTs don't get as far as the confirmation
+            let spend = psbt::check(psbt, &ours, BdkWallet::get_balance()?.0)?;
+            let fee = psbt::fee(psbt)?.unwrap_or(0);
+            Preview {
+                kind: "psbt".to_string(),
+                recipient: outputs
+                    .iter()
+                    .zip(&ours)
+                    .map(|((recipient, sats), ours)| {
+                        if *ours {
+                            trf("{}: {} (ours)", &[recipient, sats])
+                        } else {
+                            format!("{}: {}", recipient, sats)
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                // the change doesn't leave the wallet
+                amount_sats: spend - fee,
+                fee_sats: fee,
+                ..Default::default()
+            }
+        }
+        InputNetwork::LnWithdraw(url) => Preview {
+            kind: "withdraw".to_string(),
+            recipient: url.clone(),
+            amount_sats: satoshis.unwrap_or(0),
+            ..Default::default()
+        },
+        InputNetwork::LnPay(_) => return Err(pick_amount(inpeval.sendable_msat)),
+        InputNetwork::LnChannel(url) => Preview {
+            kind: "channel request".to_string(),
+            recipient: url.clone(),
+            ..Default::default()
+        },
+        InputNetwork::PrivKey(_) => Preview {
+            kind: "sweep".to_string(),
+            recipient: "private keys".to_string(),
+            ..Default::default()
+        },
+    };
+    Ok(preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fees() {
+        assert_eq!(max_routing_fee_sats(10_000), 150);
+        assert_eq!(onchain_fee_sats(10.0), 1410);
+        assert_eq!(batch_fee_sats(10.0, 1), 1410);
+        assert_eq!(batch_fee_sats(10.0, 3), 2030);
+        let json: Value = serde_json::from_str(r#"{"1": 20.5, "6": 8.1, "144": 1.0}"#).unwrap();
+        assert_eq!(parse_fee_estimate(&json).unwrap(), 8.1);
+        assert!(parse_fee_estimate(&Value::Null).is_err());
+    }
+}
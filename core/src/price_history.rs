@@ -0,0 +1,124 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history::HistoryEntry;
+use crate::rates::get_json;
+use crate::storage::{load_json, save_json};
+
+use chrono::{DateTime, NaiveDate};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Daily closing prices of one BTC, by date formatted as YYYY-MM-DD
+type DailyPrices = BTreeMap<String, f64>;
+
+fn cache_file(currency: &str) -> String {
+    format!("prices_{}.json", currency.to_lowercase())
+}
+
+/// The price of one BTC on the given days. Prices that are not in the cache are fetched
+/// from CoinGecko in one request covering the whole range, and cached.
+pub fn daily_prices(currency: &str, dates: &[NaiveDate]) -> Result<DailyPrices, String> {
+    let mut cache: DailyPrices = load_json(&cache_file(currency))?;
+    let missing = dates
+        .iter()
+        .filter(|d| !cache.contains_key(&d.to_string()))
+        .collect::<Vec<_>>();
+    if let (Some(from), Some(to)) = (missing.iter().min(), missing.iter().max()) {
+        let from = from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+        let to = to.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp();
+        let json = get_json(&format!(
+            "https://api.coingecko.com/api/v3/coins/bitcoin/market_chart/range?vs_currency={}&from={}&to={}",
+            currency.to_lowercase(),
+            from,
+            to
+        ))?;
+        cache.extend(parse_market_chart(&json)?);
+        save_json(&cache_file(currency), &cache)?;
+    }
+    Ok(dates
+        .iter()
+        .filter_map(|d| cache.get(&d.to_string()).map(|p| (d.to_string(), *p)))
+        .collect())
+}
+
+/// Set the fiat rate of the entries that have none, or one in another currency,
+/// to the price of their day
+pub fn fill_missing(entries: &mut [HistoryEntry], currency: &str) -> Result<(), String> {
+    let date = |e: &HistoryEntry| {
+        DateTime::from_timestamp(e.timestamp, 0)
+            .filter(|_| e.timestamp > 0)
+            .map(|dt| dt.date_naive())
+    };
+    let needs_rate = |e: &HistoryEntry| e.fiat_rate.is_none() || e.currency != currency;
+    let dates = entries
+        .iter()
+        .filter(|e| needs_rate(e))
+        .filter_map(date)
+        .collect::<Vec<_>>();
+    if dates.is_empty() {
+        return Ok(());
+    }
+    let prices = daily_prices(currency, &dates)?;
+    for e in entries.iter_mut().filter(|e| needs_rate(e)) {
+        if let Some(price) = date(e).and_then(|d| prices.get(&d.to_string())) {
+            e.fiat_rate = Some(*price);
+            e.currency = currency.to_string();
+        }
+    }
+    Ok(())
+}
+
+/// The last price of every day in a CoinGecko market chart
+fn parse_market_chart(json: &Value) -> Result<DailyPrices, String> {
+    let prices = json["prices"]
+        .as_array()
+        .ok_or("no prices in the market chart")?;
+    let mut daily = DailyPrices::new();
+    for point in prices {
+        let (ms, price) = match (point[0].as_i64(), point[1].as_f64()) {
+            (Some(ms), Some(price)) => (ms, price),
+            _ => return Err(format!("invalid price point {}", point)),
+        };
+        if let Some(dt) = DateTime::from_timestamp(ms / 1_000, 0) {
+            daily.insert(dt.date_naive().to_string(), price);
+        }
+    }
+    Ok(daily)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_market_chart() {
+        let json = serde_json::from_str(
+            r#"{"prices":[[1700000000000,30000.5],[1700050000000,31000.0],[1700100000000,32000.0]]}"#,
+        )
+        .unwrap();
+        let daily = parse_market_chart(&json).unwrap();
+        assert_eq!(daily.len(), 2);
+        assert_eq!(daily["2023-11-14"], 30000.5);
+        assert_eq!(daily["2023-11-15"], 32000.0);
+    }
+
+    #[test]
+    fn test_parse_invalid_market_chart() {
+        let json = serde_json::from_str(r#"{"error":"rate limited"}"#).unwrap();
+        assert!(parse_market_chart(&json).is_err());
+    }
+}
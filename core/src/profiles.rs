@@ -0,0 +1,347 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::constants::APP_NAME;
+use crate::demo;
+use crate::logging;
+use crate::pin;
+use crate::wallet::BdkWallet;
+use crate::watchonly::{WatchOnly, WATCH_ONLY_FILE};
+
+use ldk_node::bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::Path, path::PathBuf, str::FromStr, sync::Mutex};
+
+/// Lives next to the data of the default profile, and is shared by all profiles
+const PROFILES_FILE: &str = "profiles.json";
+/// The data of the other profiles is kept in subdirectories of this one
+const PROFILES_DIR: &str = "profiles";
+/// The data of the test networks is kept in subdirectories of this one
+const NETWORKS_DIR: &str = "networks";
+/// The wallet that existed before there were profiles
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The name of the active profile, once it was read
+static ACTIVE: Mutex<Option<String>> = Mutex::new(None);
+/// The network of all profiles, once it was read
+static NETWORK: Mutex<Option<Network>> = Mutex::new(None);
+
+/// The independent wallets, each with its own seed, data directory and node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profiles {
+    pub names: Vec<String>,
+    pub active: String,
+    /// let the user choose the wallet when the app starts
+    pub ask_at_startup: bool,
+    /// bitcoin, testnet, signet or regtest. Each network has its own set of wallets.
+    pub network: String,
+}
+
+impl Default for Profiles {
+    fn default() -> Self {
+        Self {
+            names: vec![DEFAULT_PROFILE.to_string()],
+            active: DEFAULT_PROFILE.to_string(),
+            ask_at_startup: false,
+            network: Network::Bitcoin.to_string(),
+        }
+    }
+}
+
+impl Profiles {
+    pub fn load() -> Result<Self, String> {
+        let file = root_dir().join(PROFILES_FILE);
+        if !file.exists() {
+            return Ok(Self::default());
+        }
+        let json = fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read the profiles {:?}: {}", file, e))?;
+        serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse the profiles {:?}: {}", file, e))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let root = root_dir();
+        fs::create_dir_all(&root).map_err(|e| format!("Failed to create directory: {}", e))?;
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize the profiles: {}", e))?;
+        fs::write(root.join(PROFILES_FILE), json)
+            .map_err(|e| format!("Failed to write the profiles: {}", e))
+    }
+}
+
+/// The top level directory of the app data. It is where Qt puts the AppDataLocation, so
+/// the command line client finds the data of the GUI. UTWALLET_DATA_DIR overrides it.
+pub fn root_dir() -> PathBuf {
+    let dir = match env::var_os("UTWALLET_DATA_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => app_data_dir(env::var("XDG_DATA_HOME").ok(), env::var("HOME").ok()),
+    };
+    // the demo wallet must never mix with a real one
+    if demo::is_active() {
+        dir.join("demo")
+    } else {
+        dir
+    }
+}
+
+fn app_data_dir(xdg_data_home: Option<String>, home: Option<String>) -> PathBuf {
+    let data_home = xdg_data_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| PathBuf::from(home.unwrap_or_default()).join(".local/share"));
+    data_home.join(APP_NAME)
+}
+
+/// Mainnet keeps using the top level directory, the test networks get their own
+fn network_root(root: &Path, network: Network) -> PathBuf {
+    if network == Network::Bitcoin {
+        root.to_path_buf()
+    } else {
+        root.join(NETWORKS_DIR).join(network.to_string())
+    }
+}
+
+/// The default profile keeps using the top level directory, so existing wallets stay where
+/// they are
+fn dir_of(root: &Path, name: &str) -> PathBuf {
+    if name == DEFAULT_PROFILE {
+        root.to_path_buf()
+    } else {
+        root.join(PROFILES_DIR).join(name)
+    }
+}
+
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || name.len() > 32
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(
+            "A wallet name consists of up to 32 letters, digits, dashes or underscores".to_string(),
+        );
+    }
+    Ok(())
+}
+
+/// The name of the active profile
+pub fn active() -> String {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    active
+        .get_or_insert_with(|| {
+            Profiles::load()
+                .unwrap_or_else(|e| {
+                    log_error!("{}", e);
+                    Profiles::default()
+                })
+                .active
+        })
+        .clone()
+}
+
+/// UTWALLET_NETWORK overrides the network chosen in the app, e.g. for developing on regtest.
+/// A typo there must not end up on mainnet, so an unknown override is an error.
+fn pick_network(env: Option<String>, stored: &str) -> Result<Network, String> {
+    match env.filter(|n| !n.is_empty()) {
+        Some(name) => Network::from_str(&name)
+            .map_err(|e| format!("Unknown network {} in UTWALLET_NETWORK: {}", name, e)),
+        None => Ok(Network::from_str(stored).unwrap_or_else(|e| {
+            log_error!("Unknown network {}: {}", stored, e);
+            Network::Bitcoin
+        })),
+    }
+}
+
+/// Refuse to start with an unknown UTWALLET_NETWORK
+pub fn check_override() -> Result<(), String> {
+    pick_network(env::var("UTWALLET_NETWORK").ok(), "bitcoin").map(|_| ())
+}
+
+/// The network the wallets run on, mainnet unless the user chose a test network
+pub fn network() -> Network {
+    // the demo wallet shows mainnet data
+    if demo::is_active() {
+        return Network::Bitcoin;
+    }
+    let mut network = NETWORK.lock().unwrap_or_else(|e| e.into_inner());
+    *network.get_or_insert_with(|| {
+        let profiles = Profiles::load().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Profiles::default()
+        });
+        // the binaries refuse to start with an unknown override, see check_override()
+        pick_network(env::var("UTWALLET_NETWORK").ok(), &profiles.network).unwrap_or_else(|e| {
+            log_error!("{}", e);
+            pick_network(None, &profiles.network).unwrap_or(Network::Bitcoin)
+        })
+    })
+}
+
+/// The directory with the seed, the ldk storage and the app's files of the active profile
+pub fn data_dir() -> PathBuf {
+    dir_of(&network_root(&root_dir(), network()), &active())
+}
+
+/// Whether a file in the top level directory belongs to all profiles rather than to the
+/// default one
+pub fn is_shared(file_name: &str) -> bool {
+    file_name == PROFILES_FILE
+        || file_name == PROFILES_DIR
+        || file_name == NETWORKS_DIR
+        || file_name == logging::LOG_DIR
+}
+
+pub fn create(name: &str) -> Result<(), String> {
+    validate_name(name)?;
+    let mut profiles = Profiles::load()?;
+    if profiles.names.iter().any(|n| n == name) {
+        return Err(format!("A wallet named {} already exists", name));
+    }
+    profiles.names.push(name.to_string());
+    profiles.save()
+}
+
+/// Create a profile that tracks the wallet of a public descriptor or extended public key
+pub fn create_watch_only(name: &str, input: &str) -> Result<(), String> {
+    let watch_only = WatchOnly::from_input(input)?;
+    create(name)?;
+    let dir = dir_of(&network_root(&root_dir(), network()), name);
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    let json = serde_json::to_string(&watch_only)
+        .map_err(|e| format!("Failed to serialize the descriptors: {}", e))?;
+    fs::write(dir.join(WATCH_ONLY_FILE), json)
+        .map_err(|e| format!("Failed to write the descriptors: {}", e))
+}
+
+/// Stop the node of the active profile and start the one of the selected profile
+pub fn select(name: &str) -> Result<(), String> {
+    let mut profiles = Profiles::load()?;
+    if !profiles.names.iter().any(|n| n == name) {
+        return Err(format!("There is no wallet named {}", name));
+    }
+    if active() == name {
+        return Ok(());
+    }
+    pin::require()?;
+    BdkWallet::with_node_stopped(|| {
+        profiles.active = name.to_string();
+        profiles.save()?;
+        *ACTIVE
+            .lock()
+            .map_err(|e| format!("Unable to get the mutex for the profiles: {:?}", e))? =
+            Some(name.to_string());
+        Ok(())
+    })?;
+    // the PIN of the other wallet has to be entered
+    pin::lock();
+    Ok(())
+}
+
+/// Stop the node and start it again on the other network, with the wallets of that network
+pub fn select_network(name: &str) -> Result<(), String> {
+    let selected =
+        Network::from_str(name).map_err(|e| format!("Unknown network {}: {}", name, e))?;
+    if network() == selected {
+        return Ok(());
+    }
+    pin::require()?;
+    BdkWallet::with_node_stopped(|| {
+        let mut profiles = Profiles::load()?;
+        profiles.network = selected.to_string();
+        profiles.save()?;
+        *NETWORK
+            .lock()
+            .map_err(|e| format!("Unable to get the mutex for the profiles: {:?}", e))? =
+            Some(selected);
+        Ok(())
+    })?;
+    // the wallets of the other network have their own PIN
+    pin::lock();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_data_dir() {
+        assert_eq!(
+            app_data_dir(None, Some("/home/phablet".to_string())),
+            PathBuf::from("/home/phablet/.local/share/utlnwallet.ulrichard")
+        );
+        assert_eq!(
+            app_data_dir(Some("/data".to_string()), Some("/home/phablet".to_string())),
+            PathBuf::from("/data/utlnwallet.ulrichard")
+        );
+        assert_eq!(
+            app_data_dir(
+                Some("relative".to_string()),
+                Some("/home/phablet".to_string())
+            ),
+            PathBuf::from("/home/phablet/.local/share/utlnwallet.ulrichard")
+        );
+    }
+
+    #[test]
+    fn test_pick_network() {
+        assert_eq!(pick_network(None, "bitcoin"), Ok(Network::Bitcoin));
+        assert_eq!(pick_network(None, "signet"), Ok(Network::Signet));
+        assert_eq!(
+            pick_network(Some("regtest".to_string()), "bitcoin"),
+            Ok(Network::Regtest)
+        );
+        assert_eq!(
+            pick_network(Some("".to_string()), "testnet"),
+            Ok(Network::Testnet)
+        );
+        assert_eq!(pick_network(None, "moonnet"), Ok(Network::Bitcoin));
+        assert!(pick_network(Some("regtset".to_string()), "signet").is_err());
+    }
+
+    #[test]
+    fn test_dir_of() {
+        let root = Path::new("/data");
+        assert_eq!(dir_of(root, DEFAULT_PROFILE), PathBuf::from("/data"));
+        assert_eq!(
+            dir_of(root, "business"),
+            PathBuf::from("/data/profiles/business")
+        );
+    }
+
+    #[test]
+    fn test_network_root() {
+        let root = Path::new("/data");
+        assert_eq!(network_root(root, Network::Bitcoin), PathBuf::from("/data"));
+        assert_eq!(
+            network_root(root, Network::Testnet),
+            PathBuf::from("/data/networks/testnet")
+        );
+        assert!(is_shared(NETWORKS_DIR));
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("business").is_ok());
+        assert!(validate_name("my_wallet-2").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("../etc").is_err());
+        assert!(validate_name("a b").is_err());
+    }
+}
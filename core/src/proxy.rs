@@ -0,0 +1,108 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::settings::Settings;
+
+use lnurl::{BlockingClient, Builder as LnUrlBuilder};
+use std::env;
+
+const SCHEMES: &[&str] = &["socks5://", "socks5h://", "http://", "https://"];
+const PROXY_VARS: &[&str] = &["ALL_PROXY", "HTTPS_PROXY", "HTTP_PROXY"];
+
+/// Accepts an empty string for no proxy, or an url like socks5h://127.0.0.1:9050 for Tor
+pub fn validate(proxy: &str) -> Result<(), String> {
+    if proxy.is_empty() || SCHEMES.iter().any(|s| proxy.starts_with(s)) {
+        Ok(())
+    } else {
+        Err(format!(
+            "The proxy has to start with one of {}",
+            SCHEMES.join(", ")
+        ))
+    }
+}
+
+/// Route the http traffic of ldk-node through the proxy. It offers no setting for that, but
+/// its esplora and gossip clients take the proxy from the environment. The environment is
+/// only safe to change before other threads run, so this is called once at startup, and a
+/// changed proxy reaches the node with the next start of the app.
+/// This covers the http traffic only. ldk-node connects to the lightning peers directly,
+/// without a way to configure a proxy, so they still see the IP address of the phone.
+pub fn apply_settings() -> Result<(), String> {
+    let proxy = Settings::load()?.proxy;
+    validate(&proxy)?;
+    for var in PROXY_VARS {
+        if proxy.is_empty() {
+            env::remove_var(var);
+        } else {
+            env::set_var(var, &proxy);
+        }
+    }
+    Ok(())
+}
+
+/// The proxy of the settings, None to connect directly
+pub fn configured() -> Result<Option<String>, String> {
+    let proxy = Settings::load()?.proxy;
+    validate(&proxy)?;
+    Ok(Some(proxy).filter(|p| !p.is_empty()))
+}
+
+/// An http client that goes through the proxy of the settings as they are now, rather than
+/// the one in the environment from the start of the app
+pub fn http_client() -> Result<reqwest::Client, String> {
+    let builder = reqwest::Client::builder().no_proxy();
+    let builder = match configured()? {
+        Some(proxy) => builder.proxy(
+            reqwest::Proxy::all(&proxy).map_err(|e| format!("Invalid proxy {}: {}", proxy, e))?,
+        ),
+        None => builder,
+    };
+    builder
+        .build()
+        .map_err(|e| format!("Failed to create the http client: {}", e))
+}
+
+/// Fails in offline mode, where the app makes no network requests at all
+pub fn check_online() -> Result<(), String> {
+    if Settings::load()?.offline {
+        return Err("Not available in offline mode".to_string());
+    }
+    Ok(())
+}
+
+/// The lnurl client with the proxy of the settings
+pub fn lnurl_client() -> Result<BlockingClient, String> {
+    check_online()?;
+    let builder = LnUrlBuilder::default();
+    let builder = match configured()? {
+        Some(proxy) => builder.proxy(&proxy),
+        None => builder,
+    };
+    builder.build_blocking().map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert!(validate("").is_ok());
+        assert!(validate("socks5h://127.0.0.1:9050").is_ok());
+        assert!(validate("http://proxy.example.com:3128").is_ok());
+        assert!(validate("127.0.0.1:9050").is_err());
+    }
+}
@@ -0,0 +1,389 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! PSBTs pasted into the send field, e.g. from coordinator software or an air-gapped setup.
+//! Once the user confirmed the amount and the fee, the wallet signs the inputs it holds the
+//! keys for and broadcasts the transaction if it is complete. Otherwise the signed PSBT is
+//! saved for the next signer.
+
+use crate::constants::{PSBT_MAX_FEE_PERCENT, PSBT_MAX_FEE_SATS};
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::recovery;
+use crate::storage::data_file;
+
+use bdk::{
+    bitcoin::{
+        bip32::ExtendedPrivKey, psbt::PartiallySignedTransaction as Psbt, secp256k1::Secp256k1,
+        Address, Network, PublicKey, ScriptBuf,
+    },
+    SignOptions,
+};
+use chrono::Utc;
+use ldk_node::bip39::Mnemonic;
+use std::{fs, str::FromStr};
+
+/// base64 of the magic bytes every PSBT starts with
+pub const PSBT_PREFIX: &str = "cHNidP8";
+
+pub fn parse(input: &str) -> Result<Psbt, String> {
+    Psbt::from_str(input.trim()).map_err(|e| trf("Invalid PSBT: {}", &[&e]))
+}
+
+/// The outputs as address and sats, the script in hex where it has no address
+pub fn outputs(psbt: &Psbt, network: Network) -> Vec<(String, u64)> {
+    psbt.unsigned_tx
+        .output
+        .iter()
+        .map(|o| {
+            let recipient = Address::from_script(&o.script_pubkey, network)
+                .map(|a| a.to_string())
+                .unwrap_or_else(|_| o.script_pubkey.to_hex_string());
+            (recipient, o.value)
+        })
+        .collect()
+}
+
+/// The sum of the amounts, an error if it overflows, as it would for a forged PSBT
+fn total(mut values: impl Iterator<Item = u64>) -> Result<u64, String> {
+    values
+        .try_fold(0u64, |sum, value| sum.checked_add(value))
+        .ok_or_else(|| tr("The amounts of the PSBT add up to more than there can be"))
+}
+
+/// The fee, if the PSBT carries the amounts of all the inputs
+pub fn fee(psbt: &Psbt) -> Result<Option<u64>, String> {
+    let mut inputs = Vec::new();
+    for (txin, input) in psbt.unsigned_tx.input.iter().zip(&psbt.inputs) {
+        let vout = txin.previous_output.vout as usize;
+        inputs.push(match (&input.witness_utxo, &input.non_witness_utxo) {
+            (Some(utxo), _) => utxo.value,
+            (None, Some(tx)) => match tx.output.get(vout) {
+                Some(output) => output.value,
+                None => return Ok(None),
+            },
+            (None, None) => return Ok(None),
+        });
+    }
+    let inputs = total(inputs.into_iter())?;
+    let outputs = total(psbt.unsigned_tx.output.iter().map(|o| o.value))?;
+    inputs
+        .checked_sub(outputs)
+        .map(Some)
+        .ok_or_else(|| tr("The outputs of the PSBT are worth more than its inputs"))
+}
+
+/// Which outputs pay back to us, going by the key origins the PSBT carries for them, as
+/// coordinators add them to the change. The output has to pay to the P2WPKH script of the
+/// key as well, anyone who knows our xpub can attach our origins to their own output.
+pub fn our_outputs(
+    psbt: &Psbt,
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<Vec<bool>, String> {
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
+        .map_err(|e| format!("Failed to derive the master key: {}", e))?;
+    let fingerprint = master.fingerprint(&secp);
+    Ok(psbt
+        .outputs
+        .iter()
+        .zip(&psbt.unsigned_tx.output)
+        .map(|(output, txout)| {
+            output
+                .bip32_derivation
+                .iter()
+                .any(|(pubkey, (origin, path))| {
+                    *origin == fingerprint
+                        && master
+                            .derive_priv(&secp, path)
+                            .map_or(false, |key| key.private_key.public_key(&secp) == *pubkey)
+                        && PublicKey::new(*pubkey)
+                            .wpubkey_hash()
+                            .map_or(false, |hash| {
+                                txout.script_pubkey == ScriptBuf::new_v0_p2wpkh(&hash)
+                            })
+                })
+        })
+        .collect())
+}
+
+/// What the transaction costs us: the outputs to others, and the fee if it is known
+pub fn spend(psbt: &Psbt, ours: &[bool]) -> Result<u64, String> {
+    let sent = total(
+        psbt.unsigned_tx
+            .output
+            .iter()
+            .zip(ours.iter().chain(std::iter::repeat(&false)))
+            .filter(|(_, ours)| !**ours)
+            .map(|(o, _)| o.value),
+    )?;
+    total([sent, fee(psbt)?.unwrap_or(0)].into_iter())
+}
+
+/// Refuse PSBTs whose fee is unknown or absurd, or which would spend more than the spendable
+/// balance, which leaves out the reserve of the node. Returns what the transaction costs us.
+pub fn check(psbt: &Psbt, ours: &[bool], spendable: u64) -> Result<u64, WalletError> {
+    let fee = fee(psbt)
+        .map_err(WalletError::InvalidInput)?
+        .ok_or(WalletError::InvalidInput(tr(
+            "The PSBT doesn't carry the amounts of its inputs, so its fee can't be checked",
+        )))?;
+    let outputs = total(psbt.unsigned_tx.output.iter().map(|o| o.value))
+        .map_err(WalletError::InvalidInput)?;
+    if fee > PSBT_MAX_FEE_SATS
+        || fee.saturating_mul(100) > outputs.saturating_mul(PSBT_MAX_FEE_PERCENT)
+    {
+        return Err(WalletError::InvalidAmount(trf(
+            "The fee of {} sats of the PSBT is too high",
+            &[&fee],
+        )));
+    }
+    let amount = spend(psbt, ours).map_err(WalletError::InvalidInput)?;
+    if amount > spendable {
+        return Err(WalletError::InvalidAmount(trf(
+            "Only {} sats can be spent, the rest is the reserve for the channels",
+            &[&spendable],
+        )));
+    }
+    Ok(amount)
+}
+
+/// Sign our inputs, then broadcast the transaction or save the PSBT for the other signers
+pub async fn sign(
+    mut psbt: Psbt,
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<String, WalletError> {
+    // the inputs are only recognized as ours once the wallet knows its coins
+    let (wallet, blockchain) = recovery::seed_wallet(mnemonic, network).await?;
+
+    let before = psbt.clone();
+    let finalized = wallet
+        .sign(&mut psbt, SignOptions::default())
+        .map_err(|e| WalletError::Node(trf("Failed to sign the PSBT: {}", &[&e])))?;
+    if finalized {
+        let tx = psbt.extract_tx();
+        blockchain.broadcast(&tx).await.map_err(|e| {
+            WalletError::Network(trf("Failed to broadcast the transaction: {}", &[&e]))
+        })?;
+        log_info!("PSBT broadcast: {}", tx.txid());
+        return Ok(tx.txid().to_string());
+    }
+    if psbt == before {
+        return Err(WalletError::InvalidInput(tr(
+            "None of the inputs of the PSBT belong to this wallet",
+        )));
+    }
+    let file = data_file(&format!(
+        "signed_{}.psbt",
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ));
+    fs::write(&file, psbt.to_string())
+        .map_err(|e| WalletError::Storage(format!("Failed to write the PSBT {:?}: {}", file, e)))?;
+    Ok(trf(
+        "signed our inputs, the PSBT for the other signers is saved to {}",
+        &[&file.to_string_lossy()],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::{absolute::LockTime, OutPoint, Sequence, Transaction, TxIn, TxOut, Witness};
+
+    fn example() -> Psbt {
+        let addr = Address::from_str("bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa")
+            .unwrap()
+            .assume_checked();
+        let tx = Transaction {
+            version: 2,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::from_str(
+                    "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16:1",
+                )
+                .unwrap(),
+                script_sig: ScriptBuf::new(),
+                sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                witness: Witness::new(),
+            }],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: addr.script_pubkey(),
+                },
+                TxOut {
+                    value: 0,
+                    script_pubkey: ScriptBuf::new_op_return(&[1u8, 2, 3]),
+                },
+            ],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    #[test]
+    fn test_our_outputs() {
+        let mnemonic = Mnemonic::from_str(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        )
+        .unwrap();
+        let secp = Secp256k1::new();
+        let master = ExtendedPrivKey::new_master(Network::Bitcoin, &mnemonic.to_seed("")).unwrap();
+        let path = bdk::bitcoin::bip32::DerivationPath::from_str("m/84'/0'/0'/1/0").unwrap();
+        let key = master.derive_priv(&secp, &path).unwrap().private_key;
+
+        let mut psbt = example();
+        assert_eq!(
+            our_outputs(&psbt, &mnemonic, Network::Bitcoin).unwrap(),
+            vec![false, false]
+        );
+        assert_eq!(spend(&psbt, &[false, false]), Ok(50_000));
+
+        // a valid origin of ours on an output that pays someone else
+        psbt.outputs[0].bip32_derivation.insert(
+            key.public_key(&secp),
+            (master.fingerprint(&secp), path.clone()),
+        );
+        assert_eq!(
+            our_outputs(&psbt, &mnemonic, Network::Bitcoin).unwrap(),
+            vec![false, false]
+        );
+
+        // the first output is our change
+        psbt.unsigned_tx.output[0].script_pubkey = ScriptBuf::new_v0_p2wpkh(
+            &PublicKey::new(key.public_key(&secp))
+                .wpubkey_hash()
+                .unwrap(),
+        );
+        let ours = our_outputs(&psbt, &mnemonic, Network::Bitcoin).unwrap();
+        assert_eq!(ours, vec![true, false]);
+        assert_eq!(spend(&psbt, &ours), Ok(0));
+
+        // a key origin claiming our fingerprint with a foreign key
+        psbt.outputs[0].bip32_derivation.clear();
+        psbt.outputs[0].bip32_derivation.insert(
+            bdk::bitcoin::secp256k1::PublicKey::from_secret_key(
+                &secp,
+                &bdk::bitcoin::secp256k1::SecretKey::from_slice(&[1; 32]).unwrap(),
+            ),
+            (master.fingerprint(&secp), path),
+        );
+        assert_eq!(
+            our_outputs(&psbt, &mnemonic, Network::Bitcoin).unwrap(),
+            vec![false, false]
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let encoded = example().to_string();
+        assert!(encoded.starts_with(PSBT_PREFIX));
+        assert_eq!(parse(&format!(" {}\n", encoded)).unwrap(), example());
+        assert!(parse("cHNidP8garbage").is_err());
+    }
+
+    #[test]
+    fn test_outputs_and_fee() {
+        let mut psbt = example();
+        let outputs = outputs(&psbt, Network::Bitcoin);
+        assert_eq!(
+            outputs[0],
+            (
+                "bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa".to_string(),
+                50_000
+            )
+        );
+        assert_eq!(outputs[1].0, "6a03010203");
+        assert_eq!(fee(&psbt), Ok(None));
+
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 50_300,
+            script_pubkey: ScriptBuf::new(),
+        });
+        assert_eq!(fee(&psbt), Ok(Some(300)));
+
+        // outputs worth more than the inputs
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = 49_000;
+        assert!(fee(&psbt).is_err());
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut psbt = example();
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 50_300,
+            script_pubkey: ScriptBuf::new(),
+        });
+        psbt.unsigned_tx.output[1].value = u64::MAX;
+        assert!(fee(&psbt).is_err());
+        assert!(spend(&psbt, &[false, false]).is_err());
+        assert!(matches!(
+            check(&psbt, &[false, false], u64::MAX),
+            Err(WalletError::InvalidInput(_))
+        ));
+
+        // inputs adding up beyond u64 can't hide a large output
+        let mut psbt = example();
+        psbt.unsigned_tx
+            .input
+            .push(psbt.unsigned_tx.input[0].clone());
+        psbt.inputs.push(Default::default());
+        for input in psbt.inputs.iter_mut() {
+            input.witness_utxo = Some(TxOut {
+                value: u64::MAX / 2 + 1,
+                script_pubkey: ScriptBuf::new(),
+            });
+        }
+        assert!(fee(&psbt).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let mut psbt = example();
+        let ours = [false, false];
+        assert!(matches!(
+            check(&psbt, &ours, 100_000),
+            Err(WalletError::InvalidInput(_))
+        ));
+
+        psbt.inputs[0].witness_utxo = Some(TxOut {
+            value: 50_300,
+            script_pubkey: ScriptBuf::new(),
+        });
+        assert_eq!(check(&psbt, &ours, 100_000).unwrap(), 50_300);
+        // the reserve of the node stays untouched
+        assert!(matches!(
+            check(&psbt, &ours, 50_299),
+            Err(WalletError::InvalidAmount(_))
+        ));
+
+        // more than a tenth of the outputs
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = 56_000;
+        assert!(matches!(
+            check(&psbt, &ours, 1_000_000),
+            Err(WalletError::InvalidAmount(_))
+        ));
+
+        // above the absolute limit, even though it's a small share
+        psbt.unsigned_tx.output[0].value = 5_000_000;
+        psbt.inputs[0].witness_utxo.as_mut().unwrap().value = 5_150_000;
+        assert!(matches!(
+            check(&psbt, &ours, 10_000_000),
+            Err(WalletError::InvalidAmount(_))
+        ));
+    }
+}
@@ -0,0 +1,89 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::profiles;
+
+use qrcode_png::{Color, QrCode, QrCodeEcc};
+use std::fs;
+
+/// Where older versions wrote the QR codes, they are removed at startup
+const STALE_FILES: &[&str] = &["receiving.png", "receipt.png", "pairing.png"];
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Render the content as a QR code in PNG format
+pub fn png(content: &str) -> Result<Vec<u8>, String> {
+    let mut qrcode = QrCode::new(content, QrCodeEcc::Medium)
+        .map_err(|e| format!("Failed to construct a QR code: {}", e))?;
+
+    qrcode.margin(2);
+    qrcode.zoom(6);
+
+    qrcode
+        .generate(Color::Grayscale(0, 255))
+        .map_err(|e| format!("Failed to generate a QR code: {}", e))
+}
+
+/// Render the content as a QR code in a data url, which QML shows without touching the disk
+pub fn data_url(content: &str) -> Result<String, String> {
+    Ok(format!("data:image/png;base64,{}", base64(&png(content)?)))
+}
+
+fn base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Delete the QR codes older versions left in the app data directory
+pub fn remove_stale() {
+    for name in STALE_FILES {
+        let file = profiles::root_dir().join(name);
+        if file.exists() {
+            if let Err(e) = fs::remove_file(&file) {
+                log_error!("Failed to remove {:?}: {}", file, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+}
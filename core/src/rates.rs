@@ -0,0 +1,482 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history;
+use crate::proxy;
+use crate::settings::Settings;
+use crate::storage::{load_json, save_json};
+use crate::ticker;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+
+const RATE_CACHE_FILE: &str = "rate.json";
+
+/// The latest rate, shared by everything that displays or converts fiat values
+static LATEST_RATE: Mutex<Option<Rate>> = Mutex::new(None);
+
+/// Informed about every refresh of the rate
+static LISTENERS: Mutex<Vec<Box<dyn Fn(Result<Rate, String>) + Send>>> = Mutex::new(Vec::new());
+
+/// An exchange rate, as fetched or as remembered from the last fetch
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rate {
+    /// the price of one BTC
+    pub rate: f64,
+    pub currency: String,
+    /// the name of the rate source
+    pub source: String,
+    /// unix timestamp of the fetch
+    pub timestamp: i64,
+    /// loaded from the cache because the sources couldn't be reached
+    #[serde(skip)]
+    pub stale: bool,
+}
+
+impl Rate {
+    /// A note for the user on how old the rate is, empty if it is fresh
+    pub fn stale_note(&self) -> String {
+        if !self.stale {
+            return "".to_string();
+        }
+        match DateTime::from_timestamp(self.timestamp, 0) {
+            Some(dt) => format!(" (stale, from {})", dt.format("%Y-%m-%d %H:%M")),
+            None => " (stale)".to_string(),
+        }
+    }
+}
+
+/// A service that quotes the price of one BTC in fiat currencies
+pub trait RateSource {
+    /// identifies the source in the settings and the GUI
+    fn name(&self) -> &'static str;
+
+    fn price(&self, currency: &str) -> Result<f64, String>;
+}
+
+pub struct CoinMarketCap {
+    pub api_key: String,
+}
+
+impl RateSource for CoinMarketCap {
+    fn name(&self) -> &'static str {
+        "coinmarketcap"
+    }
+
+    fn price(&self, currency: &str) -> Result<f64, String> {
+        let json = get_json_with(
+            &format!(
+                "https://pro-api.coinmarketcap.com/v1/cryptocurrency/quotes/latest?symbol=BTC&convert={}",
+                currency.to_uppercase()
+            ),
+            &[("X-CMC_PRO_API_KEY", &self.api_key)],
+        )?;
+        parse_coinmarketcap(&json, currency)
+    }
+}
+
+/// https://coinmarketcap.com/api/documentation/v1/#operation/getV1CryptocurrencyQuotesLatest
+fn parse_coinmarketcap(json: &Value, currency: &str) -> Result<f64, String> {
+    if let Some(msg) = json["status"]["error_message"].as_str() {
+        return Err(msg.to_string());
+    }
+    json["data"]["BTC"]["quote"][currency.to_uppercase()]["price"]
+        .as_f64()
+        .ok_or_else(|| format!("no {} price in the response", currency))
+}
+
+/// https://docs.coingecko.com/reference/simple-price
+pub struct CoinGecko;
+
+impl RateSource for CoinGecko {
+    fn name(&self) -> &'static str {
+        "coingecko"
+    }
+
+    fn price(&self, currency: &str) -> Result<f64, String> {
+        let json = get_json(&format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies={}",
+            currency.to_lowercase()
+        ))?;
+        parse_coingecko(&json, currency)
+    }
+}
+
+fn parse_coingecko(json: &Value, currency: &str) -> Result<f64, String> {
+    if let Some(msg) = json["status"]["error_message"].as_str() {
+        return Err(msg.to_string());
+    }
+    json["bitcoin"][currency.to_lowercase()]
+        .as_f64()
+        .ok_or_else(|| format!("no {} price in the response", currency))
+}
+
+/// https://docs.kraken.com/api/docs/rest-api/get-ticker-information
+/// Kraken trades BTC directly against CHF, EUR, USD, GBP, JPY, CAD and AUD.
+pub struct Kraken;
+
+impl RateSource for Kraken {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn price(&self, currency: &str) -> Result<f64, String> {
+        let json = get_json(&format!(
+            "https://api.kraken.com/0/public/Ticker?pair=XBT{}",
+            currency.to_uppercase()
+        ))?;
+        parse_kraken(&json, currency)
+    }
+}
+
+/// The price of the last trade. The result is keyed by Kraken's internal pair name,
+/// such as XXBTZUSD, so the only entry is taken.
+fn parse_kraken(json: &Value, currency: &str) -> Result<f64, String> {
+    if let Some(errors) = json["error"].as_array().filter(|e| !e.is_empty()) {
+        let msg = errors
+            .iter()
+            .filter_map(|e| e.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        return Err(if msg.contains("Unknown asset pair") {
+            format!("no BTC/{} market", currency.to_uppercase())
+        } else {
+            msg
+        });
+    }
+    json["result"]
+        .as_object()
+        .and_then(|r| r.values().next())
+        .and_then(|pair| pair["c"][0].as_str())
+        .and_then(|price| price.parse::<f64>().ok())
+        .ok_or_else(|| format!("no {} price in the response", currency))
+}
+
+/// https://mempool.space/docs/api/rest#get-price
+/// Needs no API key, but only quotes USD, EUR, GBP, CAD, CHF, AUD and JPY.
+pub struct Mempool;
+
+impl RateSource for Mempool {
+    fn name(&self) -> &'static str {
+        "mempool"
+    }
+
+    fn price(&self, currency: &str) -> Result<f64, String> {
+        let json = get_json("https://mempool.space/api/v1/prices")?;
+        parse_mempool(&json, currency)
+    }
+}
+
+fn parse_mempool(json: &Value, currency: &str) -> Result<f64, String> {
+    json[currency.to_uppercase()]
+        .as_f64()
+        .filter(|p| *p > 0.0)
+        .ok_or_else(|| format!("no {} price in the response", currency))
+}
+
+/// The names of all rate sources, in the default order
+pub const RATE_SOURCES: &[&str] = &["coinmarketcap", "kraken", "coingecko", "mempool"];
+
+/// The sources to ask, in the order of the settings. CoinMarketCap is only used with a key
+/// of the user. If none of the configured sources is usable, all keyless ones are asked.
+pub fn sources(settings: &Settings) -> Vec<Box<dyn RateSource>> {
+    let source = |name: &str| -> Option<Box<dyn RateSource>> {
+        match name {
+            "coinmarketcap" if !settings.cmc_api_key.is_empty() => Some(Box::new(CoinMarketCap {
+                api_key: settings.cmc_api_key.clone(),
+            })),
+            "kraken" => Some(Box::new(Kraken)),
+            "coingecko" => Some(Box::new(CoinGecko)),
+            "mempool" => Some(Box::new(Mempool)),
+            _ => None,
+        }
+    };
+    let sources = settings
+        .rate_sources
+        .iter()
+        .filter_map(|name| source(name))
+        .collect::<Vec<_>>();
+    if sources.is_empty() {
+        return RATE_SOURCES
+            .iter()
+            .filter_map(|name| source(name))
+            .collect();
+    }
+    sources
+}
+
+/// Ask the sources in order until one knows the price. Returns the price and the name of the
+/// source that provided it.
+pub fn fetch_rate(
+    sources: &[Box<dyn RateSource>],
+    currency: &str,
+) -> Result<(f64, &'static str), String> {
+    let mut errors = Vec::new();
+    for source in sources {
+        match source.price(currency) {
+            Ok(rate) => return Ok((rate, source.name())),
+            Err(e) => {
+                log_error!(
+                    "{} failed to provide the exchange rate: {}",
+                    source.name(),
+                    e
+                );
+                errors.push(format!("{}: {}", source.name(), e));
+            }
+        }
+    }
+    Err(format!(
+        "Failed to get exchange rate: {}",
+        errors.join(", ")
+    ))
+}
+
+/// Fetch the rate from the configured sources and cache it on disk. If no source can be
+/// reached, the cached rate is returned, marked as stale.
+pub fn current_rate(settings: &Settings) -> Result<Rate, String> {
+    let fetched = fetch_rate(&sources(settings), &settings.currency).map(|(rate, source)| Rate {
+        rate,
+        currency: settings.currency.clone(),
+        source: source.to_string(),
+        timestamp: Utc::now().timestamp(),
+        stale: false,
+    });
+    if let Ok(rate) = &fetched {
+        save_json(RATE_CACHE_FILE, &Some(rate)).unwrap_or_else(|e| log_error!("{}", e));
+        ticker::record(rate).unwrap_or_else(|e| log_error!("{}", e));
+    }
+    let cached = load_json::<Option<Rate>>(RATE_CACHE_FILE).unwrap_or_else(|e| {
+        log_error!("{}", e);
+        None
+    });
+    with_cache_fallback(fetched, cached, &settings.currency)
+}
+
+/// The rate of the last refresh, if there was one
+pub fn latest() -> Option<Rate> {
+    LATEST_RATE.lock().ok().and_then(|r| r.clone())
+}
+
+/// Fetch the rate, make it the latest one and inform the listeners
+pub fn refresh(settings: &Settings) -> Result<Rate, String> {
+    let res = current_rate(settings);
+    if let Ok(rate) = &res {
+        if let Ok(mut latest) = LATEST_RATE.lock() {
+            *latest = Some(rate.clone());
+        }
+        history::set_exchange_rate(rate.rate, &rate.currency);
+    }
+    if let Ok(listeners) = LISTENERS.lock() {
+        for listener in listeners.iter() {
+            listener(res.clone());
+        }
+    }
+    res
+}
+
+/// Register a listener for the rate refreshes, which the scheduler triggers periodically
+pub fn subscribe(listener: Box<dyn Fn(Result<Rate, String>) + Send>) {
+    if let Ok(mut listeners) = LISTENERS.lock() {
+        listeners.push(listener);
+    }
+}
+
+/// Fall back to the cached rate if fetching failed and the cache is in the right currency
+fn with_cache_fallback(
+    fetched: Result<Rate, String>,
+    cached: Option<Rate>,
+    currency: &str,
+) -> Result<Rate, String> {
+    match (fetched, cached) {
+        (Ok(rate), _) => Ok(rate),
+        (Err(_), Some(cached)) if cached.currency == currency => Ok(Rate {
+            stale: true,
+            ..cached
+        }),
+        (Err(e), _) => Err(e),
+    }
+}
+
+pub fn get_json(url: &str) -> Result<Value, String> {
+    get_json_with(url, &[])
+}
+
+/// Fetch json with additional headers, e.g. an api key
+pub fn get_json_with(url: &str, headers: &[(&str, &str)]) -> Result<Value, String> {
+    proxy::check_online()?;
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+    rt.block_on(async {
+        let mut req = proxy::http_client()?.get(url);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| format!("request failed: {}", e))?;
+        let status = resp.status();
+        if status.as_u16() == 429 {
+            return Err("rate limited, try again later".to_string());
+        }
+        match resp.json::<Value>().await {
+            // some services explain errors in the body, which the parsers report
+            Ok(json) if status.is_success() || json.is_object() => Ok(json),
+            Err(e) if status.is_success() => Err(format!("invalid response: {}", e)),
+            _ => Err(format!("request failed with status {}", status)),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(&'static str, Option<f64>);
+
+    impl RateSource for Fixed {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn price(&self, _currency: &str) -> Result<f64, String> {
+            self.1.ok_or_else(|| "offline".to_string())
+        }
+    }
+
+    #[test]
+    fn test_fallback() {
+        let sources: Vec<Box<dyn RateSource>> = vec![
+            Box::new(Fixed("a", None)),
+            Box::new(Fixed("b", Some(50_000.0))),
+        ];
+        assert_eq!(fetch_rate(&sources, "CHF"), Ok((50_000.0, "b")));
+    }
+
+    #[test]
+    fn test_all_failing() {
+        let sources: Vec<Box<dyn RateSource>> =
+            vec![Box::new(Fixed("a", None)), Box::new(Fixed("b", None))];
+        assert_eq!(
+            fetch_rate(&sources, "CHF"),
+            Err("Failed to get exchange rate: a: offline, b: offline".to_string())
+        );
+    }
+
+    #[test]
+    fn test_cache_fallback() {
+        let cached = Rate {
+            rate: 40_000.0,
+            currency: "CHF".to_string(),
+            source: "coingecko".to_string(),
+            timestamp: 1_700_000_000,
+            stale: false,
+        };
+        let fresh = Rate {
+            rate: 50_000.0,
+            ..cached.clone()
+        };
+        assert_eq!(
+            with_cache_fallback(Ok(fresh.clone()), Some(cached.clone()), "CHF"),
+            Ok(fresh)
+        );
+        let stale = with_cache_fallback(Err("offline".to_string()), Some(cached.clone()), "CHF");
+        assert_eq!(stale.as_ref().map(|r| r.stale), Ok(true));
+        assert_eq!(
+            stale.unwrap().stale_note(),
+            " (stale, from 2023-11-14 22:13)"
+        );
+        assert_eq!(
+            with_cache_fallback(Err("offline".to_string()), Some(cached), "EUR"),
+            Err("offline".to_string())
+        );
+    }
+
+    #[test]
+    fn test_sources() {
+        let names = |s: &Settings| sources(s).iter().map(|s| s.name()).collect::<Vec<_>>();
+        let mut settings = Settings::default();
+        assert_eq!(names(&settings), vec!["kraken", "coingecko", "mempool"]);
+        settings.cmc_api_key = "abc".to_string();
+        assert_eq!(
+            names(&settings),
+            vec!["coinmarketcap", "kraken", "coingecko", "mempool"]
+        );
+        settings.rate_sources = vec!["mempool".to_string(), "kraken".to_string()];
+        assert_eq!(names(&settings), vec!["mempool", "kraken"]);
+        settings.cmc_api_key = "".to_string();
+        settings.rate_sources = vec!["coinmarketcap".to_string()];
+        assert_eq!(names(&settings), vec!["kraken", "coingecko", "mempool"]);
+    }
+
+    #[test]
+    fn test_parse_coinmarketcap() {
+        let json = serde_json::from_str(
+            r#"{"status":{"error_code":0,"error_message":null},"data":{"BTC":{"symbol":"BTC","quote":{"CHF":{"price":33000.5}}}}}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_coinmarketcap(&json, "chf"), Ok(33000.5));
+        assert!(parse_coinmarketcap(&json, "EUR").is_err());
+        let json = serde_json::from_str(
+            r#"{"status":{"error_code":1001,"error_message":"This API Key is invalid."}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_coinmarketcap(&json, "CHF"),
+            Err("This API Key is invalid.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_kraken() {
+        let json = serde_json::from_str(
+            r#"{"error":[],"result":{"XBTCHF":{"a":["33001.0","1","1.000"],"c":["33000.5","0.001"]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_kraken(&json, "CHF"), Ok(33000.5));
+        let json = serde_json::from_str(r#"{"error":["EQuery:Unknown asset pair"]}"#).unwrap();
+        assert_eq!(
+            parse_kraken(&json, "sek"),
+            Err("no BTC/SEK market".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_mempool() {
+        let json =
+            serde_json::from_str(r#"{"time":1700000000,"USD":37000,"CHF":33000,"JPY":0}"#).unwrap();
+        assert_eq!(parse_mempool(&json, "chf"), Ok(33000.0));
+        assert!(parse_mempool(&json, "JPY").is_err());
+        assert!(parse_mempool(&json, "SEK").is_err());
+    }
+
+    #[test]
+    fn test_parse_coingecko() {
+        let json = serde_json::from_str(r#"{"bitcoin":{"chf":54321.5}}"#).unwrap();
+        assert_eq!(parse_coingecko(&json, "CHF"), Ok(54321.5));
+        assert!(parse_coingecko(&json, "EUR").is_err());
+        let json = serde_json::from_str(
+            r#"{"status":{"error_code":429,"error_message":"You've exceeded the Rate Limit"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_coingecko(&json, "CHF"),
+            Err("You've exceeded the Rate Limit".to_string())
+        );
+    }
+}
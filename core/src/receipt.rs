@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history::HistoryEntry;
+use crate::storage::to_hex;
+use crate::wallet::BdkWallet;
+
+use chrono::DateTime;
+use ldk_node::payment::PaymentKind;
+
+/// The preimage of a lightning payment proves that it was paid.
+/// For everything else we can only refer to the id.
+pub fn proof_of_payment(id: &str) -> Result<String, String> {
+    let payment = BdkWallet::list_payments()?
+        .into_iter()
+        .find(|p| to_hex(&p.id.0) == id);
+    let preimage = match payment.map(|p| p.kind) {
+        Some(PaymentKind::Bolt11 { preimage, .. })
+        | Some(PaymentKind::Bolt11Jit { preimage, .. })
+        | Some(PaymentKind::Bolt12Offer { preimage, .. })
+        | Some(PaymentKind::Bolt12Refund { preimage, .. })
+        | Some(PaymentKind::Spontaneous { preimage, .. }) => preimage.map(|p| to_hex(&p.0)),
+        _ => None,
+    };
+    Ok(preimage.unwrap_or_else(|| id.to_string()))
+}
+
+/// A short plain text receipt that can be shared with the other party of the payment
+pub fn text(entry: &HistoryEntry, proof: &str) -> String {
+    let mut lines = vec!["utwallet payment receipt".to_string()];
+    if let Some(dt) = DateTime::from_timestamp(entry.timestamp, 0).filter(|_| entry.timestamp > 0) {
+        lines.push(format!("date: {}", dt.format("%Y-%m-%d %H:%M UTC")));
+    }
+    lines.push(format!(
+        "{}: {} BTC",
+        if entry.direction == "in" {
+            "received"
+        } else {
+            "sent"
+        },
+        entry.amount_msat.unwrap_or(0) as f64 / 100_000_000_000.0
+    ));
+    if let (Some(msat), Some(rate)) = (entry.amount_msat, entry.fiat_rate) {
+        lines.push(format!(
+            "value: {:.2} {}",
+            msat as f64 / 100_000_000_000.0 * rate,
+            entry.currency
+        ));
+    }
+    if let Some(fee) = entry.fee_msat {
+        lines.push(format!("fee: {} sats", fee / 1_000));
+    }
+    if !entry.alias.is_empty() {
+        lines.push(format!("counterparty: {}", entry.alias));
+    } else if !entry.peer.is_empty() {
+        lines.push(format!("counterparty: {}", entry.peer));
+    }
+    lines.push(format!("type: {}", entry.kind));
+    lines.push(format!("status: {}", entry.status));
+    if proof != entry.id {
+        lines.push(format!("payment id: {}", entry.id));
+        lines.push(format!("preimage: {}", proof));
+    } else {
+        lines.push(format!("id: {}", entry.id));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receipt_lightning() {
+        let entry = HistoryEntry {
+            id: "ab01".to_string(),
+            kind: "bolt11".to_string(),
+            direction: "out".to_string(),
+            status: "succeeded".to_string(),
+            amount_msat: Some(21_000_000),
+            timestamp: 1_700_000_000,
+            fee_msat: Some(3_000),
+            fiat_rate: Some(40_000.0),
+            currency: "CHF".to_string(),
+            alias: "ACINQ".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            text(&entry, "cafe"),
+            "utwallet payment receipt\ndate: 2023-11-14 22:13 UTC\nsent: 0.00021 BTC\nvalue: 8.40 CHF\nfee: 3 sats\ncounterparty: ACINQ\ntype: bolt11\nstatus: succeeded\npayment id: ab01\npreimage: cafe"
+        );
+    }
+
+    #[test]
+    fn test_receipt_without_proof() {
+        let entry = HistoryEntry {
+            id: "cd02".to_string(),
+            kind: "onchain".to_string(),
+            direction: "in".to_string(),
+            status: "succeeded".to_string(),
+            amount_msat: Some(100_000_000),
+            ..Default::default()
+        };
+        assert_eq!(
+            text(&entry, "cd02"),
+            "utwallet payment receipt\nreceived: 0.001 BTC\ntype: onchain\nstatus: succeeded\nid: cd02"
+        );
+    }
+}
@@ -0,0 +1,205 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::error::WalletError;
+use crate::esplora;
+use crate::i18n::trf;
+use crate::profiles;
+use crate::qr;
+use crate::storage::data_file;
+use crate::wallet::BdkWallet;
+
+use bdk::{blockchain::EsploraBlockchain, database::MemoryDatabase, SyncOptions, Wallet};
+use chrono::Utc;
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey};
+use ldk_node::bitcoin::secp256k1::Secp256k1;
+use ldk_node::bitcoin::Network;
+use miniscript::{Descriptor, DescriptorPublicKey};
+use std::{fs, path::PathBuf, str::FromStr};
+
+/// ldk-node derives its on-chain wallet from the seed according to BIP84, with coin type 1
+/// on the test networks
+pub fn account_path(network: Network) -> String {
+    let coin_type = if network == Network::Bitcoin { 0 } else { 1 };
+    format!("m/84'/{}'/0'", coin_type)
+}
+
+const INSTRUCTIONS: &str = "\
+How to recover the funds of this wallet without the app
+
+1. On-chain funds: enter the seed words into any wallet that supports BIP84 native segwit
+   (bc1q...) addresses, e.g. Sparrow or Electrum. The descriptors below can be imported as
+   watch-only, to check the balance without exposing the seed.
+2. Lightning funds are held in channels with the peers listed below. The seed alone doesn't
+   restore them. If an encrypted backup of the app exists, restore it in utwallet first.
+   Otherwise ask the peers to close the channels. The funds then become spendable with tools
+   for LDK based nodes, using the same seed words.
+3. Never restore an outdated channel backup while the old phone might still be running the
+   wallet, that can cost the channel balance.";
+
+/// The BIP32 master key fingerprint, which identifies the seed without revealing anything
+pub fn fingerprint(mnemonic: &Mnemonic) -> Result<String, String> {
+    let master = ExtendedPrivKey::new_master(Network::Bitcoin, &mnemonic.to_seed(""))
+        .map_err(|e| format!("Failed to derive the master key: {}", e))?;
+    Ok(master.fingerprint(&Secp256k1::new()).to_string())
+}
+
+/// The public receive and change descriptors of the on-chain wallet, with key origin
+pub fn descriptors(mnemonic: &Mnemonic, network: Network) -> Result<(String, String), String> {
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
+        .map_err(|e| format!("Failed to derive the master key: {}", e))?;
+    let account_path = account_path(network);
+    let path = DerivationPath::from_str(&account_path)
+        .map_err(|e| format!("Invalid derivation path: {}", e))?;
+    let account = master
+        .derive_priv(&secp, &path)
+        .map_err(|e| format!("Failed to derive the account key: {}", e))?;
+    let xpub = ExtendedPubKey::from_priv(&secp, &account);
+    let origin = format!("[{}/{}]", master.fingerprint(&secp), &account_path[2..]);
+    let desc = |chain: u8| {
+        Descriptor::<DescriptorPublicKey>::from_str(&format!(
+            "wpkh({}{}/{}/*)",
+            origin, xpub, chain
+        ))
+        .map(|d| d.to_string())
+        .map_err(|e| format!("Failed to construct the descriptor: {}", e))
+    };
+    Ok((desc(0)?, desc(1)?))
+}
+
+/// The private receive and change descriptors of the on-chain wallet, for spending with bdk
+/// where the node can't build the transaction
+pub fn private_descriptors(
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<(String, String), String> {
+    let master = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
+        .map_err(|e| format!("Failed to derive the master key: {}", e))?;
+    let account_path = account_path(network);
+    let desc = |chain: u8| format!("wpkh({}/{}/{}/*)", master, &account_path[2..], chain);
+    Ok((desc(0), desc(1)))
+}
+
+/// The on-chain wallet of the seed in bdk, synced and with the chain source to broadcast
+/// with. It sees the same coins as the node, which only learns about the spend on its next
+/// sync, so the callers have to leave the node's reserve alone.
+pub async fn seed_wallet(
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<(Wallet<MemoryDatabase>, EsploraBlockchain), WalletError> {
+    let (desc, change_desc) = private_descriptors(mnemonic, network).map_err(WalletError::Other)?;
+    let wallet = Wallet::new(
+        &desc,
+        Some(&change_desc),
+        network,
+        MemoryDatabase::default(),
+    )
+    .map_err(|e| WalletError::Node(trf("Failed to construct the wallet: {}", &[&e])))?;
+    let blockchain = esplora::blockchain(&esplora::current()).map_err(WalletError::Network)?;
+    wallet
+        .sync(&blockchain, SyncOptions::default())
+        .await
+        .map_err(|e| WalletError::Network(trf("Failed to sync the wallet: {}", &[&e])))?;
+    Ok((wallet, blockchain))
+}
+
+/// Write the recovery kit as plain text and as a printable page with QR codes, into a
+/// directory of its own. The seed words are only included on request.
+pub fn export(include_seed: bool) -> Result<PathBuf, String> {
+    let mnemonic = BdkWallet::mnemonic()?;
+    let (receive, change) = descriptors(&mnemonic, profiles::network())?;
+    let node_id = BdkWallet::node_id()?;
+    let channels = BdkWallet::channel_peers()?;
+
+    let mut text = vec![
+        "utwallet recovery kit".to_string(),
+        format!("created: {}", Utc::now().format("%Y-%m-%d %H:%M UTC")),
+        "".to_string(),
+        INSTRUCTIONS.to_string(),
+        "".to_string(),
+    ];
+    let mut codes = vec![];
+    if include_seed {
+        text.push(format!("seed words: {}", mnemonic));
+        codes.push(("seed", mnemonic.to_string()));
+    }
+    text.push(format!("wallet fingerprint: {}", fingerprint(&mnemonic)?));
+    text.push(format!("receive descriptor: {}", receive));
+    text.push(format!("change descriptor: {}", change));
+    text.push(format!("lightning node id: {}", node_id));
+    codes.push(("receive", receive));
+    codes.push(("change", change));
+    codes.push(("node", node_id));
+    if channels.is_empty() {
+        text.push("no open channels".to_string());
+    }
+    for (peer, address, capacity, funding) in channels {
+        text.push(format!(
+            "channel with {}@{}: {} sats, funded by {}",
+            peer, address, capacity, funding
+        ));
+    }
+    let text = text.join("\n");
+
+    let dir = data_file(&format!(
+        "recovery_kit_{}",
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ));
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    fs::write(dir.join("recovery_kit.txt"), &text)
+        .map_err(|e| format!("Failed to write the recovery kit: {}", e))?;
+
+    let mut html = format!(
+        "<html><head><meta charset=\"utf-8\"><title>utwallet recovery kit</title></head><body><pre>{}</pre>",
+        text.replace('&', "&amp;").replace('<', "&lt;")
+    );
+    for (name, content) in codes {
+        let file = format!("qr_{}.png", name);
+        fs::write(dir.join(&file), qr::png(&content)?)
+            .map_err(|e| format!("Failed to write the QR code: {}", e))?;
+        html += &format!(
+            "<div style=\"display:inline-block;margin:1em\"><img src=\"{}\"><br>{}</div>",
+            file, name
+        );
+    }
+    html += "</body></html>";
+    fs::write(dir.join("recovery_kit.html"), html)
+        .map_err(|e| format!("Failed to write the recovery kit: {}", e))?;
+
+    Ok(dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptors() {
+        let mnemonic = Mnemonic::parse("abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about").unwrap();
+        let (receive, change) = descriptors(&mnemonic, Network::Bitcoin).unwrap();
+        let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+        assert!(receive.starts_with("wpkh([73c5da0a/84"));
+        assert!(receive.contains(&format!("{}/0/*)", xpub)));
+        assert!(change.contains(&format!("{}/1/*)", xpub)));
+        assert_eq!(fingerprint(&mnemonic).unwrap(), "73c5da0a");
+
+        let (receive, _) = descriptors(&mnemonic, Network::Testnet).unwrap();
+        assert!(receive.starts_with("wpkh([73c5da0a/84"));
+        assert!(receive.contains("]tpub"));
+    }
+}
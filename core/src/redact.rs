@@ -0,0 +1,177 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! What gets logged ends up in the system journal and in the log file, so secrets are
+//! dropped and invoices, addresses and hashes are shortened to a recognizable prefix.
+
+/// Log to stdout and the log file, with sensitive data redacted
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        $crate::logging::write(
+            $crate::logging::Level::Info,
+            &$crate::redact::redact(&format!($($arg)*)),
+        )
+    };
+}
+
+/// Log to stderr and the log file, with sensitive data redacted
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => {
+        $crate::logging::write(
+            $crate::logging::Level::Error,
+            &$crate::redact::redact(&format!($($arg)*)),
+        )
+    };
+}
+
+/// Only logged in verbose mode, with sensitive data redacted
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        $crate::logging::write(
+            $crate::logging::Level::Debug,
+            &$crate::redact::redact(&format!($($arg)*)),
+        )
+    };
+}
+
+const INVOICE_PREFIXES: [&str; 6] = ["lnbc", "lntb", "lnbcrt", "lno", "lni", "lnurl"];
+const ADDRESS_PREFIXES: [&str; 3] = ["bc1", "tb1", "bcrt1"];
+const KEY_PREFIXES: [&str; 6] = ["xprv", "tprv", "yprv", "zprv", "uprv", "vprv"];
+/// Debug output of types that must never show up in a log
+const SECRET_TYPES: [&str; 2] = ["PaymentPreimage(", "PaymentSecret("];
+
+enum Sensitivity {
+    Public,
+    /// enough to recognize it, not enough to use it
+    Shorten,
+    Secret,
+}
+
+fn classify(token: &str) -> Sensitivity {
+    let lower = token.to_lowercase();
+    let is_base58 = token
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() && !"0OIl".contains(c));
+    if KEY_PREFIXES.iter().any(|p| token.starts_with(p)) {
+        Sensitivity::Secret
+    } else if is_base58 && (51..=52).contains(&token.len()) && "59KLc".contains(&token[..1]) {
+        // WIF private key
+        Sensitivity::Secret
+    } else if token.len() >= 20 && INVOICE_PREFIXES.iter().any(|p| lower.starts_with(p)) {
+        Sensitivity::Shorten
+    } else if token.len() >= 26 && ADDRESS_PREFIXES.iter().any(|p| lower.starts_with(p)) {
+        Sensitivity::Shorten
+    } else if is_base58
+        && (26..=35).contains(&token.len())
+        && "123mn".contains(&token[..1])
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+    {
+        Sensitivity::Shorten
+    } else if token.len() >= 64 && token.chars().all(|c| c.is_ascii_hexdigit()) {
+        Sensitivity::Shorten
+    } else {
+        Sensitivity::Public
+    }
+}
+
+fn redact_token(token: &str) -> String {
+    match classify(token) {
+        Sensitivity::Public => token.to_string(),
+        Sensitivity::Shorten => format!("{}…{}", &token[..8], &token[token.len() - 4..]),
+        Sensitivity::Secret => "[redacted]".to_string(),
+    }
+}
+
+/// Drop the contents of secret types, e.g. PaymentPreimage([1, 2, ..])
+fn drop_secret_types(text: &str) -> String {
+    let mut text = text.to_string();
+    for ty in SECRET_TYPES {
+        let mut from = 0;
+        while let Some(pos) = text[from..].find(ty) {
+            let start = from + pos + ty.len();
+            let mut depth = 1;
+            let end = text[start..]
+                .char_indices()
+                .find_map(|(i, c)| {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        _ => {}
+                    }
+                    (depth == 0).then_some(start + i)
+                })
+                .unwrap_or(text.len());
+            text.replace_range(start..end, "[redacted]");
+            from = start;
+        }
+    }
+    text
+}
+
+/// Redact secrets and shorten invoices, addresses and hashes
+pub fn redact(text: &str) -> String {
+    let text = drop_secret_types(text);
+    let mut result = String::with_capacity(text.len());
+    let mut token = String::new();
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            token.push(c);
+        } else {
+            result.push_str(&redact_token(&token));
+            token.clear();
+            result.push(c);
+        }
+    }
+    result.push_str(&redact_token(&token));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("getting balances"), "getting balances");
+        assert_eq!(
+            redact("payment 21000 is above 20000"),
+            "payment 21000 is above 20000"
+        );
+        assert_eq!(
+            redact("on-chain payment sent: 7f3b2c9c8e4f5d6a1b2c3d4e5f6a7b8c9d0e1f2a3b4c5d6e7f8a9b0c1d2e3f40"),
+            "on-chain payment sent: 7f3b2c9c…3f40"
+        );
+        assert_eq!(
+            redact("sweeping 1000 to bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"),
+            "sweeping 1000 to bc1qar0s…5mdq"
+        );
+        assert_eq!(
+            redact("Unable to pay the invoice lnbc10u1pjqz8xmpp5abcdefghijklmn"),
+            "Unable to pay the invoice lnbc10u1…klmn"
+        );
+        assert_eq!(
+            redact("key tprv8ZgxMBicQKsPd9TeAdPADNnSyH9SSUUbTVeFszDE23Ki6TBB5nCefAdHkK8Fm3qMQR6sHwA56zqRmKmxnHk37JkiFzvncDqoKmPWubu7hDF"),
+            "key [redacted]"
+        );
+        assert_eq!(
+            redact("PaymentClaimable { payment_preimage: Some(PaymentPreimage([1, 2, 3])), amount: 5 }"),
+            "PaymentClaimable { payment_preimage: Some(PaymentPreimage([redacted])), amount: 5 }"
+        );
+    }
+}
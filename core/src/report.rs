@@ -0,0 +1,227 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history::HistoryEntry;
+
+use chrono::DateTime;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+/// Totals over a period, with the profit and loss computed with the average cost method
+#[derive(Debug, Default, PartialEq)]
+pub struct Report {
+    pub received_msat: u64,
+    pub spent_msat: u64,
+    pub fees_msat: u64,
+    /// fiat value of what was received, at the time it was received
+    pub received_fiat: f64,
+    /// fiat value of what was spent, at the time it was spent
+    pub spent_fiat: f64,
+    /// gain or loss of what was spent in the period, compared to its cost basis
+    pub realized_pnl: f64,
+    /// gain or loss of what is still held at the end of the period, valued at the current rate
+    pub unrealized_pnl: f64,
+}
+
+impl Report {
+    /// Summarize the succeeded payments with timestamps between from and to (inclusive).
+    /// Payments before the period still count towards the cost basis.
+    pub fn summarize(entries: &[HistoryEntry], from: i64, to: i64, current_rate: f64) -> Self {
+        let mut payments = entries
+            .iter()
+            .filter(|e| e.status == "succeeded" && e.timestamp <= to)
+            .filter(|e| !e.is_channel_event())
+            .collect::<Vec<_>>();
+        payments.sort_by_key(|e| e.timestamp);
+
+        let mut report = Self::default();
+        let mut holdings_btc = 0.0;
+        let mut cost_basis = 0.0;
+        for e in payments {
+            let btc = e.amount_msat.unwrap_or(0) as f64 / 100_000_000_000.0;
+            let rate = e.fiat_rate.unwrap_or(current_rate);
+            let in_period = e.timestamp >= from;
+            if e.direction == "in" {
+                holdings_btc += btc;
+                cost_basis += btc * rate;
+                if in_period {
+                    report.received_msat += e.amount_msat.unwrap_or(0);
+                    report.received_fiat += btc * rate;
+                }
+            } else {
+                let fee_btc = e.fee_msat.unwrap_or(0) as f64 / 100_000_000_000.0;
+                let avg_cost = if holdings_btc > 0.0 {
+                    cost_basis / holdings_btc
+                } else {
+                    rate
+                };
+                let disposed = (btc + fee_btc).min(holdings_btc);
+                holdings_btc -= disposed;
+                cost_basis -= disposed * avg_cost;
+                if in_period {
+                    report.spent_msat += e.amount_msat.unwrap_or(0);
+                    report.fees_msat += e.fee_msat.unwrap_or(0);
+                    report.spent_fiat += btc * rate;
+                    report.realized_pnl += btc * rate - disposed * avg_cost;
+                }
+            }
+        }
+        report.unrealized_pnl = holdings_btc * current_rate - cost_basis;
+
+        report
+    }
+
+    /// A short summary for the GUI
+    pub fn to_text(&self, currency: &str) -> String {
+        format!(
+            "received: {} BTC ({:.2} {cur})\nspent: {} BTC ({:.2} {cur})\nfees: {} BTC\nrealized P&L: {:.2} {cur}\nunrealized P&L: {:.2} {cur}",
+            self.received_msat as f64 / 100_000_000_000.0,
+            self.received_fiat,
+            self.spent_msat as f64 / 100_000_000_000.0,
+            self.spent_fiat,
+            self.fees_msat as f64 / 100_000_000_000.0,
+            self.realized_pnl,
+            self.unrealized_pnl,
+            cur = currency
+        )
+    }
+}
+
+/// Aggregated activity of one calendar month
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MonthStats {
+    /// formatted as YYYY-MM
+    pub month: String,
+    pub count_in: u32,
+    pub count_out: u32,
+    pub received_msat: u64,
+    pub sent_msat: u64,
+    pub routing_fees_msat: u64,
+    pub onchain_fees_msat: u64,
+}
+
+/// Group the succeeded payments by month, oldest first. Entries without a timestamp are skipped.
+pub fn monthly_stats(entries: &[HistoryEntry]) -> Vec<MonthStats> {
+    let mut months = BTreeMap::<String, MonthStats>::new();
+    for e in entries.iter().filter(|e| e.status == "succeeded") {
+        let month = match DateTime::from_timestamp(e.timestamp, 0) {
+            Some(dt) if e.timestamp > 0 => dt.format("%Y-%m").to_string(),
+            _ => continue,
+        };
+        let stats = months.entry(month.clone()).or_insert_with(|| MonthStats {
+            month,
+            ..Default::default()
+        });
+        let fee = e.fee_msat.unwrap_or(0);
+        if e.kind == "onchain" || e.is_channel_event() {
+            stats.onchain_fees_msat += fee;
+        } else {
+            stats.routing_fees_msat += fee;
+        }
+        if e.is_channel_event() {
+            continue;
+        }
+        if e.direction == "in" {
+            stats.count_in += e.merged.max(1);
+            stats.received_msat += e.amount_msat.unwrap_or(0);
+        } else {
+            stats.count_out += e.merged.max(1);
+            stats.sent_msat += e.amount_msat.unwrap_or(0);
+        }
+    }
+    months.into_values().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(direction: &str, sats: u64, timestamp: i64, rate: f64) -> HistoryEntry {
+        HistoryEntry {
+            kind: "bolt11".to_string(),
+            direction: direction.to_string(),
+            status: "succeeded".to_string(),
+            amount_msat: Some(sats * 1_000),
+            timestamp,
+            fiat_rate: Some(rate),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_buy_and_sell() {
+        let entries = vec![
+            payment("in", 100_000_000, 100, 20_000.0),
+            payment("in", 100_000_000, 200, 40_000.0),
+            payment("out", 100_000_000, 300, 50_000.0),
+        ];
+        let report = Report::summarize(&entries, 0, 1_000, 60_000.0);
+        assert_eq!(report.received_msat, 200_000_000_000);
+        assert_eq!(report.spent_msat, 100_000_000_000);
+        assert_eq!(report.received_fiat, 60_000.0);
+        assert_eq!(report.spent_fiat, 50_000.0);
+        // average cost 30k, sold at 50k
+        assert_eq!(report.realized_pnl, 20_000.0);
+        // one BTC left with a cost basis of 30k, now worth 60k
+        assert_eq!(report.unrealized_pnl, 30_000.0);
+    }
+
+    #[test]
+    fn test_period() {
+        let entries = vec![
+            payment("in", 100_000_000, 100, 20_000.0),
+            payment("out", 50_000_000, 300, 30_000.0),
+            payment("out", 10_000_000, 2_000, 30_000.0),
+        ];
+        let report = Report::summarize(&entries, 200, 1_000, 30_000.0);
+        assert_eq!(report.received_msat, 0);
+        assert_eq!(report.spent_msat, 50_000_000_000);
+        assert_eq!(report.realized_pnl, 5_000.0);
+        assert_eq!(report.unrealized_pnl, 5_000.0);
+    }
+
+    #[test]
+    fn test_monthly_stats() {
+        let mut out = payment("out", 1_000, 1_700_000_000, 30_000.0);
+        out.fee_msat = Some(5_000);
+        let entries = vec![
+            payment("in", 10_000, 1_698_000_000, 30_000.0),
+            out,
+            payment("in", 2_000, 1_700_000_100, 30_000.0),
+            payment("in", 2_000, 0, 30_000.0),
+        ];
+        let stats = monthly_stats(&entries);
+        assert_eq!(stats.len(), 2);
+        assert_eq!(stats[0].month, "2023-10");
+        assert_eq!(stats[0].count_in, 1);
+        assert_eq!(stats[0].received_msat, 10_000_000);
+        assert_eq!(stats[1].month, "2023-11");
+        assert_eq!(stats[1].count_in, 1);
+        assert_eq!(stats[1].count_out, 1);
+        assert_eq!(stats[1].sent_msat, 1_000_000);
+        assert_eq!(stats[1].routing_fees_msat, 5_000);
+    }
+
+    #[test]
+    fn test_monthly_stats_aggregate() {
+        let mut agg = payment("in", 10_000, 1_698_000_000, 30_000.0);
+        agg.kind = "aggregate".to_string();
+        agg.merged = 7;
+        let stats = monthly_stats(&[agg]);
+        assert_eq!(stats[0].count_in, 7);
+        assert_eq!(stats[0].received_msat, 10_000_000);
+    }
+}
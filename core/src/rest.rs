@@ -0,0 +1,328 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! An optional REST API on localhost, for home automation scripts and a future web UI: the
+//! balance, new invoices and the state of the payments. Every request has to carry the
+//! token as `Authorization: Bearer <token>`. The token of a paired desktop companion is
+//! accepted as well, but only to read. Payments can't be sent over it.
+
+use crate::error::WalletError;
+use crate::history;
+use crate::pairing;
+use crate::profiles;
+use crate::secrets;
+use crate::settings::Settings;
+use crate::storage::to_hex;
+use crate::wallet::BdkWallet;
+
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    io::Read,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread,
+};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub const REST_TOKEN: &str = "rest_token";
+
+/// requests with a larger body are refused
+const MAX_BODY: u64 = 16 * 1024;
+
+static SERVER: Mutex<Option<Arc<Server>>> = Mutex::new(None);
+
+/// What a request may do, going by the token it carries
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Access {
+    Full,
+    /// the token of a paired companion, which may only read
+    ReadOnly,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default)]
+struct InvoiceRequest {
+    /// 0 for an invoice without amount
+    amount_sats: u64,
+    description: String,
+}
+
+/// The access token, created on first use
+pub fn token() -> Result<String, String> {
+    secrets::token(REST_TOKEN)
+}
+
+/// Replace the token, so that the scripts using the old one lose access
+pub fn reset_token() -> Result<String, String> {
+    secrets::new_token(REST_TOKEN)
+}
+
+/// Serve the API if it is enabled in the settings. Only one process can serve it, the
+/// daemon stops when it hands the node over.
+pub fn start() -> Result<(), String> {
+    let settings = Settings::load()?;
+    if !settings.rest_api {
+        return Ok(());
+    }
+    let mut server = SERVER
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the REST server: {:?}", e))?;
+    if server.is_some() {
+        return Ok(());
+    }
+    let token = token()?;
+    let http = Server::http(("127.0.0.1", settings.rest_port))
+        .map(Arc::new)
+        .map_err(|e| {
+            format!(
+                "Failed to serve the REST API on port {}: {}",
+                settings.rest_port, e
+            )
+        })?;
+    let serving = http.clone();
+    thread::spawn(move || {
+        for request in serving.incoming_requests() {
+            handle(request, &token);
+        }
+        log_info!("the REST API stopped");
+    });
+    log_info!(
+        "serving the REST API on http://127.0.0.1:{}",
+        settings.rest_port
+    );
+    *server = Some(http);
+    Ok(())
+}
+
+/// Stop serving, e.g. because the API was disabled or the token changed
+pub fn stop() {
+    match SERVER.lock() {
+        Ok(mut server) => {
+            if let Some(server) = server.take() {
+                server.unblock();
+            }
+        }
+        Err(e) => log_error!("Unable to get the mutex for the REST server: {:?}", e),
+    }
+}
+
+/// Serve with the current settings and token
+pub fn restart() -> Result<(), String> {
+    stop();
+    start()
+}
+
+fn handle(mut request: Request, token: &str) {
+    let authorization = request
+        .headers()
+        .iter()
+        .find(|h| h.field.equiv("Authorization"))
+        .map(|h| h.value.as_str().to_string());
+    let (status, body) = if let Some(access) =
+        access(authorization.as_deref(), token, pairing::verify_token)
+    {
+        let method = request.method().clone();
+        let url = request.url().to_string();
+        let mut body = String::new();
+        let res = request
+            .as_reader()
+            .take(MAX_BODY)
+            .read_to_string(&mut body)
+            .map_err(|e| WalletError::InvalidInput(format!("Failed to read the request: {}", e)))
+            .and_then(|_| dispatch(access, &method, &url, &body));
+        match res {
+            Ok(Some(value)) => (200, value),
+            Ok(None) => (404, json!({ "error": "not_found" })),
+            Err(e) => {
+                log_error!("REST {} {} failed: {}", method, url, e);
+                (
+                    status(&e),
+                    json!({ "error": e.code(), "message": e.to_string() }),
+                )
+            }
+        }
+    } else {
+        (401, json!({ "error": "unauthorized" }))
+    };
+    let mut response = Response::from_string(body.to_string()).with_status_code(status);
+    if let Ok(header) = Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]) {
+        response = response.with_header(header);
+    }
+    if let Err(e) = request.respond(response) {
+        log_error!("Failed to answer a REST request: {}", e);
+    }
+}
+
+/// None if there is no such resource
+fn dispatch(
+    access: Access,
+    method: &Method,
+    url: &str,
+    body: &str,
+) -> Result<Option<Value>, WalletError> {
+    if access == Access::ReadOnly && *method != Method::Get {
+        return Err(WalletError::Unauthorized(
+            "The pairing token only grants read access".to_string(),
+        ));
+    }
+    let path = url
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
+    match (method, path) {
+        (Method::Get, "/v1/info") => Ok(Some(json!({
+            "wallet": profiles::active(),
+            "network": profiles::network().to_string(),
+            "node_id": BdkWallet::node_id()?,
+            "channel": BdkWallet::get_channel_status()?,
+        }))),
+        (Method::Get, "/v1/balance") => {
+            let (onchain, lightning) = BdkWallet::get_balance()?;
+            Ok(Some(json!({
+                "onchain_sats": onchain,
+                "lightning_sats": lightning,
+            })))
+        }
+        (Method::Post, "/v1/invoices") => {
+            let req = parse_invoice_request(body)?;
+            let amount = (req.amount_sats > 0).then_some(req.amount_sats);
+            let invoice = BdkWallet::create_invoice(amount, &req.description)?;
+            let hash = Bolt11Invoice::from_str(&invoice)
+                .map(|inv| to_hex(inv.payment_hash().as_ref()))
+                .map_err(|e| WalletError::Node(format!("Created an invalid invoice: {}", e)))?;
+            log_info!("invoice {} created over REST", hash);
+            Ok(Some(json!({ "invoice": invoice, "payment_hash": hash })))
+        }
+        (Method::Get, "/v1/payments") => {
+            let payments = history::all_entries()?
+                .into_iter()
+                .filter(|e| !e.is_channel_event())
+                .collect::<Vec<_>>();
+            Ok(Some(json!(payments)))
+        }
+        (Method::Get, path) if path.starts_with("/v1/payments/") => {
+            let id = &path["/v1/payments/".len()..];
+            Ok(history::all_entries()?
+                .into_iter()
+                .find(|e| e.id == id)
+                .map(|e| json!(e)))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn parse_invoice_request(body: &str) -> Result<InvoiceRequest, WalletError> {
+    if body.trim().is_empty() {
+        return Ok(InvoiceRequest::default());
+    }
+    serde_json::from_str(body)
+        .map_err(|e| WalletError::InvalidInput(format!("Invalid invoice request: {}", e)))
+}
+
+/// Whether the Authorization header carries the token or one the companion was paired with,
+/// compared in constant time. None if it carries neither.
+fn access(
+    authorization: Option<&str>,
+    token: &str,
+    paired: impl Fn(&str) -> bool,
+) -> Option<Access> {
+    let presented = authorization?.strip_prefix("Bearer ")?;
+    if secrets::same_token(presented, token) {
+        Some(Access::Full)
+    } else if paired(presented) {
+        Some(Access::ReadOnly)
+    } else {
+        None
+    }
+}
+
+/// The http status code for an error
+fn status(e: &WalletError) -> u16 {
+    match e {
+        WalletError::Unauthorized(_) => 403,
+        WalletError::InvalidInput(_) | WalletError::InvalidAmount(_) => 400,
+        WalletError::NotInitialized | WalletError::Cancelled(_) => 503,
+        WalletError::Network(_) => 502,
+        _ => 500,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_access() {
+        let unpaired = |_: &str| false;
+        assert_eq!(
+            access(Some("Bearer abc123"), "abc123", unpaired),
+            Some(Access::Full)
+        );
+        assert_eq!(access(Some("Bearer abc124"), "abc123", unpaired), None);
+        assert_eq!(access(Some("Bearer abc12"), "abc123", unpaired), None);
+        assert_eq!(access(Some("abc123"), "abc123", unpaired), None);
+        assert_eq!(access(None, "abc123", unpaired), None);
+        assert_eq!(access(Some("Bearer "), "", unpaired), None);
+
+        let paired = |presented: &str| secrets::same_token(presented, "def456");
+        assert_eq!(
+            access(Some("Bearer def456"), "abc123", paired),
+            Some(Access::ReadOnly)
+        );
+        assert_eq!(
+            access(Some("Bearer abc123"), "abc123", paired),
+            Some(Access::Full)
+        );
+        assert_eq!(access(Some("Bearer def45"), "abc123", paired), None);
+        // a revoked pairing leaves no token behind
+        let revoked = |presented: &str| secrets::same_token(presented, "");
+        assert_eq!(access(Some("Bearer "), "abc123", revoked), None);
+    }
+
+    #[test]
+    fn test_read_only() {
+        assert!(matches!(
+            dispatch(Access::ReadOnly, &Method::Post, "/v1/invoices", ""),
+            Err(WalletError::Unauthorized(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_invoice_request() {
+        assert_eq!(parse_invoice_request(""), Ok(InvoiceRequest::default()));
+        assert_eq!(
+            parse_invoice_request(r#"{"amount_sats": 2100, "description": "coffee"}"#),
+            Ok(InvoiceRequest {
+                amount_sats: 2100,
+                description: "coffee".to_string(),
+            })
+        );
+        assert!(matches!(
+            parse_invoice_request("2100"),
+            Err(WalletError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_status() {
+        assert_eq!(status(&WalletError::InvalidAmount("".to_string())), 400);
+        assert_eq!(status(&WalletError::NotInitialized), 503);
+        assert_eq!(status(&WalletError::Storage("".to_string())), 500);
+    }
+}
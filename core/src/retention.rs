@@ -0,0 +1,157 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::history::HistoryEntry;
+
+use chrono::DateTime;
+use std::collections::BTreeMap;
+
+/// The result of compacting the history
+#[derive(Debug, Default, PartialEq)]
+pub struct Compaction {
+    /// the ids of the entries that can be deleted
+    pub removed: Vec<String>,
+    /// one entry per month and direction, standing in for the removed payments
+    pub aggregates: Vec<HistoryEntry>,
+}
+
+/// Select the completed payments older than the cutoff for removal. With keep_totals, the
+/// succeeded ones are summed up per month and direction, so that reports and statistics stay
+/// correct. Pending payments and channel events are never touched.
+pub fn compact(entries: &[HistoryEntry], cutoff: i64, keep_totals: bool) -> Compaction {
+    let mut compaction = Compaction::default();
+    let mut months = BTreeMap::<(String, String), HistoryEntry>::new();
+    for e in entries {
+        if e.timestamp <= 0 || e.timestamp >= cutoff || e.is_channel_event() {
+            continue;
+        }
+        if e.status != "succeeded" && e.status != "failed" {
+            continue;
+        }
+        compaction.removed.push(e.id.clone());
+        if !keep_totals || e.status != "succeeded" {
+            continue;
+        }
+        let month = match DateTime::from_timestamp(e.timestamp, 0) {
+            Some(dt) => dt.format("%Y-%m").to_string(),
+            None => continue,
+        };
+        let agg = months
+            .entry((month.clone(), e.direction.clone()))
+            .or_insert_with(|| HistoryEntry {
+                id: format!("aggregate-{}-{}", e.direction, month),
+                kind: "aggregate".to_string(),
+                direction: e.direction.clone(),
+                status: "succeeded".to_string(),
+                currency: e.currency.clone(),
+                ..Default::default()
+            });
+        merge(agg, e);
+    }
+    compaction.aggregates = months.into_values().collect();
+    compaction
+}
+
+/// Add a payment to an aggregate. The fiat rate is averaged, weighted by the amount.
+fn merge(agg: &mut HistoryEntry, e: &HistoryEntry) {
+    let amount = agg.amount_msat.unwrap_or(0);
+    let added = e.amount_msat.unwrap_or(0);
+    agg.fiat_rate = match (agg.fiat_rate, e.fiat_rate) {
+        (Some(a), Some(b)) if amount + added > 0 => {
+            Some((a * amount as f64 + b * added as f64) / (amount + added) as f64)
+        }
+        (None, Some(b)) if amount == 0 => Some(b),
+        (rate, _) => rate,
+    };
+    if agg.currency.is_empty() {
+        agg.currency = e.currency.clone();
+    }
+    agg.amount_msat = Some(amount + added);
+    agg.fee_msat = match (agg.fee_msat, e.fee_msat) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+    };
+    agg.timestamp = agg.timestamp.max(e.timestamp);
+    agg.merged += e.merged.max(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payment(id: &str, direction: &str, status: &str, sats: u64, timestamp: i64) -> HistoryEntry {
+        HistoryEntry {
+            id: id.to_string(),
+            kind: "bolt11".to_string(),
+            direction: direction.to_string(),
+            status: status.to_string(),
+            amount_msat: Some(sats * 1_000),
+            timestamp,
+            fee_msat: Some(1_000),
+            fiat_rate: Some(30_000.0),
+            currency: "CHF".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compact() {
+        let entries = vec![
+            payment("a", "in", "succeeded", 1_000, 1_698_000_000),
+            payment("b", "in", "succeeded", 3_000, 1_698_000_100),
+            payment("c", "out", "failed", 2_000, 1_698_000_200),
+            payment("d", "out", "pending", 2_000, 1_698_000_300),
+            payment("e", "in", "succeeded", 5_000, 1_700_000_000),
+        ];
+        let compaction = compact(&entries, 1_699_000_000, true);
+        assert_eq!(compaction.removed, vec!["a", "b", "c"]);
+        assert_eq!(compaction.aggregates.len(), 1);
+        let agg = &compaction.aggregates[0];
+        assert_eq!(agg.id, "aggregate-in-2023-10");
+        assert_eq!(agg.amount_msat, Some(4_000_000));
+        assert_eq!(agg.fee_msat, Some(2_000));
+        assert_eq!(agg.fiat_rate, Some(30_000.0));
+        assert_eq!(agg.timestamp, 1_698_000_100);
+        assert_eq!(agg.merged, 2);
+    }
+
+    #[test]
+    fn test_compact_existing_aggregate() {
+        let mut agg = payment(
+            "aggregate-in-2023-10",
+            "in",
+            "succeeded",
+            1_000,
+            1_698_000_000,
+        );
+        agg.kind = "aggregate".to_string();
+        agg.fiat_rate = Some(20_000.0);
+        agg.merged = 10;
+        let entries = vec![agg, payment("b", "in", "succeeded", 1_000, 1_698_000_100)];
+        let compaction = compact(&entries, 1_699_000_000, true);
+        assert_eq!(compaction.removed.len(), 2);
+        assert_eq!(compaction.aggregates[0].merged, 11);
+        assert_eq!(compaction.aggregates[0].fiat_rate, Some(25_000.0));
+    }
+
+    #[test]
+    fn test_prune_without_totals() {
+        let entries = vec![payment("a", "in", "succeeded", 1_000, 1_698_000_000)];
+        let compaction = compact(&entries, 1_699_000_000, false);
+        assert_eq!(compaction.removed, vec!["a"]);
+        assert!(compaction.aggregates.is_empty());
+    }
+}
@@ -0,0 +1,376 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Keeps the wallet up to date in the background: syncs the wallets, refreshes the fee
+//! estimates and the exchange rate, processes the events of the node, and moves the payments
+//! to the lightning address to the node. The intervals are stretched while running on battery
+//! and after failures. After switching networks the peers are reconnected and the wallets
+//! synced right away. In the low data mode the syncs are less frequent, and the exchange rate
+//! and the server checks are deferred.
+
+use crate::constants::{
+    ESPLORA_CHECK_SECS, EVENT_POLL_SECS, FEE_REFRESH_SECS, LNADDRESS_SWEEP_SECS, LOW_DATA_FACTOR,
+    NETWORK_POLL_SECS,
+};
+use crate::error::WalletError;
+use crate::esplora;
+use crate::gossip;
+use crate::lnaddress;
+use crate::netwatch::{self, Connection};
+use crate::preview;
+use crate::rates;
+use crate::settings::Settings;
+use crate::wallet::{BdkWallet, WalletEvent};
+
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+const POWER_SUPPLY_DIR: &str = "/sys/class/power_supply";
+
+/// failures stretch the interval up to this many times
+const MAX_FAILURE_BACKOFF: u32 = 8;
+
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// the node was handed over to another process
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Task {
+    Sync,
+    Fees,
+    Rate,
+    Events,
+    /// check the esplora servers and fail over if needed
+    Health,
+    /// look for a switch between WiFi, mobile data and offline
+    Network,
+    /// move the payments to the lightning address from the provider to the node
+    LnAddress,
+}
+
+impl Task {
+    const ALL: [Task; 7] = [
+        Task::Network,
+        Task::Events,
+        Task::Sync,
+        Task::Fees,
+        Task::Rate,
+        Task::Health,
+        Task::LnAddress,
+    ];
+
+    fn needs_network(self) -> bool {
+        !matches!(self, Task::Events | Task::Network)
+    }
+
+    /// can wait until data is cheap again
+    fn optional(self) -> bool {
+        matches!(self, Task::Rate | Task::Health)
+    }
+
+    fn base_secs(self, settings: &Settings) -> u64 {
+        match self {
+            Task::Sync => settings.sync_interval_secs as u64,
+            Task::Fees => FEE_REFRESH_SECS,
+            Task::Rate => settings.rate_interval_secs as u64,
+            Task::Events => EVENT_POLL_SECS,
+            Task::Health => ESPLORA_CHECK_SECS,
+            Task::Network => NETWORK_POLL_SECS,
+            Task::LnAddress => LNADDRESS_SWEEP_SECS,
+        }
+    }
+}
+
+/// What the GUI hears from the scheduler
+pub enum Update {
+    /// a sync of the wallets started
+    Syncing,
+    /// the wallets are synced
+    Synced,
+    Balance {
+        onchain_sats: u64,
+        lightning_sats: u64,
+    },
+    /// our share of the channel balance, empty without a channel
+    Channel(String),
+    Event(WalletEvent),
+    /// the device switched to another network
+    Network(Connection),
+    /// the low data mode started or ended
+    LowData(bool),
+    Failed(Task, WalletError),
+}
+
+/// When the tasks are due next, and how often they failed in a row
+struct Schedule {
+    tasks: Vec<(Task, Instant, u32)>,
+}
+
+impl Schedule {
+    fn new(now: Instant) -> Self {
+        Self {
+            tasks: Task::ALL.iter().map(|task| (*task, now, 0)).collect(),
+        }
+    }
+
+    fn due(&self, now: Instant) -> Vec<Task> {
+        self.tasks
+            .iter()
+            .filter(|(_, due, _)| *due <= now)
+            .map(|(task, _, _)| *task)
+            .collect()
+    }
+
+    /// Run the task with the next round
+    fn trigger(&mut self, task: Task, now: Instant) {
+        if let Some(entry) = self.tasks.iter_mut().find(|(t, _, _)| *t == task) {
+            entry.1 = now;
+        }
+    }
+
+    fn done(&mut self, task: Task, ok: bool, now: Instant, base_secs: u64, battery_factor: u32) {
+        if let Some(entry) = self.tasks.iter_mut().find(|(t, _, _)| *t == task) {
+            entry.2 = if ok { 0 } else { entry.2 + 1 };
+            entry.1 = now + interval(base_secs, battery_factor, entry.2);
+        }
+    }
+}
+
+/// The interval stretched by the battery factor, and doubled for every failure in a row
+fn interval(base_secs: u64, battery_factor: u32, failures: u32) -> Duration {
+    let backoff = 1u32
+        .checked_shl(failures)
+        .unwrap_or(MAX_FAILURE_BACKOFF)
+        .min(MAX_FAILURE_BACKOFF);
+    Duration::from_secs(base_secs.max(1) * battery_factor as u64 * backoff as u64)
+}
+
+/// How much longer the intervals get on battery, and even more when it runs low
+fn battery_factor(discharging: bool, capacity: Option<u8>) -> u32 {
+    match (discharging, capacity) {
+        (false, _) => 1,
+        (true, Some(capacity)) if capacity < 20 => 4,
+        (true, _) => 2,
+    }
+}
+
+/// Whether the device runs on battery, and how full it is
+fn battery_state() -> (bool, Option<u8>) {
+    let entries = match fs::read_dir(POWER_SUPPLY_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return (false, None),
+    };
+    let read = |dir: &Path, name: &str| {
+        fs::read_to_string(dir.join(name))
+            .map(|s| s.trim().to_string())
+            .unwrap_or_default()
+    };
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if read(&dir, "type") == "Battery" {
+            return (
+                read(&dir, "status") == "Discharging",
+                read(&dir, "capacity").parse().ok(),
+            );
+        }
+    }
+    (false, None)
+}
+
+/// Hand the balances to the GUI, after anything that may have changed them
+fn notify_balance(notify: &impl Fn(Update)) -> Result<(), WalletError> {
+    let (onchain_sats, lightning_sats) = BdkWallet::get_balance()?;
+    notify(Update::Balance {
+        onchain_sats,
+        lightning_sats,
+    });
+    notify(Update::Channel(BdkWallet::get_channel_status()?));
+    Ok(())
+}
+
+/// Run the task, and return the tasks that should follow right away
+fn run_task(
+    task: Task,
+    settings: &Settings,
+    notify: &impl Fn(Update),
+) -> Result<Vec<Task>, WalletError> {
+    match task {
+        Task::Sync => {
+            notify(Update::Syncing);
+            BdkWallet::sync()?;
+            notify(Update::Synced);
+            notify_balance(notify)?;
+        }
+        Task::Fees => {
+            preview::refresh_fee_estimate()?;
+        }
+        Task::Rate => {
+            rates::refresh(settings)?;
+            // the fiat value changed
+            notify_balance(notify)?;
+        }
+        Task::Events => {
+            let mut any = false;
+            while let Some(event) = BdkWallet::handle_ldk_event()? {
+                notify(Update::Event(event));
+                any = true;
+            }
+            if any {
+                notify_balance(notify)?;
+            }
+        }
+        Task::Health => {
+            if let (url, true) = esplora::select() {
+                log_info!("restarting the node with {}", url);
+                BdkWallet::restart()?;
+                notify(Update::Synced);
+                notify_balance(notify)?;
+            } else if gossip::check(BdkWallet::rgs_snapshot()?)? {
+                log_info!("restarting the node with the other gossip source");
+                BdkWallet::restart()?;
+            }
+        }
+        Task::LnAddress => {
+            lnaddress::sweep(settings)?;
+        }
+        Task::Network => {
+            if let Some(connection) = netwatch::changed() {
+                log_info!("the network changed to {}", connection.name());
+                notify(Update::Network(connection));
+                if connection != Connection::Offline {
+                    // the connections of the old network are dead, even if they look alive
+                    BdkWallet::reconnect_peers()?;
+                    return Ok(vec![Task::Health, Task::Sync]);
+                }
+            }
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Start the background thread, only the first call has an effect
+pub fn start(notify: impl Fn(Update) + Send + 'static) {
+    if STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    thread::spawn(move || {
+        let mut schedule = Schedule::new(Instant::now());
+        let mut low_data = false;
+        loop {
+            let due = schedule.due(Instant::now());
+            if !due.is_empty() && !PAUSED.load(Ordering::SeqCst) {
+                let settings = Settings::load().unwrap_or_else(|e| {
+                    log_error!("{}", e);
+                    Settings::default()
+                });
+                let factor = if settings.battery_saver {
+                    let (discharging, capacity) = battery_state();
+                    battery_factor(discharging, capacity)
+                } else {
+                    1
+                };
+                if settings.low_data.applies(netwatch::current()) != low_data {
+                    low_data = !low_data;
+                    log_info!("low data mode {}", if low_data { "on" } else { "off" });
+                    notify(Update::LowData(low_data));
+                }
+                for task in due {
+                    let skip = settings.offline || (low_data && task.optional());
+                    let res = if skip && task.needs_network() {
+                        Ok(Vec::new())
+                    } else {
+                        run_task(task, &settings, &notify)
+                    };
+                    let ok = res.is_ok();
+                    match res {
+                        Ok(follow_ups) => {
+                            for follow_up in follow_ups {
+                                schedule.trigger(follow_up, Instant::now());
+                            }
+                        }
+                        Err(e) => {
+                            log_error!("{:?} failed: {}", task, e);
+                            if task == Task::Sync {
+                                // the esplora server might be down
+                                schedule.trigger(Task::Health, Instant::now());
+                            }
+                            if matches!(task, Task::Sync | Task::Events) {
+                                notify(Update::Failed(task, e));
+                            }
+                        }
+                    }
+                    let base_secs = task.base_secs(&settings);
+                    let factor = if low_data && matches!(task, Task::Sync | Task::Fees) {
+                        factor * LOW_DATA_FACTOR
+                    } else {
+                        factor
+                    };
+                    schedule.done(task, ok, Instant::now(), base_secs, factor);
+                }
+            }
+            thread::sleep(Duration::from_secs(1));
+        }
+    });
+}
+
+/// Stop running the tasks while there is no node, and resume
+pub fn pause(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intervals() {
+        assert_eq!(interval(60, 1, 0), Duration::from_secs(60));
+        assert_eq!(interval(60, 2, 1), Duration::from_secs(240));
+        assert_eq!(interval(60, 1, 10), Duration::from_secs(480));
+        assert_eq!(interval(60, 1, 40), Duration::from_secs(480));
+        assert_eq!(battery_factor(false, Some(5)), 1);
+        assert_eq!(battery_factor(true, None), 2);
+        assert_eq!(battery_factor(true, Some(50)), 2);
+        assert_eq!(battery_factor(true, Some(10)), 4);
+    }
+
+    #[test]
+    fn test_schedule() {
+        let now = Instant::now();
+        let mut schedule = Schedule::new(now);
+        assert_eq!(schedule.due(now).len(), Task::ALL.len());
+
+        schedule.done(Task::Sync, true, now, 60, 1);
+        assert!(!schedule.due(now).contains(&Task::Sync));
+        assert!(schedule
+            .due(now + Duration::from_secs(60))
+            .contains(&Task::Sync));
+
+        schedule.done(Task::Fees, false, now, 60, 1);
+        assert!(!schedule
+            .due(now + Duration::from_secs(60))
+            .contains(&Task::Fees));
+        assert!(schedule
+            .due(now + Duration::from_secs(120))
+            .contains(&Task::Fees));
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::profiles;
+use crate::storage::{self, data_file, to_hex};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use keyring::Entry;
+use rand_core::{OsRng, RngCore};
+use std::{collections::HashMap, fs, os::unix::fs::PermissionsExt, path::Path};
+
+const SERVICE: &str = "utlnwallet.ulrichard";
+/// Holds the secrets where no keyring is available. Only readable by the owner.
+const FALLBACK_FILE: &str = "secrets.json";
+const NONCE_LEN: usize = 12;
+const KEY_LEN: usize = 32;
+
+/// The key the seed is encrypted with
+pub const MNEMONIC_KEY: &str = "mnemonic_key";
+pub const CMC_API_KEY: &str = "cmc_api_key";
+
+/// The secrets are kept apart per profile
+fn entry(name: &str) -> Result<Entry, keyring::Error> {
+    Entry::new(SERVICE, &format!("{}/{}", profiles::active(), name))
+}
+
+/// Whether the platform secret service can be used
+pub fn keyring_available() -> bool {
+    match entry("probe").and_then(|e| e.get_password()) {
+        Ok(_) | Err(keyring::Error::NoEntry) => true,
+        Err(e) => {
+            log_info!("no keyring available: {}", e);
+            false
+        }
+    }
+}
+
+fn load_fallback() -> Result<HashMap<String, String>, String> {
+    storage::load_json(FALLBACK_FILE)
+}
+
+fn save_fallback(secrets: &HashMap<String, String>) -> Result<(), String> {
+    let json = serde_json::to_string(secrets)
+        .map_err(|e| format!("Failed to serialize the secrets: {}", e))?;
+    write_private(&data_file(FALLBACK_FILE), json.as_bytes())
+}
+
+/// Make a file only accessible by its owner
+pub fn restrict_permissions(file: &Path) -> Result<(), String> {
+    fs::set_permissions(file, fs::Permissions::from_mode(0o600))
+        .map_err(|e| format!("Failed to restrict the permissions of {:?}: {}", file, e))
+}
+
+/// Whether anybody but the owner may access the file
+pub fn is_exposed(file: &Path) -> Result<bool, String> {
+    let meta = fs::metadata(file)
+        .map_err(|e| format!("Failed to read the permissions of {:?}: {}", file, e))?;
+    Ok(meta.permissions().mode() & 0o077 != 0)
+}
+
+/// Write a file only accessible by its owner. The data goes to a temporary file first, so a
+/// crash leaves either the old or the new content behind, never a truncated file.
+pub fn write_private(file: &Path, data: &[u8]) -> Result<(), String> {
+    storage::write_file(file, data, 0o600)
+}
+
+/// Read a secret from the keyring, or from the fallback file
+pub fn get(name: &str) -> Result<Option<String>, String> {
+    match entry(name).and_then(|e| e.get_password()) {
+        Ok(secret) => return Ok(Some(secret)),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => log_error!("Failed to read {} from the keyring: {}", name, e),
+    }
+    Ok(load_fallback()?.remove(name))
+}
+
+/// A random token kept under the name, created on first use
+pub fn token(name: &str) -> Result<String, String> {
+    match get(name)? {
+        Some(token) if !token.is_empty() => Ok(token),
+        _ => new_token(name),
+    }
+}
+
+/// Replace the token kept under the name, so that the ones who know the old one lose access
+pub fn new_token(name: &str) -> Result<String, String> {
+    let mut token = [0u8; 32];
+    OsRng.fill_bytes(&mut token);
+    let token = to_hex(&token);
+    set(name, &token)?;
+    Ok(token)
+}
+
+/// Whether the presented token is the expected one, compared in constant time
+pub fn same_token(presented: &str, token: &str) -> bool {
+    !token.is_empty()
+        && presented.len() == token.len()
+        && presented
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// Store a secret in the keyring if there is one, otherwise in the fallback file.
+/// An empty secret is removed.
+pub fn set(name: &str, secret: &str) -> Result<(), String> {
+    let stored = entry(name).and_then(|e| {
+        if secret.is_empty() {
+            match e.delete_password() {
+                Err(keyring::Error::NoEntry) => Ok(()),
+                res => res,
+            }
+        } else {
+            e.set_password(secret)
+        }
+    });
+    let mut fallback = load_fallback()?;
+    match stored {
+        Ok(()) => {
+            // don't leave a copy behind from when there was no keyring
+            if fallback.remove(name).is_some() {
+                save_fallback(&fallback)?;
+            }
+        }
+        Err(e) => {
+            log_error!("Failed to store {} in the keyring: {}", name, e);
+            let changed = if secret.is_empty() {
+                fallback.remove(name).is_some()
+            } else {
+                fallback.insert(name.to_string(), secret.to_string()) != Some(secret.to_string())
+            };
+            if changed {
+                save_fallback(&fallback)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The key as the cipher takes it, a corrupted one from the keyring fails instead of panicking
+fn cipher(key: &[u8]) -> Result<ChaCha20Poly1305, String> {
+    if key.len() != KEY_LEN {
+        return Err(format!(
+            "The key has {} bytes instead of {}",
+            key.len(),
+            KEY_LEN
+        ));
+    }
+    Ok(ChaCha20Poly1305::new(Key::from_slice(key)))
+}
+
+/// Encrypt with a key, the nonce is stored in front
+pub fn seal(key: &[u8], plain: &[u8]) -> Result<Vec<u8>, String> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let encrypted = cipher(key)?
+        .encrypt(Nonce::from_slice(&nonce), plain)
+        .map_err(|e| format!("Failed to encrypt: {}", e))?;
+    Ok([&nonce[..], &encrypted].concat())
+}
+
+pub fn open(key: &[u8], sealed: &[u8]) -> Result<Vec<u8>, String> {
+    if sealed.len() < NONCE_LEN {
+        return Err("The encrypted data is truncated".to_string());
+    }
+    cipher(key)?
+        .decrypt(
+            Nonce::from_slice(&sealed[..NONCE_LEN]),
+            &sealed[NONCE_LEN..],
+        )
+        .map_err(|_| "Failed to decrypt, the key doesn't match".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_open() {
+        let key = [7u8; 32];
+        let sealed = seal(&key, b"abandon ability").unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), b"abandon ability");
+        assert!(open(&[8u8; 32], &sealed).is_err());
+        assert!(open(&key, &sealed[..5]).is_err());
+        assert!(seal(&key[..31], b"abandon").is_err());
+        assert!(open(&[7u8; 33], &sealed).is_err());
+    }
+
+    #[test]
+    fn test_write_private() {
+        let file = std::env::temp_dir().join(format!("utwallet-test-{}", std::process::id()));
+        fs::write(&file, "old").unwrap();
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(is_exposed(&file).unwrap());
+        write_private(&file, b"new").unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert!(!is_exposed(&file).unwrap());
+        assert!(!storage::temp_file(&file).exists());
+        fs::remove_file(&file).unwrap();
+    }
+}
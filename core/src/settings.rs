@@ -0,0 +1,202 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::constants::{
+    ESPLORA_SERVERS, EVENT_LOG_LENGTH, RATE_REFRESH_SECS, REGTEST_ESPLORA_SERVERS, REST_PORT,
+    SIGNET_ESPLORA_SERVERS, SYNC_REFRESH_SECS, TESTNET_ESPLORA_SERVERS,
+};
+use crate::format::Denomination;
+use crate::history;
+use crate::netwatch::LowData;
+use crate::profiles;
+use crate::rates::RATE_SOURCES;
+use crate::secrets::{self, CMC_API_KEY};
+use crate::storage::{load_json, save_json};
+
+use ldk_node::bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences, persisted as json in the app data directory
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// payments older than this are pruned, 0 keeps everything
+    pub retention_days: u32,
+    /// replace pruned payments by monthly totals instead of dropping them
+    pub keep_totals: bool,
+    /// the fiat currency to display amounts in
+    pub currency: String,
+    /// a personal CoinMarketCap API key, the keyless sources are used without one.
+    /// It is kept with the other secrets, older versions stored it here.
+    #[serde(skip_serializing)]
+    pub cmc_api_key: String,
+    /// whether amounts are shown and entered in BTC or sats
+    pub denomination: Denomination,
+    /// the names of the enabled rate sources, in the order they are asked
+    pub rate_sources: Vec<String>,
+    /// unix timestamp of when the user proved to have written down the seed, 0 if never
+    pub seed_backup_confirmed: i64,
+    /// accept the fingerprint reader instead of the PIN
+    pub biometric_unlock: bool,
+    /// lock the wallet after this many seconds without user activity, 0 never locks
+    pub auto_lock_secs: u32,
+    /// mirror the channel state to this versioned storage service, empty keeps it local only
+    pub vss_url: String,
+    /// the rapid gossip sync server, empty for the default one of the network
+    pub rgs_url: String,
+    /// remind to back up the seed once the balance exceeds this many sats
+    pub backup_reminder_sats: u64,
+    /// send the http traffic through this proxy, e.g. socks5h://127.0.0.1:9050 for Tor.
+    /// The lightning peers are connected directly regardless.
+    pub proxy: String,
+    /// make no network requests at all
+    pub offline: bool,
+    /// payments above this many sats require entering the PIN again, 0 never asks
+    pub pin_threshold_sats: u64,
+    /// how often the wallets are synced in the background
+    pub sync_interval_secs: u32,
+    /// how often the exchange rate is fetched in the background
+    pub rate_interval_secs: u32,
+    /// sync less often while running on battery
+    pub battery_saver: bool,
+    /// the esplora servers to sync with, the first working one is used
+    pub esplora_servers: Vec<String>,
+    /// an Electrum server like ssl://electrs.local:50002 for the fee estimates, empty uses esplora
+    pub electrum_url: String,
+    /// also log the debug messages
+    pub verbose_logging: bool,
+    /// when to sync less often and skip the exchange rate and the server checks
+    pub low_data: LowData,
+    /// how many events the log on the main page keeps
+    pub event_log_length: u32,
+    /// serve the REST API on localhost
+    pub rest_api: bool,
+    pub rest_port: u16,
+    /// called with the details of every paid invoice, empty calls nothing
+    pub webhook_url: String,
+    /// the LNbits instance that serves the lightning address
+    pub lnaddress_provider: String,
+    /// the id of the LNURLp pay link behind the lightning address
+    pub lnaddress_link: String,
+    /// the claimed lightning address like me@domain, empty without one
+    pub lnaddress: String,
+    /// notify about payments and channel events the daemon receives while the app is closed
+    pub notifications: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            retention_days: 0,
+            keep_totals: true,
+            currency: "CHF".to_string(),
+            cmc_api_key: "".to_string(),
+            denomination: Denomination::Btc,
+            rate_sources: RATE_SOURCES.iter().map(|s| s.to_string()).collect(),
+            seed_backup_confirmed: 0,
+            biometric_unlock: false,
+            auto_lock_secs: 300,
+            vss_url: "".to_string(),
+            rgs_url: "".to_string(),
+            backup_reminder_sats: 100_000,
+            proxy: "".to_string(),
+            offline: false,
+            pin_threshold_sats: 0,
+            sync_interval_secs: SYNC_REFRESH_SECS as u32,
+            rate_interval_secs: RATE_REFRESH_SECS as u32,
+            battery_saver: true,
+            esplora_servers: default_esplora_servers(profiles::network())
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            electrum_url: "".to_string(),
+            verbose_logging: false,
+            low_data: LowData::Off,
+            event_log_length: EVENT_LOG_LENGTH,
+            rest_api: false,
+            rest_port: REST_PORT,
+            notifications: true,
+            webhook_url: "".to_string(),
+            lnaddress_provider: "".to_string(),
+            lnaddress_link: "".to_string(),
+            lnaddress: "".to_string(),
+        }
+    }
+}
+
+/// The CoinMarketCap key of the active profile, the settings are loaded too often to ask the
+/// keyring every time
+static CMC_KEY_CACHE: Mutex<Option<(String, Option<String>)>> = Mutex::new(None);
+
+fn cached_cmc_api_key() -> Result<Option<String>, String> {
+    let profile = profiles::active();
+    let mut cache = CMC_KEY_CACHE
+        .lock()
+        .map_err(|e| format!("Unable to get the mutex for the api key: {:?}", e))?;
+    match &*cache {
+        Some((cached, key)) if *cached == profile => Ok(key.clone()),
+        _ => {
+            let key = secrets::get(CMC_API_KEY)?;
+            *cache = Some((profile, key.clone()));
+            Ok(key)
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Result<Self, String> {
+        let mut settings: Self = load_json(SETTINGS_FILE)?;
+        if let Some(key) = cached_cmc_api_key()? {
+            settings.cmc_api_key = key;
+        }
+        Ok(settings)
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        if cached_cmc_api_key()?.unwrap_or_default() != self.cmc_api_key {
+            secrets::set(CMC_API_KEY, &self.cmc_api_key)?;
+            if let Ok(mut cache) = CMC_KEY_CACHE.lock() {
+                *cache = Some((profiles::active(), Some(self.cmc_api_key.clone())));
+            }
+        }
+        save_json(SETTINGS_FILE, self)
+    }
+}
+
+/// The settings are kept per network, so each one starts out with servers of its own
+fn default_esplora_servers(network: Network) -> &'static [&'static str] {
+    match network {
+        Network::Testnet => TESTNET_ESPLORA_SERVERS,
+        Network::Signet => SIGNET_ESPLORA_SERVERS,
+        Network::Regtest => REGTEST_ESPLORA_SERVERS,
+        _ => ESPLORA_SERVERS,
+    }
+}
+
+/// The fiat currencies that can be selected for display
+pub const CURRENCIES: &[&str] = &["CHF", "EUR", "USD", "GBP", "JPY", "CAD", "AUD"];
+
+/// Prune the history according to the settings, and return how many entries were removed
+pub fn apply_retention() -> Result<usize, String> {
+    let settings = Settings::load()?;
+    if settings.retention_days == 0 {
+        return Ok(0);
+    }
+    history::prune(settings.retention_days, settings.keep_totals)
+}
@@ -0,0 +1,114 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Files handed to other apps through the content hub. The app data directory is hidden from
+//! the user, so they are staged in the cache instead, and removed again at the next start.
+
+use crate::constants::APP_NAME;
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const SHARE_DIR: &str = "share";
+
+/// Where the files are staged, in the cache like Qt's CacheLocation
+pub fn dir() -> PathBuf {
+    cache_dir(env::var("XDG_CACHE_HOME").ok(), env::var("HOME").ok())
+        .join(APP_NAME)
+        .join(SHARE_DIR)
+}
+
+fn cache_dir(xdg_cache_home: Option<String>, home: Option<String>) -> PathBuf {
+    xdg_cache_home
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| PathBuf::from(home.unwrap_or_default()).join(".cache"))
+}
+
+/// Only keep the characters that are safe in a file name on any peer
+fn file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn url(file: &Path) -> String {
+    format!("file://{}", file.to_string_lossy())
+}
+
+/// Stage the data under the name, and return the url to hand to the content hub
+pub fn write(name: &str, data: &[u8]) -> Result<String, String> {
+    let dir = dir();
+    fs::create_dir_all(&dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    let file = dir.join(file_name(name));
+    fs::write(&file, data).map_err(|e| format!("Failed to stage {:?}: {}", file, e))?;
+    Ok(url(&file))
+}
+
+/// Stage a copy of the file
+pub fn copy(file: &Path) -> Result<String, String> {
+    let name = file
+        .file_name()
+        .ok_or_else(|| format!("Not a file: {:?}", file))?
+        .to_string_lossy()
+        .to_string();
+    let data = fs::read(file).map_err(|e| format!("Failed to read {:?}: {}", file, e))?;
+    write(&name, &data)
+}
+
+/// Remove the files that were shared before
+pub fn clear() {
+    let dir = dir();
+    if dir.exists() {
+        if let Err(e) = fs::remove_dir_all(&dir) {
+            log_error!("Failed to remove {:?}: {}", dir, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache_dir() {
+        assert_eq!(
+            cache_dir(None, Some("/home/phablet".to_string())),
+            PathBuf::from("/home/phablet/.cache")
+        );
+        assert_eq!(
+            cache_dir(
+                Some("/cache".to_string()),
+                Some("/home/phablet".to_string())
+            ),
+            PathBuf::from("/cache")
+        );
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(file_name("receipt_0a1b.txt"), "receipt_0a1b.txt");
+        assert_eq!(file_name("../x y.png"), ".._x_y.png");
+    }
+}
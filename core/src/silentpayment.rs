@@ -0,0 +1,310 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Sending to BIP352 silent payment codes. The taproot output is derived from the keys of
+//! the inputs the transaction spends, so the on-chain wallet of the seed is loaded into bdk,
+//! the transaction built with a placeholder output, and the output replaced before signing.
+
+use crate::error::WalletError;
+use crate::i18n::{tr, trf};
+use crate::recovery;
+
+use bdk::{
+    bitcoin::{
+        bip32::ExtendedPrivKey,
+        consensus::serialize,
+        hashes::{sha256, Hash, HashEngine},
+        key::TweakedPublicKey,
+        secp256k1::{PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey},
+        Network, OutPoint, ScriptBuf, Txid,
+    },
+    FeeRate, SignOptions,
+};
+use bech32::{FromBase32, Variant};
+use ldk_node::bip39::Mnemonic;
+use std::fmt;
+
+/// A silent payment code like sp1q..., with the scan and the spend key of the receiver
+#[derive(Clone, Debug, PartialEq)]
+pub struct SilentPaymentCode {
+    code: String,
+    scan: PublicKey,
+    spend: PublicKey,
+}
+
+impl SilentPaymentCode {
+    /// Decode the code, which has to be for the network of the wallet
+    pub fn parse(code: &str, network: Network) -> Result<Self, String> {
+        let (hrp, data, variant) =
+            bech32::decode(code).map_err(|e| trf("Invalid silent payment code: {}", &[&e]))?;
+        let expected = if network == Network::Bitcoin {
+            "sp"
+        } else {
+            "tsp"
+        };
+        if hrp != expected {
+            return Err(trf(
+                "The silent payment code doesn't look like it is for {}",
+                &[&network],
+            ));
+        }
+        // the versions up to 30 start with the same keys, only 0 is known so far
+        let version = data.first().map(|v| v.to_u8());
+        if variant != Variant::Bech32m || !matches!(version, Some(0..=30)) {
+            return Err(tr("Unsupported silent payment code version"));
+        }
+        let keys = Vec::<u8>::from_base32(&data[1..])
+            .map_err(|e| trf("Invalid silent payment code: {}", &[&e]))?;
+        if keys.len() < 66 || (version == Some(0) && keys.len() != 66) {
+            return Err(tr("The silent payment code has the wrong length"));
+        }
+        let key = |bytes: &[u8]| {
+            PublicKey::from_slice(bytes).map_err(|e| trf("Invalid silent payment code: {}", &[&e]))
+        };
+        Ok(Self {
+            code: code.to_string(),
+            scan: key(&keys[..33])?,
+            spend: key(&keys[33..66])?,
+        })
+    }
+}
+
+impl fmt::Display for SilentPaymentCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code)
+    }
+}
+
+fn tagged_hash(tag: &str, msg: &[u8]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(tag.as_byte_array());
+    engine.input(tag.as_byte_array());
+    engine.input(msg);
+    sha256::Hash::from_engine(engine).to_byte_array()
+}
+
+fn scalar(bytes: [u8; 32]) -> Result<Scalar, String> {
+    Scalar::from_be_bytes(bytes).map_err(|_| "Hash out of the curve order".to_string())
+}
+
+/// The x-only key of the first output to the code, from the keys of all the inputs. The
+/// inputs have to be P2WPKH, whose keys are taken as they are.
+fn output_key(
+    input_keys: &[SecretKey],
+    outpoints: &[OutPoint],
+    code: &SilentPaymentCode,
+) -> Result<XOnlyPublicKey, String> {
+    let secp = Secp256k1::new();
+    let (first, rest) = input_keys
+        .split_first()
+        .ok_or_else(|| "No inputs to derive the silent payment from".to_string())?;
+    let sum = rest.iter().try_fold(*first, |sum, key| {
+        sum.add_tweak(&Scalar::from(*key))
+            .map_err(|_| "The keys of the inputs cancel out".to_string())
+    })?;
+    let smallest = outpoints
+        .iter()
+        .map(serialize)
+        .min()
+        .ok_or_else(|| "No inputs to derive the silent payment from".to_string())?;
+    let mut msg = smallest;
+    msg.extend_from_slice(&sum.public_key(&secp).serialize());
+    let input_hash = scalar(tagged_hash("BIP0352/Inputs", &msg))?;
+
+    let tweak = sum
+        .mul_tweak(&input_hash)
+        .map_err(|e| format!("Failed to tweak the input keys: {}", e))?;
+    let shared = code
+        .scan
+        .mul_tweak(&secp, &Scalar::from(tweak))
+        .map_err(|e| format!("Failed to derive the shared secret: {}", e))?;
+    let mut msg = shared.serialize().to_vec();
+    // the first output to the receiver, k = 0
+    msg.extend_from_slice(&0u32.to_be_bytes());
+    let t = scalar(tagged_hash("BIP0352/SharedSecret", &msg))?;
+    let output = code
+        .spend
+        .add_exp_tweak(&secp, &t)
+        .map_err(|e| format!("Failed to derive the output key: {}", e))?;
+    Ok(output.x_only_public_key().0)
+}
+
+fn p2tr(key: XOnlyPublicKey) -> ScriptBuf {
+    ScriptBuf::new_v1_p2tr_tweaked(TweakedPublicKey::dangerous_assume_tweaked(key))
+}
+
+/// Send the amount to the code from the on-chain wallet of the seed
+pub async fn send(
+    code: &SilentPaymentCode,
+    amount: u64,
+    sat_per_vbyte: f64,
+    mnemonic: &Mnemonic,
+    network: Network,
+) -> Result<Txid, WalletError> {
+    let secp = Secp256k1::new();
+    let master = ExtendedPrivKey::new_master(network, &mnemonic.to_seed(""))
+        .map_err(|e| WalletError::Other(format!("Failed to derive the master key: {}", e)))?;
+    let (wallet, blockchain) = recovery::seed_wallet(mnemonic, network).await?;
+
+    // a taproot output of the same size, so that the fee still fits after the swap
+    let placeholder = p2tr(code.spend.x_only_public_key().0);
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(placeholder.clone(), amount)
+        .fee_rate(FeeRate::from_sat_per_vb(sat_per_vbyte as f32))
+        .enable_rbf();
+    let (mut psbt, _) = builder
+        .finish()
+        .map_err(|e| WalletError::Node(trf("Failed to build the transaction: {}", &[&e])))?;
+
+    let mut input_keys = Vec::new();
+    for input in &psbt.inputs {
+        let (pubkey, (_, path)) = input
+            .bip32_derivation
+            .iter()
+            .next()
+            .ok_or_else(|| WalletError::Node(tr("An input of the wallet has no key origin")))?;
+        let key = master
+            .derive_priv(&secp, path)
+            .map_err(|e| WalletError::Node(format!("Failed to derive an input key: {}", e)))?
+            .private_key;
+        if key.public_key(&secp) != *pubkey {
+            return Err(WalletError::Node(tr(
+                "An input key doesn't match its origin",
+            )));
+        }
+        input_keys.push(key);
+    }
+    let outpoints: Vec<OutPoint> = psbt
+        .unsigned_tx
+        .input
+        .iter()
+        .map(|txin| txin.previous_output)
+        .collect();
+    let script = p2tr(output_key(&input_keys, &outpoints, code).map_err(WalletError::Node)?);
+    for output in psbt.unsigned_tx.output.iter_mut() {
+        if output.script_pubkey == placeholder {
+            output.script_pubkey = script.clone();
+        }
+    }
+
+    let finalized = wallet
+        .sign(&mut psbt, SignOptions::default())
+        .map_err(|e| WalletError::Node(trf("Failed to sign the transaction: {}", &[&e])))?;
+    if !finalized {
+        return Err(WalletError::Node(tr("Failed to sign all the inputs")));
+    }
+    let tx = psbt.extract_tx();
+    blockchain
+        .broadcast(&tx)
+        .await
+        .map_err(|e| WalletError::Network(trf("Failed to broadcast the transaction: {}", &[&e])))?;
+    log_info!("silent payment sent: {}", tx.txid());
+    Ok(tx.txid())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::{u5, ToBase32};
+    use std::str::FromStr;
+
+    fn secret(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn encode(hrp: &str, version: u8, scan: &PublicKey, spend: &PublicKey) -> String {
+        let mut keys = scan.serialize().to_vec();
+        keys.extend_from_slice(&spend.serialize());
+        let mut data = vec![u5::try_from_u8(version).unwrap()];
+        data.extend(keys.to_base32());
+        bech32::encode(hrp, data, Variant::Bech32m).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let secp = Secp256k1::new();
+        let scan = secret(1).public_key(&secp);
+        let spend = secret(2).public_key(&secp);
+        let code = encode("sp", 0, &scan, &spend);
+        assert!(code.starts_with("sp1q"));
+        let parsed = SilentPaymentCode::parse(&code, Network::Bitcoin).unwrap();
+        assert_eq!(parsed.scan, scan);
+        assert_eq!(parsed.spend, spend);
+        assert_eq!(parsed.to_string(), code);
+
+        assert!(SilentPaymentCode::parse(&code, Network::Testnet).is_err());
+        let tcode = encode("tsp", 0, &scan, &spend);
+        assert!(SilentPaymentCode::parse(&tcode, Network::Signet).is_ok());
+        assert!(
+            SilentPaymentCode::parse(&encode("sp", 31, &scan, &spend), Network::Bitcoin).is_err()
+        );
+        assert!(SilentPaymentCode::parse(&code[..code.len() - 1], Network::Bitcoin).is_err());
+    }
+
+    /// Sending vectors from send_and_receive_test_vectors.json of BIP352, the inputs are
+    /// P2PKH and P2WPKH, whose keys are used as they are
+    #[test]
+    fn test_output_key() {
+        let code = SilentPaymentCode::parse(
+            "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv",
+            Network::Bitcoin,
+        )
+        .unwrap();
+        let keys = [
+            SecretKey::from_str("eadc78165ff1f8ea94ad7cfdc54990738a4c53f6e0507b42154201b8e5dff3b1")
+                .unwrap(),
+            SecretKey::from_str("93f5ed907ad5b2bdbbdcb5d9116ebc0a4e1f92f910d5260237fa45a9408aad16")
+                .unwrap(),
+        ];
+        let outpoint = |s: &str| OutPoint::from_str(s).unwrap();
+        let txid = "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16";
+        let vectors = [
+            // simple send: two inputs
+            (
+                [
+                    outpoint(&format!("{}:0", txid)),
+                    outpoint("a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d:0"),
+                ],
+                "3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1",
+            ),
+            // simple send: two inputs from the same transaction
+            (
+                [
+                    outpoint(&format!("{}:3", txid)),
+                    outpoint(&format!("{}:7", txid)),
+                ],
+                "79e71baa2ba3fc66396de3a04f168c7bf24d6870ec88ca877754790c1db357b6",
+            ),
+            // the outpoints are ordered by their serialization, not by the vout
+            (
+                [
+                    outpoint(&format!("{}:1", txid)),
+                    outpoint(&format!("{}:256", txid)),
+                ],
+                "a85ef8701394b517a4b35217c4bd37ac01ebeed4b008f8d0879f9e09ba95319c",
+            ),
+        ];
+        for (outpoints, expected) in vectors {
+            let expected = XOnlyPublicKey::from_str(expected).unwrap();
+            assert_eq!(output_key(&keys, &outpoints, &code).unwrap(), expected);
+            // the order of the inputs doesn't matter
+            let swapped = output_key(&[keys[1], keys[0]], &[outpoints[1], outpoints[0]], &code);
+            assert_eq!(swapped.unwrap(), expected);
+        }
+    }
+}
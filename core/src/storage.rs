@@ -0,0 +1,132 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The json files of the app in the data directory of the active wallet. They are replaced
+//! through a temporary file, so a crash leaves either the old or the new content behind.
+
+use crate::profiles;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+pub fn data_file(name: &str) -> PathBuf {
+    profiles::data_dir().join(name)
+}
+
+/// Where write_file puts the data before it replaces the file
+pub fn temp_file(file: &Path) -> PathBuf {
+    let mut name = file.as_os_str().to_owned();
+    name.push(".tmp");
+    PathBuf::from(name)
+}
+
+/// Replace the file with the data, which get the permissions of the mode
+pub fn write_file(file: &Path, data: &[u8], mode: u32) -> Result<(), String> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("Failed to create directory: {}", e))?;
+    }
+    let tmp = temp_file(file);
+    let mut output = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode)
+        .open(&tmp)
+        .map_err(|e| format!("Failed to create {:?}: {}", tmp, e))?;
+    // the mode only applies to newly created files
+    fs::set_permissions(&tmp, fs::Permissions::from_mode(mode))
+        .map_err(|e| format!("Failed to set the permissions of {:?}: {}", tmp, e))?;
+    output
+        .write_all(data)
+        .and_then(|_| output.sync_all())
+        .map_err(|e| format!("Failed to write {:?}: {}", tmp, e))?;
+    fs::rename(&tmp, file).map_err(|e| format!("Failed to replace {:?}: {}", file, e))?;
+    if let Some(dir) = file.parent() {
+        fs::File::open(dir)
+            .and_then(|d| d.sync_all())
+            .map_err(|e| format!("Failed to sync {:?}: {}", dir, e))?;
+    }
+    Ok(())
+}
+
+pub fn load_json<T: DeserializeOwned + Default>(name: &str) -> Result<T, String> {
+    let file = data_file(name);
+    if !file.exists() {
+        return Ok(T::default());
+    }
+    let json =
+        fs::read_to_string(&file).map_err(|e| format!("Failed to read {:?}: {}", file, e))?;
+    serde_json::from_str(&json).map_err(|e| format!("Failed to parse {:?}: {}", file, e))
+}
+
+pub fn save_json<T: Serialize>(name: &str, data: &T) -> Result<(), String> {
+    let file = data_file(name);
+    let json =
+        serde_json::to_string(data).map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+    write_file(&file, json.as_bytes(), 0o644)
+}
+
+/// Lower case hex representation of some bytes
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The error doesn't repeat the input, it may be a key
+pub fn from_hex(hex: &str) -> Result<Vec<u8>, String> {
+    if hex.len() % 2 != 0 {
+        return Err(format!("Invalid hex of odd length {}", hex.len()));
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+                .ok_or_else(|| format!("Invalid hex at position {}", i))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(to_hex(&[0, 0xab, 0x7f]), "00ab7f");
+        assert_eq!(from_hex("00ab7F").unwrap(), vec![0, 0xab, 0x7f]);
+        let err = from_hex("00secret").unwrap_err();
+        assert!(!err.contains("secret"));
+        assert!(from_hex("abc").is_err());
+    }
+
+    #[test]
+    fn test_write_file() {
+        let dir =
+            std::env::temp_dir().join(format!("utwallet-storage-test-{}", std::process::id()));
+        let file = dir.join("settings.json");
+        write_file(&file, b"old", 0o644).unwrap();
+        write_file(&file, b"new", 0o644).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "new");
+        assert!(!temp_file(&file).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
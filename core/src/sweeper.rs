@@ -1,7 +1,9 @@
+use crate::error::WalletError;
+use crate::esplora;
+use crate::i18n::trf;
 use crate::input_eval::PrivateKeys;
 use bdk::{
     bitcoin::{Address, Network},
-    blockchain::EsploraBlockchain,
     database::MemoryDatabase,
     SignOptions, SyncOptions, Wallet,
 };
@@ -16,7 +18,7 @@ impl Sweeper {
         &self,
         privkeys: &PrivateKeys,
         destination: &Address,
-    ) -> Result<String, String> {
+    ) -> Result<String, WalletError> {
         let descriptors = Self::descriptors(privkeys)?;
 
         // note: I tried to use tokio JoinSet here to make it cocurrent, but bdk::wallet is not suitable to pass between threads.
@@ -37,46 +39,49 @@ impl Sweeper {
         }
     }
 
-    async fn sweep_one(&self, desc: &str, destination: &Address) -> Result<Option<String>, String> {
+    async fn sweep_one(
+        &self,
+        desc: &str,
+        destination: &Address,
+    ) -> Result<Option<String>, WalletError> {
         let wallet = Wallet::new(desc, None, self.network, MemoryDatabase::default())
-            .map_err(|e| format!("Failed to construct sweep wallet: {}", e))?;
-        let blockchain = EsploraBlockchain::new(&self.esplora_url, 20);
+            .map_err(|e| WalletError::Node(trf("Failed to construct sweep wallet: {}", &[&e])))?;
+        let blockchain = esplora::blockchain(&self.esplora_url).map_err(WalletError::Network)?;
         wallet
             .sync(&blockchain, SyncOptions::default())
             .await
-            .map_err(|e| format!("Failed to sync sweep wallet: {}", e))?;
+            .map_err(|e| WalletError::Network(trf("Failed to sync sweep wallet: {}", &[&e])))?;
 
         if let Ok(bal) = wallet.get_balance() {
             if bal.get_total() <= 0 {
                 return Ok(None);
             }
-            println!("sweeping {} to {}", bal, destination.to_string());
+            log_info!("sweeping {} to {}", bal, destination.to_string());
             let mut builder = wallet.build_tx();
             builder
                 .drain_wallet()
                 .drain_to(destination.script_pubkey())
                 .enable_rbf();
-            let (mut psbt, _) = builder
-                .finish()
-                .map_err(|e| format!("Failed to construct sweep transaction: {}", e))?;
+            let (mut psbt, _) = builder.finish().map_err(|e| {
+                WalletError::Node(trf("Failed to construct sweep transaction: {}", &[&e]))
+            })?;
             let signopt = SignOptions {
                 ..Default::default()
             };
-            wallet
-                .sign(&mut psbt, signopt)
-                .map_err(|e| format!("Failed to sign sweep transaction: {}", e))?;
+            wallet.sign(&mut psbt, signopt).map_err(|e| {
+                WalletError::Node(trf("Failed to sign sweep transaction: {}", &[&e]))
+            })?;
             let tx = psbt.extract_tx();
-            blockchain
-                .broadcast(&tx)
-                .await
-                .map_err(|e| format!("Failed to broadcast sweep transaction: {}", e))?;
+            blockchain.broadcast(&tx).await.map_err(|e| {
+                WalletError::Network(trf("Failed to broadcast sweep transaction: {}", &[&e]))
+            })?;
             Ok(Some(format!("swept {}", bal.get_total())))
         } else {
             Ok(None)
         }
     }
 
-    fn descriptors(privkeys: &PrivateKeys) -> Result<Vec<String>, String> {
+    fn descriptors(privkeys: &PrivateKeys) -> Result<Vec<String>, WalletError> {
         match privkeys {
             PrivateKeys::Desc(desc) => Ok(vec![desc.to_string()]),
             PrivateKeys::Pk(_) | PrivateKeys::Epk(_) => {
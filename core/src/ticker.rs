@@ -0,0 +1,107 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::rates::{get_json, Rate};
+use crate::storage::{load_json, save_json};
+
+use serde_json::Value;
+
+/// How far back the series reaches
+const SERIES_SECS: i64 = 24 * 3600;
+/// One sample is kept per bucket
+const BUCKET_SECS: i64 = 3600;
+
+/// Prices of one BTC as (unix timestamp, price), oldest first
+pub type Series = Vec<(i64, f64)>;
+
+fn series_file(currency: &str) -> String {
+    format!("price_series_{}.json", currency.to_lowercase())
+}
+
+/// Add a freshly fetched rate to the series of its currency. If the series is too short to
+/// draw, it is backfilled from CoinGecko first.
+pub fn record(rate: &Rate) -> Result<(), String> {
+    let file = series_file(&rate.currency);
+    let mut series: Series = load_json(&file)?;
+    if series.len() < 2 {
+        let json = get_json(&format!(
+            "https://api.coingecko.com/api/v3/coins/bitcoin/market_chart?vs_currency={}&days=1",
+            rate.currency.to_lowercase()
+        ))?;
+        for (timestamp, price) in parse_market_chart(&json)? {
+            push_sample(&mut series, timestamp, price);
+        }
+    }
+    push_sample(&mut series, rate.timestamp, rate.rate);
+    save_json(&file, &series)
+}
+
+/// The series of the configured currency
+pub fn series(currency: &str) -> Result<Series, String> {
+    load_json(&series_file(currency))
+}
+
+/// Add a sample, keeping only the latest one per hour and only the last day
+fn push_sample(series: &mut Series, timestamp: i64, price: f64) {
+    match series.last_mut() {
+        Some(last) if timestamp < last.0 => return,
+        Some(last) if last.0 / BUCKET_SECS == timestamp / BUCKET_SECS => *last = (timestamp, price),
+        _ => series.push((timestamp, price)),
+    }
+    series.retain(|(t, _)| *t > timestamp - SERIES_SECS);
+}
+
+fn parse_market_chart(json: &Value) -> Result<Series, String> {
+    json["prices"]
+        .as_array()
+        .ok_or("no prices in the market chart")?
+        .iter()
+        .map(|point| match (point[0].as_i64(), point[1].as_f64()) {
+            (Some(ms), Some(price)) => Ok((ms / 1_000, price)),
+            _ => Err(format!("invalid price point {}", point)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_sample() {
+        let mut series = Series::new();
+        push_sample(&mut series, 3_600, 1.0);
+        push_sample(&mut series, 3_700, 2.0);
+        assert_eq!(series, vec![(3_700, 2.0)]);
+        push_sample(&mut series, 7_200, 3.0);
+        assert_eq!(series, vec![(3_700, 2.0), (7_200, 3.0)]);
+        push_sample(&mut series, 3_800, 4.0);
+        assert_eq!(series.len(), 2);
+        push_sample(&mut series, 3_700 + SERIES_SECS, 5.0);
+        assert_eq!(series, vec![(7_200, 3.0), (3_700 + SERIES_SECS, 5.0)]);
+    }
+
+    #[test]
+    fn test_parse_market_chart() {
+        let json =
+            serde_json::from_str(r#"{"prices":[[1700000000000,30000.5],[1700003600000,31000.0]]}"#)
+                .unwrap();
+        assert_eq!(
+            parse_market_chart(&json),
+            Ok(vec![(1_700_000_000, 30000.5), (1_700_003_600, 31000.0)])
+        );
+    }
+}
@@ -0,0 +1,387 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Checks of the single input fields, without going to the network, so the GUI can mark the
+//! offending field while the user types.
+
+use crate::batch;
+use crate::format::Denomination;
+use crate::i18n::{tr, trf};
+use crate::input_eval::{
+    is_node_id, normalize_case, parse_fiat, parse_satoshis, BTC_ADDRESS_PATTERN,
+};
+use crate::psbt::{self, PSBT_PREFIX};
+use crate::rates::Rate;
+use crate::silentpayment::SilentPaymentCode;
+
+use ldk_node::bitcoin::{
+    bip32::ExtendedPrivKey, blockdata::constants::ChainHash, Address, Network, PrivateKey,
+};
+use ldk_node::lightning::offers::offer::{Amount, Offer};
+use ldk_node::lightning::offers::refund::Refund;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use lnurl::lnurl::LnUrl;
+use regex::Regex;
+use std::str::FromStr;
+
+/// the longest description a BOLT11 invoice can hold
+const MAX_DESCRIPTION_BYTES: usize = 639;
+/// below this, on-chain outputs are considered dust
+const DUST_SATS: u64 = 546;
+const MAX_SATS: u64 = 21_000_000 * 100_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Field {
+    Address,
+    Amount,
+    Description,
+}
+
+impl FromStr for Field {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "address" => Ok(Self::Address),
+            "amount" => Ok(Self::Amount),
+            "description" => Ok(Self::Description),
+            _ => Err(format!("Unknown input field {}", s)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Validation {
+    Valid,
+    /// usable, but the user should have a second look
+    Warning(String),
+    Error(String),
+}
+
+impl Validation {
+    pub fn level(&self) -> &'static str {
+        match self {
+            Self::Valid => "valid",
+            Self::Warning(_) => "warning",
+            Self::Error(_) => "error",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::Valid => "",
+            Self::Warning(msg) | Self::Error(msg) => msg,
+        }
+    }
+}
+
+/// Check the value of one field. The address has to be for the network, the amount is in
+/// the denomination or in the currency of the rate.
+pub fn validate_input(
+    field: Field,
+    value: &str,
+    network: Network,
+    denomination: Denomination,
+    rate: Option<&Rate>,
+) -> Validation {
+    match field {
+        Field::Address => validate_address(value.trim(), network),
+        Field::Amount => validate_amount(value.trim(), denomination, rate),
+        Field::Description => validate_description(value),
+    }
+}
+
+fn validate_address(value: &str, network: Network) -> Validation {
+    if value.is_empty() {
+        return Validation::Error(tr("The address field needs to be filled"));
+    }
+    let value = &normalize_case(value);
+
+    let onchain = Regex::new(&format!(
+        r"^(?:bitcoin:)?({})(?:\?.*)?$",
+        BTC_ADDRESS_PATTERN
+    ))
+    .expect("valid regex");
+    if let Some(caps) = onchain.captures(value) {
+        return match Address::from_str(&caps[1]).map(|a| a.require_network(network)) {
+            Ok(Ok(_)) => Validation::Valid,
+            Ok(Err(_)) => Validation::Error(trf(
+                "The address doesn't look like it is for {}",
+                &[&network],
+            )),
+            Err(e) => Validation::Error(trf("Invalid address: {}", &[&e])),
+        };
+    }
+
+    if value.contains('\n') {
+        return match batch::parse(value, network) {
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(e),
+        };
+    }
+    if value.starts_with(PSBT_PREFIX) {
+        return match psbt::parse(value) {
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(e),
+        };
+    }
+    let lower = value.to_lowercase();
+    let lower = lower.strip_prefix("lightning:").unwrap_or(&lower);
+    if lower.starts_with("lnbc") || lower.starts_with("lntb") {
+        return match Bolt11Invoice::from_str(lower) {
+            Ok(invoice) if invoice.network() != network => {
+                Validation::Error(trf("The invoice is not for {}", &[&network]))
+            }
+            Ok(invoice) if invoice.is_expired() => Validation::Error(tr("The invoice expired")),
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(trf("Invalid invoice: {}", &[&e])),
+        };
+    }
+    if lower.starts_with("lno1") {
+        return match Offer::from_str(lower) {
+            Ok(offer) if matches!(offer.amount(), Some(Amount::Currency { .. })) => {
+                Validation::Error(tr("For BOLT12 we only support BTC at the moment"))
+            }
+            Ok(offer) if !offer.supports_chain(ChainHash::using_genesis_block(network)) => {
+                Validation::Error(trf("The offer can't be paid on {}", &[&network]))
+            }
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(trf("Invalid offer: {}", &[&format!("{:?}", e)])),
+        };
+    }
+    if lower.starts_with("lnr1") {
+        return match Refund::from_str(lower) {
+            Ok(refund) if refund.chain() != ChainHash::using_genesis_block(network) => {
+                Validation::Error(trf("The refund is not for {}", &[&network]))
+            }
+            Ok(refund) if refund.is_expired() => Validation::Error(tr("The refund expired")),
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(trf("Invalid refund: {}", &[&format!("{:?}", e)])),
+        };
+    }
+    if lower.starts_with("sp1") || lower.starts_with("tsp1") {
+        return match SilentPaymentCode::parse(lower, network) {
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(e),
+        };
+    }
+    if lower.starts_with("lnurl1") {
+        return match LnUrl::from_str(lower) {
+            Ok(_) => Validation::Valid,
+            Err(e) => Validation::Error(trf("Invalid LNURL: {}", &[&e])),
+        };
+    }
+    // resolved when sending
+    let lnaddr =
+        Regex::new(r"^[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,6}$").expect("valid regex");
+    if value.starts_with("lnurlw://") || value.starts_with("https://") || lnaddr.is_match(value) {
+        return Validation::Valid;
+    }
+
+    if PrivateKey::from_wif(value).is_ok() || ExtendedPrivKey::from_str(value).is_ok() {
+        return Validation::Warning(tr(
+            "This is a private key, all its funds are swept into this wallet",
+        ));
+    }
+    if is_node_id(value) {
+        return Validation::Warning(tr("A node id can only be used to open a channel"));
+    }
+
+    Validation::Error(tr("Unknown input format"))
+}
+
+fn validate_amount(value: &str, denomination: Denomination, rate: Option<&Rate>) -> Validation {
+    // invoices and LNURL often carry the amount
+    if value.is_empty() {
+        return Validation::Valid;
+    }
+    let sats = match parse_fiat(value) {
+        Some((fiat, currency)) => match rate {
+            Some(rate) if rate.currency != currency => {
+                return Validation::Error(trf(
+                    "The exchange rate is known in {}, not in {}",
+                    &[&rate.currency, &currency],
+                ))
+            }
+            Some(rate) if rate.rate > 0.0 => (fiat / rate.rate * 100_000_000.0) as u64,
+            _ => {
+                return Validation::Warning(tr("The exchange rate is not known yet"));
+            }
+        },
+        None => match denomination
+            .to_btc(value)
+            .and_then(|btc| parse_satoshis(&btc).map_err(|e| e.to_string()))
+        {
+            Ok(sats) => sats,
+            Err(e) => return Validation::Error(e),
+        },
+    };
+    if sats == 0 {
+        Validation::Error(tr("The amount needs to be above zero"))
+    } else if sats > MAX_SATS {
+        Validation::Error(tr("The amount is above 21 million bitcoin"))
+    } else if sats < DUST_SATS {
+        Validation::Warning(tr("The amount is too small for an on-chain payment"))
+    } else {
+        Validation::Valid
+    }
+}
+
+fn validate_description(value: &str) -> Validation {
+    if value.len() > MAX_DESCRIPTION_BYTES {
+        Validation::Error(trf(
+            "The description is longer than {} bytes",
+            &[&MAX_DESCRIPTION_BYTES],
+        ))
+    } else if value.chars().any(char::is_control) {
+        Validation::Warning(tr("The description contains control characters"))
+    } else {
+        Validation::Valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate() -> Rate {
+        Rate {
+            rate: 50_000.0,
+            currency: "CHF".to_string(),
+            source: "test".to_string(),
+            timestamp: 0,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn test_address() {
+        let check = |value| {
+            validate_input(
+                Field::Address,
+                value,
+                Network::Bitcoin,
+                Denomination::Btc,
+                None,
+            )
+        };
+        assert_eq!(
+            check("3M5f673Ler6iJbatJNvex7EYANRsydSQXE"),
+            Validation::Valid
+        );
+        assert_eq!(
+            check("bitcoin:bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa?amount=1"),
+            Validation::Valid
+        );
+        assert_eq!(
+            check("bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"),
+            Validation::Valid
+        );
+        assert_eq!(
+            check("BITCOIN:BC1QA8DN66XN2YQ4FCAEE4F0GWKKR6E6EM643CM8FA?AMOUNT=1"),
+            Validation::Valid
+        );
+        assert_eq!(check("satoshi@example.com"), Validation::Valid);
+        assert_eq!(
+            check("bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.001\n3M5f673Ler6iJbatJNvex7EYANRsydSQXE, 0.002"),
+            Validation::Valid
+        );
+        assert_eq!(
+            check("bc1qa8dn66xn2yq4fcaee4f0gwkkr6e6em643cm8fa, 0.001\n3M5f673Ler6iJbatJNvex7EYANRsydSQXE").level(),
+            "error"
+        );
+        assert_eq!(check("").level(), "error");
+        assert_eq!(check("1234567890").level(), "error");
+        assert_eq!(check("lnbc1garbage").level(), "error");
+        assert_eq!(check("sp1qgarbage").level(), "error");
+        assert_eq!(
+            check("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").level(),
+            "error"
+        );
+
+        let testnet = validate_input(
+            Field::Address,
+            "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+            Network::Testnet,
+            Denomination::Btc,
+            None,
+        );
+        assert_eq!(testnet, Validation::Valid);
+    }
+
+    #[test]
+    fn test_amount() {
+        let check = |value| {
+            validate_input(
+                Field::Amount,
+                value,
+                Network::Bitcoin,
+                Denomination::Sats,
+                Some(&rate()),
+            )
+        };
+        assert_eq!(check(""), Validation::Valid);
+        assert_eq!(check("1000"), Validation::Valid);
+        assert_eq!(check("10").level(), "warning");
+        assert_eq!(check("0").level(), "error");
+        assert_eq!(check("abc").level(), "error");
+        assert_eq!(check("5 CHF"), Validation::Valid);
+        assert_eq!(check("5 EUR").level(), "error");
+        assert_eq!(
+            validate_input(
+                Field::Amount,
+                "5 EUR",
+                Network::Bitcoin,
+                Denomination::Sats,
+                None
+            )
+            .level(),
+            "warning"
+        );
+        assert_eq!(
+            validate_input(
+                Field::Amount,
+                "0.001",
+                Network::Bitcoin,
+                Denomination::Btc,
+                None
+            ),
+            Validation::Valid
+        );
+    }
+
+    #[test]
+    fn test_description() {
+        let check = |value: &str| {
+            validate_input(
+                Field::Description,
+                value,
+                Network::Bitcoin,
+                Denomination::Btc,
+                None,
+            )
+        };
+        assert_eq!(check("lunch split"), Validation::Valid);
+        assert_eq!(check("a\u{7}b").level(), "warning");
+        assert_eq!(check(&"x".repeat(640)).level(), "error");
+    }
+
+    #[test]
+    fn test_field() {
+        assert_eq!(Field::from_str("amount"), Ok(Field::Amount));
+        assert!(Field::from_str("fee").is_err());
+    }
+}
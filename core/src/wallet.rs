@@ -0,0 +1,1927 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::batch;
+use crate::constants::{
+    LDK_NODE_VERSION, LN_ULR, NODE_RESTART_RETRY_SECS, NODE_WAIT_SECS, SEED_SPEND_VBYTES,
+};
+use crate::demo;
+use crate::error::WalletError;
+use crate::esplora;
+use crate::eventlog::{self, Severity};
+use crate::gossip;
+use crate::history::{self, HistoryEntry};
+use crate::i18n::{noop, tr, trf};
+use crate::input_eval::{sats_to_msat, PrivateKeys};
+use crate::instance;
+use crate::invoices;
+use crate::message;
+use crate::payjoin;
+use crate::payments;
+use crate::pin;
+use crate::pos;
+use crate::preview;
+use crate::profiles;
+use crate::proxy;
+use crate::psbt;
+use crate::recovery;
+use crate::secrets;
+use crate::settings::Settings;
+use crate::silentpayment::{self, SilentPaymentCode};
+use crate::storage::{self, from_hex, to_hex};
+use crate::watchonly;
+use crate::webhook;
+
+use chrono::Utc;
+use ldk_node::bip39::Mnemonic;
+use ldk_node::bitcoin::hashes::Hash;
+use ldk_node::bitcoin::{
+    psbt::PartiallySignedTransaction, secp256k1::PublicKey, Address, Network, Txid,
+};
+use ldk_node::lightning::events::{ClosureReason, PaymentFailureReason};
+use ldk_node::lightning::offers::offer::{Amount, Offer};
+use ldk_node::lightning::offers::refund::Refund;
+use ldk_node::lightning::routing::gossip::NodeId;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use ldk_node::payment::{PaymentDetails, PaymentDirection, PaymentId, PaymentStatus};
+use ldk_node::{Builder, Event, Node};
+use lnurl::api::LnUrlResponse;
+use rand_core::{OsRng, RngCore};
+use serde::Serialize;
+use std::{
+    fs,
+    fs::create_dir_all,
+    future::Future,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+pub struct BdkWallet {}
+
+/// The running node, and whether it is being replaced
+struct NodeSlot {
+    node: Option<Arc<Node>>,
+    changing: bool,
+}
+
+/// The calls only hold the lock to clone the handle, so a slow sync doesn't hold up the
+/// others. The node is replaced on restarts, profile switches and suspend.
+static UTNODE: Mutex<NodeSlot> = Mutex::new(NodeSlot {
+    node: None,
+    changing: false,
+});
+static NODE_CHANGED: Condvar = Condvar::new();
+/// Only one caller at a time stops and rebuilds the node
+static NODE_CHANGE: Mutex<()> = Mutex::new(());
+/// the payment hashes of the invoices we sent for BOLT12 refunds, until the funds arrive
+static PENDING_REFUNDS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The running node. While it is being replaced, wait for the new one rather than failing.
+fn running_node() -> Result<Arc<Node>, WalletError> {
+    let slot = UTNODE.lock().map_err(WalletError::poisoned)?;
+    let (slot, _) = NODE_CHANGED
+        .wait_timeout_while(slot, Duration::from_secs(NODE_WAIT_SECS), |s| {
+            s.changing && s.node.is_none()
+        })
+        .map_err(WalletError::poisoned)?;
+    slot.node.clone().ok_or(WalletError::NotInitialized)
+}
+
+/// Take the node out, the calls wait until put_node hands them the next one
+fn take_node() -> Result<Option<Arc<Node>>, WalletError> {
+    let mut slot = UTNODE.lock().map_err(WalletError::poisoned)?;
+    slot.changing = true;
+    Ok(slot.node.take())
+}
+
+fn put_node(node: Option<Node>) {
+    let mut slot = UTNODE.lock().unwrap_or_else(|e| e.into_inner());
+    slot.node = node.map(Arc::new);
+    slot.changing = false;
+    NODE_CHANGED.notify_all();
+}
+
+/// Hand out the new node, or none if it couldn't be built
+fn put_built_node(node: Result<Option<Node>, WalletError>) -> Result<(), WalletError> {
+    match node {
+        Ok(node) => {
+            put_node(node);
+            Ok(())
+        }
+        Err(e) => {
+            put_node(None);
+            Err(e)
+        }
+    }
+}
+
+fn stop_node(node: &Node) -> Result<(), WalletError> {
+    log_info!("stopping the ldk-node");
+    node.stop()
+        .map_err(|e| WalletError::Node(trf("Failed to stop ldk-node: {}", &[&format!("{:?}", e)])))
+}
+
+/// Take the node out and stop it. It is left out if stopping failed.
+fn take_and_stop_node() -> Result<(), WalletError> {
+    if let Some(node) = take_node()? {
+        if let Err(e) = stop_node(&node) {
+            put_node(None);
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// The seed words in plain text, where no keyring is available, or in a restored backup
+const MNEMONIC_FILE: &str = "mnemonic.txt";
+/// The seed words encrypted with a key from the keyring
+const SEALED_MNEMONIC_FILE: &str = "mnemonic.enc";
+
+/// A facade for bdk::Wallet with a singleton instance
+impl BdkWallet {
+    /// Bring up the node of the active profile, reporting the stages along the way
+    pub fn init_node(progress: impl Fn(StartupStage)) -> Result<(), WalletError> {
+        let _change = NODE_CHANGE.lock().map_err(WalletError::poisoned)?;
+        take_node()?;
+        put_built_node(Self::create_node_for_profile(&progress))
+    }
+
+    pub fn payto(recipient: Address, amount: u64) -> Result<Txid, WalletError> {
+        pin::require_for_amount(amount).map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::send_onchain(amount);
+        }
+        let node = running_node()?;
+
+        //if let Err(e) = node.sync_wallets() {
+        //    eprintln!("Failed to sync the wallet: {:?}", e);
+        //}
+
+        let txid = node
+            .onchain_payment()
+            .send_to_address(&recipient, amount)
+            .map_err(|e| {
+                WalletError::Node(trf("Failed to send on-chain: {}", &[&format!("{:?}", e)]))
+            })?;
+
+        log_info!("on-chain payment sent: {}", txid);
+
+        Ok(txid)
+    }
+
+    /// Pay several addresses with a single transaction, which is cheaper than one each
+    pub fn payto_many(recipients: &[(Address, u64)]) -> Result<Txid, WalletError> {
+        let amount = batch::total(recipients).map_err(WalletError::InvalidAmount)?;
+        pin::require_for_amount(amount).map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::send_onchain(amount);
+        }
+        Self::spend_from_seed(amount, |mnemonic, sat_per_vbyte| async move {
+            batch::send(recipients, sat_per_vbyte, &mnemonic, profiles::network()).await
+        })
+    }
+
+    /// Run a send that bdk builds from the seed, where the node can't build the transaction.
+    /// The node only learns about the spent coins on its next sync, so the amount must fit
+    /// into what the node considers spendable. That leaves the reserve it keeps to bump the
+    /// fees of anchor channels at force closes.
+    fn spend_from_seed<T, F, Fut>(amount: u64, send: F) -> Result<T, WalletError>
+    where
+        F: FnOnce(Mnemonic, f64) -> Fut,
+        Fut: Future<Output = Result<T, WalletError>>,
+    {
+        proxy::check_online().map_err(WalletError::Network)?;
+        let sat_per_vbyte = preview::refresh_fee_estimate()?;
+        let spendable = running_node()?
+            .list_balances()
+            .spendable_onchain_balance_sats;
+        let fee = (sat_per_vbyte * SEED_SPEND_VBYTES as f64).ceil() as u64;
+        if amount.saturating_add(fee) > spendable {
+            return Err(WalletError::InvalidAmount(trf(
+                "Only {} sats can be spent, the rest is the reserve for the channels",
+                &[&spendable.saturating_sub(fee)],
+            )));
+        }
+        let mnemonic = Self::mnemonic()?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        rt.block_on(send(mnemonic, sat_per_vbyte))
+    }
+
+    /// Sign the text with the key of one of our on-chain addresses, to prove we own it
+    pub fn sign_message(address: &str, text: &str) -> Result<String, WalletError> {
+        pin::require().map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return Err(WalletError::Other(tr(
+                "Signing messages is not available in the demo wallet",
+            )));
+        }
+        let mnemonic = Self::mnemonic()?;
+        message::sign(&mnemonic, profiles::network(), address, text)
+            .map_err(WalletError::InvalidInput)
+    }
+
+    /// Check the signature of a message, for any on-chain address
+    pub fn verify_message(address: &str, text: &str, signature: &str) -> Result<bool, WalletError> {
+        message::verify(profiles::network(), address, text, signature)
+            .map_err(WalletError::InvalidInput)
+    }
+
+    /// Pay the address with a BIP78 payjoin. Like for silent payments, the transaction is
+    /// built from the seed, and sent without payjoin if the receiver fails.
+    pub fn payjoin(recipient: &Address, amount: u64, endpoint: &str) -> Result<Txid, WalletError> {
+        pin::require_for_amount(amount).map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::send_onchain(amount);
+        }
+        Self::spend_from_seed(amount, |mnemonic, sat_per_vbyte| async move {
+            payjoin::send(
+                recipient,
+                amount,
+                endpoint,
+                sat_per_vbyte,
+                &mnemonic,
+                profiles::network(),
+            )
+            .await
+        })
+    }
+
+    /// Pay a silent payment code. The node can't build the output, so the transaction is
+    /// built from the seed, and the node learns about it with the next sync.
+    pub fn pay_silent(code: &SilentPaymentCode, amount: u64) -> Result<Txid, WalletError> {
+        pin::require_for_amount(amount).map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::send_onchain(amount);
+        }
+        Self::spend_from_seed(amount, |mnemonic, sat_per_vbyte| async move {
+            silentpayment::send(code, amount, sat_per_vbyte, &mnemonic, profiles::network()).await
+        })
+    }
+
+    /// Sign a pasted PSBT with the on-chain keys. Returns the txid once it's broadcast,
+    /// or where the PSBT was saved if other signers are still missing. Nothing is signed
+    /// unless `confirm` accepts what it costs us and the fee, both in sats.
+    pub fn sign_psbt(
+        psbt: PartiallySignedTransaction,
+        confirm: impl FnOnce(u64, u64) -> bool,
+    ) -> Result<String, WalletError> {
+        let mnemonic = Self::mnemonic()?;
+        // the change stays ours, so only what leaves the wallet counts for the PIN
+        let ours = psbt::our_outputs(&psbt, &mnemonic, profiles::network())?;
+        let amount = psbt::check(&psbt, &ours, Self::get_balance()?.0)?;
+        pin::require_for_amount(amount).map_err(WalletError::Unauthorized)?;
+        let fee = psbt::fee(&psbt)
+            .map_err(WalletError::InvalidInput)?
+            .unwrap_or(0);
+        if !confirm(amount, fee) {
+            return Err(WalletError::Other(tr("The PSBT was not signed")));
+        }
+        if demo::is_active() {
+            return demo::send_onchain(amount).map(|txid| txid.to_string());
+        }
+        Self::spend_from_seed(amount, |mnemonic, _| async move {
+            psbt::sign(psbt, &mnemonic, profiles::network()).await
+        })
+    }
+
+    pub fn channel_open(amount: u64, node_id: Option<&str>) -> Result<(), WalletError> {
+        if demo::is_active() {
+            return demo::channel_open(amount);
+        }
+        let node = running_node()?;
+
+        // the default peer only exists on mainnet
+        let node_id = match node_id {
+            Some(node_id) => node_id,
+            None if profiles::network() == Network::Bitcoin => LN_ULR,
+            None => {
+                return Err(WalletError::InvalidInput(trf(
+                    "Enter the node id of a peer on {} to open a channel",
+                    &[&profiles::network()],
+                )))
+            }
+        };
+        let (node_id, node_addr) = node_id
+            .split_once('@')
+            .and_then(|(id, addr)| Some((PublicKey::from_str(id).ok()?, addr.parse().ok()?)))
+            .ok_or_else(|| {
+                WalletError::InvalidInput(trf(
+                    "Invalid node id, expected <pubkey>@<host>:<port>: {}",
+                    &[&node_id],
+                ))
+            })?;
+        node.connect_open_channel(node_id, node_addr, amount, None, None, false)
+            .map_err(|e| {
+                WalletError::Node(trf("Failed to open a channel: {}", &[&format!("{:?}", e)]))
+            })?;
+
+        Ok(())
+    }
+
+    pub fn channel_close() -> Result<(), WalletError> {
+        pin::require().map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::channel_close();
+        }
+        let node = running_node()?;
+
+        let channels = node.list_channels();
+        for c in channels {
+            node.close_channel(&c.user_channel_id, c.counterparty_node_id)
+                .map_err(|e| {
+                    WalletError::Node(trf("Failed to close a channel: {}", &[&format!("{:?}", e)]))
+                })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn create_invoice(amount: Option<u64>, desc: &str) -> Result<String, WalletError> {
+        if demo::is_active() {
+            return demo::create_invoice(amount, desc);
+        }
+        let node = running_node()?;
+
+        let expiry_secs = 60 * 15;
+        let invoice = if let Some(amount) = amount {
+            node.bolt11_payment()
+                .receive(sats_to_msat(amount)?, desc, expiry_secs)
+        } else {
+            node.bolt11_payment()
+                .receive_variable_amount(desc, expiry_secs)
+        }
+        .map_err(|e| {
+            WalletError::Node(trf(
+                "Failed to create an invoice: {}",
+                &[&format!("{:?}", e)],
+            ))
+        })?;
+
+        invoices::add_invoice(&invoice, desc).unwrap_or_else(|e| log_error!("{}", e));
+
+        Ok(invoice.to_string())
+    }
+
+    /// Checks if the invoice was already paid from this wallet
+    pub fn invoice_paid(invoice: &Bolt11Invoice) -> Result<bool, WalletError> {
+        if demo::is_active() {
+            return Ok(false);
+        }
+        let node = running_node()?;
+
+        let id = PaymentId(invoice.payment_hash().to_byte_array());
+        Ok(node.payment(&id).map_or(false, |p| {
+            p.direction == PaymentDirection::Outbound && p.status == PaymentStatus::Succeeded
+        }))
+    }
+
+    pub fn pay_invoice(
+        invoice: &Bolt11Invoice,
+        amount: Option<u64>,
+    ) -> Result<String, WalletError> {
+        let sats = invoice
+            .amount_milli_satoshis()
+            .map(|msats| msats / 1_000)
+            .or(amount)
+            .unwrap_or(0);
+        pin::require_for_amount(sats).map_err(WalletError::Unauthorized)?;
+        if Self::invoice_paid(invoice)? {
+            return Err(WalletError::InvalidInput(tr(
+                "This invoice was already paid from this wallet",
+            )));
+        }
+        if demo::is_active() {
+            return demo::send_lightning(sats);
+        }
+
+        let node = running_node()?;
+
+        let ph = match (invoice.amount_milli_satoshis(), amount) {
+            (Some(_amount), None) => node.bolt11_payment().send(invoice).map_err(|e| {
+                WalletError::Node(trf("Unable to pay the invoice: {}", &[&format!("{:?}", e)]))
+            }),
+            (Some(amount_inv), Some(amount_field)) => {
+                let amount_field = sats_to_msat(amount_field)?;
+                if amount_inv.abs_diff(amount_field) > 1_000_000 {
+                    Err(WalletError::InvalidAmount(trf(
+                        "amount of the invoice {} and in the field {} don't match",
+                        &[&amount_inv, &amount_field],
+                    )))
+                } else {
+                    node.bolt11_payment().send(invoice).map_err(|e| {
+                        WalletError::Node(trf(
+                            "Unable to pay the invoice: {}",
+                            &[&format!("{:?}", e)],
+                        ))
+                    })
+                }
+            }
+            (None, Some(amount)) => node
+                .bolt11_payment()
+                .send_using_amount(invoice, sats_to_msat(amount)?)
+                .map_err(|e| {
+                    WalletError::Node(trf(
+                        "Unable to pay the invoice with {} sats: {}",
+                        &[&amount, &format!("{:?}", e)],
+                    ))
+                }),
+            (None, None) => Err(WalletError::InvalidAmount(tr(
+                "No amount to pay the invoice!",
+            ))),
+        }?;
+
+        history::record_payment_peer(&ph, &invoice.recover_payee_pub_key().to_string())
+            .unwrap_or_else(|e| log_error!("{}", e));
+
+        let ph = to_hex(&ph.0);
+        log_info!("lightning payment sent: {}", ph);
+
+        Ok(ph)
+    }
+
+    pub fn pay_offer(
+        offer: &Offer,
+        amount: Option<u64>,
+        desc: &str,
+    ) -> Result<String, WalletError> {
+        pin::require().map_err(WalletError::Unauthorized)?;
+        let msats_min = match offer.amount() {
+            Some(Amount::Bitcoin { amount_msats }) => Some(amount_msats),
+            Some(Amount::Currency { .. }) => {
+                return Err(WalletError::InvalidInput(tr(
+                    "For BOLT12 we only support BTC at the moment",
+                )));
+            }
+            None => None,
+        };
+        pin::require_for_amount(
+            msats_min
+                .map(|msats| *msats / 1_000)
+                .or(amount)
+                .unwrap_or(0),
+        )
+        .map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return demo::send_lightning(
+                msats_min
+                    .map(|msats| *msats / 1_000)
+                    .or(amount)
+                    .unwrap_or(0),
+            );
+        }
+
+        let node = running_node()?;
+
+        let desc = if desc.is_empty() {
+            None
+        } else {
+            Some(desc.to_string())
+        };
+
+        let ph = match (msats_min, amount) {
+            (Some(_amount), None) => node.bolt12_payment().send(offer, desc).map_err(|e| {
+                WalletError::Node(trf("Unable to pay the invoice: {}", &[&format!("{:?}", e)]))
+            }),
+            (Some(amount_inv), Some(amount_field)) => {
+                let amount_field = sats_to_msat(amount_field)?;
+                if amount_inv.abs_diff(amount_field) > 1_000_000 {
+                    Err(WalletError::InvalidAmount(trf(
+                        "amount of the invoice {} and in the field {} don't match",
+                        &[&amount_inv, &amount_field],
+                    )))
+                } else {
+                    node.bolt12_payment().send(offer, desc).map_err(|e| {
+                        WalletError::Node(trf(
+                            "Unable to pay the invoice: {}",
+                            &[&format!("{:?}", e)],
+                        ))
+                    })
+                }
+            }
+            (None, Some(amount)) => node
+                .bolt12_payment()
+                .send_using_amount(offer, desc, sats_to_msat(amount)?)
+                .map_err(|e| {
+                    WalletError::Node(trf(
+                        "Unable to pay the invoice with {} sats: {}",
+                        &[&amount, &format!("{:?}", e)],
+                    ))
+                }),
+            (None, None) => Err(WalletError::InvalidAmount(tr(
+                "No amount to pay the invoice!",
+            ))),
+        }?;
+
+        let ph = to_hex(&ph.0);
+        log_info!("lightning payment sent: {}", ph);
+
+        Ok(ph)
+    }
+
+    pub fn withdraw(url: &str, satoshis: Option<u64>) -> Result<String, WalletError> {
+        if demo::is_active() {
+            return Err(WalletError::Other(tr(
+                "Withdrawing is not available in the demo wallet",
+            )));
+        }
+        let url = url.replace("lnurlw://", "https://");
+        let client = proxy::lnurl_client().map_err(WalletError::Network)?;
+        let resp = client
+            .make_request(&url)
+            .map_err(|e| WalletError::Network(trf("Failed to query lnurl: {}", &[&e])))?;
+        if let LnUrlResponse::LnUrlWithdrawResponse(lnurlw) = resp {
+            log_debug!("{:?}", lnurlw);
+            let msats = if let Some(sats) = satoshis {
+                let msats = sats_to_msat(sats)?;
+                if msats > lnurlw.max_withdrawable {
+                    return Err(WalletError::InvalidAmount(trf(
+                        "payment {} is above {}",
+                        &[&msats, &lnurlw.max_withdrawable],
+                    )));
+                }
+                if let Some(minw) = lnurlw.min_withdrawable {
+                    if msats < minw {
+                        return Err(WalletError::InvalidAmount(trf(
+                            "payment {} is below {}",
+                            &[&msats, &minw],
+                        )));
+                    }
+                }
+                msats
+            } else {
+                lnurlw.max_withdrawable
+            };
+            let invoice = Self::create_invoice(Some(msats / 1_000), &lnurlw.default_description)?;
+            let callback = format!(
+                "{}&num_satoshis={}&k1={}&pr={}",
+                lnurlw.callback,
+                msats / 1_000,
+                lnurlw.k1,
+                invoice
+            );
+            let client = proxy::http_client()?;
+            let rt = tokio::runtime::Runtime::new()
+                .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+
+            let body = rt
+                .block_on(client.get(callback).send())
+                .map_err(|e| {
+                    WalletError::Network(trf("failed to request lnurl payment: {}", &[&e]))
+                })
+                .and_then(|resp| {
+                    rt.block_on(resp.text()).map_err(|e| {
+                        WalletError::Network(trf(
+                            "failed to receive lnurl payment response: {}",
+                            &[&e],
+                        ))
+                    })
+                });
+            let outcome = match &body {
+                Ok(b) => b.clone(),
+                Err(e) => e.to_string(),
+            };
+            invoices::mark_withdraw(&invoice, &url, &outcome)
+                .unwrap_or_else(|e| log_error!("{}", e));
+            let body = body?;
+            log_debug!("lnurl response: {}", body); // k1 is required?
+
+            Ok(body)
+        } else {
+            Err(WalletError::Network(tr("invalid response to lnurl")))
+        }
+    }
+
+    /// Ask an LNURL-channel service to open a private channel to us (LUD-02). The channel
+    /// itself shows up as a pending channel event once the service funded it.
+    pub fn lnurl_channel(url: &str) -> Result<String, WalletError> {
+        if demo::is_active() {
+            return Err(WalletError::Other(tr(
+                "Channel requests are not available in the demo wallet",
+            )));
+        }
+        let client = proxy::lnurl_client().map_err(WalletError::Network)?;
+        let resp = client
+            .make_request(url)
+            .map_err(|e| WalletError::Network(trf("Failed to query lnurl: {}", &[&e])))?;
+        let channel = match resp {
+            LnUrlResponse::LnUrlChannelResponse(channel) => channel,
+            _ => return Err(WalletError::Network(tr("invalid response to lnurl"))),
+        };
+        log_debug!("{:?}", channel);
+        let (peer, addr) = channel
+            .uri
+            .split_once('@')
+            .and_then(|(id, addr)| Some((PublicKey::from_str(id).ok()?, addr.parse().ok()?)))
+            .ok_or_else(|| {
+                WalletError::Network(trf(
+                    "The LNURL service sent an invalid node uri: {}",
+                    &[&channel.uri],
+                ))
+            })?;
+        let node_id = {
+            let node = running_node()?;
+            node.connect(peer, addr, true).map_err(|e| {
+                WalletError::Network(trf(
+                    "Failed to connect to {}: {}",
+                    &[&channel.uri, &format!("{:?}", e)],
+                ))
+            })?;
+            node.node_id().to_string()
+        };
+
+        let callback = channel_callback(&channel.callback, &channel.k1, &node_id);
+        let client = proxy::http_client()?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        let body: serde_json::Value = rt
+            .block_on(async { client.get(callback).send().await?.json().await })
+            .map_err(|e| WalletError::Network(trf("failed to request the channel: {}", &[&e])))?;
+        log_debug!("lnurl response: {}", body);
+        if body["status"].as_str() != Some("OK") {
+            return Err(WalletError::Network(trf(
+                "The LNURL service refused to open the channel: {}",
+                &[&body["reason"].as_str().unwrap_or("no reason given")],
+            )));
+        }
+
+        let msg = trf("{} is opening a channel to us", &[&peer]);
+        eventlog::push(Severity::Info, &msg, &peer.to_string());
+        Ok(msg)
+    }
+
+    /// Answer a BOLT12 refund with an invoice, the payer then sends the funds
+    pub fn request_refund(refund: &Refund) -> Result<String, WalletError> {
+        if demo::is_active() {
+            return Err(WalletError::Other(tr(
+                "Refunds are not available in the demo wallet",
+            )));
+        }
+        let node = running_node()?;
+        let invoice = node
+            .bolt12_payment()
+            .request_refund_payment(refund)
+            .map_err(|e| {
+                WalletError::Node(trf(
+                    "Failed to request the refund: {}",
+                    &[&format!("{:?}", e)],
+                ))
+            })?;
+        let hash = to_hex(&invoice.payment_hash().0);
+        PENDING_REFUNDS
+            .lock()
+            .map_err(WalletError::poisoned)?
+            .push(hash.clone());
+        let msg = trf(
+            "Sent an invoice for the refund of {} sats",
+            &[&(refund.amount_msats() / 1_000)],
+        );
+        eventlog::push(Severity::Info, &msg, &hash);
+        Ok(msg)
+    }
+
+    pub fn sweep(privkeys: &PrivateKeys) -> Result<String, WalletError> {
+        if demo::is_active() {
+            return Err(WalletError::Other(tr(
+                "Sweeping is not available in the demo wallet",
+            )));
+        }
+        proxy::check_online().map_err(WalletError::Network)?;
+        let sw = crate::sweeper::Sweeper {
+            esplora_url: esplora::current(),
+            network: profiles::network(),
+        };
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+
+        rt.block_on(sw.sweep(privkeys, &Self::get_address()?))
+    }
+
+    /// Record the next event of the node, if there is one, and hand it on for the GUI
+    pub fn handle_ldk_event() -> Result<Option<WalletEvent>, WalletError> {
+        if demo::is_active() {
+            return demo::next_event();
+        }
+        let node = running_node()?;
+
+        if let Some(event) = node.next_event() {
+            log_debug!("ldk event: {:?}", event);
+            let wallet_event = match &event {
+                Event::PaymentReceived {
+                    payment_id,
+                    payment_hash,
+                    amount_msat,
+                } => {
+                    if let Some(id) = payment_id {
+                        history::record_payment_meta(id, None, None)
+                            .unwrap_or_else(|e| log_error!("{}", e));
+                    }
+                    webhook::invoice_paid(&to_hex(&payment_hash.0), *amount_msat);
+                    pos::payment_received(&to_hex(&payment_hash.0));
+                    refund_received(&to_hex(&payment_hash.0), *amount_msat);
+                    Some(WalletEvent::PaymentReceived {
+                        payment_hash: to_hex(&payment_hash.0),
+                        amount_msat: *amount_msat,
+                    })
+                }
+                Event::PaymentFailed {
+                    payment_id,
+                    payment_hash,
+                    reason,
+                } => {
+                    let reason = failure_reason(*reason);
+                    if let Some(id) = payment_id {
+                        history::record_payment_meta(id, None, Some(reason))
+                            .unwrap_or_else(|e| log_error!("{}", e));
+                        payments::settle(&to_hex(&id.0), Err(reason.to_string()));
+                    }
+                    Some(WalletEvent::PaymentFailed {
+                        payment_hash: to_hex(&payment_hash.0),
+                        reason: reason.to_string(),
+                    })
+                }
+                Event::PaymentSuccessful {
+                    payment_id,
+                    payment_hash,
+                    fee_paid_msat,
+                } => {
+                    if let Some(id) = payment_id {
+                        history::record_payment_meta(id, *fee_paid_msat, None)
+                            .unwrap_or_else(|e| log_error!("{}", e));
+                        payments::settle(&to_hex(&id.0), Ok(()));
+                    }
+                    Some(WalletEvent::PaymentSuccessful {
+                        payment_hash: to_hex(&payment_hash.0),
+                        fee_paid_msat: *fee_paid_msat,
+                    })
+                }
+                Event::ChannelPending {
+                    channel_id,
+                    counterparty_node_id,
+                    ..
+                } => {
+                    let channel = node
+                        .list_channels()
+                        .into_iter()
+                        .find(|c| c.channel_id == *channel_id);
+                    let amount_msat = channel.as_ref().map(|c| c.channel_value_sats * 1_000);
+                    // e.g. requested over LNURL-channel
+                    let inbound = channel.map_or(false, |c| !c.is_outbound);
+                    history::add_record(HistoryEntry {
+                        id: format!("open-{}", channel_id),
+                        kind: "channel_open".to_string(),
+                        direction: if inbound { "in" } else { "out" }.to_string(),
+                        status: "pending".to_string(),
+                        amount_msat,
+                        peer: counterparty_node_id.to_string(),
+                        channel_id: channel_id.to_string(),
+                        timestamp: Utc::now().timestamp(),
+                        initiator: if inbound { "peer" } else { "us" }.to_string(),
+                        ..Default::default()
+                    })
+                    .unwrap_or_else(|e| log_error!("{}", e));
+                    Some(WalletEvent::ChannelPending {
+                        channel_id: channel_id.to_string(),
+                        peer: counterparty_node_id.to_string(),
+                        inbound,
+                    })
+                }
+                Event::ChannelReady {
+                    channel_id,
+                    counterparty_node_id,
+                    ..
+                } => {
+                    let peer = counterparty_node_id
+                        .map(|id| id.to_string())
+                        .unwrap_or_default();
+                    history::add_record(HistoryEntry {
+                        id: format!("ready-{}", channel_id),
+                        kind: "channel_ready".to_string(),
+                        status: "succeeded".to_string(),
+                        peer: peer.clone(),
+                        channel_id: channel_id.to_string(),
+                        timestamp: Utc::now().timestamp(),
+                        ..Default::default()
+                    })
+                    .unwrap_or_else(|e| log_error!("{}", e));
+                    Some(WalletEvent::ChannelReady {
+                        channel_id: channel_id.to_string(),
+                        peer,
+                    })
+                }
+                Event::ChannelClosed {
+                    channel_id,
+                    counterparty_node_id,
+                    reason,
+                    ..
+                } => {
+                    // after a force close, the funds are swept to the on-chain wallet once the timelock expired
+                    let kind = match reason {
+                        Some(ClosureReason::HolderForceClosed { .. })
+                        | Some(ClosureReason::CounterpartyForceClosed { .. }) => "force_close",
+                        _ => "channel_close",
+                    };
+                    let initiator = match reason {
+                        Some(ClosureReason::HolderForceClosed { .. })
+                        | Some(ClosureReason::LocallyInitiatedCooperativeClosure) => "us",
+                        Some(ClosureReason::CounterpartyForceClosed { .. })
+                        | Some(ClosureReason::CounterpartyInitiatedCooperativeClosure)
+                        | Some(ClosureReason::CounterpartyCoopClosedUnfundedChannel) => "peer",
+                        _ => "",
+                    };
+                    history::add_record(HistoryEntry {
+                        id: format!("close-{}", channel_id),
+                        kind: kind.to_string(),
+                        direction: "in".to_string(),
+                        status: "succeeded".to_string(),
+                        amount_msat: None,
+                        peer: counterparty_node_id
+                            .map(|id| id.to_string())
+                            .unwrap_or_default(),
+                        channel_id: channel_id.to_string(),
+                        timestamp: Utc::now().timestamp(),
+                        reason: reason.as_ref().map(|r| r.to_string()).unwrap_or_default(),
+                        initiator: initiator.to_string(),
+                        ..Default::default()
+                    })
+                    .unwrap_or_else(|e| log_error!("{}", e));
+                    Some(WalletEvent::ChannelClosed {
+                        channel_id: channel_id.to_string(),
+                        reason: reason.as_ref().map(|r| r.to_string()).unwrap_or_default(),
+                    })
+                }
+                _ => None,
+            };
+
+            node.event_handled();
+
+            Ok(wallet_event)
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn get_address() -> Result<Address, WalletError> {
+        if demo::is_active() {
+            return demo::address();
+        }
+        if watchonly::is_watch_only() {
+            return watchonly::address().map_err(WalletError::from);
+        }
+        let node = running_node()?;
+
+        node.onchain_payment().new_address().map_err(|e| {
+            WalletError::Node(trf("Unable to get an address: {}", &[&format!("{:?}", e)]))
+        })
+    }
+
+    /// The spendable on-chain and the lightning balance in sats
+    pub fn get_balance() -> Result<(u64, u64), WalletError> {
+        if demo::is_active() {
+            return demo::balance();
+        }
+        if watchonly::is_watch_only() {
+            return Ok((watchonly::balance()?, 0));
+        }
+        let node = running_node()?;
+
+        log_debug!("getting balances");
+        let ocbal = node.list_balances().spendable_onchain_balance_sats;
+
+        let lnbal = node.list_balances().total_lightning_balance_sats;
+
+        Ok((ocbal, lnbal))
+    }
+
+    /// Whether a usable channel has the outbound capacity for the amount
+    pub fn can_pay_lightning(amount_msat: Option<u64>) -> Result<bool, WalletError> {
+        if demo::is_active() {
+            return demo::can_pay_lightning(amount_msat);
+        }
+        let node = running_node()?;
+
+        let amount_msat = amount_msat.unwrap_or(1);
+        Ok(node
+            .list_channels()
+            .iter()
+            .any(|c| c.is_usable && c.outbound_capacity_msat >= amount_msat))
+    }
+
+    pub fn list_payments() -> Result<Vec<PaymentDetails>, WalletError> {
+        if demo::is_active() {
+            return demo::list_payments();
+        }
+        let node = running_node()?;
+
+        Ok(node.list_payments())
+    }
+
+    pub fn payment(id: &PaymentId) -> Result<Option<PaymentDetails>, WalletError> {
+        if demo::is_active() {
+            return demo::payment(id);
+        }
+        let node = running_node()?;
+
+        Ok(node.payment(id))
+    }
+
+    /// The alias and color a node announced in the gossip network
+    pub fn node_alias(node_id: &str) -> Result<Option<(String, String)>, WalletError> {
+        if demo::is_active() {
+            return Ok(None);
+        }
+        let node = running_node()?;
+
+        let node_id = PublicKey::from_str(node_id).map_err(|e| {
+            WalletError::InvalidInput(trf("Failed to parse the node id {}: {}", &[&node_id, &e]))
+        })?;
+        let info = node.network_graph().node(&NodeId::from_pubkey(&node_id));
+
+        Ok(info
+            .and_then(|n| n.announcement_info)
+            .map(|a| (a.alias.to_string(), format!("#{}", to_hex(&a.rgb)))))
+    }
+
+    pub fn node_id() -> Result<String, WalletError> {
+        if demo::is_active() {
+            return demo::node_id();
+        }
+        let node = running_node()?;
+
+        Ok(node.node_id().to_string())
+    }
+
+    /// The master key fingerprint and the node id, to tell wallets apart, e.g. after a restore
+    pub fn identity() -> Result<(String, String), WalletError> {
+        let fingerprint = recovery::fingerprint(&Self::mnemonic()?)?;
+        Ok((fingerprint, Self::node_id()?))
+    }
+
+    /// What there is to know about the node, for the about page and bug reports
+    pub fn node_info() -> Result<NodeInfo, WalletError> {
+        if demo::is_active() {
+            return demo::node_info();
+        }
+        let node = running_node()?;
+
+        let peers = node.list_peers();
+        let channels = node.list_channels();
+        Ok(NodeInfo {
+            node_id: node.node_id().to_string(),
+            listening_addresses: node
+                .listening_addresses()
+                .unwrap_or_default()
+                .iter()
+                .map(|a| a.to_string())
+                .collect(),
+            peers: peers.len(),
+            connected_peers: peers.iter().filter(|p| p.is_connected).count(),
+            channels: channels.len(),
+            usable_channels: channels.iter().filter(|c| c.is_usable).count(),
+            block_height: node.status().current_best_block.height,
+            network: profiles::network().to_string(),
+            ldk_node_version: LDK_NODE_VERSION.to_string(),
+            app_version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    }
+
+    /// The channels as (peer node id, peer address, capacity in sats, funding outpoint)
+    pub fn channel_peers() -> Result<Vec<(String, String, u64, String)>, WalletError> {
+        if demo::is_active() {
+            return demo::channel_peers();
+        }
+        let node = running_node()?;
+
+        let peers = node.list_peers();
+        Ok(node
+            .list_channels()
+            .into_iter()
+            .map(|c| {
+                let address = peers
+                    .iter()
+                    .find(|p| p.node_id == c.counterparty_node_id)
+                    .map(|p| p.address.to_string())
+                    .unwrap_or_default();
+                let funding = c.funding_txo.map(|o| o.to_string()).unwrap_or_default();
+                (
+                    c.counterparty_node_id.to_string(),
+                    address,
+                    c.channel_value_sats,
+                    funding,
+                )
+            })
+            .collect())
+    }
+
+    /// Rebuild the on-chain wallet from the chain source, for when the local store got corrupted
+    /// or the wallet was restored from a backup. The lightning state is kept.
+    pub fn rescan() -> Result<(), WalletError> {
+        if demo::is_active() {
+            return Ok(());
+        }
+        let _change = NODE_CHANGE.lock().map_err(WalletError::poisoned)?;
+        take_and_stop_node()?;
+        let node = match Self::rebuild_wallet() {
+            Ok(node) => node,
+            Err(e) => {
+                // the channel state is untouched, so the previous node can come back
+                if let Err(e) = Self::restart_node() {
+                    log_error!("{}", e);
+                }
+                return Err(e);
+            }
+        };
+        log_info!("rescanning the wallets");
+        let res = node.sync_wallets().map_err(|e| {
+            WalletError::Node(trf(
+                "Failed to sync the wallets: {}",
+                &[&format!("{:?}", e)],
+            ))
+        });
+        put_node(Some(node));
+        res
+    }
+
+    /// Drop the on-chain wallet database and build a node that starts it over
+    fn rebuild_wallet() -> Result<Node, WalletError> {
+        // the bdk database only caches what is on the chain, so it can be dropped safely
+        let ldk_dir = Self::ldk_dir();
+        if ldk_dir.exists() {
+            let files = fs::read_dir(&ldk_dir).map_err(|e| {
+                WalletError::Storage(trf(
+                    "Failed to list {}: {}",
+                    &[&format!("{:?}", ldk_dir), &e],
+                ))
+            })?;
+            for file in files.flatten() {
+                if file.file_name().to_string_lossy().starts_with("bdk_wallet") {
+                    fs::remove_file(file.path()).map_err(|e| {
+                        format!(
+                            "Failed to remove the wallet database {:?}: {}",
+                            file.path(),
+                            e
+                        )
+                    })?;
+                }
+            }
+        }
+
+        Self::create_node(&|_| {})
+    }
+
+    /// Stop the node and leave it to other processes
+    pub fn shutdown() -> Result<(), WalletError> {
+        let _change = NODE_CHANGE.lock().map_err(WalletError::poisoned)?;
+        take_and_stop_node()?;
+        put_node(None);
+        instance::release(&Self::data_dir());
+        Ok(())
+    }
+
+    /// Build the node again, e.g. to use another esplora server
+    pub fn restart() -> Result<(), WalletError> {
+        Self::with_node_stopped(|| Ok::<(), WalletError>(()))
+    }
+
+    /// Connect to the peers again, after the network changed. Returns how many were reconnected.
+    pub fn reconnect_peers() -> Result<usize, WalletError> {
+        if demo::is_active() {
+            return Ok(1);
+        }
+        let node = running_node()?;
+
+        let mut reconnected = 0;
+        for peer in node.list_peers().into_iter().filter(|p| p.is_persisted) {
+            if peer.is_connected {
+                if let Err(e) = node.disconnect(peer.node_id) {
+                    log_error!("Failed to disconnect from {}: {:?}", peer.node_id, e);
+                }
+            }
+            match node.connect(peer.node_id, peer.address.clone(), true) {
+                Ok(()) => reconnected += 1,
+                Err(e) => log_error!("Failed to reconnect to {}: {:?}", peer.node_id, e),
+            }
+        }
+        Ok(reconnected)
+    }
+
+    /// Sync the on-chain and the lightning wallet with the chain
+    pub fn sync() -> Result<(), WalletError> {
+        if demo::is_active() {
+            return Ok(());
+        }
+        let node = running_node()?;
+
+        node.sync_wallets().map_err(|e| {
+            WalletError::Node(trf(
+                "Failed to sync the wallets: {}",
+                &[&format!("{:?}", e)],
+            ))
+        })
+    }
+
+    /// The height of the chain tip the node knows about, and the unix timestamp of the older
+    /// of the last on-chain and lightning wallet syncs
+    pub fn sync_status() -> Result<(u32, Option<u64>), WalletError> {
+        if demo::is_active() {
+            return Ok(demo::sync_status());
+        }
+        let node = running_node()?;
+
+        let status = node.status();
+        let last_sync = match (
+            status.latest_onchain_wallet_sync_timestamp,
+            status.latest_lightning_wallet_sync_timestamp,
+        ) {
+            (Some(onchain), Some(lightning)) => Some(onchain.min(lightning)),
+            (onchain, lightning) => onchain.or(lightning),
+        };
+        Ok((status.current_best_block.height, last_sync))
+    }
+
+    /// The timestamp of the newest rapid gossip sync snapshot the node applied
+    pub fn rgs_snapshot() -> Result<Option<u32>, WalletError> {
+        if demo::is_active() {
+            // the demo wallet has no gossip to fall back from
+            return Ok(Some(Utc::now().timestamp() as u32));
+        }
+        let node = running_node()?;
+
+        Ok(node.status().latest_rgs_snapshot_timestamp)
+    }
+
+    pub fn get_channel_status() -> Result<String, WalletError> {
+        if demo::is_active() {
+            return demo::channel_status();
+        }
+        let node = running_node()?;
+
+        let mut channels = node.list_channels();
+        if let Some(channel) = channels.pop() {
+            let mut our_share = channel.outbound_capacity_msat as f32
+                / (channel.outbound_capacity_msat as f32 + channel.inbound_capacity_msat as f32);
+            if !channel.is_usable {
+                our_share = -our_share;
+            }
+            log_debug!("channel status: {}", our_share);
+            Ok(format!("{}", our_share))
+        } else {
+            Ok("".to_string())
+        }
+    }
+
+    /// Stop the node while the files in the data directory are accessed, and start it again
+    /// afterwards, regardless of the outcome
+    pub fn with_node_stopped<T, E, F>(f: F) -> Result<T, E>
+    where
+        E: From<WalletError>,
+        F: FnOnce() -> Result<T, E>,
+    {
+        let _change = NODE_CHANGE.lock().map_err(WalletError::poisoned)?;
+        take_and_stop_node()?;
+        let res = f();
+        Self::restart_node()?;
+        res
+    }
+
+    /// Build the node of the active profile again after it was stopped. A second attempt
+    /// keeps a passing failure from leaving the wallet without a node until the app restarts.
+    fn restart_node() -> Result<(), WalletError> {
+        let node = Self::create_node_for_profile(&|_| {}).or_else(|e| {
+            log_error!("Failed to start the node again, retrying: {}", e);
+            thread::sleep(Duration::from_secs(NODE_RESTART_RETRY_SECS));
+            Self::create_node_for_profile(&|_| {})
+        });
+        put_built_node(node)
+    }
+
+    /// Delete all the data and start over with a fresh wallet
+    pub fn reset() -> Result<(), WalletError> {
+        pin::require().map_err(WalletError::Unauthorized)?;
+        if demo::is_active() {
+            return Err(WalletError::Other(tr("The demo wallet can't be reset")));
+        }
+        let (balance, channels) = {
+            let node = running_node()?;
+            let balances = node.list_balances();
+            (
+                balances.total_onchain_balance_sats + balances.total_lightning_balance_sats,
+                node.list_channels().len(),
+            )
+        };
+        let backup_confirmed = Settings::load()?.seed_backup_confirmed > 0;
+        reset_allowed(backup_confirmed, balance, channels)?;
+
+        let data_dir = Self::data_dir();
+        Self::with_node_stopped(|| {
+            log_info!("deleting {:?}", data_dir);
+            let entries = fs::read_dir(&data_dir).map_err(|e| {
+                WalletError::Storage(trf(
+                    "Failed to list {}: {}",
+                    &[&format!("{:?}", data_dir), &e],
+                ))
+            })?;
+            for entry in entries.flatten() {
+                // the other profiles are kept below the directory of the default one
+                if profiles::is_shared(&entry.file_name().to_string_lossy()) {
+                    continue;
+                }
+                let path = entry.path();
+                let res = if path.is_dir() {
+                    fs::remove_dir_all(&path)
+                } else {
+                    fs::remove_file(&path)
+                };
+                res.map_err(|e| {
+                    WalletError::Storage(trf(
+                        "Failed to delete {}: {}",
+                        &[&format!("{:?}", path), &e],
+                    ))
+                })?;
+            }
+            Ok(())
+        })
+    }
+
+    /// The seed words of the wallet, for making a backup
+    pub fn mnemonic() -> Result<Mnemonic, WalletError> {
+        if demo::is_active() {
+            return demo::mnemonic();
+        }
+        let (words, _) = read_mnemonic(&Self::data_dir())?
+            .ok_or(WalletError::Storage(tr("The wallet has no seed")))?;
+        Mnemonic::parse(words.trim())
+            .map_err(|e| WalletError::InvalidInput(trf("Failed to parse mnemonic: {}", &[&e])))
+    }
+
+    /// Watch-only profiles hold no keys, so they run without a node
+    fn create_node_for_profile(
+        progress: &dyn Fn(StartupStage),
+    ) -> Result<Option<Node>, WalletError> {
+        if watchonly::is_watch_only() {
+            log_info!("watch-only wallet, not starting the ldk-node");
+            return Ok(None);
+        }
+        if demo::is_active() {
+            return Ok(None);
+        }
+        Self::create_node(progress).map(Some)
+    }
+
+    fn create_node(progress: &dyn Fn(StartupStage)) -> Result<Node, WalletError> {
+        instance::acquire(&Self::data_dir()).map_err(WalletError::Storage)?;
+        let mnemonic = read_or_generate_mnemonic(&Self::data_dir())?;
+        let ldk_dir = Self::ldk_dir();
+
+        progress(StartupStage::Probing);
+        let (esplora, _) = esplora::select();
+
+        progress(StartupStage::Building);
+        log_info!("building the ldk-node with {}", esplora);
+        let mut builder = Builder::new();
+        let network = profiles::network();
+        builder.set_network(network);
+        builder.set_esplora_server(esplora);
+        builder.set_entropy_bip39_mnemonic(mnemonic, None);
+        builder.set_storage_dir_path(ldk_dir.to_str().unwrap().to_string());
+        gossip::configure(&mut builder);
+        let vss_url = Settings::load()?.vss_url;
+        let node = if vss_url.is_empty() {
+            builder.build()
+        } else if !Self::vss_supported() {
+            // left over from a build with the remote storage
+            log_error!(
+                "This build doesn't support the remote storage, ignoring {}",
+                vss_url
+            );
+            builder.build()
+        } else {
+            Self::build_with_vss(&builder, vss_url, &mnemonic)?
+        }
+        .map_err(|e| {
+            WalletError::Node(trf("Failed to build ldk-node: {}", &[&format!("{:?}", e)]))
+        })?;
+
+        // without starting, the node still hands out addresses and signs, but doesn't connect
+        if proxy::check_online().is_err() {
+            log_info!("offline mode, not starting the ldk-node");
+            return Ok(node);
+        }
+
+        progress(StartupStage::Starting);
+        log_info!("starting the ldk-node");
+        node.start().map_err(|e| {
+            WalletError::Node(trf("Failed to start ldk-node: {}", &[&format!("{:?}", e)]))
+        })?;
+        log_info!("ldk-node started");
+
+        Ok(node)
+    }
+
+    /// The directory holding the seed, the ldk storage and the app's own files
+    pub fn data_dir() -> PathBuf {
+        profiles::data_dir()
+    }
+
+    /// Whether the node can keep its state in a versioned storage service
+    pub fn vss_supported() -> bool {
+        cfg!(vss)
+    }
+
+    /// Mirror the channel state to a versioned storage service. The store is named after the
+    /// seed, so that it is found again when the wallet is recovered from the seed.
+    /// ldk-node only offers this when built with `RUSTFLAGS="--cfg vss"`.
+    #[cfg(vss)]
+    fn build_with_vss(
+        builder: &Builder,
+        vss_url: String,
+        mnemonic: &Mnemonic,
+    ) -> Result<Result<Node, ldk_node::BuildError>, WalletError> {
+        let hash = ldk_node::bitcoin::hashes::sha256::Hash::hash(&mnemonic.to_seed(""));
+        let store_id = format!("utwallet_{}", to_hex(&hash.as_byte_array()[..16]));
+        log_info!("using the VSS at {}", vss_url);
+        Ok(builder.build_with_vss_store(vss_url, store_id))
+    }
+
+    #[cfg(not(vss))]
+    fn build_with_vss(
+        _builder: &Builder,
+        _vss_url: String,
+        _mnemonic: &Mnemonic,
+    ) -> Result<Result<Node, ldk_node::BuildError>, WalletError> {
+        Err(WalletError::Other(tr(
+            "This build doesn't support the remote storage, unset the VSS url",
+        )))
+    }
+
+    fn ldk_dir() -> PathBuf {
+        Self::data_dir().join("ldk")
+    }
+}
+
+/// Log the arrival of a refund we sent an invoice for
+fn refund_received(payment_hash: &str, amount_msat: u64) {
+    let mut pending = match PENDING_REFUNDS.lock() {
+        Ok(pending) => pending,
+        Err(e) => {
+            log_error!("{}", e);
+            return;
+        }
+    };
+    if let Some(pos) = pending.iter().position(|hash| hash == payment_hash) {
+        pending.remove(pos);
+        let msg = trf("Refund of {} sats received", &[&(amount_msat / 1_000)]);
+        eventlog::push(Severity::Info, &msg, payment_hash);
+    }
+}
+
+/// The LUD-02 callback, asking for a private channel to our node
+fn channel_callback(callback: &str, k1: &str, node_id: &str) -> String {
+    let separator = if callback.contains('?') { '&' } else { '?' };
+    format!(
+        "{}{}k1={}&remoteid={}&private=1",
+        callback, separator, k1, node_id
+    )
+}
+
+/// Deleting the wallet is only safe once the channels are closed, and the remaining funds can
+/// be recovered from a backed up seed
+fn reset_allowed(
+    backup_confirmed: bool,
+    balance_sats: u64,
+    channels: usize,
+) -> Result<(), WalletError> {
+    if channels > 0 {
+        return Err(WalletError::Node(tr(
+            "Close the channels before resetting the wallet",
+        )));
+    }
+    if balance_sats > 0 && !backup_confirmed {
+        return Err(WalletError::InvalidAmount(trf(
+            "The wallet still holds {} sats, confirm the seed backup or empty it first",
+            &[&balance_sats],
+        )));
+    }
+    Ok(())
+}
+
+/// The state of the node, without anything that would allow spending
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NodeInfo {
+    pub node_id: String,
+    pub listening_addresses: Vec<String>,
+    pub peers: usize,
+    pub connected_peers: usize,
+    pub channels: usize,
+    pub usable_channels: usize,
+    pub block_height: u32,
+    pub network: String,
+    pub ldk_node_version: String,
+    pub app_version: String,
+}
+
+/// The stages of bringing up the node, for the splash screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartupStage {
+    Probing,
+    Building,
+    Starting,
+}
+
+impl StartupStage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Probing => "probing",
+            Self::Building => "building",
+            Self::Starting => "starting",
+        }
+    }
+}
+
+/// The events of the node that the GUI reacts to
+#[derive(Clone, Debug, PartialEq)]
+pub enum WalletEvent {
+    PaymentReceived {
+        payment_hash: String,
+        amount_msat: u64,
+    },
+    PaymentSuccessful {
+        payment_hash: String,
+        fee_paid_msat: Option<u64>,
+    },
+    PaymentFailed {
+        payment_hash: String,
+        reason: String,
+    },
+    ChannelPending {
+        channel_id: String,
+        peer: String,
+        /// opened by the peer
+        inbound: bool,
+    },
+    ChannelReady {
+        channel_id: String,
+        peer: String,
+    },
+    ChannelClosed {
+        channel_id: String,
+        reason: String,
+    },
+}
+
+impl WalletEvent {
+    /// A line for the event log
+    pub fn describe(&self) -> String {
+        match self {
+            Self::PaymentReceived { amount_msat, .. } => {
+                trf("received {} sats", &[&(amount_msat / 1_000)])
+            }
+            Self::PaymentSuccessful { fee_paid_msat, .. } => trf(
+                "payment sent, {} sats fee",
+                &[&(fee_paid_msat.unwrap_or(0) / 1_000)],
+            ),
+            Self::PaymentFailed { reason, .. } => trf("payment failed: {}", &[&tr(reason)]),
+            Self::ChannelPending {
+                peer,
+                inbound: true,
+                ..
+            } => trf("incoming channel from {} pending", &[peer]),
+            Self::ChannelPending { peer, .. } => trf("channel with {} pending", &[peer]),
+            Self::ChannelReady { peer, .. } => trf("channel with {} ready", &[peer]),
+            Self::ChannelClosed { reason, .. } => trf("channel closed: {}", &[reason]),
+        }
+    }
+
+    /// The payment hash or the channel id the event is about
+    pub fn reference(&self) -> &str {
+        match self {
+            Self::PaymentReceived { payment_hash, .. }
+            | Self::PaymentSuccessful { payment_hash, .. }
+            | Self::PaymentFailed { payment_hash, .. } => payment_hash,
+            Self::ChannelPending { channel_id, .. }
+            | Self::ChannelReady { channel_id, .. }
+            | Self::ChannelClosed { channel_id, .. } => channel_id,
+        }
+    }
+}
+
+/// A human readable explanation of why a lightning payment failed
+fn failure_reason(reason: Option<PaymentFailureReason>) -> &'static str {
+    match reason {
+        Some(PaymentFailureReason::RouteNotFound) => noop("no route found"),
+        Some(PaymentFailureReason::RetriesExhausted) => {
+            noop("insufficient capacity along the routes")
+        }
+        Some(PaymentFailureReason::PaymentExpired) => noop("invoice expired"),
+        Some(PaymentFailureReason::RecipientRejected) => noop("rejected by the recipient"),
+        Some(PaymentFailureReason::UserAbandoned) => noop("abandoned"),
+        _ => noop("unexpected error"),
+    }
+}
+
+/// Read the seed words, and whether they were encrypted. A plain file takes precedence, as
+/// that is what a restored backup brings.
+fn read_mnemonic(data_dir: &Path) -> Result<Option<(String, bool)>, WalletError> {
+    let plain = data_dir.join(MNEMONIC_FILE);
+    if plain.exists() {
+        let words = fs::read_to_string(&plain).map_err(|e| {
+            WalletError::Storage(trf(
+                "Failed to read the mnemonic file {}: {}",
+                &[&format!("{:?}", plain), &e],
+            ))
+        })?;
+        return Ok(Some((words, false)));
+    }
+    let sealed = data_dir.join(SEALED_MNEMONIC_FILE);
+    if sealed.exists() {
+        let key = secrets::get(secrets::MNEMONIC_KEY)?.ok_or(WalletError::Storage(tr(
+            "The key for the encrypted seed is missing from the keyring",
+        )))?;
+        let data = fs::read(&sealed).map_err(|e| {
+            WalletError::Storage(trf(
+                "Failed to read the mnemonic file {}: {}",
+                &[&format!("{:?}", sealed), &e],
+            ))
+        })?;
+        let words = secrets::open(&from_hex(&key)?, &data)?;
+        let words = String::from_utf8(words)
+            .map_err(|e| WalletError::Storage(trf("Failed to decode the mnemonic: {}", &[&e])))?;
+        return Ok(Some((words, true)));
+    }
+    Ok(None)
+}
+
+/// Tighten the permissions of seed files written by older versions, and drop leftovers of an
+/// interrupted write. The rename in write_private never happened then, so the seed is intact.
+fn harden_mnemonic_files(data_dir: &Path) -> Result<(), WalletError> {
+    for name in [MNEMONIC_FILE, SEALED_MNEMONIC_FILE] {
+        let file = data_dir.join(name);
+        if file.exists() && secrets::is_exposed(&file)? {
+            log_info!("restricting the permissions of {:?}", file);
+            secrets::restrict_permissions(&file)?;
+        }
+        let tmp = storage::temp_file(&file);
+        if tmp.exists() {
+            log_info!("removing the leftover {:?}", tmp);
+            fs::remove_file(&tmp).map_err(|e| {
+                WalletError::Storage(trf("Failed to remove {}: {}", &[&format!("{:?}", tmp), &e]))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+fn read_or_generate_mnemonic(data_dir: &Path) -> Result<Mnemonic, WalletError> {
+    if data_dir.exists() {
+        harden_mnemonic_files(data_dir)?;
+    }
+    let (mnemonic_words, sealed) = match read_mnemonic(data_dir)? {
+        Some(found) => found,
+        None => {
+            // Generate fresh mnemonic
+            let mut entropy = [0u8; 16];
+            OsRng.fill_bytes(&mut entropy);
+            let mnemonic = Mnemonic::from_entropy(&entropy)
+                .map_err(|e| format!("Failed to generate mnemonic: {:?}", e))?;
+            (mnemonic.to_string(), false)
+        }
+    };
+
+    let mnemonic = Mnemonic::parse(&mnemonic_words)
+        .map_err(|e| WalletError::InvalidInput(trf("Failed to parse mnemonic: {}", &[&e])))?;
+
+    if !sealed {
+        store_mnemonic(data_dir, &mnemonic_words)?;
+    }
+
+    Ok(mnemonic)
+}
+
+/// Persist the seed encrypted with a key from the keyring. Without a keyring, there is no
+/// better place for the key than next to the seed, so it is kept in plain text.
+fn store_mnemonic(data_dir: &Path, mnemonic_words: &str) -> Result<(), WalletError> {
+    create_dir_all(data_dir)
+        .map_err(|e| WalletError::Storage(trf("Failed to create directory: {}", &[&e])))?;
+    let plain = data_dir.join(MNEMONIC_FILE);
+    if !secrets::keyring_available() {
+        secrets::write_private(&plain, mnemonic_words.as_bytes())?;
+        return verify_stored_mnemonic(data_dir, mnemonic_words);
+    }
+
+    let key = match secrets::get(secrets::MNEMONIC_KEY)? {
+        Some(key) => from_hex(&key)?,
+        None => {
+            let mut key = [0u8; 32];
+            OsRng.fill_bytes(&mut key);
+            secrets::set(secrets::MNEMONIC_KEY, &to_hex(&key))?;
+            key.to_vec()
+        }
+    };
+    let sealed = data_dir.join(SEALED_MNEMONIC_FILE);
+    secrets::write_private(&sealed, &secrets::seal(&key, mnemonic_words.as_bytes())?)?;
+    // the plain copy only goes once the sealed one is known to be readable
+    if let Err(e) = verify_sealed_mnemonic(&sealed, &key, mnemonic_words) {
+        log_error!("{}, keeping the seed in plain text", e);
+        secrets::write_private(&plain, mnemonic_words.as_bytes())?;
+        return verify_stored_mnemonic(data_dir, mnemonic_words);
+    }
+    if plain.exists() {
+        fs::remove_file(&plain).map_err(|e| {
+            WalletError::Storage(trf("Failed to remove the plain mnemonic file: {}", &[&e]))
+        })?;
+    }
+    Ok(())
+}
+
+/// Open the sealed seed with the key as the keyring hands it out, rather than the one still in
+/// memory, so a keyring that doesn't keep what it was given can't cost the seed
+fn verify_sealed_mnemonic(
+    sealed: &Path,
+    key: &[u8],
+    mnemonic_words: &str,
+) -> Result<(), WalletError> {
+    let stored_key = match secrets::get(secrets::MNEMONIC_KEY)? {
+        Some(stored) => from_hex(&stored)?,
+        None => Vec::new(),
+    };
+    if stored_key != key {
+        return Err(WalletError::Storage(tr(
+            "The keyring doesn't hand out the seed key it was given",
+        )));
+    }
+    let sealed_words = secrets::open(&stored_key, &fs::read(sealed).unwrap_or_default())
+        .map_err(|e| format!("The encrypted seed can't be read back: {}", e))?;
+    if sealed_words != mnemonic_words.as_bytes() {
+        return Err(WalletError::Storage(tr(
+            "The encrypted seed doesn't match after writing it",
+        )));
+    }
+    Ok(())
+}
+
+/// Read the seed back, so a failed write shows up before any funds depend on it
+fn verify_stored_mnemonic(data_dir: &Path, mnemonic_words: &str) -> Result<(), WalletError> {
+    match read_mnemonic(data_dir)? {
+        Some((words, _)) if words == mnemonic_words => Ok(()),
+        _ => Err(WalletError::Storage(tr(
+            "The seed doesn't match after writing it",
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use electrsd::{
+        bitcoind::{self, bitcoincore_rpc::RpcApi, BitcoinD},
+        electrum_client::ElectrumApi,
+        ElectrsD,
+    };
+    use std::{
+        net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener},
+        thread::sleep,
+        time::Duration,
+    };
+
+    #[test]
+    fn test_reset_allowed() {
+        assert!(reset_allowed(false, 0, 0).is_ok());
+        assert!(reset_allowed(true, 1000, 0).is_ok());
+        assert!(reset_allowed(false, 1000, 0).is_err());
+        assert!(reset_allowed(true, 0, 1).is_err());
+    }
+
+    struct RegTestEnv {
+        /// Instance of the bitcoin core daemon
+        bitcoind: BitcoinD,
+        /// Instance of the electrs electrum server
+        electrsd: ElectrsD,
+        /// ldk-node instances
+        ldk_nodes: Vec<Node>,
+    }
+
+    impl RegTestEnv {
+        /// set up local bitcoind and electrs instances in regtest mode, and connect a number of ldk-nodes to it.
+        pub fn new(num_nodes: u8) -> Self {
+            let bitcoind_exe =
+                bitcoind::downloaded_exe_path().expect("bitcoind version feature must be enabled");
+            let mut btc_conf = bitcoind::Conf::default();
+            btc_conf.network = "regtest";
+            let bitcoind = BitcoinD::with_conf(bitcoind_exe, &btc_conf).unwrap();
+            let electrs_exe =
+                electrsd::downloaded_exe_path().expect("electrs version feature must be enabled");
+            let mut elect_conf = electrsd::Conf::default();
+            elect_conf.http_enabled = true;
+            elect_conf.network = "regtest";
+            let electrsd = ElectrsD::with_conf(electrs_exe, &bitcoind, &elect_conf).unwrap();
+
+            // start the ldk-nodes
+            let ldk_nodes = (0..num_nodes)
+                .map(|i| {
+                    let listen = SocketAddr::new(
+                        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+                        Self::get_available_port(),
+                    );
+                    let mut builder = Builder::new();
+                    builder.set_network(Network::Regtest);
+                    builder.set_esplora_server(electrsd.esplora_url.clone().unwrap());
+                    let node = builder.build().unwrap();
+                    node.start().unwrap();
+                    println!("{:?} starting at {:?}", i, listen);
+                    node
+                })
+                .collect::<Vec<_>>();
+
+            RegTestEnv {
+                bitcoind,
+                electrsd,
+                ldk_nodes,
+            }
+        }
+
+        /// fund on-chain wallets
+        pub fn fund_on_chain_wallets(&self, num_blocks: &[usize], retries: u8) {
+            // generate coins to the node addresses
+            num_blocks
+                .iter()
+                .zip(self.ldk_nodes.iter())
+                .enumerate()
+                .for_each(|(i, (num_blocks, node))| {
+                    let addr = node.onchain_payment().new_address().unwrap();
+                    println!("{} Generating {} blocks to {}", i, num_blocks, addr);
+                    self.generate_to_address(*num_blocks, &addr);
+                });
+
+            // generate another 100 blocks to make the funds available
+            let addr = self
+                .ldk_nodes
+                .last()
+                .unwrap()
+                .onchain_payment()
+                .new_address()
+                .unwrap();
+            println!("Generating {} blocks to {}", 100, addr);
+            self.generate_to_address(100, &addr);
+
+            num_blocks
+                .iter()
+                .zip(self.ldk_nodes.iter())
+                .enumerate()
+                .for_each(|(i, (num_blocks, node))| {
+                    // synchronizing the nodes
+                    let _success = (0..retries)
+                        .map(|i| (i, node.sync_wallets()))
+                        .find(|(i, r)| {
+                            if let Err(e) = r {
+                                println!("{:?} sync : {:?}", i, e);
+                                sleep(Duration::from_secs(1));
+                            }
+                            r.is_ok()
+                        });
+                    // assert!(success.is_some());
+
+                    // checking the on-chain balance of the nodes
+                    (0..5).find(|_| {
+                        let bal = node.list_balances().spendable_onchain_balance_sats;
+                        if bal == 0 {
+                            sleep(Duration::from_secs(1));
+                        }
+                        bal > 0
+                    });
+                    let bal = node.list_balances().spendable_onchain_balance_sats;
+                    println!("{:?}", bal);
+                    let expected = *num_blocks as u64 * 5_000_000_000;
+                    assert_eq!(bal, expected, "node {} has a balance of {}", i, bal);
+                });
+            assert_eq!(self.get_height(), num_blocks.iter().sum::<usize>() + 101);
+        }
+
+        /// open channels
+        pub fn open_channels(&self, channels: &[(usize, usize, u64)]) {
+            channels.iter().for_each(|(n1, n2, sats)| {
+                let n1 = &self.ldk_nodes[*n1];
+                let n2 = &self.ldk_nodes[*n2];
+                n1.connect_open_channel(
+                    n2.node_id(),
+                    n2.listening_addresses().unwrap()[0].clone(),
+                    sats * 1_000,
+                    None,
+                    None,
+                    true,
+                )
+                .unwrap();
+
+                //sleep(Duration::from_secs(1));
+                //let event = node.next_event();
+                //println!("ldk event: {:?}", event);
+                //node.event_handled();
+
+                let addr1 = n1.onchain_payment().new_address().unwrap();
+                self.generate_to_address(3, &addr1);
+                let channels = n1.list_channels();
+                let chan = channels.last().unwrap();
+                println!("new channel: {:?}", chan);
+            });
+        }
+
+        fn get_height(&self) -> usize {
+            self.electrsd
+                .client
+                .block_headers_subscribe()
+                .unwrap()
+                .height
+        }
+
+        pub fn generate_to_address(&self, blocks: usize, address: &Address) {
+            let old_height = self.get_height();
+
+            self.bitcoind
+                .client
+                .generate_to_address(blocks as u64, address)
+                .unwrap();
+
+            let new_height = loop {
+                sleep(Duration::from_secs(1));
+                let new_height = self.get_height();
+                if new_height >= old_height + blocks {
+                    break new_height;
+                }
+            };
+
+            assert_eq!(new_height, old_height + blocks);
+        }
+
+        /// Returns a non-used local port if available.
+        /// Note there is a race condition during the time the method check availability and the caller
+        fn get_available_port() -> u16 {
+            // using 0 as port let the system assign a port available
+            let t = TcpListener::bind(("127.0.0.1", 0)).unwrap(); // 0 means the OS choose a free port
+            t.local_addr().map(|s| s.port()).unwrap()
+        }
+    }
+
+    #[test]
+    /// Open only one channel between two nodes
+    ///      0 --------> 1
+    fn test_regtest_two_nodes() {
+        let regtest_env = RegTestEnv::new(2);
+        regtest_env.fund_on_chain_wallets(&[1, 1], 10);
+        regtest_env.open_channels(&[(0, 1, 1_000_000)]);
+    }
+
+    #[test]
+    /// Open channels for the following graph:
+    ///      0 --------> 1
+    ///        \         / \
+    ///         \       /    >  4 ---> 5
+    ///          \     /      >
+    ///           \   <       /
+    ///            > 2 ---> 3
+    fn test_regtest_six_nodes() {
+        let regtest_env = RegTestEnv::new(6);
+        regtest_env.fund_on_chain_wallets(&[2, 2, 2, 2, 2, 2], 10);
+        regtest_env.open_channels(&[
+            (0, 1, 1_000_000_000),
+            (0, 2, 1_000_000_000),
+            (1, 2, 9_000_000_000),
+            (2, 3, 9_000_000_000),
+            (1, 4, 1_000_000_000),
+            (3, 4, 1_000_000_000),
+            (4, 5, 2_000_000_000),
+        ]);
+    }
+
+    #[test]
+    fn test_channel_callback() {
+        assert_eq!(
+            channel_callback("https://example.com/channel", "abc", "02ff"),
+            "https://example.com/channel?k1=abc&remoteid=02ff&private=1"
+        );
+        assert_eq!(
+            channel_callback("https://example.com/channel?id=1", "abc", "02ff"),
+            "https://example.com/channel?id=1&k1=abc&remoteid=02ff&private=1"
+        );
+    }
+
+    #[test]
+    fn test_regtest_sweep() {
+        let regtest_env = RegTestEnv::new(1);
+        regtest_env.fund_on_chain_wallets(&[1], 10);
+    }
+}
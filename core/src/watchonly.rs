@@ -0,0 +1,167 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::esplora;
+use crate::profiles;
+use crate::proxy;
+use crate::storage::{self, data_file};
+
+use bdk::{
+    bitcoin::Address,
+    database::MemoryDatabase,
+    descriptor::{Descriptor, DescriptorPublicKey},
+    wallet::AddressIndex,
+    SyncOptions, Wallet,
+};
+use chrono::Utc;
+use libelectrum2descriptors::{ElectrumExtendedKey, ElectrumExtendedPubKey};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, str::FromStr};
+
+/// Marks a profile as watch-only. It is kept in the data directory of the profile.
+pub const WATCH_ONLY_FILE: &str = "watch_only.json";
+
+/// The public descriptors of a wallet whose keys are kept elsewhere, e.g. in cold storage
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct WatchOnly {
+    pub descriptor: String,
+    pub change_descriptor: Option<String>,
+}
+
+impl WatchOnly {
+    /// Accepts a public descriptor, or an extended public key as exported by electrum
+    pub fn from_input(input: &str) -> Result<Self, String> {
+        let input = input.trim();
+        if let Ok(desc) = Descriptor::<DescriptorPublicKey>::from_str(input) {
+            desc.sanity_check()
+                .map_err(|e| format!("Invalid descriptor: {}", e))?;
+            return Ok(Self {
+                descriptor: desc.to_string(),
+                change_descriptor: None,
+            });
+        }
+        let xpub = ElectrumExtendedPubKey::from_str(input).map_err(|_| {
+            "Neither a public descriptor nor an extended public key. Private keys are not accepted."
+                .to_string()
+        })?;
+        let mut descriptors = xpub.to_descriptors().into_iter();
+        Ok(Self {
+            descriptor: descriptors
+                .next()
+                .ok_or("Failed to derive a descriptor from the extended public key")?,
+            change_descriptor: descriptors.next(),
+        })
+    }
+
+    fn wallet(&self) -> Result<Wallet<MemoryDatabase>, String> {
+        Wallet::new(
+            &self.descriptor,
+            self.change_descriptor.as_deref(),
+            profiles::network(),
+            MemoryDatabase::default(),
+        )
+        .map_err(|e| format!("Failed to construct the watch-only wallet: {}", e))
+    }
+
+    /// The wallet with its transactions fetched from the chain, unless in offline mode
+    fn synced(&self) -> Result<Wallet<MemoryDatabase>, String> {
+        let wallet = self.wallet()?;
+        if proxy::check_online().is_err() {
+            return Ok(wallet);
+        }
+        let blockchain = esplora::blockchain(&esplora::current())?;
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+        rt.block_on(wallet.sync(&blockchain, SyncOptions::default()))
+            .map_err(|e| format!("Failed to sync the watch-only wallet: {}", e))?;
+        Ok(wallet)
+    }
+}
+
+/// The watch-only descriptors of the active profile, None for a regular wallet
+pub fn load() -> Result<Option<WatchOnly>, String> {
+    if !data_file(WATCH_ONLY_FILE).exists() {
+        return Ok(None);
+    }
+    storage::load_json(WATCH_ONLY_FILE).map(Some)
+}
+
+pub fn is_watch_only() -> bool {
+    load().map(|w| w.is_some()).unwrap_or(false)
+}
+
+fn active() -> Result<WatchOnly, String> {
+    load()?.ok_or_else(|| "This is not a watch-only wallet".to_string())
+}
+
+/// The confirmed and unconfirmed balance in sats
+pub fn balance() -> Result<u64, String> {
+    active()?
+        .synced()?
+        .get_balance()
+        .map(|b| b.get_total())
+        .map_err(|e| format!("Failed to get the watch-only balance: {}", e))
+}
+
+pub fn address() -> Result<Address, String> {
+    active()?
+        .synced()?
+        .get_address(AddressIndex::LastUnused)
+        .map(|a| a.address)
+        .map_err(|e| format!("Unable to get an address: {}", e))
+}
+
+/// Build an unsigned transaction to be signed by the device holding the keys, and save it
+/// as a base64 PSBT
+pub fn build_psbt(recipient: &Address, amount: u64) -> Result<PathBuf, String> {
+    let wallet = active()?.synced()?;
+    let mut builder = wallet.build_tx();
+    builder
+        .add_recipient(recipient.script_pubkey(), amount)
+        .enable_rbf();
+    let (psbt, _) = builder
+        .finish()
+        .map_err(|e| format!("Failed to build the transaction: {}", e))?;
+    let file = data_file(&format!(
+        "unsigned_{}.psbt",
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ));
+    fs::write(&file, psbt.to_string())
+        .map_err(|e| format!("Failed to write the PSBT {:?}: {}", file, e))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_input() {
+        let desc = "wpkh(xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/0/*)";
+        let w = WatchOnly::from_input(desc).unwrap();
+        assert_eq!(w.change_descriptor, None);
+        assert!(w.wallet().is_ok());
+
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        let w = WatchOnly::from_input(zpub).unwrap();
+        assert!(w.descriptor.starts_with("wpkh("));
+        assert!(w.change_descriptor.is_some());
+
+        let xprv = "xprv9z1Nt86QQeoGXTjrvKgbFT924JeV1qmo2QV6m8YYTWkaVVWNc3nmeTTKsoq2PKVMfQLUKchQbazkT5FqLo4BUC2P2rVFmDnE46QBNjiAsLP";
+        assert!(WatchOnly::from_input(xprv).is_err());
+        assert!(WatchOnly::from_input(&format!("pkh({})", xprv)).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Calls the webhook of a merchant for every paid invoice, so that an order system learns
+//! about the payment without polling. The json body is signed with HMAC-SHA256 and a secret
+//! of the wallet, the signature is sent as `X-Utwallet-Signature: sha256=<hex>`.
+
+use crate::constants::WEBHOOK_RETRY_SECS;
+use crate::i18n::tr;
+use crate::invoices;
+use crate::pin;
+use crate::proxy;
+use crate::secrets;
+use crate::settings::Settings;
+use crate::storage::to_hex;
+
+use chrono::Utc;
+use serde::Serialize;
+use std::{thread, time::Duration};
+
+pub const WEBHOOK_SECRET: &str = "webhook_secret";
+
+const SIGNATURE_HEADER: &str = "X-Utwallet-Signature";
+const ATTEMPTS: u32 = 4;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct Payload {
+    /// invoice_paid, or test for the test call from the settings
+    event: String,
+    payment_hash: String,
+    amount_msat: u64,
+    description: String,
+    invoice: String,
+    /// unix timestamp
+    paid_at: i64,
+}
+
+/// Only https, except for an order system on the same host
+pub fn validate_url(url: &str) -> Result<(), String> {
+    let local = ["http://localhost", "http://127.0.0.1", "http://[::1]"];
+    if url.is_empty()
+        || url.starts_with("https://")
+        || local.iter().any(|l| {
+            url.strip_prefix(l).map_or(false, |rest| {
+                rest.is_empty() || rest.starts_with([':', '/'])
+            })
+        })
+    {
+        Ok(())
+    } else {
+        Err(tr("The webhook has to be an https url"))
+    }
+}
+
+/// The secret the calls are signed with, created on first use
+pub fn secret() -> Result<String, String> {
+    secrets::token(WEBHOOK_SECRET)
+}
+
+/// Replace the secret, the order system has to learn the new one
+pub fn reset_secret() -> Result<String, String> {
+    secrets::new_token(WEBHOOK_SECRET)
+}
+
+fn signature(secret: &str, body: &[u8]) -> String {
+    format!("sha256={}", to_hex(&pin::hmac(secret.as_bytes(), body)))
+}
+
+fn post(url: &str, payload: &Payload) -> Result<(), String> {
+    proxy::check_online()?;
+    let body = serde_json::to_vec(payload)
+        .map_err(|e| format!("Failed to serialize the webhook call: {}", e))?;
+    let signature = signature(&secret()?, &body);
+    let rt = tokio::runtime::Runtime::new()
+        .map_err(|e| format!("Failed to create a tokio runtime: {}", e))?;
+    rt.block_on(async {
+        let resp = proxy::http_client()?
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .body(body)
+            .send()
+            .await
+            .map_err(|e| format!("The webhook call failed: {}", e))?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("The webhook answered with {}", resp.status()))
+        }
+    })
+}
+
+/// Call the webhook in the background, retrying with growing delays
+fn deliver(url: String, payload: Payload) {
+    thread::spawn(move || {
+        let mut delay = WEBHOOK_RETRY_SECS;
+        for attempt in 1..=ATTEMPTS {
+            match post(&url, &payload) {
+                Ok(()) => {
+                    log_info!("webhook called for {}", payload.payment_hash);
+                    return;
+                }
+                Err(e) if attempt < ATTEMPTS => {
+                    log_error!("{}, retrying in {} seconds", e, delay);
+                    thread::sleep(Duration::from_secs(delay));
+                    delay *= 2;
+                }
+                Err(e) => log_error!("{}, giving up on {}", e, payload.payment_hash),
+            }
+        }
+    });
+}
+
+/// Tell the order system that one of our invoices was paid
+pub fn invoice_paid(payment_hash: &str, amount_msat: u64) {
+    let url = match Settings::load() {
+        Ok(settings) if !settings.webhook_url.is_empty() => settings.webhook_url,
+        Ok(_) => return,
+        Err(e) => {
+            log_error!("{}", e);
+            return;
+        }
+    };
+    // spontaneous payments and on-chain receipts have no invoice of ours
+    let invoice = match invoices::find(payment_hash) {
+        Ok(Some(invoice)) => invoice,
+        Ok(None) => return,
+        Err(e) => {
+            log_error!("{}", e);
+            return;
+        }
+    };
+    deliver(
+        url,
+        Payload {
+            event: "invoice_paid".to_string(),
+            payment_hash: payment_hash.to_string(),
+            amount_msat,
+            description: invoice.description,
+            invoice: invoice.invoice,
+            paid_at: Utc::now().timestamp(),
+        },
+    );
+}
+
+/// Call the webhook once with a made up payment, to check the setup of the order system
+pub fn test() -> Result<(), String> {
+    let url = Settings::load()?.webhook_url;
+    if url.is_empty() {
+        return Err(tr("No webhook configured"));
+    }
+    post(
+        &url,
+        &Payload {
+            event: "test".to_string(),
+            payment_hash: to_hex(&[0; 32]),
+            amount_msat: 0,
+            description: String::new(),
+            invoice: String::new(),
+            paid_at: Utc::now().timestamp(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_url() {
+        assert!(validate_url("").is_ok());
+        assert!(validate_url("https://shop.example.com/hooks/ln").is_ok());
+        assert!(validate_url("http://localhost:8080/paid").is_ok());
+        assert!(validate_url("http://127.0.0.1/paid").is_ok());
+        assert!(validate_url("http://shop.example.com/paid").is_err());
+        assert!(validate_url("http://localhost.example.com/paid").is_err());
+    }
+
+    #[test]
+    fn test_signature() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,116 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import NodeInfoModel 1.0
+
+Page {
+    id: aboutPage
+
+    signal close()
+
+    NodeInfoModel {
+        id: node_info
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('About my node')
+    }
+
+    Component.onCompleted: {
+        node_info.load();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        Label {
+            text: node_info.error
+            visible: node_info.error != ""
+            color: "red"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('Node id: %1').arg(node_info.node_id)
+            wrapMode: Text.WrapAnywhere
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('Listening on: %1').arg(node_info.listening_addresses.length > 0 ? node_info.listening_addresses.join(", ") : i18n.tr('nothing'))
+            wrapMode: Text.WrapAnywhere
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('Peers: %1 connected of %2').arg(node_info.connected_peers).arg(node_info.peers)
+        }
+
+        Label {
+            text: i18n.tr('Channels: %1 usable of %2').arg(node_info.usable_channels).arg(node_info.channels)
+        }
+
+        Label {
+            text: i18n.tr('Network: %1, block %2').arg(node_info.network).arg(node_info.block_height)
+        }
+
+        Label {
+            text: i18n.tr('utlnwallet %1, ldk-node %2').arg(node_info.app_version).arg(node_info.ldk_node_version)
+        }
+
+        Button {
+            text: i18n.tr('Copy for a bug report')
+            onClicked: {
+                var mimeData = Clipboard.newData();
+                mimeData.text = node_info.report();
+                Clipboard.push(mimeData);
+            }
+        }
+
+        Button {
+            text: i18n.tr('Export the log')
+            onClicked: {
+                log_file.text = node_info.export_log();
+            }
+        }
+
+        Label {
+            id: log_file
+            wrapMode: Text.WrapAnywhere
+            Layout.fillWidth: true
+            visible: text != ""
+        }
+
+        Button {
+            text: i18n.tr('Close')
+            onClicked: {
+                aboutPage.close();
+            }
+        }
+    }
+}
@@ -0,0 +1,181 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import Backup 1.0
+
+Page {
+    id: backupPage
+
+    signal close()
+    signal share(var urls)
+
+    Backup {
+        id: backup
+    }
+
+    PinDialog {
+        id: pin_dialog
+        anchors.fill: parent
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Backup and restore')
+    }
+
+    Component.onCompleted: {
+        backup.load();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        Label {
+            text: i18n.tr('Wallet fingerprint: %1\nNode id: %2').arg(backup.fingerprint).arg(backup.node_id)
+            wrapMode: Text.WrapAnywhere
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('The backup contains the seed, the channels, the labels and the settings, encrypted with the password. Keep a copy off the phone.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: password
+            Layout.fillWidth: true
+            placeholderText: i18n.tr('password')
+            echoMode: TextInput.Password
+            inputMethodHints: Qt.ImhSensitiveData
+        }
+
+        Button {
+            text: i18n.tr('Create backup')
+            onClicked: {
+                pin_dialog.authorize(function() {
+                    result.text = backup.create(password.text);
+                });
+            }
+        }
+
+        Label {
+            text: i18n.tr('The recovery kit explains how to get to the funds without the app, e.g. for an heir. Print it and keep it safe.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        CheckBox {
+            id: kit_seed
+            text: i18n.tr('Include the seed words')
+        }
+
+        Button {
+            text: i18n.tr('Create recovery kit')
+            onClicked: {
+                pin_dialog.authorize(function() {
+                    result.text = backup.recovery_kit(kit_seed.checked);
+                });
+            }
+        }
+
+        Label {
+            text: i18n.tr('Pair a desktop companion, which can show the balances and the history, but not spend.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        RowLayout {
+            Button {
+                text: i18n.tr('Show pairing code')
+                onClicked: {
+                    pin_dialog.authorize(function() {
+                        pairing_qr.source = "";
+                        pairing_qr.source = backup.pairing_qr();
+                    });
+                }
+            }
+
+            Button {
+                text: i18n.tr('Revoke')
+                onClicked: {
+                    backup.revoke_pairing();
+                    pairing_qr.source = "";
+                }
+            }
+        }
+
+        Image {
+            id: pairing_qr
+            visible: source != ""
+            cache: false
+            Layout.preferredWidth: units.gu(30)
+            Layout.preferredHeight: units.gu(30)
+            fillMode: Image.PreserveAspectFit
+        }
+
+        Label {
+            text: i18n.tr('Restore a backup. Only possible while this wallet is empty. Restoring an older backup than the latest can lose the channel balance.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        ComboBox {
+            id: backup_file
+            Layout.fillWidth: true
+            model: backup.files
+        }
+
+        RowLayout {
+            Button {
+                text: i18n.tr('Restore')
+                enabled: backup_file.currentText != ""
+                onClicked: {
+                    result.text = backup.restore(backup_file.currentText, password.text);
+                }
+            }
+
+            Button {
+                text: i18n.tr('Share')
+                enabled: backup_file.currentText != ""
+                onClicked: {
+                    var url = backup.share(backup_file.currentText);
+                    if (url != "") {
+                        backupPage.share([url]);
+                    }
+                }
+            }
+        }
+
+        Label {
+            id: result
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+    }
+}
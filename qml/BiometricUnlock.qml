@@ -0,0 +1,62 @@
+/*
+ * Copyright © 2020 Rodney Dawes
+ *
+ * This project is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This project is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+import QtQuick 2.7
+import Biometryd 0.0
+
+// Asks the system's biometric service to identify the user.
+// Loaded on demand, so the app still works where the module isn't available.
+Item {
+    id: biometricUnlock
+
+    property var operation: null
+
+    signal identified()
+    signal failed(string reason)
+
+    function start() {
+        operation = Biometryd.defaultDevice.identifier.identifyUser(user);
+        operation.start(observer);
+    }
+
+    function cancel() {
+        if (operation) {
+            operation.cancel();
+            operation = null;
+        }
+    }
+
+    User {
+        id: user
+        // the default user on Ubuntu Touch
+        uid: 32011
+    }
+
+    Observer {
+        id: observer
+
+        onSucceeded: {
+            biometricUnlock.operation = null;
+            biometricUnlock.identified();
+        }
+
+        onFailed: {
+            biometricUnlock.operation = null;
+            biometricUnlock.failed(reason);
+        }
+    }
+}
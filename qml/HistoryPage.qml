@@ -0,0 +1,215 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import HistoryModel 1.0
+
+Page {
+    id: historyPage
+
+    signal close()
+    signal showInvoices()
+    signal share(var urls)
+
+    HistoryModel {
+        id: history
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('History')
+
+        trailingActionBar.actions: [
+            Action {
+                text: history.channels_only ? i18n.tr('All entries') : i18n.tr('Channel events')
+                iconName: "network-vpn"
+                onTriggered: {
+                    history.channels_only = !history.channels_only;
+                }
+            },
+            Action {
+                text: history.show_archived ? i18n.tr('Hide archived') : i18n.tr('Show archived')
+                iconName: history.show_archived ? "view-off" : "view-on"
+                onTriggered: {
+                    history.show_archived = !history.show_archived;
+                }
+            },
+            Action {
+                text: i18n.tr('Rescan')
+                iconName: "reload"
+                onTriggered: {
+                    var err = history.rescan();
+                    rescan_error.text = err;
+                    rescan_error.visible = err != "";
+                }
+            },
+            Action {
+                text: i18n.tr('Invoices')
+                iconName: "view-list-symbolic"
+                onTriggered: {
+                    historyPage.showInvoices();
+                }
+            }
+        ]
+    }
+
+    Component.onCompleted: {
+        history.refresh();
+    }
+
+    Label {
+        id: rescan_error
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+        visible: false
+        color: "red"
+        wrapMode: Text.WordWrap
+    }
+
+    ListView {
+        id: history_list
+        anchors {
+            margins: units.gu(2)
+            top: rescan_error.visible ? rescan_error.bottom : header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: history
+
+        delegate: Item {
+            width: history_list.width
+            height: row.implicitHeight + units.gu(1)
+            opacity: model.archived ? 0.5 : 1.0
+
+            RowLayout {
+                id: row
+                anchors.fill: parent
+                spacing: units.gu(1)
+
+                Label {
+                    text: model.direction == "in" ? "+" : "-"
+                }
+                Label {
+                    text: model.timestamp
+                }
+                Label {
+                    text: model.kind
+                }
+                Label {
+                    text: model.alias != "" ? model.alias : model.peer.substring(0, 8)
+                    color: model.alias != "" && model.color != "" ? model.color : theme.palette.normal.baseText
+                    visible: model.peer != ""
+                }
+                Label {
+                    text: model.amount
+                    Layout.fillWidth: true
+                }
+                Label {
+                    text: model.reason != "" ? model.reason : (model.failureReason != "" ? model.failureReason : model.status)
+                    color: model.status == "failed" ? "red" : theme.palette.normal.baseText
+                }
+            }
+
+            MouseArea {
+                anchors.fill: parent
+                onPressAndHold: {
+                    history.archive(model.paymentId, !model.archived);
+                }
+                onClicked: {
+                    receipt_popup.paymentId = model.paymentId;
+                    receipt_text.text = history.receipt_text(model.paymentId);
+                    receipt_qr.source = history.receipt_qr(model.paymentId);
+                    receipt_popup.open();
+                }
+            }
+        }
+
+        onAtYEndChanged: {
+            if (atYEnd && history.can_fetch_more()) {
+                history.fetch_more();
+            }
+        }
+    }
+
+    Popup {
+        id: receipt_popup
+
+        property string paymentId: ""
+        anchors.centerIn: parent
+        modal: true
+
+        ColumnLayout {
+            spacing: units.gu(1)
+
+            TextArea {
+                id: receipt_text
+                readOnly: true
+                selectByMouse: true
+            }
+
+            Image {
+                id: receipt_qr
+                fillMode: Image.PreserveAspectFit
+                Layout.alignment: Qt.AlignHCenter
+            }
+
+            RowLayout {
+                Layout.alignment: Qt.AlignHCenter
+
+                Button {
+                    text: i18n.tr('Copy')
+                    onClicked: {
+                        var mimeData = Clipboard.newData();
+                        mimeData.text = receipt_text.text;
+                        Clipboard.push(mimeData);
+                    }
+                }
+
+                Button {
+                    text: i18n.tr('Share')
+                    onClicked: {
+                        var urls = history.share_receipt(receipt_popup.paymentId);
+                        if (urls.length > 0) {
+                            receipt_popup.close();
+                            historyPage.share(urls);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Timer {
+        id: history_timer;
+        interval: 2000;
+        running: true;
+        repeat: true
+
+        onTriggered: {
+            history.apply_updates();
+        }
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import InvoiceModel 1.0
+
+Page {
+    id: invoicesPage
+
+    signal close()
+
+    InvoiceModel {
+        id: invoices
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Invoices')
+    }
+
+    Component.onCompleted: {
+        invoices.refresh();
+    }
+
+    ListView {
+        id: invoice_list
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+        clip: true
+        model: invoices
+
+        delegate: RowLayout {
+            width: invoice_list.width
+            spacing: units.gu(1)
+
+            Label {
+                text: amount
+            }
+            Label {
+                text: withdrawUrl != "" ? withdrawUrl : description
+                Layout.fillWidth: true
+                elide: Text.ElideRight
+            }
+            Label {
+                text: withdrawStatus != "" ? withdrawStatus : (paid ? i18n.tr('paid') : (expired ? i18n.tr('expired') : expiry))
+                color: withdrawStatus == "never paid" ? "red" : theme.palette.normal.baseText
+            }
+        }
+    }
+
+    Timer {
+        id: invoices_timer;
+        interval: 5000;
+        running: true;
+        repeat: true
+
+        onTriggered: {
+            invoices.refresh();
+        }
+    }
+}
@@ -0,0 +1,84 @@
+/*
+ * Copyright © 2020 Rodney Dawes
+ *
+ * This project is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This project is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+import QtQuick 2.7
+import QtQuick.Layouts 1.1
+import QtQuick.Controls 2.2
+
+import PinLock 1.0
+
+// Covers the app while the wallet is locked
+Rectangle {
+    id: lockScreen
+
+    color: "#111111"
+    visible: pin_lock.locked
+
+    PinLock {
+        id: pin_lock
+
+        Component.onCompleted: {
+            pin_lock.start();
+        }
+    }
+
+    // keep the input away from the pages underneath
+    MouseArea {
+        anchors.fill: parent
+    }
+
+    ColumnLayout {
+        anchors.centerIn: parent
+        width: parent.width * 0.8
+        spacing: 12
+
+        Label {
+            text: i18n.tr("The wallet is locked")
+            font.pixelSize: 24
+        }
+
+        TextField {
+            id: pin_input
+            Layout.fillWidth: true
+            echoMode: TextInput.Password
+            inputMethodHints: Qt.ImhDigitsOnly | Qt.ImhSensitiveData
+            onAccepted: unlock_button.clicked()
+        }
+
+        Label {
+            id: pin_error
+            Layout.fillWidth: true
+            color: "red"
+            wrapMode: Text.WordWrap
+            visible: text != ""
+        }
+
+        Button {
+            id: unlock_button
+            Layout.alignment: Qt.AlignRight
+            text: i18n.tr("Unlock")
+            onClicked: {
+                pin_error.text = pin_lock.verify(pin_input.text);
+                pin_input.text = "";
+            }
+        }
+    }
+
+    function activity() {
+        pin_lock.activity();
+    }
+}
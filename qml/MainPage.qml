@@ -21,6 +21,10 @@ import QtQuick.Layouts 1.3
 import Qt.labs.settings 1.0
 
 import Greeter 1.0
+import PriceModel 1.0
+import EventLogModel 1.0
+import AmountFormat 1.0
+import RateProvider 1.0
 
 // for widgets visit:
 // https://doc.qt.io/qt-6/qtquick-controls2-qmlmodule.html
@@ -29,14 +33,241 @@ Page {
     id: mainPage
 
     signal scanCode()
+    signal showHistory()
+    signal showSettings()
+    signal showSeed()
+    signal showPos()
+    signal share(var urls)
 
     Greeter {
         id: greeter
+
+        onBackup_reminder: {
+            backup_reminder.visible = true;
+        }
+
+        onPreview_ready: {
+            error_hint.text = "";
+            confirm_popup.open();
+        }
+
+        onFailed: {
+            error_hint.text = mainPage.errorHint(code);
+            if (action == "enter_pin") {
+                pin_dialog.authorize(function() {});
+            }
+        }
+
+        onInput_evaluated: {
+            send_address.text = address;
+            send_amount.text = amount;
+            desc_txt.text = description;
+            comment_hint.text = comment_max > 0 ? i18n.tr('The recipient received the first %1 characters of the description as comment').arg(comment_max) : "";
+            range_hint.text = sendable_max > 0 ? i18n.tr('Pick an amount between %1 and %2 sats').arg(sendable_min).arg(sendable_max) : "";
+        }
+
+        onMessage_signed: {
+            message_signature.text = signature;
+            message_status.text = i18n.tr('Signed, copy the signature to the service');
+        }
+
+        onInvoice_paid: {
+            error_hint.text = i18n.tr('The invoice was paid');
+            send_amount.text = "";
+            desc_txt.text = "";
+            greeter.update_address();
+        }
+
+        onPayment_failed: {
+            error_hint.text = i18n.tr('Payment failed: %1').arg(reason);
+        }
+
+        onChannel_changed: {
+            var chan = greeter.channel_status;
+            if (chan == "") {
+                channel1.visible = false;
+                btn_channel_open.enabled = true;
+                btn_channel_close.enabled = false;
+            } else {
+                channel1.visible = true;
+                btn_channel_open.enabled = false;
+                btn_channel_close.enabled = true;
+                channel1.value = Math.abs(parseFloat(chan));
+            }
+        }
+    }
+
+    RateProvider {
+        id: rate_provider
+
+        onRate_changed: {
+            prices.refresh();
+        }
+    }
+
+    AmountFormat {
+        id: amount_format
+    }
+
+    PinDialog {
+        id: pin_dialog
+        anchors.fill: parent
+    }
+
+    Popup {
+        id: confirm_popup
+
+        x: parent.width / 2 - width / 2
+        y: parent.height / 2 - height / 2
+        width: parent.width * 0.9
+
+        modal: true
+        closePolicy: Popup.CloseOnEscape
+
+        ColumnLayout {
+            width: parent.width
+            spacing: units.gu(1)
+
+            Label {
+                text: i18n.tr('Confirm the payment')
+                font.bold: true
+            }
+
+            Label {
+                text: i18n.tr('Requested by another app')
+                visible: greeter.preview_external
+                color: "orange"
+            }
+
+            Label {
+                text: i18n.tr('Type: %1').arg(greeter.preview_kind)
+            }
+
+            Label {
+                text: i18n.tr('To: %1').arg(greeter.preview_alias != "" ? greeter.preview_alias + " (" + greeter.preview_recipient + ")" : greeter.preview_recipient)
+                wrapMode: Text.WrapAnywhere
+                Layout.fillWidth: true
+            }
+
+            Label {
+                text: i18n.tr('Amount: %1 %2').arg(amount_format.sats(greeter.preview_amount_sats)).arg(greeter.unit())
+            }
+
+            Label {
+                text: (greeter.preview_fee_is_max ? i18n.tr('Fee: at most %1 %2') : i18n.tr('Fee: about %1 %2')).arg(amount_format.sats(greeter.preview_fee_sats)).arg(greeter.unit())
+            }
+
+            RowLayout {
+                Layout.alignment: Qt.AlignRight
+
+                Button {
+                    text: i18n.tr('Cancel')
+                    onClicked: {
+                        greeter.cancel();
+                        confirm_popup.close();
+                    }
+                }
+
+                Button {
+                    text: i18n.tr('Confirm')
+                    onClicked: {
+                        confirm_popup.close();
+                        pin_dialog.authorizePayment(greeter.preview_amount_sats, function() {
+                            greeter.confirm();
+                            send_address.text = "";
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    Popup {
+        id: message_popup
+
+        x: parent.width / 2 - width / 2
+        y: parent.height / 2 - height / 2
+        width: parent.width * 0.9
+
+        modal: true
+        closePolicy: Popup.CloseOnEscape | Popup.CloseOnPressOutside
+
+        ColumnLayout {
+            width: parent.width
+            spacing: units.gu(1)
+
+            Label {
+                text: i18n.tr('Sign or verify a message')
+                font.bold: true
+            }
+
+            TextField {
+                id: message_address
+                placeholderText: i18n.tr('Address')
+                Layout.fillWidth: true
+            }
+
+            TextArea {
+                id: message_text
+                placeholderText: i18n.tr('Message')
+                Layout.fillWidth: true
+            }
+
+            TextField {
+                id: message_signature
+                placeholderText: i18n.tr('Signature')
+                Layout.fillWidth: true
+            }
+
+            Label {
+                id: message_status
+                visible: text != ""
+                wrapMode: Text.WordWrap
+                Layout.fillWidth: true
+            }
+
+            RowLayout {
+                Layout.alignment: Qt.AlignRight
+
+                Button {
+                    text: i18n.tr('Verify')
+                    onClicked: {
+                        message_status.text = greeter.verify_message(message_address.text, message_text.text, message_signature.text);
+                    }
+                }
+
+                Button {
+                    text: i18n.tr('Sign')
+                    onClicked: {
+                        pin_dialog.authorize(function() {
+                            greeter.sign_message(message_address.text, message_text.text);
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    EventLogModel {
+        id: eventlog_model
+    }
+
+    PriceModel {
+        id: prices
+
+        onSeries_changed: {
+            sparkline.requestPaint();
+        }
     }
         
     anchors.fill: parent
 
     Component.onCompleted: {
+        rate_provider.start();
+        eventlog_model.start();
+        // syncs, rates and node events are handled in the background from now on
+        greeter.start();
+
         if (args.values.url && (args.values.url.match(/^bitcoin/) || args.values.url.match(/^lightning/))) {
             console.log("Incoming Url on Closed App");
             handleUrl(args.values.url);
@@ -57,7 +288,33 @@ Page {
 
     header: PageHeader {
         id: header
-        title: i18n.tr('utwallet')
+        title: greeter.balance_text != "" ? greeter.balance_text : i18n.tr('utwallet')
+
+        trailingActionBar.actions: [
+            Action {
+                text: i18n.tr('Settings')
+                iconName: "settings"
+                onTriggered: {
+                    mainPage.showSettings();
+                }
+            },
+            Action {
+                text: i18n.tr('Sign message')
+                iconName: "edit"
+                onTriggered: {
+                    message_address.text = greeter.receiving_address.match(/^(bc1|tb1|bcrt1)/) ? greeter.receiving_address : "";
+                    message_status.text = "";
+                    message_popup.open();
+                }
+            },
+            Action {
+                text: i18n.tr('Point of sale')
+                iconName: "stock_store"
+                onTriggered: {
+                    mainPage.showPos();
+                }
+            }
+        ]
     }
 
     ColumnLayout {
@@ -70,15 +327,36 @@ Page {
             bottom: parent.bottom
         }
 
+        Label {
+            id: sync_status
+            Layout.fillWidth: true
+            visible: greeter.sync_state != ""
+            color: greeter.sync_state == "error" ? "red" : "gray"
+            text: {
+                if (greeter.connection == "offline") return i18n.tr('No network connection. Last sync: %1').arg(greeter.last_sync);
+                if (greeter.sync_state == "syncing") return i18n.tr('Syncing ...');
+                if (greeter.low_data) return i18n.tr('Saving data. Block %1, synced %2').arg(greeter.block_height).arg(greeter.last_sync);
+                if (greeter.sync_state == "error") return i18n.tr('Sync failed, the balance may be outdated. Last sync: %1').arg(greeter.last_sync);
+                return i18n.tr('Block %1, synced %2').arg(greeter.block_height).arg(greeter.last_sync);
+            }
+        }
+
         Button {
-            text: i18n.tr('Scan')
+            id: backup_reminder
             visible: false
+            Layout.fillWidth: true
+            text: i18n.tr('The seed of this wallet is not backed up yet. Back it up now.')
             onClicked: {
-                main_timer.stop();
+                backup_reminder.visible = false;
+                mainPage.showSeed();
+            }
+        }
 
+        Button {
+            text: i18n.tr('Scan')
+            visible: false
+            onClicked: {
                 mainPage.scanCode();
-
-                main_timer.start();
             }
         }
 
@@ -87,27 +365,38 @@ Page {
             text: i18n.tr('Address or Invoice')
         }
         
-        TextField {
+        // several lines of "address, amount" pay to many
+        TextArea {
             id: send_address
             placeholderText: i18n.tr('Address or Invoice')
             Layout.fillWidth: true
+            autoSize: true
+            maximumLineCount: 6
+            wrapMode: TextEdit.WrapAnywhere
+            inputMethodHints: Qt.ImhNoPredictiveText
+            onTextChanged: {
+                mainPage.markField("address", send_address, hint_send_address);
+            }
+        }
+
+        Label {
+            id: hint_send_address
+            visible: text != ""
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
         }
         
         Button {
             id: btn_eval
             text: i18n.tr('Evaluate Address or Invoice')
             onClicked: {
-                var txt = greeter.evaluate_address_input(send_address.text, send_amount.text, desc_txt.text);
-                var words = txt.split(";");
-                send_address.text = words[0];
-                send_amount.text = words[1];
-                desc_txt.text = words[2];
+                greeter.evaluate_address_input(send_address.text, send_amount.text, desc_txt.text);
             }
         }
 
         Label {
             id: label_send_amount
-            text: i18n.tr('Amount [BTC]')
+            text: i18n.tr('Amount [%1, or fiat like 5 CHF]').arg(greeter.unit())
         }
         
         RowLayout {
@@ -119,15 +408,30 @@ Page {
                 width: units.gu(20)
                 onTextChanged: {
                     label_fiat.text = greeter.fiat(send_amount.text);
+                    mainPage.markField("amount", send_amount, hint_send_amount);
+                }
+
+                Connections {
+                    target: rate_provider
+                    onRate_changed: {
+                        label_fiat.text = greeter.fiat(send_amount.text);
+                    }
                 }
             }
 
             Label {
                 id: label_fiat
-                text: i18n.tr('CHF 0')
+                text: ""
             }
         }
 
+        Label {
+            id: hint_send_amount
+            visible: text != ""
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
         Label {
             id: label_desc_txt
             text: i18n.tr('Description')
@@ -137,6 +441,32 @@ Page {
             id: desc_txt
             placeholderText: i18n.tr('lunch split')
             width: units.gu(20)
+            onTextChanged: {
+                mainPage.markField("description", desc_txt, hint_desc_txt);
+            }
+        }
+
+        Label {
+            id: hint_desc_txt
+            visible: text != ""
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Label {
+            id: range_hint
+            visible: text != ""
+            color: "gray"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Label {
+            id: comment_hint
+            visible: text != ""
+            color: "gray"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
         }
 
         RowLayout {
@@ -145,44 +475,114 @@ Page {
             Button {
                 text: i18n.tr('Send')
                 onClicked: {
-                    main_timer.stop();
-
-                    greeter.send(send_address.text, send_amount.text, desc_txt.text);
-                    send_address.text = "";
-
-                    main_timer.interval = 1000;
-                    main_timer.start();
+                    greeter.prepare(send_address.text, send_amount.text, desc_txt.text);
                 }
             }
 
             Button {
                 text: i18n.tr('Create Invoice')
                 onClicked: {
-                    main_timer.stop();
+                    greeter.request(send_amount.text, desc_txt.text);
+                }
+            }
+
+            Button {
+                text: i18n.tr('Lightning Address')
+                onClicked: {
+                    greeter.show_lnaddress();
+                }
+            }
+        }
 
-                    receive_qr_code.visible = false
-                    receive_qr_code.source = greeter.request(send_amount.text, desc_txt.text);
-                    receive_qr_code.visible = true;
-                    label_receive_addr.text = greeter.receiving_address;
+        RowLayout {
+            spacing: units.gu(2)
+            visible: greeter.payment_state == "pending" || greeter.payment_state == "in_flight"
 
-                    main_timer.interval = 10000;
-                    main_timer.start();
+            ActivityIndicator {
+                running: parent.visible
+            }
+
+            Label {
+                text: greeter.payment_state == "pending" ? i18n.tr('Preparing the payment') : i18n.tr('Payment on its way')
+            }
+
+            Button {
+                text: i18n.tr('Abort')
+                visible: greeter.payment_state == "pending"
+                onClicked: {
+                    greeter.cancel_payment(greeter.payment_handle);
                 }
             }
         }
 
+        Canvas {
+            id: sparkline
+            Layout.fillWidth: true
+            height: units.gu(4)
+            visible: prices.max > prices.min
+
+            onPaint: {
+                var ctx = getContext("2d");
+                ctx.reset();
+                var count = prices.rowCount();
+                if (count < 2) {
+                    return;
+                }
+                ctx.strokeStyle = "steelblue";
+                ctx.lineWidth = 2;
+                ctx.beginPath();
+                for (var i = 0; i < count; i++) {
+                    var x = i * width / (count - 1);
+                    var y = height - (prices.price(i) - prices.min) * height / (prices.max - prices.min);
+                    if (i == 0) {
+                        ctx.moveTo(x, y);
+                    } else {
+                        ctx.lineTo(x, y);
+                    }
+                }
+                ctx.stroke();
+            }
+        }
+
+        Label {
+            text: i18n.tr('1 BTC = %1 (%2)').arg(amount_format.fiat(rate_provider.rate)).arg(rate_provider.stale ? i18n.tr('%1, stale since %2').arg(rate_provider.source).arg(rate_provider.last_updated) : rate_provider.source)
+            visible: rate_provider.rate > 0
+        }
+
 	ProgressBar {
 	    id: channel1
 	    value: 0.5
 	    visible: false
 	}
 
-	TextArea {
-	    id: eventlog
+        ListView {
+            id: eventlog
             Layout.fillWidth: true
-            enabled: false
-	    text: "node is starting\n\n\n\n\n"
-	}
+            Layout.preferredHeight: units.gu(12)
+            clip: true
+            model: eventlog_model
+
+            delegate: Label {
+                width: eventlog.width
+                elide: Text.ElideRight
+                color: severity == "error" ? "red" : (greeter.busy ? "steelblue" : "black")
+                text: timestamp + "  " + message
+            }
+
+            Label {
+                visible: eventlog.count == 0
+                color: "gray"
+                text: i18n.tr('node is starting')
+            }
+        }
+
+        Label {
+            id: error_hint
+            Layout.fillWidth: true
+            color: "red"
+            wrapMode: Text.WordWrap
+            visible: text != ""
+        }
 
         Label {
             id: label_receive
@@ -192,9 +592,11 @@ Page {
         Image {
             id: receive_qr_code
             fillMode: Image.Stretch
+            cache: false
+            source: greeter.receiving_qr
             
             Component.onCompleted: {
-                receive_qr_code.source = greeter.address_qr();
+                greeter.update_address();
             }
             
             MouseArea {
@@ -205,17 +607,18 @@ Page {
                     mimeData.color = "green";
                     Clipboard.push(mimeData);
                 }
+                onPressAndHold: {
+                    var url = greeter.share_receiving_qr();
+                    if (url != "") {
+                        mainPage.share([url]);
+                    }
+                }
             }
         }
 
         Label {
             id: label_receive_addr
-            text: i18n.tr('Address')
-            
-            Component.onCompleted: {
-                label_receive_addr.text = greeter.address();
-            }
-
+            text: greeter.receiving_address != "" ? greeter.receiving_address : i18n.tr('Address')
         }
         
         RowLayout {
@@ -225,9 +628,7 @@ Page {
                 id: btn_channel_open;
                 text: i18n.tr('Channel Open')
                 onClicked: {
-                    main_timer.stop();
                     greeter.channel_open(send_amount.text, send_address.text);
-                    main_timer.start();
                 }
             }
 
@@ -236,9 +637,16 @@ Page {
                 text: i18n.tr('Channel Close')
                 enabled: false
                 onClicked: {
-                    main_timer.stop();
-                    greeter.channel_close();
-                    main_timer.start();
+                    pin_dialog.authorize(function() {
+                        greeter.channel_close();
+                    });
+                }
+            }
+
+            Button {
+                text: i18n.tr('History')
+                onClicked: {
+                    mainPage.showHistory();
                 }
             }
 
@@ -252,77 +660,15 @@ Page {
             }
         }
 
-        Timer {
-            id: main_timer;
-            interval: 2000;
-            running: true;
-            repeat: true
-            
-            onTriggered: {
-                console.time("main timer");
-                main_timer.stop();
-                eventlog.color = "steelblue"
-                
-                header.title = greeter.update_balance();
-                receive_qr_code.source = greeter.address_qr();
-                label_receive_addr.text = greeter.receiving_address;
-
-                var chan = greeter.update_channel();
-                if (chan == "") {
-                    channel1.visible = false;
-                    btn_channel_open.enabled = true;
-                    btn_channel_close.enabled = false;
-                } else {
-                    channel1.visible = true;
-                    btn_channel_open.enabled = false;
-                    btn_channel_close.enabled = true;
-                    channel1.value = Math.abs(parseFloat(chan));
-                    if (chan.startsWith("-")) {
-                    	// channel1.color = "red";
-                    } else {
-                    	// channel1.color = "green";
-                    }
-                }
-
-                eventlog.color = "black"
-                main_timer.interval = 20000;
-                main_timer.start();
-                console.timeEnd("main timer");
-            }
-        }
-
-        Timer {
-            id: exchange_timer;
-            interval: 600000;
-            running: true;
-            repeat: true
-
-            onTriggered: {
-                console.time("exchange timer");
-                eventlog.color = "steelblue"
-
-                var rate = greeter.update_exchange_rate();
-
-                eventlog.color = "black"
-                console.timeEnd("exchange timer");
-            }
-        }
-        Timer {
-            id: event_timer;
-            interval: 2000;
-            running: true;
-            repeat: true
-
-            onTriggered: {
-                // console.log("event timer enter");
-                event_timer.stop();
-                eventlog.color = "steelblue"
-
-                eventlog.text = greeter.ldk_events();
+        Connections {
+            target: Qt.application
 
-                eventlog.color = "black"
-                event_timer.start();
-                // console.log("event timer leave");
+            onStateChanged: {
+                if (Qt.application.state == Qt.ApplicationSuspended) {
+                    greeter.set_active(false);
+                } else if (Qt.application.state == Qt.ApplicationActive) {
+                    greeter.set_active(true);
+                }
             }
         }
 
@@ -341,17 +687,35 @@ Page {
 
     }
 
+    // Show the problems of a field right below it, nothing while it is empty
+    function markField(field, input, hint) {
+        var result = input.text == "" ? {level: "valid", message: ""} : greeter.validate_input(field, input.text);
+        hint.text = result.message;
+        hint.color = result.level == "error" ? "red" : "orange";
+    }
+
+    // A translated explanation for the code of a failed wallet operation
+    function errorHint(code) {
+        switch (code) {
+        case "not_initialized": return i18n.tr("The wallet is not running, please restart the app");
+        case "poisoned": return i18n.tr("The wallet ran into an internal error, please restart the app");
+        case "unauthorized": return i18n.tr("Please enter the PIN and try again");
+        case "invalid_input": return i18n.tr("Please check the address or invoice");
+        case "invalid_amount": return i18n.tr("Please check the amount");
+        case "network": return i18n.tr("A server could not be reached, please try again later");
+        case "node": return i18n.tr("The lightning node refused, please try again later");
+        case "storage": return i18n.tr("The wallet files could not be accessed");
+        default: return "";
+        }
+    }
+
     function handleUrl(url) {
         if (url === "") {
             console.log("DEBUG: Error. The incoming url is empty");
             return;
         }
 
-        var txt = greeter.evaluate_address_input(url, '', '');
-        var words = txt.split(";");
-        send_address.text = words[0];
-        send_amount.text = words[1];
-        desc_txt.text = words[2];
+        greeter.evaluate_address_input(url, '', '');
     }
 
 
@@ -0,0 +1,172 @@
+/*
+ * Copyright © 2020 Rodney Dawes
+ *
+ * This project is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This project is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ */
+import QtQuick 2.7
+import QtQuick.Layouts 1.1
+import QtQuick.Controls 2.2
+
+import PinLock 1.0
+
+Item {
+    id: pinRect
+
+    property var action: null
+    // the PIN has to be typed, the fingerprint is not enough
+    property bool typed: false
+
+    visible: pinPopup.opened
+
+    PinLock {
+        id: pin_lock
+    }
+
+    Loader {
+        id: biometric
+        active: pin_lock.biometric
+        source: "BiometricUnlock.qml"
+    }
+
+    Connections {
+        target: biometric.item
+        ignoreUnknownSignals: true
+
+        onIdentified: {
+            if (pinRect.typed) {
+                return;
+            }
+            pin_error.text = pin_lock.biometric_identified();
+            if (pin_error.text == "") {
+                pinRect.finish();
+            }
+        }
+
+        onFailed: {
+            pin_error.text = i18n.tr("Fingerprint not recognized, please enter the PIN");
+        }
+    }
+
+    // Run the action right away if no PIN is required, otherwise ask for it first
+    function authorize(callback) {
+        pin_lock.load();
+        if (!pin_lock.is_set || pin_lock.authorized()) {
+            callback();
+            return;
+        }
+        ask(callback, false);
+    }
+
+    // Payments above the threshold always ask for the PIN again
+    function authorizePayment(sats, callback) {
+        pin_lock.load();
+        if (!pin_lock.needs_fresh_pin(sats)) {
+            authorize(callback);
+            return;
+        }
+        ask(callback, true);
+    }
+
+    function ask(callback, mustType) {
+        action = callback;
+        typed = mustType;
+        pin_input.text = "";
+        pin_error.text = "";
+        pinPopup.open();
+        if (!typed && biometric.status == Loader.Ready) {
+            biometric.item.start();
+        }
+    }
+
+    function submit() {
+        pin_error.text = pin_lock.verify(pin_input.text);
+        pin_input.text = "";
+        if (pin_error.text == "") {
+            finish();
+        }
+    }
+
+    function finish() {
+        if (biometric.status == Loader.Ready) {
+            biometric.item.cancel();
+        }
+        pinPopup.close();
+        var callback = action;
+        action = null;
+        callback();
+    }
+
+    Popup {
+        id: pinPopup
+
+        x: parent.width / 2 - width / 2
+        y: parent.height / 2 - height / 2
+
+        modal: true
+        closePolicy: Popup.CloseOnEscape
+
+        background: Rectangle {
+            color: "#111111"
+            opacity: 0.99
+            radius: 8
+        }
+
+        ColumnLayout {
+            width: parent.width
+            spacing: 12
+
+            Label {
+                text: typed ? i18n.tr("Enter your PIN again to confirm this large payment") : biometric.status == Loader.Ready ? i18n.tr("Touch the sensor or enter your PIN") : i18n.tr("Enter your PIN")
+                font.pixelSize: 24
+            }
+
+            TextField {
+                id: pin_input
+                Layout.fillWidth: true
+                echoMode: TextInput.Password
+                inputMethodHints: Qt.ImhDigitsOnly | Qt.ImhSensitiveData
+                onAccepted: pinRect.submit()
+            }
+
+            Label {
+                id: pin_error
+                Layout.fillWidth: true
+                color: "red"
+                wrapMode: Text.WordWrap
+                visible: text != ""
+            }
+
+            RowLayout {
+                Layout.alignment: Qt.AlignRight
+
+                Button {
+                    text: i18n.tr("Cancel")
+                    onClicked: {
+                        if (biometric.status == Loader.Ready) {
+                            biometric.item.cancel();
+                        }
+                        pinRect.action = null;
+                        pinPopup.close();
+                    }
+                }
+
+                Button {
+                    text: i18n.tr("OK")
+                    onClicked: pinRect.submit()
+                }
+            }
+        }
+    }
+}
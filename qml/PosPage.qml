@@ -0,0 +1,196 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import PosModel 1.0
+import RateProvider 1.0
+
+Page {
+    id: posPage
+
+    signal close()
+
+    PosModel {
+        id: pos
+    }
+
+    RateProvider {
+        id: rate
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Point of sale')
+
+        trailingActionBar.actions: [
+            Action {
+                text: i18n.tr('Daily report')
+                iconName: "document-open"
+                onTriggered: {
+                    report_label.text = pos.report("");
+                    report_popup.open();
+                }
+            }
+        ]
+    }
+
+    Component.onCompleted: {
+        rate.start();
+        pos.start();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+            bottom: parent.bottom
+        }
+
+        Label {
+            text: pos.amount + " " + rate.currency
+            font.pixelSize: units.gu(5)
+            Layout.alignment: Qt.AlignHCenter
+        }
+
+        Label {
+            visible: pos.tip_percent > 0
+            text: i18n.tr('with tip: %1 %2').arg(pos.total).arg(rate.currency)
+            Layout.alignment: Qt.AlignHCenter
+        }
+
+        Label {
+            visible: rate.rate == 0
+            text: i18n.tr('The exchange rate is not known yet')
+            color: "red"
+            Layout.alignment: Qt.AlignHCenter
+        }
+
+        Label {
+            visible: pos.error != ""
+            text: pos.error
+            color: "red"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        GridLayout {
+            visible: pos.state == "idle" || pos.state == "creating"
+            columns: 3
+            Layout.alignment: Qt.AlignHCenter
+
+            Repeater {
+                model: ["1", "2", "3", "4", "5", "6", "7", "8", "9", "C", "0", "<"]
+
+                Button {
+                    text: modelData
+                    Layout.preferredWidth: units.gu(8)
+                    Layout.preferredHeight: units.gu(6)
+                    onClicked: {
+                        pos.key(modelData);
+                    }
+                }
+            }
+        }
+
+        RowLayout {
+            visible: pos.state == "idle"
+            Layout.alignment: Qt.AlignHCenter
+
+            Label {
+                text: i18n.tr('Tip')
+            }
+
+            Repeater {
+                model: [0, 5, 10, 15]
+
+                Button {
+                    text: modelData + "%"
+                    highlighted: pos.tip_percent == modelData
+                    onClicked: {
+                        pos.tip_percent = modelData;
+                    }
+                }
+            }
+        }
+
+        Button {
+            visible: pos.state == "idle" || pos.state == "creating"
+            enabled: pos.state == "idle" && rate.rate > 0
+            text: i18n.tr('Charge')
+            Layout.fillWidth: true
+            onClicked: {
+                pos.charge();
+            }
+        }
+
+        Image {
+            visible: pos.state == "waiting"
+            source: pos.qr
+            cache: false
+            fillMode: Image.PreserveAspectFit
+            Layout.fillWidth: true
+            Layout.fillHeight: true
+        }
+
+        Label {
+            visible: pos.state != "idle" && pos.state != "creating"
+            text: {
+                if (pos.state == "paid") return i18n.tr('Paid, thank you!');
+                if (pos.state == "expired") return i18n.tr('The invoice expired');
+                return i18n.tr('Waiting for %1 sats').arg(pos.sats);
+            }
+            color: pos.state == "paid" ? "green" : (pos.state == "expired" ? "red" : theme.palette.normal.baseText)
+            font.pixelSize: units.gu(3)
+            Layout.alignment: Qt.AlignHCenter
+        }
+
+        Button {
+            visible: pos.state != "idle" && pos.state != "creating"
+            text: pos.state == "waiting" ? i18n.tr('Cancel') : i18n.tr('Next sale')
+            Layout.fillWidth: true
+            onClicked: {
+                pos.new_sale();
+            }
+        }
+    }
+
+    Popup {
+        id: report_popup
+        anchors.centerIn: parent
+        modal: true
+
+        Label {
+            id: report_label
+        }
+    }
+
+    Timer {
+        interval: 5000
+        running: pos.state == "waiting"
+        repeat: true
+
+        onTriggered: {
+            pos.check_expiry();
+        }
+    }
+}
@@ -0,0 +1,153 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import ProfileModel 1.0
+
+Page {
+    id: profilesPage
+
+    signal close()
+
+    ProfileModel {
+        id: profiles
+    }
+
+    PinDialog {
+        id: pin_dialog
+        anchors.fill: parent
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Wallets')
+    }
+
+    Component.onCompleted: {
+        profiles.load();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        Label {
+            text: i18n.tr('Each wallet has its own seed, channels and history.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Repeater {
+            model: profiles.names
+
+            RadioButton {
+                text: modelData
+                checked: modelData == profiles.active
+                onClicked: {
+                    var name = modelData;
+                    pin_dialog.authorize(function() {
+                        result.text = profiles.select(name);
+                        if (result.text == "") {
+                            profilesPage.close();
+                        }
+                    });
+                }
+            }
+        }
+
+        RowLayout {
+            spacing: units.gu(1)
+
+            TextField {
+                id: new_name
+                placeholderText: i18n.tr('business')
+                Layout.fillWidth: true
+            }
+
+            Button {
+                text: i18n.tr('Create')
+                onClicked: {
+                    if (watch_descriptor.text == "") {
+                        result.text = profiles.create(new_name.text);
+                    } else {
+                        result.text = profiles.create_watch_only(new_name.text, watch_descriptor.text);
+                    }
+                    new_name.text = "";
+                    watch_descriptor.text = "";
+                }
+            }
+        }
+
+        TextField {
+            id: watch_descriptor
+            Layout.fillWidth: true
+            placeholderText: i18n.tr('xpub or descriptor, for a watch-only wallet')
+            inputMethodHints: Qt.ImhNoPredictiveText
+        }
+
+        Label {
+            text: i18n.tr('Network')
+        }
+
+        ComboBox {
+            model: ["bitcoin", "testnet", "signet", "regtest"]
+            currentIndex: model.indexOf(profiles.network)
+            Layout.fillWidth: true
+            onActivated: {
+                var name = model[index];
+                pin_dialog.authorize(function() {
+                    result.text = profiles.select_network(name);
+                    if (result.text == "") {
+                        profilesPage.close();
+                    }
+                });
+            }
+        }
+
+        Label {
+            text: i18n.tr('The coins on the test networks are worthless, each network has its own wallets.')
+            visible: profiles.network != "bitcoin"
+            color: "orange"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        CheckBox {
+            text: i18n.tr('Choose the wallet when the app starts')
+            checked: profiles.ask_at_startup
+            onClicked: {
+                profiles.ask_at_startup = checked;
+            }
+        }
+
+        Label {
+            id: result
+            color: "red"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+    }
+}
@@ -0,0 +1,161 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import SeedBackup 1.0
+
+Page {
+    id: seedPage
+
+    signal close()
+
+    SeedBackup {
+        id: seed
+    }
+
+    PinDialog {
+        id: pin_dialog
+        anchors.fill: parent
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Seed backup')
+    }
+
+    Component.onCompleted: {
+        seed.load();
+    }
+
+    Component.onDestruction: {
+        seed.hide();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        Label {
+            text: seed.backup_confirmed ? i18n.tr('The backup of this wallet was confirmed.') : i18n.tr('This wallet has no confirmed backup yet.')
+            color: seed.backup_confirmed ? theme.palette.normal.baseText : "red"
+        }
+
+        Label {
+            text: i18n.tr('Anyone who sees these words can take your funds. Write them down on paper and keep them safe.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        CheckBox {
+            id: acknowledged
+            text: i18n.tr('Nobody is watching my screen')
+        }
+
+        Button {
+            text: seed.words.length > 0 ? i18n.tr('Hide the words') : i18n.tr('Show the words')
+            onClicked: {
+                if (seed.words.length > 0) {
+                    seed.hide();
+                } else {
+                    pin_dialog.authorize(function() {
+                        seed_error.text = seed.reveal(acknowledged.checked);
+                    });
+                }
+            }
+        }
+
+        Label {
+            id: seed_error
+            color: "red"
+            visible: text != ""
+        }
+
+        GridLayout {
+            columns: 3
+            visible: seed.words.length > 0
+
+            Repeater {
+                model: seed.words
+
+                Label {
+                    text: (index + 1) + ". " + modelData
+                }
+            }
+        }
+
+        Button {
+            text: i18n.tr('Verify my backup')
+            onClicked: {
+                seed.hide();
+                seed.new_challenge();
+                verify_result.text = "";
+            }
+        }
+
+        Label {
+            visible: seed.challenge.length > 0
+            text: i18n.tr('Enter the words number %1, separated by spaces').arg(seed.challenge.join(", "))
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: answers
+            visible: seed.challenge.length > 0
+            Layout.fillWidth: true
+            inputMethodHints: Qt.ImhNoPredictiveText | Qt.ImhSensitiveData
+            onAccepted: {
+                verify_result.text = seed.verify(answers.text) ? i18n.tr('Backup confirmed') : i18n.tr('The words do not match');
+                answers.text = "";
+            }
+        }
+
+        Label {
+            id: verify_result
+        }
+
+        Label {
+            text: i18n.tr('Or check the complete backup, without showing the seed:')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextArea {
+            id: full_backup
+            Layout.fillWidth: true
+            wrapMode: TextEdit.WordWrap
+            inputMethodHints: Qt.ImhNoPredictiveText | Qt.ImhSensitiveData
+        }
+
+        Button {
+            text: i18n.tr('Check')
+            onClicked: {
+                verify_result.text = seed.verify_mnemonic(full_backup.text) ? i18n.tr('The backup matches the seed') : i18n.tr('The backup does not match the seed');
+                full_backup.text = "";
+            }
+        }
+    }
+}
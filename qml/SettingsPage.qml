@@ -0,0 +1,748 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import QtQuick.Layouts 1.3
+
+import PinLock 1.0
+import SettingsModel 1.0
+
+Page {
+    id: settingsPage
+
+    signal close()
+    signal showSeed()
+    signal showBackup()
+    signal showProfiles()
+    signal showAbout()
+
+    SettingsModel {
+        id: settings
+    }
+
+    PinLock {
+        id: pin_lock
+    }
+
+    PinDialog {
+        id: pin_dialog
+        anchors.fill: parent
+    }
+
+    header: PageHeader {
+        id: header
+        title: i18n.tr('Settings')
+
+        trailingActionBar.actions: [
+            Action {
+                text: i18n.tr('Wallets')
+                iconName: "account"
+                onTriggered: {
+                    settingsPage.showProfiles();
+                }
+            },
+            Action {
+                text: i18n.tr('Backup')
+                iconName: "save"
+                onTriggered: {
+                    settingsPage.showBackup();
+                }
+            },
+            Action {
+                text: i18n.tr('About my node')
+                iconName: "info"
+                onTriggered: {
+                    settingsPage.showAbout();
+                }
+            },
+            Action {
+                text: i18n.tr('Seed backup')
+                iconName: "security-alert"
+                onTriggered: {
+                    settingsPage.showSeed();
+                }
+            }
+        ]
+    }
+
+    Component.onCompleted: {
+        settings.load();
+        pin_lock.load();
+    }
+
+    ColumnLayout {
+        spacing: units.gu(2)
+        anchors {
+            margins: units.gu(2)
+            top: header.bottom
+            left: parent.left
+            right: parent.right
+        }
+
+        Label {
+            text: i18n.tr('Fiat currency')
+        }
+
+        ComboBox {
+            id: currency
+            model: settings.currencies()
+            currentIndex: model.indexOf(settings.currency)
+            onActivated: {
+                settings.currency = model[index];
+            }
+        }
+
+        Label {
+            text: i18n.tr('Denomination')
+        }
+
+        ComboBox {
+            id: denomination
+            model: ["btc", "sats"]
+            currentIndex: model.indexOf(settings.denomination)
+            onActivated: {
+                settings.denomination = model[index];
+            }
+        }
+
+        Label {
+            text: i18n.tr('CoinMarketCap API key (optional)')
+        }
+
+        TextField {
+            id: cmc_api_key
+            text: settings.cmc_api_key
+            Layout.fillWidth: true
+            echoMode: TextInput.PasswordEchoOnEdit
+            onEditingFinished: {
+                settings.cmc_api_key = cmc_api_key.text;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Exchange rate sources, in the order they are asked')
+        }
+
+        Repeater {
+            model: settings.all_rate_sources()
+
+            RowLayout {
+                spacing: units.gu(1)
+
+                CheckBox {
+                    text: modelData
+                    checked: settings.rate_sources.indexOf(modelData) >= 0
+                    onClicked: {
+                        settings.enable_rate_source(modelData, checked);
+                    }
+                }
+
+                Label {
+                    text: settings.rate_sources.indexOf(modelData) >= 0 ? "#" + (settings.rate_sources.indexOf(modelData) + 1) : ""
+                }
+
+                Button {
+                    text: i18n.tr('Up')
+                    enabled: settings.rate_sources.indexOf(modelData) > 0
+                    onClicked: {
+                        settings.move_rate_source_up(modelData);
+                    }
+                }
+            }
+        }
+
+        CheckBox {
+            text: i18n.tr('Offline mode: no network access, only addresses, signing and QR codes')
+            checked: settings.offline
+            onClicked: {
+                settings.offline = checked;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Esplora servers, in use: %1').arg(settings.esplora_current)
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Repeater {
+            model: settings.esplora_servers
+
+            RowLayout {
+                spacing: units.gu(1)
+
+                Label {
+                    text: modelData
+                    color: settings.esplora_unreachable.indexOf(modelData) >= 0 ? "red" : "black"
+                    elide: Text.ElideMiddle
+                    Layout.fillWidth: true
+                }
+
+                Button {
+                    text: i18n.tr('Remove')
+                    enabled: settings.esplora_servers.length > 1
+                    onClicked: {
+                        esplora_error.text = settings.remove_esplora_server(modelData);
+                    }
+                }
+            }
+        }
+
+        RowLayout {
+            spacing: units.gu(1)
+
+            TextField {
+                id: esplora_url
+                placeholderText: "https://"
+                inputMethodHints: Qt.ImhUrlCharactersOnly
+                Layout.fillWidth: true
+            }
+
+            Button {
+                text: i18n.tr('Add')
+                onClicked: {
+                    esplora_error.text = settings.add_esplora_server(esplora_url.text);
+                    if (esplora_error.text == "") {
+                        esplora_url.text = "";
+                    }
+                }
+            }
+
+            Button {
+                text: i18n.tr('Check')
+                onClicked: {
+                    settings.check_esplora_servers();
+                }
+            }
+        }
+
+        Label {
+            id: esplora_error
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+            visible: text != ""
+        }
+
+        Label {
+            text: i18n.tr('Electrum server for the fee estimates, e.g. ssl://electrs.local:50002. The lightning node keeps syncing from esplora.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: electrum_url
+            text: settings.electrum_url
+            placeholderText: "ssl://"
+            Layout.fillWidth: true
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+            onEditingFinished: {
+                settings.electrum_url = electrum_url.text;
+            }
+        }
+
+        Label {
+            text: settings.electrum_status
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+            visible: text != "" && settings.electrum_url != ""
+        }
+
+        Label {
+            text: i18n.tr('Sync the wallets every ... seconds')
+        }
+
+        TextField {
+            id: sync_interval_secs
+            text: settings.sync_interval_secs
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 30 }
+            onEditingFinished: {
+                settings.sync_interval_secs = parseInt(sync_interval_secs.text) || 120;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Keep the last ... events in the log')
+        }
+
+        TextField {
+            id: event_log_length
+            text: settings.event_log_length
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 5 }
+            onEditingFinished: {
+                settings.event_log_length = parseInt(event_log_length.text) || 50;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Fetch the exchange rate every ... seconds')
+        }
+
+        TextField {
+            id: rate_interval_secs
+            text: settings.rate_interval_secs
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 60 }
+            onEditingFinished: {
+                settings.rate_interval_secs = parseInt(rate_interval_secs.text) || 600;
+            }
+        }
+
+        CheckBox {
+            text: i18n.tr('Verbose logging, for bug reports')
+            checked: settings.verbose_logging
+            onClicked: {
+                settings.verbose_logging = checked;
+            }
+        }
+
+        CheckBox {
+            text: i18n.tr('Sync less often while running on battery')
+            checked: settings.battery_saver
+            onClicked: {
+                settings.battery_saver = checked;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Save data by syncing less often and skipping the exchange rate')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        ComboBox {
+            id: low_data
+            model: ["off", "mobile", "always"]
+            displayText: {
+                if (currentText == "mobile") return i18n.tr('On mobile data');
+                if (currentText == "always") return i18n.tr('Always');
+                return i18n.tr('Never');
+            }
+            currentIndex: model.indexOf(settings.low_data)
+            onActivated: {
+                settings.low_data = model[index];
+            }
+        }
+
+        Label {
+            text: i18n.tr('Proxy for the http traffic, e.g. socks5h://127.0.0.1:9050 for Tor. The node uses it after the app restarted.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: proxy
+            text: settings.proxy
+            Layout.fillWidth: true
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+            onEditingFinished: {
+                settings.proxy = proxy.text;
+            }
+        }
+
+        Label {
+            visible: settings.proxy != ""
+            text: i18n.tr('The lightning peers are still connected directly, not through the proxy. They see the IP address of the phone, so the wallet can not be used anonymously over Tor.')
+            color: "red"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        CheckBox {
+            text: i18n.tr('Notify about payments the background node receives')
+            checked: settings.notifications
+            onClicked: {
+                settings.notifications = checked;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Webhook, called with the details of every paid invoice')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: webhook_url
+            text: settings.webhook_url
+            Layout.fillWidth: true
+            placeholderText: "https://"
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+            onEditingFinished: {
+                settings.webhook_url = webhook_url.text;
+            }
+        }
+
+        Label {
+            visible: settings.webhook_url != ""
+            text: i18n.tr('The calls are signed with this secret, in the X-Utwallet-Signature header')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            visible: settings.webhook_url != ""
+            text: settings.webhook_secret
+            readOnly: true
+            Layout.fillWidth: true
+        }
+
+        RowLayout {
+            visible: settings.webhook_url != ""
+
+            Button {
+                text: i18n.tr('Test')
+                onClicked: {
+                    settings.test_webhook();
+                }
+            }
+
+            Button {
+                text: i18n.tr('New secret')
+                onClicked: {
+                    settings.reset_webhook_secret();
+                }
+            }
+        }
+
+        Label {
+            text: settings.webhook_status
+            visible: text != ""
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('Lightning address, served by the LNURLp extension of an LNbits wallet. The payments wait there until the wallet moves them to its node.')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: lnaddress_provider
+            visible: settings.lnaddress == ""
+            text: settings.lnaddress_provider
+            Layout.fillWidth: true
+            placeholderText: "https://"
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+        }
+
+        TextField {
+            id: lnaddress_key
+            visible: settings.lnaddress == ""
+            Layout.fillWidth: true
+            placeholderText: i18n.tr('admin key of the LNbits wallet')
+            echoMode: TextInput.Password
+        }
+
+        RowLayout {
+            visible: settings.lnaddress == ""
+
+            TextField {
+                id: lnaddress_username
+                Layout.fillWidth: true
+                placeholderText: i18n.tr('name')
+                inputMethodHints: Qt.ImhNoAutoUppercase | Qt.ImhPreferLowercase
+            }
+
+            Button {
+                text: i18n.tr('Claim')
+                onClicked: {
+                    settings.claim_lnaddress(lnaddress_provider.text, lnaddress_key.text, lnaddress_username.text);
+                    lnaddress_key.text = "";
+                }
+            }
+        }
+
+        RowLayout {
+            visible: settings.lnaddress != ""
+
+            TextField {
+                text: settings.lnaddress
+                readOnly: true
+                Layout.fillWidth: true
+            }
+
+            Button {
+                text: i18n.tr('Give up')
+                onClicked: {
+                    settings.release_lnaddress();
+                }
+            }
+        }
+
+        Label {
+            text: settings.lnaddress_status
+            visible: text != ""
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        CheckBox {
+            text: i18n.tr('REST API on localhost, for scripts on the phone')
+            checked: settings.rest_api
+            onClicked: {
+                settings.rest_api = checked;
+            }
+        }
+
+        Label {
+            visible: settings.rest_api
+            text: i18n.tr('Port')
+        }
+
+        TextField {
+            id: rest_port
+            visible: settings.rest_api
+            text: settings.rest_port
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 1024; top: 65535 }
+            onEditingFinished: {
+                settings.rest_port = parseInt(rest_port.text) || 8421;
+            }
+        }
+
+        Label {
+            visible: settings.rest_api
+            text: i18n.tr('Send this token as "Authorization: Bearer <token>"')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            visible: settings.rest_api
+            text: settings.rest_token
+            readOnly: true
+            Layout.fillWidth: true
+        }
+
+        Button {
+            visible: settings.rest_api
+            text: i18n.tr('New token')
+            onClicked: {
+                settings.reset_rest_token();
+            }
+        }
+
+        Label {
+            text: i18n.tr('Other apps pass this token to GetBalance and CreateInvoice over D-Bus')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            text: settings.dbus_token
+            readOnly: true
+            Layout.fillWidth: true
+        }
+
+        Button {
+            text: i18n.tr('New token')
+            onClicked: {
+                settings.reset_dbus_token();
+            }
+        }
+
+        Label {
+            text: i18n.tr('Remote channel storage (VSS) url, empty keeps it on the phone only')
+            visible: settings.vss_supported
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: vss_url
+            text: settings.vss_url
+            visible: settings.vss_supported
+            Layout.fillWidth: true
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+            onEditingFinished: {
+                settings.vss_url = vss_url.text;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Rapid gossip sync url, empty for the default server')
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        TextField {
+            id: rgs_url
+            text: settings.rgs_url
+            Layout.fillWidth: true
+            inputMethodHints: Qt.ImhUrlCharactersOnly
+            onEditingFinished: {
+                settings.rgs_url = rgs_url.text;
+            }
+        }
+
+        Label {
+            text: settings.rgs_status
+            visible: text != ""
+            color: "red"
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+
+        Label {
+            text: i18n.tr('Keep history for days (0 = forever)')
+        }
+
+        TextField {
+            id: retention_days
+            text: settings.retention_days
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 0 }
+            onEditingFinished: {
+                settings.retention_days = parseInt(retention_days.text) || 0;
+            }
+        }
+
+        CheckBox {
+            text: i18n.tr('Keep monthly totals of pruned payments')
+            checked: settings.keep_totals
+            onClicked: {
+                settings.keep_totals = checked;
+            }
+        }
+
+        Button {
+            text: i18n.tr('Prune now')
+            onClicked: {
+                prune_result.text = settings.prune();
+            }
+        }
+
+        Label {
+            id: prune_result
+        }
+
+        Label {
+            text: pin_lock.is_set ? i18n.tr('Change the PIN') : i18n.tr('Set a PIN to protect payments and the seed')
+        }
+
+        TextField {
+            id: current_pin
+            visible: pin_lock.is_set
+            placeholderText: i18n.tr('current PIN')
+            echoMode: TextInput.Password
+            inputMethodHints: Qt.ImhDigitsOnly | Qt.ImhSensitiveData
+        }
+
+        TextField {
+            id: new_pin
+            placeholderText: i18n.tr('new PIN')
+            echoMode: TextInput.Password
+            inputMethodHints: Qt.ImhDigitsOnly | Qt.ImhSensitiveData
+        }
+
+        Button {
+            text: i18n.tr('Save PIN')
+            onClicked: {
+                pin_result.text = pin_lock.set_pin(current_pin.text, new_pin.text) || i18n.tr('PIN saved');
+                current_pin.text = "";
+                new_pin.text = "";
+            }
+        }
+
+        Label {
+            id: pin_result
+        }
+
+        Label {
+            text: i18n.tr('Lock after minutes of inactivity (0 = never)')
+        }
+
+        TextField {
+            id: auto_lock
+            text: Math.round(settings.auto_lock_secs / 60)
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 0 }
+            onEditingFinished: {
+                settings.auto_lock_secs = (parseInt(auto_lock.text) || 0) * 60;
+            }
+        }
+
+        CheckBox {
+            text: i18n.tr('Accept the fingerprint instead of the PIN')
+            enabled: pin_lock.is_set
+            checked: settings.biometric_unlock
+            onClicked: {
+                var enabled = checked;
+                checked = Qt.binding(function() { return settings.biometric_unlock; });
+                pin_dialog.authorize(function() {
+                    settings.biometric_unlock = enabled;
+                });
+            }
+        }
+
+        Label {
+            text: i18n.tr('Remind to back up the seed above sats')
+        }
+
+        TextField {
+            id: backup_reminder_sats
+            text: settings.backup_reminder_sats
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 0 }
+            onEditingFinished: {
+                settings.backup_reminder_sats = parseInt(backup_reminder_sats.text) || 0;
+            }
+        }
+
+        Label {
+            text: i18n.tr('Ask for the PIN again for payments above sats (0 never asks)')
+        }
+
+        TextField {
+            id: pin_threshold_sats
+            text: settings.pin_threshold_sats
+            inputMethodHints: Qt.ImhDigitsOnly
+            validator: IntValidator { bottom: 0 }
+            onEditingFinished: {
+                settings.pin_threshold_sats = parseInt(pin_threshold_sats.text) || 0;
+            }
+        }
+
+        CheckBox {
+            id: reset_confirmed
+            text: i18n.tr('Delete the seed, the channels and all other data')
+        }
+
+        Button {
+            text: i18n.tr('Reset wallet')
+            enabled: reset_confirmed.checked
+            onClicked: {
+                pin_dialog.authorize(function() {
+                    reset_result.text = settings.reset_wallet(reset_confirmed.checked);
+                    reset_confirmed.checked = false;
+                    pin_lock.load();
+                });
+            }
+        }
+
+        Label {
+            id: reset_result
+            wrapMode: Text.WordWrap
+            Layout.fillWidth: true
+        }
+    }
+}
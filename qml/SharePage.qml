@@ -0,0 +1,57 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+import QtQuick 2.7
+import QtQuick.Controls 2.2
+import Ubuntu.Components 1.3
+import Ubuntu.Content 1.3
+
+// Hands the staged files to the app the user picks, through the content hub
+Page {
+    id: sharePage
+
+    property var urls: []
+
+    signal close()
+
+    ContentPeerPicker {
+        anchors.fill: parent
+        showTitle: true
+        contentType: ContentType.All
+        handler: ContentHandler.Share
+
+        onPeerSelected: {
+            var transfer = peer.request();
+            var items = [];
+            for (var i = 0; i < sharePage.urls.length; i++) {
+                items.push(contentItem.createObject(sharePage, { "url": sharePage.urls[i] }));
+            }
+            transfer.items = items;
+            transfer.state = ContentTransfer.Charged;
+            sharePage.close();
+        }
+
+        onCancelPressed: {
+            sharePage.close();
+        }
+    }
+
+    Component {
+        id: contentItem
+
+        ContentItem {}
+    }
+}
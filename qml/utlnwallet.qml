@@ -21,6 +21,9 @@ import QtQuick.Controls 2.2
 import QtQuick.Window 2.0
 import "."
 
+import NodeStartup 1.0
+import ProfileModel 1.0
+
 ApplicationWindow {
     id: root
 
@@ -39,6 +42,22 @@ ApplicationWindow {
         }
     }
 
+    ProfileModel {
+        id: profiles
+    }
+
+    NodeStartup {
+        id: startup
+
+        onReady: {
+            pageStack.replace(mainPageComponent);
+            profiles.load();
+            if (profiles.ask_at_startup && profiles.names.length > 1) {
+                pageStack.push(profilesPageComponent);
+            }
+        }
+    }
+
     StackView {
         id: pageStack
         anchors.fill: parent
@@ -46,7 +65,8 @@ ApplicationWindow {
         anchors.margins: 0
         focus: true
         Component.onCompleted: {
-            pageStack.push(mainPageComponent);
+            pageStack.push(splashComponent);
+            startup.start();
         }
         Keys.onBackPressed: {
             if (depth > 1) {
@@ -57,6 +77,67 @@ ApplicationWindow {
         }
     }
 
+    // every touch postpones the auto-lock
+    MouseArea {
+        anchors.fill: parent
+        z: 1
+        propagateComposedEvents: true
+        onPressed: {
+            lockScreen.activity();
+            mouse.accepted = false;
+        }
+    }
+
+    LockScreen {
+        id: lockScreen
+        anchors.fill: parent
+        z: 2
+    }
+
+    Component {
+        id: splashComponent
+
+        Page {
+            ColumnLayout {
+                anchors.centerIn: parent
+                width: parent.width - units.gu(4)
+
+                BusyIndicator {
+                    Layout.alignment: Qt.AlignHCenter
+                    running: startup.stage != "failed"
+                }
+
+                Label {
+                    Layout.alignment: Qt.AlignHCenter
+                    text: {
+                        if (startup.stage == "probing") return i18n.tr('Looking for a chain server');
+                        if (startup.stage == "building") return i18n.tr('Loading the wallet');
+                        if (startup.stage == "starting") return i18n.tr('Starting the lightning node');
+                        if (startup.stage == "failed") return i18n.tr('The wallet could not be started');
+                        return i18n.tr('utlnwallet');
+                    }
+                }
+
+                Label {
+                    Layout.fillWidth: true
+                    wrapMode: Text.WordWrap
+                    horizontalAlignment: Text.AlignHCenter
+                    text: startup.error
+                    visible: startup.error != ""
+                }
+
+                Button {
+                    Layout.alignment: Qt.AlignHCenter
+                    text: i18n.tr('Retry')
+                    visible: startup.stage == "failed"
+                    onClicked: {
+                        startup.start();
+                    }
+                }
+            }
+        }
+    }
+
     Component {
         id: mainPageComponent
         MainPage {
@@ -68,6 +149,162 @@ ApplicationWindow {
                 console.assert(scanPageComponent != null, "scanPageComponent must not be empty");
                 pageStack.push(scanPageComponent);
             }
+
+            onShowHistory: {
+                pageStack.push(historyPageComponent);
+            }
+
+            onShowSettings: {
+                pageStack.push(settingsPageComponent);
+            }
+
+            onShowSeed: {
+                pageStack.push(seedPageComponent);
+            }
+
+            onShowPos: {
+                pageStack.push(posPageComponent);
+            }
+
+            onShare: {
+                pageStack.push(sharePageComponent, { "urls": urls });
+            }
+        }
+    }
+
+    Component {
+        id: settingsPageComponent
+
+        SettingsPage {
+            id: settingsPage
+
+            onShowSeed: {
+                pageStack.push(seedPageComponent);
+            }
+
+            onShowBackup: {
+                pageStack.push(backupPageComponent);
+            }
+
+            onShowProfiles: {
+                pageStack.push(profilesPageComponent);
+            }
+
+            onShowAbout: {
+                pageStack.push(aboutPageComponent);
+            }
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: seedPageComponent
+
+        SeedPage {
+            id: seedPage
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: backupPageComponent
+
+        BackupPage {
+            id: backupPage
+
+            onShare: {
+                pageStack.push(sharePageComponent, { "urls": urls });
+            }
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: aboutPageComponent
+
+        AboutPage {
+            id: aboutPage
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: profilesPageComponent
+
+        ProfilesPage {
+            id: profilesPage
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: historyPageComponent
+
+        HistoryPage {
+            id: historyPage
+
+            onShowInvoices: {
+                pageStack.push(invoicesPageComponent);
+            }
+
+            onShare: {
+                pageStack.push(sharePageComponent, { "urls": urls });
+            }
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: invoicesPageComponent
+
+        InvoicesPage {
+            id: invoicesPage
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: posPageComponent
+
+        PosPage {
+            id: posPage
+
+            onClose: {
+                pageStack.pop();
+            }
+        }
+    }
+
+    Component {
+        id: sharePageComponent
+
+        SharePage {
+            id: sharePage
+
+            onClose: {
+                pageStack.pop();
+            }
         }
     }
 
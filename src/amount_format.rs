@@ -0,0 +1,70 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::format::{Denomination, NumberFormat};
+use utwallet_core::settings::Settings;
+
+use qmetaobject::*;
+
+/// Formatting of amounts according to the denomination setting and the system locale
+#[derive(QObject, Default)]
+pub struct AmountFormat {
+    base: qt_base_class!(trait QObject),
+
+    sats: qt_method!(fn(&self, sats: u64) -> QString),
+    msat: qt_method!(fn(&self, msat: u64) -> QString),
+    fiat: qt_method!(fn(&self, value: f64) -> QString),
+    number: qt_method!(fn(&self, value: f64, decimals: u32) -> QString),
+}
+
+impl AmountFormat {
+    /// An amount in the configured denomination, including the unit
+    fn sats(&self, sats: u64) -> QString {
+        let d = denomination();
+        format!(
+            "{} {}",
+            NumberFormat::system().localize(&d.format_sats(sats)),
+            d.unit()
+        )
+        .into()
+    }
+
+    fn msat(&self, msat: u64) -> QString {
+        let d = denomination();
+        format!(
+            "{} {}",
+            NumberFormat::system().localize(&d.format_msat(msat)),
+            d.unit()
+        )
+        .into()
+    }
+
+    /// A fiat amount with two decimals and the configured currency
+    fn fiat(&self, value: f64) -> QString {
+        let currency = Settings::load().unwrap_or_default().currency;
+        format!("{} {}", NumberFormat::system().number(value, 2), currency).into()
+    }
+
+    fn number(&self, value: f64, decimals: u32) -> QString {
+        NumberFormat::system()
+            .number(value, decimals as usize)
+            .into()
+    }
+}
+
+fn denomination() -> Denomination {
+    Settings::load().map(|s| s.denomination).unwrap_or_default()
+}
@@ -0,0 +1,377 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::i18n::{tr, trf};
+use utwallet_core::pairing;
+use utwallet_core::pin;
+use utwallet_core::profiles;
+use utwallet_core::recovery;
+use utwallet_core::secrets;
+use utwallet_core::share;
+use utwallet_core::storage::{from_hex, to_hex};
+use utwallet_core::wallet::BdkWallet;
+
+use chrono::Utc;
+use qmetaobject::*;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Identifies the file format, in case it ever has to change
+const MAGIC: &[u8] = b"UTWBACKUP1";
+/// Backups are exposed to offline guessing, so the key derivation is slower than for the PIN
+const ITERATIONS: u32 = 200_000;
+const SALT_LEN: usize = 16;
+/// Files that are cheap to fetch again and not worth backing up
+const CACHES: &[&str] = &["rate.json", "prices_", "price_series_"];
+
+/// A file from the data directory, with its path relative to it
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct BackupFile {
+    path: String,
+    data: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Archive {
+    created: i64,
+    files: Vec<BackupFile>,
+}
+
+/// Whether a file from the data directory belongs into the backup
+fn included(rel_path: &str) -> bool {
+    rel_path == "mnemonic.txt"
+        || rel_path.starts_with("ldk/")
+        || (rel_path.ends_with(".json")
+            && !rel_path.contains('/')
+            && !CACHES.iter().any(|c| rel_path.starts_with(c)))
+}
+
+/// Check every entry of the archive and decode its content, before anything is replaced
+fn checked_files(archive: &Archive) -> Result<Vec<(String, Vec<u8>)>, String> {
+    archive
+        .files
+        .iter()
+        .map(|f| {
+            if !included(&f.path) || f.path.contains("..") {
+                return Err(format!("Unexpected file in the backup: {}", f.path));
+            }
+            Ok((f.path.clone(), from_hex(&f.data)?))
+        })
+        .collect()
+}
+
+/// Collect the files below dir recursively
+fn collect(dir: &Path, base: &Path, files: &mut Vec<BackupFile>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("Failed to list {:?}: {}", dir, e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if dir == base && profiles::is_shared(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        if path.is_dir() {
+            collect(&path, base, files)?;
+            continue;
+        }
+        let rel_path = path
+            .strip_prefix(base)
+            .map_err(|e| format!("Unexpected path {:?}: {}", path, e))?
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !included(&rel_path) {
+            continue;
+        }
+        let data = fs::read(&path).map_err(|e| format!("Failed to read {:?}: {}", path, e))?;
+        files.push(BackupFile {
+            path: rel_path,
+            data: to_hex(&data),
+        });
+    }
+    Ok(())
+}
+
+/// Encrypt with a key derived from the password. The salt is stored in front.
+fn encrypt(plain: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = pin::pbkdf2(password, &salt, ITERATIONS);
+    Ok([MAGIC, &salt, &secrets::seal(&key, plain)?].concat())
+}
+
+fn decrypt(data: &[u8], password: &str) -> Result<Vec<u8>, String> {
+    let header = MAGIC.len() + SALT_LEN;
+    if data.len() < header || !data.starts_with(MAGIC) {
+        return Err(tr("This is not a utwallet backup"));
+    }
+    let key = pin::pbkdf2(password, &data[MAGIC.len()..header], ITERATIONS);
+    secrets::open(&key, &data[header..]).map_err(|_| tr("Wrong password, or the backup is damaged"))
+}
+
+/// Write an encrypted archive of the seed, the ldk storage, the labels and the settings
+pub fn create(password: &str) -> Result<PathBuf, String> {
+    if password.len() < 8 {
+        return Err(tr("The password must have at least 8 characters"));
+    }
+    let data_dir = BdkWallet::data_dir();
+    let archive = BdkWallet::with_node_stopped(|| {
+        let mut files = Vec::new();
+        collect(&data_dir, &data_dir, &mut files)?;
+        // the key of an encrypted seed stays in the keyring, so the backup carries the words
+        if !files.iter().any(|f| f.path == "mnemonic.txt") {
+            files.push(BackupFile {
+                path: "mnemonic.txt".to_string(),
+                data: to_hex(BdkWallet::mnemonic()?.to_string().as_bytes()),
+            });
+        }
+        Ok(Archive {
+            created: Utc::now().timestamp(),
+            files,
+        })
+    })?;
+    let json = serde_json::to_vec(&archive)
+        .map_err(|e| format!("Failed to serialize the backup: {}", e))?;
+    let file = data_dir.join(format!(
+        "utwallet_{}.backup",
+        Utc::now().format("%Y%m%d_%H%M%S")
+    ));
+    fs::write(&file, encrypt(&json, password)?)
+        .map_err(|e| format!("Failed to write the backup {:?}: {}", file, e))?;
+    Ok(file)
+}
+
+/// Replace the wallet by the one from the backup. Only allowed while the current wallet is
+/// empty, as its seed is overwritten. Restoring an outdated lightning state can cost the
+/// channel balance, so only the latest backup should be restored.
+pub fn restore(file: &Path, password: &str) -> Result<usize, String> {
+    let data =
+        fs::read(file).map_err(|e| format!("Failed to read the backup {:?}: {}", file, e))?;
+    let archive: Archive = serde_json::from_slice(&decrypt(&data, password)?)
+        .map_err(|e| format!("Failed to parse the backup: {}", e))?;
+    let files = checked_files(&archive)?;
+    let (onchain, lightning) = BdkWallet::get_balance()?;
+    if onchain + lightning > 0 {
+        return Err(tr(
+            "The current wallet still holds funds, empty it before restoring",
+        ));
+    }
+    let data_dir = BdkWallet::data_dir();
+    BdkWallet::with_node_stopped(|| {
+        let ldk_dir = data_dir.join("ldk");
+        if ldk_dir.exists() {
+            fs::remove_dir_all(&ldk_dir)
+                .map_err(|e| format!("Failed to remove {:?}: {}", ldk_dir, e))?;
+        }
+        for (rel_path, data) in &files {
+            let path = data_dir.join(rel_path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| format!("Failed to create directory {:?}: {}", parent, e))?;
+            }
+            secrets::write_private(&path, data)?;
+        }
+        Ok(files.len())
+    })
+}
+
+/// Creating and restoring backups from QML
+#[derive(QObject, Default)]
+pub struct Backup {
+    base: qt_base_class!(trait QObject),
+    /// the backup files in the data directory, newest first
+    files: qt_property!(QVariantList; NOTIFY files_changed),
+    files_changed: qt_signal!(),
+    /// the wallet identity, to confirm that a restore brought back the right wallet
+    fingerprint: qt_property!(QString; NOTIFY identity_changed),
+    node_id: qt_property!(QString; NOTIFY identity_changed),
+    identity_changed: qt_signal!(),
+
+    load: qt_method!(fn(&mut self)),
+    create: qt_method!(fn(&mut self, password: String) -> QString),
+    restore: qt_method!(fn(&mut self, file: String, password: String) -> QString),
+    recovery_kit: qt_method!(fn(&mut self, include_seed: bool) -> QString),
+    pairing_qr: qt_method!(fn(&mut self) -> QString),
+    revoke_pairing: qt_method!(fn(&mut self)),
+    share: qt_method!(fn(&mut self, file: String) -> QString),
+}
+
+impl Backup {
+    fn load(&mut self) {
+        let mut files = fs::read_dir(BdkWallet::data_dir())
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|e| e.path().to_string_lossy().to_string())
+                    .filter(|p| p.ends_with(".backup"))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        files.sort();
+        files.reverse();
+        self.files = files.into_iter().map(QString::from).collect();
+        self.files_changed();
+        self.load_identity();
+    }
+
+    fn load_identity(&mut self) {
+        let (fingerprint, node_id) = BdkWallet::identity().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Default::default()
+        });
+        self.fingerprint = fingerprint.into();
+        self.node_id = node_id.into();
+        self.identity_changed();
+    }
+
+    /// Returns a message for the user
+    fn create(&mut self, password: String) -> QString {
+        let res = pin::require().and_then(|_| create(&password));
+        self.load();
+        match res {
+            Ok(file) => trf("Backup written to {}", &[&file.to_string_lossy()]),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    /// Returns a message for the user
+    fn recovery_kit(&mut self, include_seed: bool) -> QString {
+        let res = pin::require().and_then(|_| recovery::export(include_seed));
+        match res {
+            Ok(dir) => trf("Recovery kit written to {}", &[&dir.to_string_lossy()]),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    /// A QR code with the pairing for a desktop companion, empty on failure
+    fn pairing_qr(&mut self) -> QString {
+        match pin::require().and_then(|_| pairing::export()) {
+            Ok(url) => url,
+            Err(e) => {
+                log_error!("{}", e);
+                "".to_string()
+            }
+        }
+        .into()
+    }
+
+    /// Stage a backup file for the content hub, and return its url, empty on failure
+    fn share(&mut self, file: String) -> QString {
+        let file = Path::new(&file);
+        let res = if file.parent() == Some(BdkWallet::data_dir().as_path())
+            && file.extension().map_or(false, |ext| ext == "backup")
+        {
+            share::copy(file)
+        } else {
+            Err(format!("Not a backup of this wallet: {:?}", file))
+        };
+        match res {
+            Ok(url) => url,
+            Err(e) => {
+                log_error!("{}", e);
+                "".to_string()
+            }
+        }
+        .into()
+    }
+
+    fn revoke_pairing(&mut self) {
+        if let Err(e) = pairing::revoke() {
+            log_error!("{}", e);
+        }
+    }
+
+    /// Returns a message for the user
+    fn restore(&mut self, file: String, password: String) -> QString {
+        let res = pin::require().and_then(|_| restore(Path::new(&file), &password));
+        self.load_identity();
+        match res {
+            Ok(count) => trf(
+                "{} files restored, the wallet fingerprint is now {}",
+                &[&count, &self.fingerprint.to_string()],
+            ),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_included() {
+        assert!(included("mnemonic.txt"));
+        assert!(included("ldk/ldk_node_data.sqlite"));
+        assert!(included("settings.json"));
+        assert!(included("payments.json"));
+        assert!(!included("rate.json"));
+        assert!(!included("prices_CHF.json"));
+        assert!(!included("koinly.csv"));
+        assert!(!included("utwallet_20240101_120000.backup"));
+    }
+
+    #[test]
+    fn test_checked_files() {
+        let file = |path: &str, data: &str| BackupFile {
+            path: path.to_string(),
+            data: data.to_string(),
+        };
+        let mut archive = Archive {
+            created: 0,
+            files: vec![
+                file("ldk/ldk_node_data.sqlite", "0102"),
+                file("settings.json", ""),
+            ],
+        };
+        assert_eq!(
+            checked_files(&archive).unwrap(),
+            vec![
+                ("ldk/ldk_node_data.sqlite".to_string(), vec![1, 2]),
+                ("settings.json".to_string(), vec![])
+            ]
+        );
+        archive.files.push(file("ldk/../../.bashrc", ""));
+        assert!(checked_files(&archive).is_err());
+        archive.files.pop();
+        archive.files.push(file("ldk/channel", "zz"));
+        assert!(checked_files(&archive).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let plain = b"abandon ability able";
+        let encrypted = encrypt(plain, "correct horse").unwrap();
+        assert!(encrypted.starts_with(MAGIC));
+        assert_eq!(decrypt(&encrypted, "correct horse").unwrap(), plain);
+        assert!(decrypt(&encrypted, "wrong horse").is_err());
+        assert!(decrypt(b"garbage", "correct horse").is_err());
+        assert_eq!(from_hex(&to_hex(plain)).unwrap(), plain);
+    }
+}
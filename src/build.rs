@@ -76,6 +76,24 @@ fn gettext() {
     let exit_status = child.wait().unwrap();
     assert!(exit_status.code() == Some(0));
 
+    // the messages of the Rust code, passed to tr(), trf() and noop()
+    let mut child = Command::new("xgettext")
+        .args(&[
+            &format!("--output={}", pot_file),
+            "--join-existing",
+            "--language=C",
+            "--keyword=tr",
+            "--keyword=trf",
+            "--keyword=noop",
+            "--from-code=UTF-8",
+        ])
+        .args(&rust_files())
+        .spawn()
+        .unwrap();
+
+    let exit_status = child.wait().unwrap();
+    assert!(exit_status.code() == Some(0));
+
     for po_file in po_files() {
         let mut child = Command::new("msgmerge")
             .args(&["--update", &po_file.to_str().unwrap(), pot_file])
@@ -94,7 +112,8 @@ fn gettext() {
         );
         fs::create_dir_all(&mo_dir).unwrap();
 
-        let mo_file = format!("{}/utwallet.ulrichard.mo", mo_dir);
+        // named after the text domain, which is the application name
+        let mo_file = format!("{}/utlnwallet.ulrichard.mo", mo_dir);
 
         let mut child = Command::new("msgfmt")
             .args(&[&po_file.to_str().unwrap(), "-o", &mo_file])
@@ -112,6 +131,13 @@ fn source_files() -> Vec<PathBuf> {
     walk_dir(PathBuf::from("qml"), "qml")
 }
 
+/// Obtains a list of the Rust files of the app and the core
+fn rust_files() -> Vec<PathBuf> {
+    let mut files = walk_dir(PathBuf::from("src"), "rs");
+    files.append(&mut walk_dir(PathBuf::from("core/src"), "rs"));
+    files
+}
+
 /// Recursively searches for files in a directory and
 /// returns a list of paths to the files
 fn walk_dir<T>(dir: PathBuf, ext: T) -> Vec<PathBuf>
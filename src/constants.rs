@@ -1,12 +0,0 @@
-pub const ESPLORA_SERVERS: &[&str] = &[
-    "https://blockstream.info/api/",
-    "https://ax101.blockeng.ch/",
-    "https://ax102.blockeng.ch/",
-];
-
-pub const RAPID_GOSSIP_SYNC_URL: &str = "https://rapidsync.lightningdevkit.org/snapshot";
-
-pub const LN_ULR: &str =
-    "03a46be38d068c2bc5af3fc13da840790ed5643f3d6d27e5e34d67ed2aec16ce67@77.74.80.179:9735";
-
-pub const COINMARKETCAP_API_KEY: &str = "214a51e6-e3b9-4711-8593-bdf8a8d7cb01";
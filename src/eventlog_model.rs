@@ -0,0 +1,121 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::eventlog::{self, LogEntry};
+use utwallet_core::settings::Settings;
+
+use chrono::DateTime;
+use qmetaobject::*;
+use std::collections::HashMap;
+
+const ROLE_TIMESTAMP: i32 = USER_ROLE;
+const ROLE_SEVERITY: i32 = USER_ROLE + 1;
+const ROLE_MESSAGE: i32 = USER_ROLE + 2;
+const ROLE_REFERENCE: i32 = USER_ROLE + 3;
+
+/// The events and errors of the wallet, newest first
+#[derive(QObject, Default)]
+pub struct EventLogModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    entries: Vec<LogEntry>,
+    /// how many entries are kept
+    length: usize,
+    started: bool,
+
+    start: qt_method!(fn(&mut self)),
+    clear: qt_method!(fn(&mut self)),
+}
+
+impl EventLogModel {
+    /// Show the persisted entries and follow the new ones
+    fn start(&mut self) {
+        self.length = Settings::load().unwrap_or_default().event_log_length as usize;
+        let entries = eventlog::load().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Vec::new()
+        });
+        self.begin_reset_model();
+        self.entries = entries;
+        self.end_reset_model();
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let qptr = QPointer::from(&*self);
+        let add = queued_callback(move |entry: LogEntry| {
+            if let Some(model) = qptr.as_pinned() {
+                model.borrow_mut().add(entry);
+            }
+        });
+        eventlog::subscribe(Box::new(add));
+    }
+
+    fn add(&mut self, entry: LogEntry) {
+        self.begin_insert_rows(0, 0);
+        self.entries.insert(0, entry);
+        self.end_insert_rows();
+        if self.entries.len() > self.length {
+            let first = self.length as i32;
+            self.begin_remove_rows(first, self.entries.len() as i32 - 1);
+            self.entries.truncate(self.length);
+            self.end_remove_rows();
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Err(e) = eventlog::clear() {
+            log_error!("{}", e);
+            return;
+        }
+        self.begin_reset_model();
+        self.entries.clear();
+        self.end_reset_model();
+    }
+}
+
+impl QAbstractListModel for EventLogModel {
+    fn row_count(&self) -> i32 {
+        self.entries.len() as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let entry = match self.entries.get(index.row() as usize) {
+            Some(e) => e,
+            None => return QVariant::default(),
+        };
+        match role {
+            ROLE_TIMESTAMP => QString::from(
+                DateTime::from_timestamp(entry.timestamp, 0)
+                    .map(|dt| dt.format("%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+            )
+            .into(),
+            ROLE_SEVERITY => QString::from(entry.severity.name()).into(),
+            ROLE_MESSAGE => QString::from(entry.message.as_str()).into(),
+            ROLE_REFERENCE => QString::from(entry.reference.as_str()).into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        let mut roles = HashMap::new();
+        roles.insert(ROLE_TIMESTAMP, "timestamp".into());
+        roles.insert(ROLE_SEVERITY, "severity".into());
+        roles.insert(ROLE_MESSAGE, "message".into());
+        roles.insert(ROLE_REFERENCE, "reference".into());
+        roles
+    }
+}
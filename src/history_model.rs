@@ -0,0 +1,409 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::format::Denomination;
+use utwallet_core::history::{
+    all_entries, exchange_rate, revalidate, set_archived, subscribe_updates, take_updates,
+    HistoryEntry,
+};
+use utwallet_core::i18n::tr;
+use utwallet_core::report::{self, Report};
+use utwallet_core::settings::Settings;
+use utwallet_core::storage::data_file;
+use utwallet_core::wallet::BdkWallet;
+use utwallet_core::{export, price_history, qr, receipt, share};
+
+use chrono::{DateTime, NaiveDate};
+use qmetaobject::*;
+use std::{collections::HashMap, fs};
+
+/// How many entries are handed to the view with every fetch_more
+const PAGE_SIZE: usize = 50;
+
+const ROLE_ID: i32 = USER_ROLE;
+const ROLE_KIND: i32 = USER_ROLE + 1;
+const ROLE_DIRECTION: i32 = USER_ROLE + 2;
+const ROLE_STATUS: i32 = USER_ROLE + 3;
+const ROLE_AMOUNT: i32 = USER_ROLE + 4;
+const ROLE_PEER: i32 = USER_ROLE + 5;
+const ROLE_CHANNEL_ID: i32 = USER_ROLE + 6;
+const ROLE_FAILURE_REASON: i32 = USER_ROLE + 7;
+const ROLE_ALIAS: i32 = USER_ROLE + 8;
+const ROLE_COLOR: i32 = USER_ROLE + 9;
+const ROLE_ARCHIVED: i32 = USER_ROLE + 10;
+const ROLE_TIMESTAMP: i32 = USER_ROLE + 11;
+const ROLE_REASON: i32 = USER_ROLE + 12;
+const ROLE_INITIATOR: i32 = USER_ROLE + 13;
+
+/// A list model over the payment store of the node.
+/// Rows are handed out page by page, so that large histories don't stall the GUI.
+#[derive(QObject, Default)]
+pub struct HistoryModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    /// all known entries, newest first
+    entries: Vec<HistoryEntry>,
+    /// the number of entries that are exposed to the view
+    loaded: usize,
+    /// the unit to show the amounts in
+    denomination: Denomination,
+    show_archived: qt_property!(bool; NOTIFY show_archived_changed WRITE set_show_archived),
+    show_archived_changed: qt_signal!(),
+    channels_only: qt_property!(bool; NOTIFY channels_only_changed WRITE set_channels_only),
+    channels_only_changed: qt_signal!(),
+
+    refresh: qt_method!(fn(&mut self)),
+    apply_updates: qt_method!(fn(&mut self)),
+    export: qt_method!(fn(&mut self, format: String) -> QString),
+    report: qt_method!(fn(&mut self, from: String, to: String) -> QString),
+    monthly_stats: qt_method!(fn(&mut self) -> QString),
+    archive: qt_method!(fn(&mut self, id: String, archived: bool)),
+    rescan: qt_method!(fn(&mut self) -> QString),
+    receipt_text: qt_method!(fn(&mut self, id: String) -> QString),
+    receipt_qr: qt_method!(fn(&mut self, id: String) -> QString),
+    share_receipt: qt_method!(fn(&mut self, id: String) -> QVariantList),
+    can_fetch_more: qt_method!(fn(&self) -> bool),
+    fetch_more: qt_method!(fn(&mut self)),
+}
+
+impl HistoryModel {
+    /// Merge the current payment store and the records into the model without resetting it.
+    fn refresh(&mut self) {
+        self.denomination = Settings::load().map(|s| s.denomination).unwrap_or_default();
+        let all = match all_entries() {
+            Ok(a) => a,
+            Err(e) => {
+                log_error!("{}", e);
+                return;
+            }
+        };
+
+        if self.entries.is_empty() {
+            // the full load already contains everything that was queued so far
+            subscribe_updates();
+            self.entries = all.into_iter().filter(|e| self.is_visible(e)).collect();
+            self.fetch_more();
+            return;
+        }
+
+        for entry in all {
+            self.upsert(entry);
+        }
+    }
+
+    /// Insert or update the rows reported by ldk events since the last call
+    fn apply_updates(&mut self) {
+        match take_updates() {
+            Ok(entries) => {
+                for entry in entries {
+                    self.upsert(entry);
+                }
+            }
+            Err(e) => log_error!("{}", e),
+        }
+    }
+
+    /// Update an existing row, or insert it on top if it is new
+    fn upsert(&mut self, entry: HistoryEntry) {
+        let hidden = !self.is_visible(&entry);
+        if let Some(pos) = self.entries.iter().position(|e| e.id == entry.id) {
+            if hidden {
+                self.remove_row(pos);
+            } else if self.entries[pos] != entry {
+                self.entries[pos] = entry;
+                if pos < self.loaded {
+                    let idx = self.row_index(pos as i32);
+                    self.data_changed(idx.clone(), idx);
+                }
+            }
+        } else if !hidden {
+            self.begin_insert_rows(0, 0);
+            self.entries.insert(0, entry);
+            self.loaded += 1;
+            self.end_insert_rows();
+        }
+    }
+
+    fn remove_row(&mut self, pos: usize) {
+        if pos < self.loaded {
+            self.begin_remove_rows(pos as i32, pos as i32);
+            self.entries.remove(pos);
+            self.loaded -= 1;
+            self.end_remove_rows();
+        } else {
+            self.entries.remove(pos);
+        }
+    }
+
+    /// Mark an entry as archived, which hides it unless show_archived is set
+    fn archive(&mut self, id: String, archived: bool) {
+        if let Err(e) = set_archived(&id, archived) {
+            log_error!("{}", e);
+            return;
+        }
+        if let Some(entry) = self.entries.iter().find(|e| e.id == id) {
+            let mut entry = entry.clone();
+            entry.archived = archived;
+            self.upsert(entry);
+        }
+    }
+
+    /// Rebuild the on-chain history from the chain source, revalidate the payments and reload
+    /// the model. Returns an error message, or an empty string on success.
+    fn rescan(&mut self) -> QString {
+        let res = BdkWallet::rescan()
+            .map_err(String::from)
+            .and_then(|_| revalidate());
+        let msg = match res {
+            Ok(dropped) => {
+                log_info!("rescan finished, dropped {} stale payment details", dropped);
+                "".to_string()
+            }
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        };
+        self.reload();
+        msg.into()
+    }
+
+    /// A plain text receipt for the payment, empty if it is unknown
+    fn receipt_text(&mut self, id: String) -> QString {
+        let entry = match self.entries.iter().find(|e| e.id == id) {
+            Some(e) => e,
+            None => return "".into(),
+        };
+        match receipt::proof_of_payment(&id) {
+            Ok(proof) => receipt::text(entry, &proof),
+            Err(e) => {
+                log_error!("{}", e);
+                "".to_string()
+            }
+        }
+        .into()
+    }
+
+    /// A QR code with the preimage or id of the payment, as a data url
+    fn receipt_qr(&mut self, id: String) -> QString {
+        match receipt::proof_of_payment(&id).and_then(|proof| qr::data_url(&proof)) {
+            Ok(url) => url,
+            Err(e) => {
+                log_error!("{}", e);
+                "".to_string()
+            }
+        }
+        .into()
+    }
+
+    /// Stage the receipt as text and as QR code for the content hub, and return their urls
+    fn share_receipt(&mut self, id: String) -> QVariantList {
+        let text = self.receipt_text(id.clone()).to_string();
+        if text.is_empty() {
+            return QVariantList::default();
+        }
+        let name = format!("receipt_{}", &id[..id.len().min(8)]);
+        let res = receipt::proof_of_payment(&id).and_then(|proof| {
+            let txt = share::write(&format!("{}.txt", name), text.as_bytes())?;
+            let png = share::write(&format!("{}.png", name), &qr::png(&proof)?)?;
+            Ok(vec![txt, png])
+        });
+        match res {
+            Ok(urls) => urls.into_iter().map(QString::from).collect(),
+            Err(e) => {
+                log_error!("{}", e);
+                QVariantList::default()
+            }
+        }
+    }
+
+    /// Whether the entry passes the filters
+    fn is_visible(&self, entry: &HistoryEntry) -> bool {
+        (self.show_archived || !entry.archived) && (!self.channels_only || entry.is_channel_event())
+    }
+
+    /// Reload the model after a filter changed
+    fn reload(&mut self) {
+        self.begin_reset_model();
+        self.entries.clear();
+        self.loaded = 0;
+        self.end_reset_model();
+        self.refresh();
+    }
+
+    fn set_show_archived(&mut self, show: bool) {
+        if show == self.show_archived {
+            return;
+        }
+        self.show_archived = show;
+        self.reload();
+        self.show_archived_changed();
+    }
+
+    fn set_channels_only(&mut self, channels_only: bool) {
+        if channels_only == self.channels_only {
+            return;
+        }
+        self.channels_only = channels_only;
+        self.reload();
+        self.channels_only_changed();
+    }
+
+    /// Write the history in the given format (koinly or cointracking) to a file and return its path
+    fn export(&mut self, format: String) -> QString {
+        let res = all_entries().and_then(|mut entries| {
+            let currency = Settings::load().unwrap_or_default().currency;
+            price_history::fill_missing(&mut entries, &currency)
+                .unwrap_or_else(|e| log_error!("Failed to get historical prices: {}", e));
+            let (csv, name) = match format.as_str() {
+                "koinly" => (export::koinly_csv(&entries), "koinly.csv"),
+                "cointracking" => (export::cointracking_csv(&entries), "cointracking.csv"),
+                _ => return Err(format!("Unknown export format {}", format)),
+            };
+            let file = data_file(name);
+            fs::write(&file, csv).map_err(|e| format!("Failed to write the export: {}", e))?;
+            Ok(file)
+        });
+        match res {
+            Ok(file) => file.to_string_lossy().to_string(),
+            Err(e) => {
+                log_error!("{}", e);
+                "".to_string()
+            }
+        }
+        .into()
+    }
+
+    /// Summarize the payments between two dates formatted as YYYY-MM-DD
+    fn report(&mut self, from: String, to: String) -> QString {
+        let parse = |d: &str| {
+            NaiveDate::parse_from_str(d, "%Y-%m-%d")
+                .map_err(|e| format!("Failed to parse the date {}: {}", d, e))
+        };
+        let res = parse(&from).and_then(|from| {
+            let to = parse(&to)?;
+            let (rate, currency) =
+                exchange_rate().ok_or_else(|| tr("The exchange rate is not known yet"))?;
+            let mut entries = all_entries()?;
+            price_history::fill_missing(&mut entries, &currency)
+                .unwrap_or_else(|e| log_error!("Failed to get historical prices: {}", e));
+            let report = Report::summarize(
+                &entries,
+                from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp(),
+                to.and_hms_opt(23, 59, 59).unwrap().and_utc().timestamp(),
+                rate,
+            );
+            Ok(report.to_text(&currency))
+        });
+        match res {
+            Ok(txt) => txt,
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    /// Activity per month as a json array, for drawing charts
+    fn monthly_stats(&mut self) -> QString {
+        let res = all_entries().and_then(|entries| {
+            serde_json::to_string(&report::monthly_stats(&entries))
+                .map_err(|e| format!("Failed to serialize the statistics: {}", e))
+        });
+        match res {
+            Ok(json) => json,
+            Err(e) => {
+                log_error!("{}", e);
+                "[]".to_string()
+            }
+        }
+        .into()
+    }
+
+    fn can_fetch_more(&self) -> bool {
+        self.loaded < self.entries.len()
+    }
+
+    fn fetch_more(&mut self) {
+        let remaining = self.entries.len() - self.loaded;
+        let count = remaining.min(PAGE_SIZE);
+        if count == 0 {
+            return;
+        }
+        let first = self.loaded as i32;
+        self.begin_insert_rows(first, first + count as i32 - 1);
+        self.loaded += count;
+        self.end_insert_rows();
+    }
+}
+
+impl QAbstractListModel for HistoryModel {
+    fn row_count(&self) -> i32 {
+        self.loaded as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let entry = match self.entries.get(index.row() as usize) {
+            Some(e) if (index.row() as usize) < self.loaded => e,
+            _ => return QVariant::default(),
+        };
+        match role {
+            ROLE_ID => QString::from(entry.id.as_str()).into(),
+            ROLE_KIND => QString::from(entry.kind.as_str()).into(),
+            ROLE_DIRECTION => QString::from(entry.direction.as_str()).into(),
+            ROLE_STATUS => QString::from(entry.status.as_str()).into(),
+            ROLE_AMOUNT => match entry.amount_msat {
+                Some(msat) => QString::from(self.denomination.format_msat(msat)).into(),
+                None => QString::from("").into(),
+            },
+            ROLE_PEER => QString::from(entry.peer.as_str()).into(),
+            ROLE_CHANNEL_ID => QString::from(entry.channel_id.as_str()).into(),
+            ROLE_FAILURE_REASON => QString::from(entry.failure_reason.as_str()).into(),
+            ROLE_ALIAS => QString::from(entry.alias.as_str()).into(),
+            ROLE_COLOR => QString::from(entry.color.as_str()).into(),
+            ROLE_ARCHIVED => entry.archived.into(),
+            ROLE_TIMESTAMP => QString::from(
+                DateTime::from_timestamp(entry.timestamp, 0)
+                    .filter(|_| entry.timestamp > 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+            )
+            .into(),
+            ROLE_REASON => QString::from(entry.reason.as_str()).into(),
+            ROLE_INITIATOR => QString::from(entry.initiator.as_str()).into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        let mut roles = HashMap::new();
+        roles.insert(ROLE_ID, "paymentId".into());
+        roles.insert(ROLE_KIND, "kind".into());
+        roles.insert(ROLE_DIRECTION, "direction".into());
+        roles.insert(ROLE_STATUS, "status".into());
+        roles.insert(ROLE_AMOUNT, "amount".into());
+        roles.insert(ROLE_PEER, "peer".into());
+        roles.insert(ROLE_CHANNEL_ID, "channelId".into());
+        roles.insert(ROLE_FAILURE_REASON, "failureReason".into());
+        roles.insert(ROLE_ALIAS, "alias".into());
+        roles.insert(ROLE_COLOR, "color".into());
+        roles.insert(ROLE_ARCHIVED, "archived".into());
+        roles.insert(ROLE_TIMESTAMP, "timestamp".into());
+        roles.insert(ROLE_REASON, "reason".into());
+        roles.insert(ROLE_INITIATOR, "initiator".into());
+        roles
+    }
+}
@@ -0,0 +1,104 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::format::Denomination;
+use utwallet_core::invoices::{load_invoices, withdraw_status, InvoiceRecord};
+use utwallet_core::settings::Settings;
+
+use chrono::Utc;
+use qmetaobject::*;
+use std::collections::HashMap;
+
+const ROLE_INVOICE: i32 = USER_ROLE;
+const ROLE_AMOUNT: i32 = USER_ROLE + 1;
+const ROLE_DESCRIPTION: i32 = USER_ROLE + 2;
+const ROLE_EXPIRY: i32 = USER_ROLE + 3;
+const ROLE_PAID: i32 = USER_ROLE + 4;
+const ROLE_EXPIRED: i32 = USER_ROLE + 5;
+const ROLE_WITHDRAW_URL: i32 = USER_ROLE + 6;
+const ROLE_WITHDRAW_STATUS: i32 = USER_ROLE + 7;
+
+/// The invoices we created for QML
+#[derive(QObject, Default)]
+pub struct InvoiceModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    invoices: Vec<(InvoiceRecord, bool)>,
+    /// the unit to show the amounts in
+    denomination: Denomination,
+
+    refresh: qt_method!(fn(&mut self)),
+}
+
+impl InvoiceModel {
+    fn refresh(&mut self) {
+        let invoices = match load_invoices() {
+            Ok(invoices) => invoices,
+            Err(e) => {
+                log_error!("{}", e);
+                return;
+            }
+        };
+        self.begin_reset_model();
+        self.denomination = Settings::load().map(|s| s.denomination).unwrap_or_default();
+        self.invoices = invoices;
+        self.end_reset_model();
+    }
+}
+
+impl QAbstractListModel for InvoiceModel {
+    fn row_count(&self) -> i32 {
+        self.invoices.len() as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let (inv, paid) = match self.invoices.get(index.row() as usize) {
+            Some(i) => i,
+            None => return QVariant::default(),
+        };
+        match role {
+            ROLE_INVOICE => QString::from(inv.invoice.as_str()).into(),
+            ROLE_AMOUNT => match inv.amount_msat {
+                Some(msat) => QString::from(self.denomination.format_msat(msat)).into(),
+                None => QString::from("").into(),
+            },
+            ROLE_DESCRIPTION => QString::from(inv.description.as_str()).into(),
+            ROLE_EXPIRY => QString::from(
+                chrono::DateTime::from_timestamp(inv.expires_at(), 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default(),
+            )
+            .into(),
+            ROLE_PAID => (*paid).into(),
+            ROLE_EXPIRED => (!paid && inv.expires_at() < Utc::now().timestamp()).into(),
+            ROLE_WITHDRAW_URL => QString::from(inv.withdraw_url.as_str()).into(),
+            ROLE_WITHDRAW_STATUS => QString::from(withdraw_status(inv, *paid)).into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        let mut roles = HashMap::new();
+        roles.insert(ROLE_INVOICE, "invoice".into());
+        roles.insert(ROLE_AMOUNT, "amount".into());
+        roles.insert(ROLE_DESCRIPTION, "description".into());
+        roles.insert(ROLE_EXPIRY, "expiry".into());
+        roles.insert(ROLE_PAID, "paid".into());
+        roles.insert(ROLE_EXPIRED, "expired".into());
+        roles.insert(ROLE_WITHDRAW_URL, "withdrawUrl".into());
+        roles.insert(ROLE_WITHDRAW_STATUS, "withdrawStatus".into());
+        roles
+    }
+}
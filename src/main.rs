@@ -20,152 +20,445 @@ extern crate cpp;
 #[macro_use]
 extern crate qmetaobject;
 use qmetaobject::*;
-use qt_core::{q_standard_paths::StandardLocation, QStandardPaths};
 
-mod constants;
-mod input_eval;
+#[macro_use]
+extern crate utwallet_core;
+
+mod amount_format;
+mod backup;
+mod eventlog_model;
+mod history_model;
+mod invoice_model;
+mod node_info;
+mod pin_lock;
+mod pos_model;
+mod price_model;
+mod profile_model;
 mod qrc;
-mod sweeper;
-mod wallet;
+mod rate_provider;
+mod seed;
+mod settings_model;
+mod startup;
+mod worker;
 
-use crate::constants::COINMARKETCAP_API_KEY;
-use crate::input_eval::{is_node_id, parse_satoshis, InputEval, InputNetwork};
-use crate::wallet::BdkWallet;
+use crate::amount_format::AmountFormat;
+use crate::backup::Backup;
+use crate::eventlog_model::EventLogModel;
+use crate::history_model::HistoryModel;
+use crate::invoice_model::InvoiceModel;
+use crate::node_info::NodeInfoModel;
+use crate::pin_lock::PinLock;
+use crate::pos_model::PosModel;
+use crate::price_model::PriceModel;
+use crate::profile_model::ProfileModel;
+use crate::rate_provider::RateProvider;
+use crate::seed::SeedBackup;
+use crate::settings_model::SettingsModel;
+use crate::startup::NodeStartup;
 
-use cmc::CmcBuilder;
-use qrcode_png::{Color, QrCode, QrCodeEcc};
-use std::{env, fs::create_dir_all, path::PathBuf /*, str::FromStr*/};
+use utwallet_core::dbus::PaymentRequest;
+use utwallet_core::error::WalletError;
+use utwallet_core::eventlog::{self, Severity};
+use utwallet_core::format::NumberFormat;
+use utwallet_core::i18n::{self, tr, trf};
+use utwallet_core::input_eval::{
+    self, is_node_id, parse_fiat, parse_satoshis, InputEval, InputNetwork,
+};
+use utwallet_core::payments::{self, Handle, Status};
+use utwallet_core::rates::Rate;
+use utwallet_core::scheduler::{Task, Update};
+use utwallet_core::settings::Settings;
+use utwallet_core::validate::{self, Field, Validation};
+use utwallet_core::wallet::{BdkWallet, WalletEvent};
+use utwallet_core::{
+    daemon, dbus, demo, lifecycle, logging, preview, profiles, proxy, qr, rates, rest, scheduler,
+    share, watchonly,
+};
 
-use gettextrs::{bindtextdomain, textdomain};
+use chrono::DateTime;
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use std::{collections::HashSet, env, path::PathBuf, process, str::FromStr, time::Instant};
 
 #[derive(QObject, Default)]
 struct Greeter {
     base: qt_base_class!(trait QObject),
-    receiving_address: qt_property!(QString),
-    eventlog: std::collections::VecDeque<String>,
-    exchange_rate: Option<f64>,
-
-    update_balance: qt_method!(
-        fn update_balance(&mut self) -> QString {
-            let (ocbal, lnbal) = self.log_err_or(BdkWallet::get_balance(), (0.0, 0.0));
-
-            let mut msg = format!("Bal: {} + {} BTC", ocbal, lnbal);
-            if self.exchange_rate.is_none() {
-                let rate = self.refresh_exchange_rate();
-                self.log_err_or(rate, 0.0);
+    receiving_address: qt_property!(QString; NOTIFY address_changed),
+    /// the QR code of the receiving address or of the invoice
+    receiving_qr: qt_property!(QString; NOTIFY address_changed),
+    address_changed: qt_signal!(),
+    /// wallet operations are running in the background
+    busy: qt_property!(bool; NOTIFY busy_changed),
+    busy_changed: qt_signal!(),
+    jobs: usize,
+    /// the periodic jobs that are still running, so they don't pile up behind a slow one
+    pending: HashSet<&'static str>,
+    /// the balance summary for the page header
+    balance_text: qt_property!(QString; NOTIFY balance_changed),
+    onchain_sats: qt_property!(u64; NOTIFY balance_changed),
+    lightning_sats: qt_property!(u64; NOTIFY balance_changed),
+    /// the total balance in the display currency, 0 while the rate is unknown
+    fiat_value: qt_property!(f64; NOTIFY balance_changed),
+    fiat_currency: qt_property!(QString; NOTIFY balance_changed),
+    balance_changed: qt_signal!(),
+    /// our share of the channel balance, empty without a channel
+    channel_status: qt_property!(QString; NOTIFY channel_changed),
+    channel_changed: qt_signal!(),
+    /// the height of the chain tip the node knows about
+    block_height: qt_property!(u32; NOTIFY sync_changed),
+    /// when the wallets were last synced, formatted as YYYY-MM-DD HH:MM
+    last_sync: qt_property!(QString; NOTIFY sync_changed),
+    /// syncing, synced or error, empty before the first sync
+    sync_state: qt_property!(QString; NOTIFY sync_changed),
+    sync_changed: qt_signal!(),
+    /// wifi, mobile, other or offline, empty before the first change
+    connection: qt_property!(QString; NOTIFY connection_changed),
+    /// syncing less often and skipping the optional requests on a metered connection
+    low_data: qt_property!(bool; NOTIFY connection_changed),
+    connection_changed: qt_signal!(),
+    /// the balance exceeds the configured threshold, but the seed backup wasn't confirmed
+    backup_reminder: qt_signal!(),
+    backup_reminded: bool,
+    /// a wallet operation failed, with the code and the action of the WalletError
+    failed: qt_signal!(code: QString, message: QString, recoverable: bool, action: QString),
+    /// the evaluated input for the send fields, with the length of the comment an LNURL-pay
+    /// service reads from the description, 0 if it reads none
+    input_evaluated: qt_signal!(address: QString, amount: QString, description: QString, comment_max: u32, sendable_min: u64, sendable_max: u64),
+    /// the signature of a message, from sign_message
+    message_signed: qt_signal!(signature: QString),
+    /// the events of the node
+    payment_received: qt_signal!(amount_sats: u64, payment_hash: QString),
+    payment_sent: qt_signal!(payment_hash: QString, fee_sats: u64),
+    payment_failed: qt_signal!(payment_hash: QString, reason: QString),
+    channel_pending: qt_signal!(channel_id: QString),
+    channel_ready: qt_signal!(channel_id: QString),
+    channel_closed: qt_signal!(channel_id: QString, reason: QString),
+    /// the invoice that is shown as QR code was paid
+    invoice_paid: qt_signal!(),
+    /// the payment hash of the invoice that is shown as QR code
+    invoice_hash: String,
+
+    start: qt_method!(
+        /// Follow the background syncs and the events of the node
+        fn start(&mut self) {
+            let qptr = QPointer::from(&*self);
+            let deliver = queued_callback(move |update: Update| {
+                if let Some(greeter) = qptr.as_pinned() {
+                    greeter.borrow_mut().apply(update);
+                }
+            });
+            scheduler::start(deliver);
+
+            let qptr = QPointer::from(&*self);
+            let request = queued_callback(move |req: PaymentRequest| {
+                if let Some(greeter) = qptr.as_pinned() {
+                    greeter.borrow_mut().confirm_external(req);
+                }
+            });
+            if let Err(e) = dbus::start(request) {
+                log_error!("{}", e);
             }
-            if let Some(rate) = self.exchange_rate {
-                msg = format!("{} -> {:.2} CHF", msg, rate as f32 * (ocbal + lnbal));
+            if let Err(e) = rest::start() {
+                log_error!("{}", e);
             }
 
-            msg.into()
+            let qptr = QPointer::from(&*self);
+            let payment = queued_callback(move |(handle, status): (Handle, Status)| {
+                if let Some(greeter) = qptr.as_pinned() {
+                    greeter.borrow_mut().show_payment(handle, status);
+                }
+            });
+            payments::subscribe(Box::new(move |handle, status| payment((handle, status))));
         }
     ),
-    update_channel: qt_method!(
-        fn update_channel(&mut self) -> QString {
-            self.log_err_or(
-                BdkWallet::get_channel_status(),
-                "channel balance unavailable".to_string(),
-            )
-            .into()
+    set_active: qt_method!(
+        /// Stop the node when the app goes to the background, and start it again when it
+        /// comes back
+        fn set_active(&mut self, active: bool) {
+            // both on the worker, so that they happen in order without blocking the GUI,
+            // and both do nothing if they come twice
+            if !active {
+                self.run(None, lifecycle::suspend, |_, ()| {});
+            } else {
+                self.run(
+                    None,
+                    || {
+                        let suspended = lifecycle::is_suspended();
+                        lifecycle::resume()?;
+                        Ok(suspended)
+                    },
+                    |greeter, resumed| {
+                        if resumed {
+                            greeter.update_balance();
+                            greeter.update_channel();
+                            greeter.update_sync_status();
+                        }
+                    },
+                );
+            }
+        }
+    ),
+    /// what the prepared payment is going to do
+    preview_kind: qt_property!(QString; NOTIFY preview_changed),
+    preview_recipient: qt_property!(QString; NOTIFY preview_changed),
+    preview_alias: qt_property!(QString; NOTIFY preview_changed),
+    preview_amount_sats: qt_property!(u64; NOTIFY preview_changed),
+    preview_fee_sats: qt_property!(u64; NOTIFY preview_changed),
+    preview_fee_is_max: qt_property!(bool; NOTIFY preview_changed),
+    preview_changed: qt_signal!(),
+    /// the preview is ready to be confirmed
+    preview_ready: qt_signal!(),
+    /// the resolved input of the prepared payment, with the amount in sats and the description
+    /// as they were previewed
+    prepared: Option<(InputEval, Option<u64>, String)>,
+    /// the id of the D-Bus payment request that is being confirmed
+    external_request: Option<u32>,
+    /// the prepared payment was requested by another app
+    preview_external: qt_property!(bool; NOTIFY preview_changed),
+
+    prepare: qt_method!(
+        /// Resolve the input and fill the preview in the background
+        fn prepare(&mut self, addr: String, amount: String, desc: String) {
+            self.prepared = None;
+            if let Some(id) = self.external_request.take() {
+                dbus::reject(id);
+            }
+            self.preview_external = false;
+            if addr.is_empty() {
+                self.push_event(tr("at least the address field needs to be filled"));
+                return;
+            }
+            self.run(
+                None,
+                move || {
+                    // resolved once, the confirmation pays what was previewed at this rate
+                    // and to this invoice
+                    let bitcoins = Self::to_bitcoins(&amount)?;
+                    let satoshis = if bitcoins.is_empty() {
+                        None
+                    } else {
+                        Some(parse_satoshis(&bitcoins)?)
+                    };
+                    let inpeval = InputEval::evaluate(&addr, &bitcoins, &desc)?;
+                    let preview = preview::prepare(&inpeval)?;
+                    Ok((inpeval, satoshis, desc, preview))
+                },
+                |greeter, (inpeval, satoshis, desc, preview)| {
+                    greeter.preview_kind = preview.kind.into();
+                    greeter.preview_recipient = preview.recipient.into();
+                    greeter.preview_alias = preview.alias.into();
+                    greeter.preview_amount_sats = preview.amount_sats;
+                    greeter.preview_fee_sats = preview.fee_sats;
+                    greeter.preview_fee_is_max = preview.fee_is_max;
+                    greeter.preview_changed();
+                    greeter.prepared = Some((inpeval, satoshis, desc));
+                    greeter.preview_ready();
+                },
+            );
         }
     ),
-    ldk_events: qt_method!(
-        fn ldk_events(&mut self) -> QString {
-            let msg = self.log_err_or(BdkWallet::handle_ldk_event(), "".to_string());
-            if !msg.is_empty() {
-                self.eventlog.push_front(msg);
+    confirm: qt_method!(
+        /// Send the prepared payment in the background, and return its handle. 0 if nothing
+        /// was prepared.
+        fn confirm(&mut self) -> u32 {
+            match self.prepared.take() {
+                Some((inpeval, satoshis, desc)) => {
+                    let external = self.external_request.take();
+                    let handle = payments::start();
+                    self.show_payment(handle, Status::Pending);
+                    self.run(
+                        None,
+                        move || {
+                            let res = Self::payto(handle, inpeval, satoshis, &desc);
+                            payments::finish(handle, &res);
+                            Ok(res)
+                        },
+                        move |greeter, res| {
+                            if let Some(id) = external {
+                                dbus::finish(id, &res);
+                            }
+                            if let Some(msg) = greeter.log_err(res) {
+                                greeter.push_event(msg);
+                                greeter.update_balance();
+                            }
+                        },
+                    );
+                    handle
+                }
+                None => {
+                    self.push_event(tr("no payment was prepared"));
+                    0
+                }
             }
-            self.eventlog.truncate(5);
-            self.eventlog
-                .iter()
-                .fold("".to_string(), |acc, msg| format!("{}\n{}", acc, msg))
-                .trim()
+        }
+    ),
+    /// the handle of the last payment that was sent
+    payment_handle: qt_property!(u32; NOTIFY payment_changed),
+    /// pending, in_flight, succeeded, failed or cancelled
+    payment_state: qt_property!(QString; NOTIFY payment_changed),
+    payment_changed: qt_signal!(),
+    payment_status: qt_method!(
+        fn payment_status(&self, handle: u32) -> QString {
+            payments::status(handle)
+                .map(|status| status.name())
+                .unwrap_or_default()
                 .into()
         }
     ),
-    send: qt_method!(
-        fn send(&mut self, addr: String, amount: String, desc: String) {
-            if addr.is_empty() {
-                self.eventlog
-                    .push_front("at least the address field needs to be filled".to_string());
-            } else if let Some(msg) = self.log_err(self.payto(&addr, &amount, &desc)) {
-                self.eventlog.push_front(msg);
+    cancel_payment: qt_method!(
+        /// Abort a payment that didn't leave yet
+        fn cancel_payment(&mut self, handle: u32) {
+            let res = payments::cancel(handle);
+            self.log_err(res);
+        }
+    ),
+    cancel: qt_method!(
+        fn cancel(&mut self) {
+            self.prepared = None;
+            if let Some(id) = self.external_request.take() {
+                dbus::reject(id);
             }
         }
     ),
     channel_open: qt_method!(
         fn channel_open(&mut self, amount: String, node_id: String) {
             if amount.is_empty() {
-                let msg = "the amount field needs to be filled".to_string();
-                eprintln!("{}", msg);
-                self.eventlog.push_front(msg);
+                let msg = tr("the amount field needs to be filled");
+                log_error!("{}", msg);
+                self.push_event(msg);
             } else {
-                self.log_err(self.channel_new(&amount, &node_id));
+                self.run(
+                    None,
+                    move || Self::channel_new(&amount, &node_id),
+                    |_, _| {},
+                );
             }
         }
     ),
     channel_close: qt_method!(
         fn channel_close(&mut self) {
-            self.log_err(BdkWallet::channel_close());
+            self.run(None, BdkWallet::channel_close, |_, _| {});
         }
     ),
     request: qt_method!(
-        fn request(&mut self, amount: String, desc: String) -> QString {
-            if let Some(invoice) = self.log_err(self.invoice(&amount, &desc)) {
-                self.receiving_address = invoice.clone().into();
-                format!(
-                    "file://{}",
-                    self.log_err(self.generate_qr(&invoice))
-                        .unwrap()
-                        .to_str()
-                        .unwrap()
-                )
-            } else {
-                "".to_string()
+        /// Create an invoice, which replaces the receiving address until the next update
+        fn request(&mut self, amount: String, desc: String) {
+            self.run(
+                None,
+                move || {
+                    let invoice = Self::invoice(&amount, &desc)?;
+                    let hash = Bolt11Invoice::from_str(&invoice)
+                        .map(|inv| inv.payment_hash().to_string())
+                        .unwrap_or_default();
+                    let qr = Self::generate_qr(&invoice)?;
+                    Ok((invoice, hash, qr))
+                },
+                |greeter, (invoice, hash, qr)| {
+                    greeter.show_receiving(invoice, qr);
+                    greeter.invoice_hash = hash;
+                },
+            );
+        }
+    ),
+    show_lnaddress: qt_method!(
+        /// Show the lightning address instead of the receiving address until the next update
+        fn show_lnaddress(&mut self) {
+            let address = Self::settings().lnaddress;
+            if address.is_empty() {
+                self.push_event(tr("No lightning address claimed, see the settings"));
+                return;
+            }
+            match Self::generate_qr(&address) {
+                Ok(qr) => self.show_receiving(address, qr),
+                Err(e) => {
+                    log_error!("{}", e);
+                    self.push_event(e);
+                }
             }
-            .into()
         }
     ),
-    address: qt_method!(
-        fn address(&mut self) -> QString {
-            let addr = self.log_err(self.get_receiving_address()).unwrap();
-            self.receiving_address = addr.clone().into();
-            addr.into()
+    update_address: qt_method!(
+        fn update_address(&mut self) {
+            self.run(
+                Some("address"),
+                || {
+                    let addr = BdkWallet::get_address()?.to_string();
+                    let qr = Self::generate_qr(&addr)?;
+                    Ok((addr, qr))
+                },
+                |greeter, (addr, qr)| greeter.show_receiving(addr, qr),
+            );
         }
     ),
-    address_qr: qt_method!(
-        fn address_qr(&mut self) -> QString {
-            let addr = self.log_err(self.get_receiving_address()).unwrap();
-            self.receiving_address = addr.clone().into();
-            format!(
-                "file://{}",
-                self.log_err(self.generate_qr(&addr))
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-            )
-            .into()
+    sign_message: qt_method!(
+        /// Sign in the background, the signature comes with message_signed
+        fn sign_message(&mut self, address: String, text: String) {
+            self.run(
+                None,
+                move || BdkWallet::sign_message(&address, &text),
+                |greeter, signature| greeter.message_signed(signature.into()),
+            );
         }
     ),
-    update_exchange_rate: qt_method!(
-        fn update_exchange_rate(&mut self) -> QString {
-            let rate = self.refresh_exchange_rate();
-            let rate = self.log_err(rate);
-            println!("exchange rate BTC-CHF: {:?}", rate);
-            if let Some(rate) = rate {
-                format!("{}", rate)
-            } else {
-                "".to_string()
+    verify_message: qt_method!(
+        /// Whether the signature is valid, as a message for the user
+        fn verify_message(&mut self, address: String, text: String, signature: String) -> QString {
+            match BdkWallet::verify_message(&address, &text, &signature) {
+                Ok(true) => tr("The signature is valid"),
+                Ok(false) => tr("The signature doesn't match the address and the message"),
+                Err(e) => e.to_string(),
             }
             .into()
         }
     ),
+    share_receiving_qr: qt_method!(
+        /// Stage the receive QR code for the content hub, and return its url
+        fn share_receiving_qr(&mut self) -> QString {
+            let addr = self.receiving_address.to_string();
+            if addr.is_empty() {
+                return QString::default();
+            }
+            let res = qr::png(&addr).and_then(|png| share::write("receive.png", &png));
+            self.log_err(res).unwrap_or_default().into()
+        }
+    ),
+    update_exchange_rate: qt_method!(
+        fn update_exchange_rate(&mut self) {
+            let settings = Self::settings();
+            self.run(
+                Some("rate"),
+                move || rates::refresh(&settings).map_err(WalletError::Network),
+                |greeter, rate| {
+                    log_debug!("exchange rate BTC-{}: {:?}", rate.currency, rate.rate);
+                    greeter.push_event(Self::rate_message(&rate));
+                },
+            );
+        }
+    ),
     fiat: qt_method!(
         fn fiat(&mut self, amount: String) -> QString {
-            if let Ok(amount) = amount.parse::<f64>() {
-                if let Some(rate) = self.exchange_rate {
-                    format!("CHF {:.2}", amount * rate)
+            let denomination = Self::settings().denomination;
+            let nf = NumberFormat::system();
+            if parse_fiat(&amount).is_some() {
+                match Self::to_bitcoins(&amount).and_then(|btc| parse_satoshis(&btc)) {
+                    Ok(sats) => format!(
+                        "= {} {}{}",
+                        nf.localize(&denomination.format_sats(sats)),
+                        denomination.unit(),
+                        rates::latest().map(|r| r.stale_note()).unwrap_or_default()
+                    ),
+                    Err(e) => e.to_string(),
+                }
+            } else if let Some(amount) = Self::to_bitcoins(&amount)
+                .ok()
+                .and_then(|btc| btc.parse::<f64>().ok())
+            {
+                if let Some(rate) = rates::latest() {
+                    format!(
+                        "{} {}{}",
+                        rate.currency,
+                        nf.number(amount * rate.rate, 2),
+                        rate.stale_note()
+                    )
                 } else {
                     "".to_string()
                 }
@@ -175,46 +468,150 @@ struct Greeter {
             .into()
         }
     ),
+    validate_input: qt_method!(
+        /// Check one of the fields address, amount or description without going online.
+        /// Returns the level (valid, warning or error) and the message.
+        fn validate_input(&self, field: String, value: String) -> QVariantMap {
+            let validation = match Field::from_str(&field) {
+                Ok(field) => validate::validate_input(
+                    field,
+                    &value,
+                    profiles::network(),
+                    Self::settings().denomination,
+                    rates::latest().as_ref(),
+                ),
+                Err(e) => Validation::Error(e),
+            };
+            let mut result = QVariantMap::default();
+            result.insert("level".into(), QString::from(validation.level()).into());
+            result.insert("message".into(), QString::from(validation.message()).into());
+            result
+        }
+    ),
+    unit: qt_method!(
+        fn unit(&self) -> QString {
+            Self::settings().denomination.unit().into()
+        }
+    ),
     evaluate_address_input: qt_method!(
-        fn evaluate_address_input(
-            &mut self,
-            addr: String,
-            amount: String,
-            desc: String,
-        ) -> QString {
-            self.log_err_or(self.evaluate_input(&addr, &amount, &desc), "".to_string())
-                .into()
+        /// Resolve the input in the background, the result comes with input_evaluated
+        fn evaluate_address_input(&mut self, addr: String, amount: String, desc: String) {
+            // supersedes the evaluations still waiting for the worker
+            let request = input_eval::new_request();
+            let requested_at = Instant::now();
+            self.run(
+                None,
+                move || Self::evaluate_input(request, requested_at, &addr, &amount, &desc),
+                |greeter, (csv, paid, comment_max, (sendable_min, sendable_max))| {
+                    if paid {
+                        greeter.push_event(tr(
+                            "Warning: this invoice was already paid from this wallet",
+                        ));
+                    }
+                    let mut fields = csv.splitn(3, ';').map(QString::from);
+                    greeter.input_evaluated(
+                        fields.next().unwrap_or_default(),
+                        fields.next().unwrap_or_default(),
+                        fields.next().unwrap_or_default(),
+                        comment_max,
+                        sendable_min,
+                        sendable_max,
+                    );
+                },
+            );
         }
     ),
 }
 
 impl Greeter {
-    fn payto(&self, addr: &str, bitcoins: &str, desc: &str) -> Result<String, String> {
-        let satoshis = if bitcoins.is_empty() {
-            None
-        } else {
-            Some(parse_satoshis(bitcoins)?)
-        };
-        let inpeval = InputEval::evaluate(addr, bitcoins, desc)?;
+    /// Send the payment as it was prepared
+    fn payto(
+        handle: Handle,
+        inpeval: InputEval,
+        satoshis: Option<u64>,
+        desc: &str,
+    ) -> Result<String, WalletError> {
+        payments::check(handle)?;
         let msg = match inpeval.network {
             InputNetwork::Mainnet(addr) => {
-                if let Some(satoshis) = satoshis {
+                if let (Some(satoshis), true) = (satoshis, watchonly::is_watch_only()) {
+                    let file = watchonly::build_psbt(&addr, satoshis)?;
+                    trf(
+                        "unsigned transaction saved to {}",
+                        &[&file.to_string_lossy()],
+                    )
+                } else if let Some(satoshis) = satoshis {
                     BdkWallet::payto(addr, satoshis)?.to_string()
                 } else {
-                    return Err("Amount field needs to be filled!".to_string());
+                    return Err(WalletError::InvalidAmount(tr(
+                        "Amount field needs to be filled!",
+                    )));
+                }
+            }
+            InputNetwork::Payjoin(addr, pj) => match (satoshis, watchonly::is_watch_only()) {
+                // the device holding the keys signs without payjoin
+                (Some(satoshis), true) => {
+                    let file = watchonly::build_psbt(&addr, satoshis)?;
+                    trf(
+                        "unsigned transaction saved to {}",
+                        &[&file.to_string_lossy()],
+                    )
                 }
+                (Some(satoshis), false) => BdkWallet::payjoin(&addr, satoshis, &pj)?.to_string(),
+                (None, _) => {
+                    return Err(WalletError::InvalidAmount(tr(
+                        "Amount field needs to be filled!",
+                    )))
+                }
+            },
+            InputNetwork::SilentPayment(code) => match (satoshis, watchonly::is_watch_only()) {
+                (_, true) => {
+                    return Err(WalletError::InvalidInput(tr(
+                        "A watch-only wallet can't pay a silent payment code",
+                    )))
+                }
+                (Some(satoshis), false) => BdkWallet::pay_silent(&code, satoshis)?.to_string(),
+                (None, false) => {
+                    return Err(WalletError::InvalidAmount(tr(
+                        "Amount field needs to be filled!",
+                    )))
+                }
+            },
+            InputNetwork::Lightning(invoice) => {
+                let id = BdkWallet::pay_invoice(&invoice, satoshis)?;
+                payments::dispatched(handle, &id);
+                id
+            }
+            InputNetwork::LightningOffer(offer) => {
+                let id = BdkWallet::pay_offer(&offer, satoshis, desc)?;
+                payments::dispatched(handle, &id);
+                id
             }
-            InputNetwork::Lightning(invoice) => BdkWallet::pay_invoice(&invoice, satoshis)?,
-            InputNetwork::LightningOffer(offer) => BdkWallet::pay_offer(&offer, satoshis, desc)?,
             InputNetwork::LnWithdraw(lnurlw) => BdkWallet::withdraw(&lnurlw, satoshis)?,
+            InputNetwork::LnChannel(url) => BdkWallet::lnurl_channel(&url)?,
+            InputNetwork::LightningRefund(refund) => BdkWallet::request_refund(&refund)?,
+            InputNetwork::LnPay(_) => return Err(input_eval::pick_amount(inpeval.sendable_msat)),
             InputNetwork::PrivKey(privkeys) => BdkWallet::sweep(&privkeys)?,
+            InputNetwork::Psbt(_) if watchonly::is_watch_only() => {
+                return Err(WalletError::InvalidInput(tr(
+                    "A watch-only wallet can't sign a PSBT",
+                )))
+            }
+            // the user confirmed the amount and the fee in the preview
+            InputNetwork::Psbt(psbt) => BdkWallet::sign_psbt(*psbt, |_, _| true)?,
+            InputNetwork::Batch(_) if watchonly::is_watch_only() => {
+                return Err(WalletError::InvalidInput(tr(
+                    "A watch-only wallet can't pay several recipients at once",
+                )))
+            }
+            InputNetwork::Batch(recipients) => BdkWallet::payto_many(&recipients)?.to_string(),
         };
 
         Ok(msg)
     }
 
-    fn channel_new(&self, amount: &str, node_id: &str) -> Result<(), String> {
-        let amount = parse_satoshis(amount)?;
+    fn channel_new(amount: &str, node_id: &str) -> Result<(), WalletError> {
+        let amount = parse_satoshis(&Self::to_bitcoins(amount)?)?;
         let node_id = if is_node_id(node_id) {
             Some(node_id)
         } else {
@@ -224,7 +621,8 @@ impl Greeter {
         Ok(())
     }
 
-    fn invoice(&self, amount: &str, desc: &str) -> Result<String, String> {
+    fn invoice(amount: &str, desc: &str) -> Result<String, WalletError> {
+        let amount = &Self::to_bitcoins(amount)?;
         let amount = if amount.is_empty() {
             None
         } else {
@@ -233,70 +631,337 @@ impl Greeter {
         BdkWallet::create_invoice(amount, desc)
     }
 
-    fn evaluate_input(&self, addr: &str, bitcoins: &str, desc: &str) -> Result<String, String> {
-        let inpeval = InputEval::evaluate(addr, bitcoins, desc)?;
-        inpeval.gui_csv()
+    /// The input for the send fields, whether it is an invoice that was already paid,
+    /// how long a comment the LNURL-pay service takes and the sats range it accepts
+    fn evaluate_input(
+        request: u64,
+        requested_at: Instant,
+        addr: &str,
+        bitcoins: &str,
+        desc: &str,
+    ) -> Result<(String, bool, u32, (u64, u64)), WalletError> {
+        let bitcoins = &Self::to_bitcoins(bitcoins)?;
+        let inpeval = InputEval::evaluate_request(request, requested_at, addr, bitcoins, desc)?;
+        let paid = match &inpeval.network {
+            InputNetwork::Lightning(invoice) => BdkWallet::invoice_paid(invoice)?,
+            _ => false,
+        };
+        Ok((
+            inpeval.gui_csv_in(Self::settings().denomination)?,
+            paid,
+            inpeval.comment_max,
+            inpeval
+                .sendable_msat
+                .map(input_eval::sendable_sats)
+                .unwrap_or_default(),
+        ))
     }
 
-    fn get_receiving_address(&self) -> Result<String, String> {
-        let addr = BdkWallet::get_address()?.to_string();
-        Ok(addr)
+    /// Convert an amount entered in fiat, such as "5chf", to BTC with the current rate.
+    /// Amounts without a currency are taken to be in the configured denomination.
+    fn to_bitcoins(amount: &str) -> Result<String, WalletError> {
+        let (value, currency) = match parse_fiat(amount) {
+            Some(fiat) => fiat,
+            None => {
+                return Self::settings()
+                    .denomination
+                    .to_btc(amount)
+                    .map_err(WalletError::InvalidAmount)
+            }
+        };
+        match rates::latest() {
+            Some(rate) if rate.currency != currency => Err(WalletError::InvalidAmount(trf(
+                "The exchange rate is known in {}, not in {}",
+                &[&rate.currency, &currency],
+            ))),
+            Some(rate) if rate.rate > 0.0 => Ok(format!("{:.8}", value / rate.rate)),
+            _ => Err(WalletError::Network(tr(
+                "The exchange rate is not known yet",
+            ))),
+        }
     }
 
-    fn generate_qr(&self, addr: &str) -> Result<PathBuf, String> {
-        let app_data_path =
-            unsafe { QStandardPaths::writable_location(StandardLocation::AppDataLocation) };
-        let app_data_path = PathBuf::from(app_data_path.to_std_string());
-        create_dir_all(&app_data_path).unwrap();
-        let qr_file = app_data_path.join("receiving.png");
+    fn generate_qr(addr: &str) -> Result<String, String> {
+        qr::data_url(addr)
+    }
 
-        let mut qrcode = QrCode::new(addr, QrCodeEcc::Medium)
-            .map_err(|e| format!("Failed to construct a QR code: {}", e))?;
+    fn show_receiving(&mut self, addr: String, qr: String) {
+        self.invoice_hash.clear();
+        self.receiving_address = addr.into();
+        self.receiving_qr = qr.into();
+        self.address_changed();
+    }
+
+    fn apply(&mut self, update: Update) {
+        match update {
+            Update::Syncing => self.set_sync_state("syncing"),
+            Update::Synced => self.update_sync_status(),
+            Update::Balance {
+                onchain_sats,
+                lightning_sats,
+            } => self.show_balance(onchain_sats, lightning_sats),
+            Update::Channel(status) => self.show_channel(status),
+            Update::Event(event) => {
+                let severity = match event {
+                    WalletEvent::PaymentFailed { .. } => Severity::Error,
+                    _ => Severity::Info,
+                };
+                eventlog::push(severity, &event.describe(), event.reference());
+                self.emit_event(event);
+            }
+            Update::Network(connection) => {
+                self.connection = connection.name().into();
+                self.connection_changed();
+                self.push_event(trf("The network changed to {}", &[&connection.name()]));
+            }
+            Update::LowData(low_data) => {
+                self.low_data = low_data;
+                self.connection_changed();
+            }
+            Update::Failed(task, e) => {
+                if task == Task::Sync {
+                    self.set_sync_state("error");
+                }
+                self.log_err::<(), _>(Err(e));
+            }
+        }
+    }
 
-        qrcode.margin(2);
-        qrcode.zoom(6);
+    /// Follow the last payment that was sent
+    fn show_payment(&mut self, handle: Handle, status: Status) {
+        if handle >= self.payment_handle {
+            self.payment_handle = handle;
+            self.payment_state = status.name().into();
+            self.payment_changed();
+        }
+    }
 
-        let buf = qrcode
-            .generate(Color::Grayscale(0, 255))
-            .map_err(|e| format!("Failed to generate a QR code: {}", e))?;
-        std::fs::write(&qr_file, buf)
-            .map_err(|e| format!("Failed to write the QR code to file: {}", e))?;
+    /// Let the user confirm a payment another app requested over D-Bus
+    fn confirm_external(&mut self, req: PaymentRequest) {
+        self.prepare(req.invoice, String::new(), String::new());
+        self.external_request = Some(req.id);
+        self.preview_external = true;
+    }
 
-        Ok(qr_file)
+    fn update_sync_status(&mut self) {
+        self.run(
+            Some("sync"),
+            BdkWallet::sync_status,
+            |greeter, (height, last_sync)| {
+                greeter.block_height = height;
+                greeter.last_sync = last_sync
+                    .and_then(|ts| DateTime::from_timestamp(ts as i64, 0))
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default()
+                    .into();
+                greeter.set_sync_state("synced");
+            },
+        );
     }
 
-    fn refresh_exchange_rate(&mut self) -> Result<f64, String> {
-        let cmc = CmcBuilder::new(COINMARKETCAP_API_KEY)
-            .convert("CHF")
-            .build();
-        let rate = cmc
-            .price("BTC")
-            .map_err(|e| format!("Failed to get exchange rate: {}", e))?;
-        self.exchange_rate = Some(rate.clone());
-        let msg = format!("1 BTC = {:.2} CHF", rate);
-        self.eventlog.push_front(msg);
-        Ok(rate)
+    fn set_sync_state(&mut self, state: &str) {
+        self.sync_state = state.into();
+        self.sync_changed();
     }
 
-    fn log_err<T>(&mut self, res: Result<T, String>) -> Option<T> {
-        match res {
-            Ok(d) => Some(d),
-            Err(err) => {
-                eprintln!("{}", err);
-                self.eventlog.push_front(err.clone());
-                //panic!("{}", err);
-                None
+    /// Hand an event of the node to the GUI, the scheduler refreshes the balances
+    fn emit_event(&mut self, event: WalletEvent) {
+        match event {
+            WalletEvent::PaymentReceived {
+                payment_hash,
+                amount_msat,
+            } => {
+                if !self.invoice_hash.is_empty() && payment_hash == self.invoice_hash {
+                    self.invoice_hash.clear();
+                    self.invoice_paid();
+                }
+                self.payment_received(amount_msat / 1_000, payment_hash.into());
+            }
+            WalletEvent::PaymentSuccessful {
+                payment_hash,
+                fee_paid_msat,
+            } => {
+                self.payment_sent(payment_hash.into(), fee_paid_msat.unwrap_or(0) / 1_000);
+            }
+            WalletEvent::PaymentFailed {
+                payment_hash,
+                reason,
+            } => self.payment_failed(payment_hash.into(), reason.into()),
+            WalletEvent::ChannelPending { channel_id, .. } => {
+                self.channel_pending(channel_id.into())
+            }
+            WalletEvent::ChannelReady { channel_id, .. } => self.channel_ready(channel_id.into()),
+            WalletEvent::ChannelClosed { channel_id, reason } => {
+                self.channel_closed(channel_id.into(), reason.into())
+            }
+        }
+    }
+
+    /// Refresh the balances right away, e.g. after sending
+    fn update_balance(&mut self) {
+        let settings = Self::settings();
+        self.run(
+            Some("balance"),
+            move || {
+                let balance = BdkWallet::get_balance()?;
+                let rate = if rates::latest().map(|r| r.currency) != Some(settings.currency.clone())
+                {
+                    Some(rates::refresh(&settings))
+                } else {
+                    None
+                };
+                Ok((balance, rate))
+            },
+            |greeter, ((ocbal, lnbal), rate)| {
+                if let Some(rate) = rate.and_then(|rate| greeter.log_err(rate)) {
+                    greeter.push_event(Self::rate_message(&rate));
+                }
+                greeter.show_balance(ocbal, lnbal);
+            },
+        );
+    }
+
+    fn update_channel(&mut self) {
+        self.run(
+            Some("channel"),
+            BdkWallet::get_channel_status,
+            |greeter, status| greeter.show_channel(status),
+        );
+    }
+
+    fn show_channel(&mut self, status: String) {
+        if self.channel_status.to_string() != status {
+            self.channel_status = status.into();
+            self.channel_changed();
+        }
+    }
+
+    fn show_balance(&mut self, ocbal: u64, lnbal: u64) {
+        let denomination = Self::settings().denomination;
+        let nf = NumberFormat::system();
+        let mut msg = format!(
+            "Bal: {} + {} {}",
+            nf.localize(&denomination.format_sats(ocbal)),
+            nf.localize(&denomination.format_sats(lnbal)),
+            denomination.unit()
+        );
+        let rate = rates::latest();
+        let fiat_value =
+            rate.as_ref().map(|r| r.rate).unwrap_or(0.0) * (ocbal + lnbal) as f64 / 100_000_000.0;
+        if let Some(rate) = &rate {
+            msg = format!(
+                "{} -> {} {}{}",
+                msg,
+                nf.number(fiat_value, 2),
+                rate.currency,
+                rate.stale_note()
+            );
+        }
+
+        if self.balance_text.to_string() == msg
+            && self.onchain_sats == ocbal
+            && self.lightning_sats == lnbal
+        {
+            return;
+        }
+        self.balance_text = msg.into();
+        self.onchain_sats = ocbal;
+        self.lightning_sats = lnbal;
+        self.fiat_value = fiat_value;
+        self.fiat_currency = rate.map(|r| r.currency).unwrap_or_default().into();
+        self.balance_changed();
+        self.remind_backup(ocbal + lnbal);
+    }
+
+    fn rate_message(rate: &Rate) -> String {
+        format!(
+            "1 BTC = {:.2} {} ({}){}",
+            rate.rate,
+            rate.currency,
+            rate.source,
+            rate.stale_note()
+        )
+    }
+
+    /// Remind to back up the seed once per session, when there is enough at stake
+    fn remind_backup(&mut self, balance_sats: u64) {
+        if self.backup_reminded || watchonly::is_watch_only() {
+            return;
+        }
+        let settings = Self::settings();
+        if seed::needs_backup_reminder(
+            balance_sats,
+            settings.backup_reminder_sats,
+            settings.seed_backup_confirmed > 0,
+        ) {
+            self.backup_reminded = true;
+            self.backup_reminder();
+        }
+    }
+
+    fn settings() -> Settings {
+        Settings::load().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Settings::default()
+        })
+    }
+
+    /// Run a wallet operation on the worker thread, and hand the result to done on the GUI
+    /// thread. Errors end up in the event log. A periodic job with a key is skipped while
+    /// the previous run is still pending.
+    fn run<R, F, D>(&mut self, key: Option<&'static str>, job: F, done: D)
+    where
+        R: Send + 'static,
+        F: FnOnce() -> Result<R, WalletError> + Send + 'static,
+        D: FnOnce(&mut Self, R) + 'static,
+    {
+        if let Some(key) = key {
+            if !self.pending.insert(key) {
+                return;
             }
         }
+        self.set_jobs(self.jobs + 1);
+        worker::run(&*self, job, move |greeter: &mut Self, res| {
+            if let Some(key) = key {
+                greeter.pending.remove(key);
+            }
+            greeter.set_jobs(greeter.jobs - 1);
+            if let Some(result) = greeter.log_err(res) {
+                done(greeter, result);
+            }
+        });
+    }
+
+    fn set_jobs(&mut self, jobs: usize) {
+        self.jobs = jobs;
+        if self.busy != (jobs > 0) {
+            self.busy = jobs > 0;
+            self.busy_changed();
+        }
     }
 
-    fn log_err_or<T>(&mut self, res: Result<T, String>, fallback: T) -> T {
+    fn push_event(&mut self, msg: String) {
+        eventlog::push(Severity::Info, &msg, "");
+    }
+
+    fn log_err<T, E: Into<WalletError>>(&mut self, res: Result<T, E>) -> Option<T> {
         match res {
-            Ok(d) => d,
+            Ok(d) => Some(d),
             Err(err) => {
-                eprintln!("{}", err);
-                self.eventlog.push_front(err);
-                fallback
+                let err = err.into();
+                if let WalletError::Cancelled(msg) = &err {
+                    log_debug!("{}", msg);
+                    return None;
+                }
+                log_error!("{}", err);
+                eventlog::push(Severity::Error, &err.to_string(), "");
+                self.failed(
+                    err.code().into(),
+                    err.to_string().into(),
+                    err.is_recoverable(),
+                    err.action().into(),
+                );
+                None
             }
         }
     }
@@ -304,6 +969,11 @@ impl Greeter {
 
 fn main() {
     init_gettext();
+    if let Err(e) = profiles::check_override() {
+        log_error!("{}", e);
+        process::exit(1);
+    }
+    demo::init();
     unsafe {
         cpp! { {
             #include <QtCore/QCoreApplication>
@@ -316,21 +986,46 @@ fn main() {
     QQuickStyle::set_style("Suru");
     qrc::load();
     qml_register_type::<Greeter>(cstr!("Greeter"), 1, 0, cstr!("Greeter"));
+    qml_register_type::<HistoryModel>(cstr!("HistoryModel"), 1, 0, cstr!("HistoryModel"));
+    qml_register_type::<EventLogModel>(cstr!("EventLogModel"), 1, 0, cstr!("EventLogModel"));
+    qml_register_type::<InvoiceModel>(cstr!("InvoiceModel"), 1, 0, cstr!("InvoiceModel"));
+    qml_register_type::<AmountFormat>(cstr!("AmountFormat"), 1, 0, cstr!("AmountFormat"));
+    qml_register_type::<RateProvider>(cstr!("RateProvider"), 1, 0, cstr!("RateProvider"));
+    qml_register_type::<PriceModel>(cstr!("PriceModel"), 1, 0, cstr!("PriceModel"));
+    qml_register_type::<Backup>(cstr!("Backup"), 1, 0, cstr!("Backup"));
+    qml_register_type::<PinLock>(cstr!("PinLock"), 1, 0, cstr!("PinLock"));
+    qml_register_type::<PosModel>(cstr!("PosModel"), 1, 0, cstr!("PosModel"));
+    qml_register_type::<ProfileModel>(cstr!("ProfileModel"), 1, 0, cstr!("ProfileModel"));
+    qml_register_type::<SeedBackup>(cstr!("SeedBackup"), 1, 0, cstr!("SeedBackup"));
+    qml_register_type::<SettingsModel>(cstr!("SettingsModel"), 1, 0, cstr!("SettingsModel"));
+    qml_register_type::<NodeStartup>(cstr!("NodeStartup"), 1, 0, cstr!("NodeStartup"));
+    qml_register_type::<NodeInfoModel>(cstr!("NodeInfoModel"), 1, 0, cstr!("NodeInfoModel"));
     let mut engine = QmlEngine::new();
 
-    println!("Initializing the node singleton.");
-    BdkWallet::init_node().unwrap();
+    logging::set_verbose(Settings::load().map(|s| s.verbose_logging).unwrap_or(false));
+    qr::remove_stale();
+    share::clear();
+    if let Err(e) = proxy::apply_settings() {
+        log_error!("{}", e);
+    }
+    log_info!(
+        "The node of the {} wallet is started once the GUI shows.",
+        profiles::active()
+    );
 
-    println!("Loading file /qml/utlnwallet.qml.");
+    log_info!("Loading file /qml/utlnwallet.qml.");
     engine.load_file("qrc:/qml/utlnwallet.qml".into());
-    println!("Entering the QML main loop.");
+    log_info!("Entering the QML main loop.");
     engine.exec();
+    if let Err(e) = BdkWallet::shutdown() {
+        log_error!("{}", e);
+    }
+    if let Err(e) = daemon::give_back() {
+        log_error!("{}", e);
+    }
 }
 
 fn init_gettext() {
-    let domain = "utlnwallet.ulrichard";
-    textdomain(domain).expect("Failed to set gettext domain");
-
     let app_dir = env::var("APP_DIR").expect("Failed to read the APP_DIR environment variable");
 
     let mut app_dir_path = PathBuf::from(app_dir);
@@ -338,7 +1033,5 @@ fn init_gettext() {
         app_dir_path = PathBuf::from("/usr");
     }
 
-    let path = app_dir_path.join("share/locale");
-
-    bindtextdomain(domain, path.to_str().unwrap()).expect("Failed to bind gettext domain");
+    i18n::init(&app_dir_path.join("share/locale")).expect("Failed to set up gettext");
 }
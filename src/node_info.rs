@@ -0,0 +1,103 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::worker;
+
+use utwallet_core::logging;
+use utwallet_core::wallet::{BdkWallet, NodeInfo};
+
+use qmetaobject::*;
+
+/// The about page of the node
+#[derive(QObject, Default)]
+pub struct NodeInfoModel {
+    base: qt_base_class!(trait QObject),
+    node_id: qt_property!(QString; NOTIFY changed),
+    listening_addresses: qt_property!(QVariantList; NOTIFY changed),
+    peers: qt_property!(u32; NOTIFY changed),
+    connected_peers: qt_property!(u32; NOTIFY changed),
+    channels: qt_property!(u32; NOTIFY changed),
+    usable_channels: qt_property!(u32; NOTIFY changed),
+    block_height: qt_property!(u32; NOTIFY changed),
+    network: qt_property!(QString; NOTIFY changed),
+    ldk_node_version: qt_property!(QString; NOTIFY changed),
+    app_version: qt_property!(QString; NOTIFY changed),
+    /// why the info couldn't be loaded, empty if it could
+    error: qt_property!(QString; NOTIFY changed),
+    changed: qt_signal!(),
+    info: NodeInfo,
+
+    load: qt_method!(fn(&mut self)),
+    report: qt_method!(fn(&self) -> QString),
+    export_log: qt_method!(fn(&self) -> QString),
+}
+
+impl NodeInfoModel {
+    /// Fetch the info in the background
+    fn load(&mut self) {
+        worker::run(
+            &*self,
+            || BdkWallet::node_info().map_err(String::from),
+            |model: &mut Self, res| match res {
+                Ok(info) => model.show(info),
+                Err(e) => {
+                    log_error!("{}", e);
+                    model.error = e.into();
+                    model.changed();
+                }
+            },
+        );
+    }
+
+    fn show(&mut self, info: NodeInfo) {
+        self.node_id = info.node_id.as_str().into();
+        self.listening_addresses = info
+            .listening_addresses
+            .iter()
+            .map(|a| QString::from(a.as_str()))
+            .collect();
+        self.peers = info.peers as u32;
+        self.connected_peers = info.connected_peers as u32;
+        self.channels = info.channels as u32;
+        self.usable_channels = info.usable_channels as u32;
+        self.block_height = info.block_height;
+        self.network = info.network.as_str().into();
+        self.ldk_node_version = info.ldk_node_version.as_str().into();
+        self.app_version = info.app_version.as_str().into();
+        self.error = QString::default();
+        self.info = info;
+        self.changed();
+    }
+
+    /// Write the log into one file and return its path, or the error
+    fn export_log(&self) -> QString {
+        match logging::export() {
+            Ok(file) => file.to_string_lossy().to_string(),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    /// The info as json, to paste into a bug report
+    fn report(&self) -> QString {
+        serde_json::to_string_pretty(&self.info)
+            .unwrap_or_else(|e| format!("Failed to serialize the node info: {}", e))
+            .into()
+    }
+}
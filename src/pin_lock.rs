@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::pin::{
+    is_locked, is_set, lock, needs_fresh_pin, require, set, subscribe, touch, verify,
+    verify_biometric,
+};
+use utwallet_core::settings::Settings;
+
+use qmetaobject::*;
+
+/// Exposes the PIN to QML
+#[derive(QObject, Default)]
+pub struct PinLock {
+    base: qt_base_class!(trait QObject),
+    is_set: qt_property!(bool; NOTIFY changed),
+    /// whether the fingerprint reader may be used instead of the PIN
+    biometric: qt_property!(bool; NOTIFY changed),
+    /// the PIN has to be entered before the wallet can be used
+    locked: qt_property!(bool; NOTIFY locked_changed),
+    locked_changed: qt_signal!(),
+    started: bool,
+    changed: qt_signal!(),
+
+    load: qt_method!(fn(&mut self)),
+    verify: qt_method!(fn(&mut self, pin: String) -> QString),
+    biometric_identified: qt_method!(fn(&mut self) -> QString),
+    set_pin: qt_method!(fn(&mut self, current: String, new: String) -> QString),
+    authorized: qt_method!(fn(&self) -> bool),
+    needs_fresh_pin: qt_method!(fn(&self, sats: u64) -> bool),
+    lock: qt_method!(fn(&mut self)),
+    activity: qt_method!(fn(&mut self)),
+    start: qt_method!(fn(&mut self)),
+}
+
+impl PinLock {
+    fn load(&mut self) {
+        self.is_set = is_set().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            true
+        });
+        self.biometric = Settings::load()
+            .map(|s| s.biometric_unlock)
+            .unwrap_or(false);
+        self.changed();
+        self.set_locked(is_locked());
+    }
+
+    /// Follow the lock state, which is managed on the Rust side
+    fn start(&mut self) {
+        self.load();
+        if self.started {
+            return;
+        }
+        self.started = true;
+        let qptr = QPointer::from(&*self);
+        let update = queued_callback(move |locked: bool| {
+            if let Some(pin_lock) = qptr.as_pinned() {
+                pin_lock.borrow_mut().set_locked(locked);
+            }
+        });
+        subscribe(Box::new(update));
+    }
+
+    fn set_locked(&mut self, locked: bool) {
+        if self.locked != locked {
+            self.locked = locked;
+            self.locked_changed();
+        }
+    }
+
+    fn activity(&mut self) {
+        touch();
+    }
+
+    /// Returns an error message, or an empty string
+    fn verify(&mut self, pin: String) -> QString {
+        let res = verify(&pin);
+        self.set_locked(is_locked());
+        res.err().unwrap_or_default().into()
+    }
+
+    /// Called when the biometric service identified the user
+    fn biometric_identified(&mut self) -> QString {
+        let res = verify_biometric();
+        self.set_locked(is_locked());
+        res.err().unwrap_or_default().into()
+    }
+
+    /// Set or change the PIN. Returns an error message, or an empty string.
+    fn set_pin(&mut self, current: String, new: String) -> QString {
+        let res = set(&current, &new);
+        self.load();
+        res.err().unwrap_or_default().into()
+    }
+
+    fn authorized(&self) -> bool {
+        require().is_ok()
+    }
+
+    fn needs_fresh_pin(&self, sats: u64) -> bool {
+        needs_fresh_pin(sats)
+    }
+
+    fn lock(&mut self) {
+        lock();
+        self.set_locked(is_locked());
+    }
+}
@@ -0,0 +1,190 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::worker;
+
+use utwallet_core::error::WalletError;
+use utwallet_core::format::NumberFormat;
+use utwallet_core::pos::{self, Sale};
+use utwallet_core::qr;
+
+use ldk_node::lightning_invoice::Bolt11Invoice;
+use qmetaobject::*;
+
+/// The point of sale mode for the keypad page
+#[derive(QObject, Default)]
+pub struct PosModel {
+    base: qt_base_class!(trait QObject),
+    /// the amount typed on the keypad, formatted for display
+    amount: qt_property!(QString; NOTIFY amount_changed),
+    tip_percent: qt_property!(u32; NOTIFY amount_changed WRITE set_tip_percent),
+    /// the total with the tip, formatted for display
+    total: qt_property!(QString; NOTIFY amount_changed),
+    amount_changed: qt_signal!(),
+    /// idle, creating, waiting, paid or expired
+    state: qt_property!(QString; NOTIFY state_changed),
+    invoice: qt_property!(QString; NOTIFY state_changed),
+    qr: qt_property!(QString; NOTIFY state_changed),
+    /// the amount of the open sale in sats
+    sats: qt_property!(u64; NOTIFY state_changed),
+    /// why the sale couldn't be charged, empty if it could
+    error: qt_property!(QString; NOTIFY state_changed),
+    state_changed: qt_signal!(),
+    /// the customer paid the open sale
+    paid: qt_signal!(),
+    /// the digits typed on the keypad, in cents
+    entered: String,
+    sale: Option<Sale>,
+    started: bool,
+
+    start: qt_method!(fn(&mut self)),
+    key: qt_method!(fn(&mut self, key: String)),
+    charge: qt_method!(fn(&mut self)),
+    check_expiry: qt_method!(fn(&mut self)),
+    new_sale: qt_method!(fn(&mut self)),
+    report: qt_method!(fn(&self, day: String) -> QString),
+}
+
+impl PosModel {
+    /// Follow the payments of the sales
+    fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        self.new_sale();
+        let qptr = QPointer::from(&*self);
+        let paid = queued_callback(move |payment_hash: String| {
+            if let Some(model) = qptr.as_pinned() {
+                model.borrow_mut().sale_paid(&payment_hash);
+            }
+        });
+        pos::subscribe(Box::new(paid));
+    }
+
+    /// A key of the keypad was pressed, the digits, "<" or "C"
+    fn key(&mut self, key: String) {
+        if self.sale.is_some() {
+            return;
+        }
+        self.entered = pos::keypad(&self.entered, &key);
+        self.show_amount();
+    }
+
+    fn set_tip_percent(&mut self, tip_percent: u32) {
+        self.tip_percent = tip_percent;
+        self.show_amount();
+    }
+
+    /// Create the invoice for the entered amount in the background
+    fn charge(&mut self) {
+        if self.state.to_string() != "idle" {
+            return;
+        }
+        let fiat_amount = pos::keypad_value(&self.entered);
+        let tip_percent = self.tip_percent;
+        self.set_state("creating");
+        worker::run(
+            &*self,
+            move || {
+                let sale = pos::charge(fiat_amount, tip_percent)?;
+                let qr = qr::data_url(&sale.invoice).map_err(WalletError::Other)?;
+                Ok::<_, WalletError>((sale, qr))
+            },
+            |model: &mut Self, res| match res {
+                Ok((sale, qr)) => {
+                    model.invoice = sale.invoice.as_str().into();
+                    model.qr = qr.into();
+                    model.sats = sale.amount_sats;
+                    model.sale = Some(sale);
+                    model.set_state("waiting");
+                }
+                Err(e) => {
+                    log_error!("{}", e);
+                    model.error = e.to_string().into();
+                    model.set_state("idle");
+                }
+            },
+        );
+    }
+
+    /// Polled while waiting, as an expired invoice can't be paid anymore
+    fn check_expiry(&mut self) {
+        let expired = match &self.sale {
+            Some(sale) if self.state.to_string() == "waiting" => sale
+                .invoice
+                .parse::<Bolt11Invoice>()
+                .map(|i| i.is_expired())
+                .unwrap_or(false),
+            _ => false,
+        };
+        if expired {
+            self.set_state("expired");
+        }
+    }
+
+    /// Clear the keypad for the next customer
+    fn new_sale(&mut self) {
+        self.sale = None;
+        self.entered.clear();
+        self.invoice = QString::default();
+        self.qr = QString::default();
+        self.sats = 0;
+        self.error = QString::default();
+        self.show_amount();
+        self.set_state("idle");
+    }
+
+    /// The totals of a day, formatted as YYYY-MM-DD, or of today if it is empty
+    fn report(&self, day: String) -> QString {
+        match pos::daily_report(&day) {
+            Ok(report) => report.to_text(),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    fn sale_paid(&mut self, payment_hash: &str) {
+        match &self.sale {
+            Some(sale) if sale.payment_hash == payment_hash => {
+                self.set_state("paid");
+                self.paid();
+            }
+            _ => (),
+        }
+    }
+
+    fn show_amount(&mut self) {
+        let nf = NumberFormat::system();
+        let amount = pos::keypad_value(&self.entered);
+        self.amount = nf.number(amount, 2).into();
+        self.total = nf
+            .number(amount + pos::tip(amount, self.tip_percent), 2)
+            .into();
+        self.amount_changed();
+    }
+
+    fn set_state(&mut self, state: &str) {
+        if state != "idle" {
+            self.error = QString::default();
+        }
+        self.state = state.into();
+        self.state_changed();
+    }
+}
@@ -0,0 +1,94 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::settings::Settings;
+use utwallet_core::ticker::{series, Series};
+
+use chrono::DateTime;
+use qmetaobject::*;
+use std::collections::HashMap;
+
+const ROLE_TIME: i32 = USER_ROLE;
+const ROLE_PRICE: i32 = USER_ROLE + 1;
+
+/// The price of the last day for drawing a sparkline
+#[derive(QObject, Default)]
+pub struct PriceModel {
+    base: qt_base_class!(trait QAbstractListModel),
+    series: Series,
+    min: qt_property!(f64; NOTIFY series_changed),
+    max: qt_property!(f64; NOTIFY series_changed),
+    series_changed: qt_signal!(),
+
+    refresh: qt_method!(fn(&mut self)),
+    price: qt_method!(fn(&self, row: i32) -> f64),
+}
+
+impl PriceModel {
+    fn refresh(&mut self) {
+        let res = Settings::load().and_then(|s| series(&s.currency));
+        let series = match res {
+            Ok(s) => s,
+            Err(e) => {
+                log_error!("{}", e);
+                return;
+            }
+        };
+        self.begin_reset_model();
+        self.min = series.iter().map(|s| s.1).fold(f64::INFINITY, f64::min);
+        self.max = series.iter().map(|s| s.1).fold(0.0, f64::max);
+        if series.is_empty() {
+            self.min = 0.0;
+        }
+        self.series = series;
+        self.end_reset_model();
+        self.series_changed();
+    }
+
+    fn price(&self, row: i32) -> f64 {
+        self.series.get(row as usize).map(|s| s.1).unwrap_or(0.0)
+    }
+}
+
+impl QAbstractListModel for PriceModel {
+    fn row_count(&self) -> i32 {
+        self.series.len() as i32
+    }
+
+    fn data(&self, index: QModelIndex, role: i32) -> QVariant {
+        let (timestamp, price) = match self.series.get(index.row() as usize) {
+            Some(s) => s,
+            None => return QVariant::default(),
+        };
+        match role {
+            ROLE_TIME => QString::from(
+                DateTime::from_timestamp(*timestamp, 0)
+                    .map(|dt| dt.format("%H:%M").to_string())
+                    .unwrap_or_default(),
+            )
+            .into(),
+            ROLE_PRICE => (*price).into(),
+            _ => QVariant::default(),
+        }
+    }
+
+    fn role_names(&self) -> HashMap<i32, QByteArray> {
+        let mut roles = HashMap::new();
+        roles.insert(ROLE_TIME, "time".into());
+        roles.insert(ROLE_PRICE, "price".into());
+        roles
+    }
+}
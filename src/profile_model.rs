@@ -0,0 +1,97 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::profiles::{
+    active, create, create_watch_only, network, select, select_network, Profiles,
+};
+use utwallet_core::rest;
+
+use qmetaobject::*;
+
+/// Lists and switches the wallet profiles from QML
+#[derive(QObject, Default)]
+pub struct ProfileModel {
+    base: qt_base_class!(trait QObject),
+    names: qt_property!(QVariantList; NOTIFY changed),
+    active: qt_property!(QString; NOTIFY changed),
+    ask_at_startup: qt_property!(bool; NOTIFY changed WRITE set_ask_at_startup),
+    /// bitcoin, testnet, signet or regtest
+    network: qt_property!(QString; NOTIFY changed),
+    changed: qt_signal!(),
+
+    load: qt_method!(fn(&mut self)),
+    create: qt_method!(fn(&mut self, name: String) -> QString),
+    create_watch_only: qt_method!(fn(&mut self, name: String, descriptor: String) -> QString),
+    select: qt_method!(fn(&mut self, name: String) -> QString),
+    select_network: qt_method!(fn(&mut self, name: String) -> QString),
+}
+
+impl ProfileModel {
+    fn load(&mut self) {
+        let profiles = Profiles::load().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Profiles::default()
+        });
+        self.names = profiles
+            .names
+            .iter()
+            .map(|n| QString::from(n.as_str()))
+            .collect();
+        self.active = active().into();
+        self.ask_at_startup = profiles.ask_at_startup;
+        self.network = network().to_string().into();
+        self.changed();
+    }
+
+    /// Returns an error message, or an empty string
+    fn create(&mut self, name: String) -> QString {
+        let res = create(name.trim());
+        self.load();
+        res.err().unwrap_or_default().into()
+    }
+
+    /// Returns an error message, or an empty string
+    fn create_watch_only(&mut self, name: String, descriptor: String) -> QString {
+        let res = create_watch_only(name.trim(), &descriptor);
+        self.load();
+        res.err().unwrap_or_default().into()
+    }
+
+    /// Returns an error message, or an empty string
+    fn select(&mut self, name: String) -> QString {
+        let res = select(&name).and_then(|_| rest::restart());
+        self.load();
+        res.err().unwrap_or_default().into()
+    }
+
+    /// Returns an error message, or an empty string
+    fn select_network(&mut self, name: String) -> QString {
+        let res = select_network(&name).and_then(|_| rest::restart());
+        self.load();
+        res.err().unwrap_or_default().into()
+    }
+
+    fn set_ask_at_startup(&mut self, ask: bool) {
+        let res = Profiles::load().and_then(|mut p| {
+            p.ask_at_startup = ask;
+            p.save()
+        });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+}
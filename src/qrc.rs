@@ -3,7 +3,19 @@ qrc!(qml_resources,
         "qml/utlnwallet.qml",
         "qml/MainPage.qml",
         "qml/ScanPage.qml",
-        "qml/ErrorDialog.qml"
+        "qml/HistoryPage.qml",
+        "qml/InvoicesPage.qml",
+        "qml/SettingsPage.qml",
+        "qml/SeedPage.qml",
+        "qml/BackupPage.qml",
+        "qml/AboutPage.qml",
+        "qml/SharePage.qml",
+        "qml/ProfilesPage.qml",
+        "qml/ErrorDialog.qml",
+        "qml/PinDialog.qml",
+        "qml/BiometricUnlock.qml",
+        "qml/LockScreen.qml",
+        "qml/PosPage.qml"
     },
 );
 
@@ -0,0 +1,91 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::rates::{latest, refresh, subscribe, Rate};
+use utwallet_core::settings::Settings;
+
+use chrono::DateTime;
+use qmetaobject::*;
+
+/// The exchange rate for QML, kept up to date in the background
+#[derive(QObject, Default)]
+pub struct RateProvider {
+    base: qt_base_class!(trait QObject),
+    /// the price of one BTC, 0 while unknown
+    rate: qt_property!(f64; NOTIFY rate_changed),
+    currency: qt_property!(QString; NOTIFY rate_changed),
+    /// the name of the service that provided the rate
+    source: qt_property!(QString; NOTIFY rate_changed),
+    /// when the rate was fetched, formatted as YYYY-MM-DD HH:MM
+    last_updated: qt_property!(QString; NOTIFY rate_changed),
+    /// the rate is from the cache, as the services couldn't be reached
+    stale: qt_property!(bool; NOTIFY rate_changed),
+    rate_changed: qt_signal!(),
+    /// why the last refresh failed, empty if it didn't
+    error: qt_property!(QString; NOTIFY error_changed),
+    error_changed: qt_signal!(),
+    started: bool,
+
+    start: qt_method!(fn(&mut self)),
+    refresh: qt_method!(fn(&mut self)),
+}
+
+impl RateProvider {
+    /// Show the latest rate and follow the refreshes
+    fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+        if let Some(rate) = latest() {
+            self.apply(Ok(rate));
+        }
+        let qptr = QPointer::from(&*self);
+        let update = queued_callback(move |rate: Result<Rate, String>| {
+            if let Some(provider) = qptr.as_pinned() {
+                provider.borrow_mut().apply(rate);
+            }
+        });
+        subscribe(Box::new(update));
+    }
+
+    /// Fetch the rate right away
+    fn refresh(&mut self) {
+        let settings = Settings::load().unwrap_or_default();
+        if let Err(e) = refresh(&settings) {
+            log_error!("{}", e);
+        }
+    }
+
+    fn apply(&mut self, rate: Result<Rate, String>) {
+        match rate {
+            Ok(rate) => {
+                self.rate = rate.rate;
+                self.currency = rate.currency.clone().into();
+                self.source = rate.source.clone().into();
+                self.last_updated = DateTime::from_timestamp(rate.timestamp, 0)
+                    .map(|dt| dt.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_default()
+                    .into();
+                self.stale = rate.stale;
+                self.error = QString::default();
+                self.rate_changed();
+            }
+            Err(e) => self.error = e.into(),
+        }
+        self.error_changed();
+    }
+}
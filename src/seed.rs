@@ -0,0 +1,252 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use utwallet_core::i18n::tr;
+use utwallet_core::pin;
+use utwallet_core::settings::Settings;
+use utwallet_core::wallet::BdkWallet;
+
+use chrono::Utc;
+use ldk_node::bip39::Mnemonic;
+use qmetaobject::*;
+use rand_core::{OsRng, RngCore};
+
+/// How many words the user has to repeat to confirm the backup
+const CHALLENGE_WORDS: usize = 3;
+
+/// Pick distinct random positions in a mnemonic, in ascending order
+fn pick_challenge<R: RngCore>(word_count: usize, count: usize, rng: &mut R) -> Vec<usize> {
+    let mut positions = Vec::new();
+    while positions.len() < count.min(word_count) {
+        let pos = rng.next_u32() as usize % word_count;
+        if !positions.contains(&pos) {
+            positions.push(pos);
+        }
+    }
+    positions.sort();
+    positions
+}
+
+/// Whether the user should be reminded to back up the seed, as the wallet holds more than
+/// they would want to lose
+pub fn needs_backup_reminder(
+    balance_sats: u64,
+    threshold_sats: u64,
+    backup_confirmed: bool,
+) -> bool {
+    !backup_confirmed && balance_sats > threshold_sats
+}
+
+/// Whether the answers match the words at the challenged positions
+fn check_answers(words: &[&str], positions: &[usize], answers: &[&str]) -> bool {
+    positions.len() == answers.len()
+        && positions.iter().zip(answers).all(|(pos, answer)| {
+            words
+                .get(*pos)
+                .map(|w| w.eq_ignore_ascii_case(answer.trim()))
+                .unwrap_or(false)
+        })
+}
+
+/// Whether the entered words are the seed, regardless of case and spacing
+fn matches_seed(seed: &Mnemonic, entered: &str) -> bool {
+    let entered = entered
+        .split_whitespace()
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Mnemonic::parse(&entered)
+        .map(|m| m == *seed)
+        .unwrap_or(false)
+}
+
+/// Shows the seed words to the user and checks that they were written down
+#[derive(QObject, Default)]
+pub struct SeedBackup {
+    base: qt_base_class!(trait QObject),
+    /// the seed words while they are revealed, empty otherwise
+    words: qt_property!(QVariantList; NOTIFY words_changed),
+    words_changed: qt_signal!(),
+    /// the 1-based positions of the words the user has to repeat
+    challenge: qt_property!(QVariantList; NOTIFY challenge_changed),
+    challenge_changed: qt_signal!(),
+    positions: Vec<usize>,
+    subscribed: bool,
+    backup_confirmed: qt_property!(bool; NOTIFY backup_confirmed_changed),
+    backup_confirmed_changed: qt_signal!(),
+
+    reveal: qt_method!(fn(&mut self, acknowledged: bool) -> QString),
+    hide: qt_method!(fn(&mut self)),
+    new_challenge: qt_method!(fn(&mut self)),
+    verify: qt_method!(fn(&mut self, answers: String) -> bool),
+    verify_mnemonic: qt_method!(fn(&mut self, words: String) -> bool),
+    load: qt_method!(fn(&mut self)),
+}
+
+impl SeedBackup {
+    fn load(&mut self) {
+        if !self.subscribed {
+            self.subscribed = true;
+            // don't keep the words around once the wallet locks
+            let qptr = QPointer::from(&*self);
+            let on_lock = queued_callback(move |locked: bool| {
+                if let Some(seed) = qptr.as_pinned() {
+                    if locked {
+                        seed.borrow_mut().hide();
+                    }
+                }
+            });
+            pin::subscribe(Box::new(on_lock));
+        }
+        self.backup_confirmed = Settings::load()
+            .map(|s| s.seed_backup_confirmed > 0)
+            .unwrap_or(false);
+        self.backup_confirmed_changed();
+    }
+
+    /// Hand out the seed words, once the user acknowledged that anyone who sees them
+    /// can take the funds. Returns an error message, or an empty string.
+    fn reveal(&mut self, acknowledged: bool) -> QString {
+        if !acknowledged {
+            return tr("Please confirm that nobody is watching").into();
+        }
+        if let Err(e) = pin::require() {
+            return e.into();
+        }
+        match BdkWallet::mnemonic() {
+            Ok(mnemonic) => {
+                self.words = mnemonic.word_iter().map(QString::from).collect();
+                self.words_changed();
+                "".into()
+            }
+            Err(e) => {
+                log_error!("{}", e);
+                e.to_string().into()
+            }
+        }
+    }
+
+    /// Forget the revealed words
+    fn hide(&mut self) {
+        self.words = QVariantList::default();
+        self.words_changed();
+    }
+
+    /// Ask for some random words of the seed
+    fn new_challenge(&mut self) {
+        let word_count = match BdkWallet::mnemonic() {
+            Ok(m) => m.word_count(),
+            Err(e) => {
+                log_error!("{}", e);
+                return;
+            }
+        };
+        self.positions = pick_challenge(word_count, CHALLENGE_WORDS, &mut OsRng);
+        self.challenge = self.positions.iter().map(|p| (*p as i32 + 1)).collect();
+        self.challenge_changed();
+    }
+
+    /// Check the space separated answers to the challenge, and remember a successful backup
+    fn verify(&mut self, answers: String) -> bool {
+        let mnemonic = match BdkWallet::mnemonic() {
+            Ok(m) => m,
+            Err(e) => {
+                log_error!("{}", e);
+                return false;
+            }
+        };
+        let words = mnemonic.word_iter().collect::<Vec<_>>();
+        let answers = answers.split_whitespace().collect::<Vec<_>>();
+        if self.positions.is_empty() || !check_answers(&words, &self.positions, &answers) {
+            return false;
+        }
+        self.positions.clear();
+        self.challenge = QVariantList::default();
+        self.challenge_changed();
+        self.hide();
+        self.confirm_backup();
+        true
+    }
+
+    /// Check a complete backup against the seed, without revealing the seed
+    fn verify_mnemonic(&mut self, words: String) -> bool {
+        let matches = BdkWallet::mnemonic()
+            .map(|seed| matches_seed(&seed, &words))
+            .unwrap_or_else(|e| {
+                log_error!("{}", e);
+                false
+            });
+        if matches {
+            self.confirm_backup();
+        }
+        matches
+    }
+
+    /// Remember that the user has a verified backup
+    fn confirm_backup(&mut self) {
+        let res = Settings::load().and_then(|mut s| {
+            s.seed_backup_confirmed = Utc::now().timestamp();
+            s.save()
+        });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pick_challenge() {
+        let positions = pick_challenge(12, 3, &mut OsRng);
+        assert_eq!(positions.len(), 3);
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+        assert!(positions.iter().all(|p| *p < 12));
+        assert_eq!(pick_challenge(2, 3, &mut OsRng), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_needs_backup_reminder() {
+        assert!(needs_backup_reminder(200_000, 100_000, false));
+        assert!(!needs_backup_reminder(200_000, 100_000, true));
+        assert!(!needs_backup_reminder(50_000, 100_000, false));
+    }
+
+    #[test]
+    fn test_matches_seed() {
+        let words = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        let seed = Mnemonic::parse(words).unwrap();
+        assert!(matches_seed(&seed, words));
+        assert!(matches_seed(
+            &seed,
+            &format!("  {}\n", words.to_uppercase())
+        ));
+        assert!(!matches_seed(&seed, &words.replace("about", "able")));
+        assert!(!matches_seed(&seed, "abandon"));
+    }
+
+    #[test]
+    fn test_check_answers() {
+        let words = ["abandon", "ability", "able", "about"];
+        assert!(check_answers(&words, &[1, 3], &["ability", " About"]));
+        assert!(!check_answers(&words, &[1, 3], &["ability", "able"]));
+        assert!(!check_answers(&words, &[1, 3], &["ability"]));
+        assert!(!check_answers(&words, &[7], &["ability"]));
+    }
+}
@@ -0,0 +1,627 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::worker;
+
+use utwallet_core::format::Denomination;
+use utwallet_core::i18n::{tr, trf};
+use utwallet_core::netwatch::LowData;
+use utwallet_core::rates::RATE_SOURCES;
+use utwallet_core::settings::{apply_retention, Settings, CURRENCIES};
+use utwallet_core::wallet::BdkWallet;
+use utwallet_core::{
+    dbus, electrum, esplora, gossip, lnaddress, logging, pin, proxy, rest, webhook,
+};
+
+use qmetaobject::*;
+
+/// Exposes the settings to QML. Every change is persisted right away.
+#[derive(QObject, Default)]
+pub struct SettingsModel {
+    base: qt_base_class!(trait QObject),
+    retention_days: qt_property!(u32; NOTIFY changed WRITE set_retention_days),
+    keep_totals: qt_property!(bool; NOTIFY changed WRITE set_keep_totals),
+    currency: qt_property!(QString; NOTIFY changed WRITE set_currency),
+    cmc_api_key: qt_property!(QString; NOTIFY changed WRITE set_cmc_api_key),
+    /// btc or sats
+    denomination: qt_property!(QString; NOTIFY changed WRITE set_denomination),
+    /// the enabled rate sources in the order they are asked
+    rate_sources: qt_property!(QVariantList; NOTIFY changed),
+    biometric_unlock: qt_property!(bool; NOTIFY changed WRITE set_biometric_unlock),
+    auto_lock_secs: qt_property!(u32; NOTIFY changed WRITE set_auto_lock_secs),
+    vss_url: qt_property!(QString; NOTIFY changed WRITE set_vss_url),
+    /// whether this build can use the remote storage at all
+    vss_supported: qt_property!(bool; NOTIFY changed),
+    /// empty for the default snapshot server of the network
+    rgs_url: qt_property!(QString; NOTIFY changed WRITE set_rgs_url),
+    /// why the last rapid gossip sync url was refused, empty if it was taken
+    rgs_status: qt_property!(QString; NOTIFY rgs_checked),
+    rgs_checked: qt_signal!(),
+    backup_reminder_sats: qt_property!(u64; NOTIFY changed WRITE set_backup_reminder_sats),
+    proxy: qt_property!(QString; NOTIFY changed WRITE set_proxy),
+    offline: qt_property!(bool; NOTIFY changed WRITE set_offline),
+    pin_threshold_sats: qt_property!(u64; NOTIFY changed WRITE set_pin_threshold_sats),
+    sync_interval_secs: qt_property!(u32; NOTIFY changed WRITE set_sync_interval_secs),
+    rate_interval_secs: qt_property!(u32; NOTIFY changed WRITE set_rate_interval_secs),
+    battery_saver: qt_property!(bool; NOTIFY changed WRITE set_battery_saver),
+    /// off, mobile or always
+    low_data: qt_property!(QString; NOTIFY changed WRITE set_low_data),
+    event_log_length: qt_property!(u32; NOTIFY changed WRITE set_event_log_length),
+    esplora_servers: qt_property!(QVariantList; NOTIFY changed),
+    /// the esplora server in use
+    esplora_current: qt_property!(QString; NOTIFY changed),
+    /// the servers that failed the last health check
+    esplora_unreachable: qt_property!(QVariantList; NOTIFY esplora_checked),
+    esplora_checked: qt_signal!(),
+    electrum_url: qt_property!(QString; NOTIFY changed WRITE set_electrum_url),
+    verbose_logging: qt_property!(bool; NOTIFY changed WRITE set_verbose_logging),
+    /// the outcome of the last check of the Electrum server
+    electrum_status: qt_property!(QString; NOTIFY esplora_checked),
+    notifications: qt_property!(bool; NOTIFY changed WRITE set_notifications),
+    webhook_url: qt_property!(QString; NOTIFY changed WRITE set_webhook_url),
+    /// for the order system to check the signature, empty without a webhook
+    webhook_secret: qt_property!(QString; NOTIFY changed),
+    /// the outcome of the last test call
+    webhook_status: qt_property!(QString; NOTIFY webhook_tested),
+    webhook_tested: qt_signal!(),
+    /// the claimed lightning address, empty without one
+    lnaddress: qt_property!(QString; NOTIFY changed),
+    lnaddress_provider: qt_property!(QString; NOTIFY changed),
+    /// the outcome of the last claim or release
+    lnaddress_status: qt_property!(QString; NOTIFY lnaddress_checked),
+    lnaddress_checked: qt_signal!(),
+    rest_api: qt_property!(bool; NOTIFY changed WRITE set_rest_api),
+    rest_port: qt_property!(u16; NOTIFY changed WRITE set_rest_port),
+    /// for the scripts using the REST API, empty while it is disabled
+    rest_token: qt_property!(QString; NOTIFY changed),
+    /// for the other apps calling the D-Bus API
+    dbus_token: qt_property!(QString; NOTIFY changed),
+    changed: qt_signal!(),
+
+    load: qt_method!(fn(&mut self)),
+    prune: qt_method!(fn(&mut self) -> QString),
+    reset_wallet: qt_method!(fn(&mut self, confirmed: bool) -> QString),
+    currencies: qt_method!(fn(&self) -> QVariantList),
+    all_rate_sources: qt_method!(fn(&self) -> QVariantList),
+    enable_rate_source: qt_method!(fn(&mut self, name: String, enabled: bool)),
+    move_rate_source_up: qt_method!(fn(&mut self, name: String)),
+    add_esplora_server: qt_method!(fn(&mut self, url: String) -> QString),
+    remove_esplora_server: qt_method!(fn(&mut self, url: String) -> QString),
+    check_esplora_servers: qt_method!(fn(&mut self)),
+    reset_rest_token: qt_method!(fn(&mut self)),
+    reset_dbus_token: qt_method!(fn(&mut self)),
+    reset_webhook_secret: qt_method!(fn(&mut self)),
+    test_webhook: qt_method!(fn(&mut self)),
+    claim_lnaddress:
+        qt_method!(fn(&mut self, provider: String, admin_key: String, username: String)),
+    release_lnaddress: qt_method!(fn(&mut self)),
+}
+
+impl SettingsModel {
+    fn load(&mut self) {
+        let settings = Settings::load().unwrap_or_else(|e| {
+            log_error!("{}", e);
+            Settings::default()
+        });
+        self.retention_days = settings.retention_days;
+        self.keep_totals = settings.keep_totals;
+        self.currency = settings.currency.into();
+        self.cmc_api_key = settings.cmc_api_key.into();
+        self.denomination = match settings.denomination {
+            Denomination::Btc => "btc",
+            Denomination::Sats => "sats",
+        }
+        .into();
+        self.rate_sources = settings
+            .rate_sources
+            .iter()
+            .map(|s| QString::from(s.as_str()))
+            .collect();
+        self.biometric_unlock = settings.biometric_unlock;
+        self.auto_lock_secs = settings.auto_lock_secs;
+        self.vss_url = settings.vss_url.into();
+        self.vss_supported = BdkWallet::vss_supported();
+        self.rgs_url = settings.rgs_url.into();
+        self.backup_reminder_sats = settings.backup_reminder_sats;
+        self.proxy = settings.proxy.into();
+        self.offline = settings.offline;
+        self.pin_threshold_sats = settings.pin_threshold_sats;
+        self.sync_interval_secs = settings.sync_interval_secs;
+        self.rate_interval_secs = settings.rate_interval_secs;
+        self.battery_saver = settings.battery_saver;
+        self.low_data = settings.low_data.name().into();
+        self.event_log_length = settings.event_log_length;
+        self.esplora_servers = settings
+            .esplora_servers
+            .iter()
+            .map(|s| QString::from(s.as_str()))
+            .collect();
+        self.esplora_current = esplora::current().into();
+        self.electrum_url = settings.electrum_url.into();
+        self.verbose_logging = settings.verbose_logging;
+        self.notifications = settings.notifications;
+        self.webhook_secret = if settings.webhook_url.is_empty() {
+            String::new()
+        } else {
+            webhook::secret().unwrap_or_else(|e| {
+                log_error!("{}", e);
+                String::new()
+            })
+        }
+        .into();
+        self.webhook_url = settings.webhook_url.into();
+        self.lnaddress = settings.lnaddress.into();
+        self.lnaddress_provider = settings.lnaddress_provider.into();
+        self.rest_api = settings.rest_api;
+        self.rest_port = settings.rest_port;
+        self.rest_token = if settings.rest_api {
+            rest::token().unwrap_or_else(|e| {
+                log_error!("{}", e);
+                String::new()
+            })
+        } else {
+            String::new()
+        }
+        .into();
+        self.dbus_token = dbus::token()
+            .unwrap_or_else(|e| {
+                log_error!("{}", e);
+                String::new()
+            })
+            .into();
+        self.changed();
+    }
+
+    /// Prune the history now, and return a message for the user
+    fn prune(&mut self) -> QString {
+        match apply_retention() {
+            Ok(count) => trf("{} entries pruned", &[&count]),
+            Err(e) => {
+                log_error!("{}", e);
+                e
+            }
+        }
+        .into()
+    }
+
+    /// Delete the wallet and start over, and return a message for the user
+    fn reset_wallet(&mut self, confirmed: bool) -> QString {
+        if !confirmed {
+            return tr("Please confirm that all data will be deleted").into();
+        }
+        let res = BdkWallet::reset();
+        self.load();
+        match res {
+            Ok(()) => tr("The wallet was reset"),
+            Err(e) => {
+                log_error!("{}", e);
+                e.to_string()
+            }
+        }
+        .into()
+    }
+
+    fn currencies(&self) -> QVariantList {
+        CURRENCIES.iter().map(|c| QString::from(*c)).collect()
+    }
+
+    fn all_rate_sources(&self) -> QVariantList {
+        RATE_SOURCES.iter().map(|s| QString::from(*s)).collect()
+    }
+
+    fn enable_rate_source(&mut self, name: String, enabled: bool) {
+        if !RATE_SOURCES.contains(&name.as_str()) {
+            log_error!("Unknown rate source {}", name);
+            return;
+        }
+        self.update(|s| {
+            s.rate_sources.retain(|n| *n != name);
+            if enabled {
+                s.rate_sources.push(name);
+            }
+        });
+    }
+
+    /// Ask this source before the one that is currently asked before it
+    fn move_rate_source_up(&mut self, name: String) {
+        self.update(|s| {
+            if let Some(pos) = s.rate_sources.iter().position(|n| *n == name) {
+                if pos > 0 {
+                    s.rate_sources.swap(pos, pos - 1);
+                }
+            }
+        });
+    }
+
+    /// Add a server to the end of the list, and return an error message for the user
+    fn add_esplora_server(&mut self, url: String) -> QString {
+        match esplora::normalize_url(&url) {
+            Ok(url) => {
+                self.update(|s| {
+                    if !s.esplora_servers.contains(&url) {
+                        s.esplora_servers.push(url);
+                    }
+                });
+                QString::default()
+            }
+            Err(e) => e.into(),
+        }
+    }
+
+    /// Remove a server, the scheduler fails over if it was the current one
+    fn remove_esplora_server(&mut self, url: String) -> QString {
+        if self.esplora_servers.len() <= 1 {
+            return tr("At least one esplora server is needed").into();
+        }
+        self.update(|s| s.esplora_servers.retain(|u| *u != url));
+        QString::default()
+    }
+
+    /// Check all the servers in the background
+    fn check_esplora_servers(&mut self) {
+        let servers = esplora::configured();
+        worker::run(
+            &*self,
+            move || {
+                let electrum = electrum::configured().map(|url| electrum::tip_height(&url));
+                (esplora::check_all(&servers), electrum)
+            },
+            |model: &mut Self,
+             (health, electrum): (Vec<(String, bool)>, Option<Result<u64, String>>)| {
+                model.esplora_unreachable = health
+                    .iter()
+                    .filter(|(_, ok)| !ok)
+                    .map(|(url, _)| QString::from(url.as_str()))
+                    .collect();
+                model.electrum_status = match electrum {
+                    Some(Ok(height)) => format!("reachable, at block {}", height),
+                    Some(Err(e)) => e,
+                    None => String::new(),
+                }
+                .into();
+                model.esplora_checked();
+            },
+        );
+    }
+
+    /// The scripts using the old token lose access
+    fn reset_rest_token(&mut self) {
+        if let Err(e) = rest::reset_token().and_then(|_| rest::restart()) {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// The apps using the old token lose access
+    fn reset_dbus_token(&mut self) {
+        if let Err(e) = dbus::reset_token() {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// The order system has to learn the new secret
+    fn reset_webhook_secret(&mut self) {
+        if let Err(e) = webhook::reset_secret() {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// Call the webhook in the background
+    fn test_webhook(&mut self) {
+        worker::run(
+            &*self,
+            webhook::test,
+            |model: &mut Self, res: Result<(), String>| {
+                model.webhook_status = match res {
+                    Ok(()) => tr("The webhook accepted the test call"),
+                    Err(e) => e,
+                }
+                .into();
+                model.webhook_tested();
+            },
+        );
+    }
+
+    /// Claim the lightning address in the background
+    fn claim_lnaddress(&mut self, provider: String, admin_key: String, username: String) {
+        worker::run(
+            &*self,
+            move || lnaddress::claim(&provider, &admin_key, &username),
+            |model: &mut Self, res: Result<String, String>| {
+                model.lnaddress_status = match res {
+                    Ok(address) => trf("You can now be paid to {}", &[&address]),
+                    Err(e) => e,
+                }
+                .into();
+                model.load();
+                model.lnaddress_checked();
+            },
+        );
+    }
+
+    /// Give the lightning address up in the background
+    fn release_lnaddress(&mut self) {
+        worker::run(
+            &*self,
+            lnaddress::release,
+            |model: &mut Self, res: Result<(), String>| {
+                model.lnaddress_status = match res {
+                    Ok(()) => tr("The lightning address was given up"),
+                    Err(e) => e,
+                }
+                .into();
+                model.load();
+                model.lnaddress_checked();
+            },
+        );
+    }
+
+    fn set_webhook_url(&mut self, url: QString) {
+        let url = url.to_string().trim().to_string();
+        match webhook::validate_url(&url) {
+            Ok(()) => self.update(|s| s.webhook_url = url),
+            Err(e) => {
+                log_error!("{}", e);
+                self.webhook_status = e.into();
+                self.webhook_tested();
+                self.load();
+            }
+        }
+    }
+
+    fn set_notifications(&mut self, enabled: bool) {
+        self.update(|s| s.notifications = enabled);
+    }
+
+    fn set_rest_api(&mut self, enabled: bool) {
+        self.update(|s| s.rest_api = enabled);
+        if let Err(e) = rest::restart() {
+            log_error!("{}", e);
+        }
+    }
+
+    fn set_rest_port(&mut self, port: u16) {
+        if port < 1024 {
+            log_error!("The REST API needs a port above 1023");
+            self.load();
+            return;
+        }
+        self.update(|s| s.rest_port = port);
+        if let Err(e) = rest::restart() {
+            log_error!("{}", e);
+        }
+    }
+
+    fn set_currency(&mut self, currency: QString) {
+        let currency = currency.to_string();
+        if !CURRENCIES.contains(&currency.as_str()) {
+            log_error!("Unsupported currency {}", currency);
+            return;
+        }
+        self.update(|s| s.currency = currency);
+    }
+
+    fn set_cmc_api_key(&mut self, key: QString) {
+        let key = key.to_string().trim().to_string();
+        self.update(|s| s.cmc_api_key = key);
+    }
+
+    fn set_denomination(&mut self, denomination: QString) {
+        match denomination.to_string().parse::<Denomination>() {
+            Ok(d) => self.update(|s| s.denomination = d),
+            Err(e) => log_error!("{}", e),
+        }
+    }
+
+    fn set_retention_days(&mut self, days: u32) {
+        self.update(|s| s.retention_days = days);
+    }
+
+    fn set_keep_totals(&mut self, keep: bool) {
+        self.update(|s| s.keep_totals = keep);
+    }
+
+    /// The fingerprint replaces the PIN, so switching it needs the PIN
+    fn set_biometric_unlock(&mut self, enabled: bool) {
+        if let Err(e) = pin::require() {
+            log_error!("{}", e);
+            self.load();
+            return;
+        }
+        self.update(|s| s.biometric_unlock = enabled);
+    }
+
+    fn set_auto_lock_secs(&mut self, secs: u32) {
+        if let Err(e) = pin::require() {
+            log_error!("{}", e);
+            self.load();
+            return;
+        }
+        self.update(|s| s.auto_lock_secs = secs);
+    }
+
+    fn set_backup_reminder_sats(&mut self, sats: u64) {
+        self.update(|s| s.backup_reminder_sats = sats);
+    }
+
+    fn set_pin_threshold_sats(&mut self, sats: u64) {
+        if let Err(e) = pin::require() {
+            log_error!("{}", e);
+            self.load();
+            return;
+        }
+        self.update(|s| s.pin_threshold_sats = sats);
+    }
+
+    fn set_sync_interval_secs(&mut self, secs: u32) {
+        self.update(|s| s.sync_interval_secs = secs);
+    }
+
+    fn set_rate_interval_secs(&mut self, secs: u32) {
+        self.update(|s| s.rate_interval_secs = secs);
+    }
+
+    fn set_battery_saver(&mut self, enabled: bool) {
+        self.update(|s| s.battery_saver = enabled);
+    }
+
+    fn set_event_log_length(&mut self, length: u32) {
+        self.update(|s| s.event_log_length = length);
+    }
+
+    fn set_low_data(&mut self, mode: QString) {
+        match mode.to_string().parse::<LowData>() {
+            Ok(mode) => self.update(|s| s.low_data = mode),
+            Err(e) => log_error!("{}", e),
+        }
+    }
+
+    /// The node starts over with the state in the new store, so existing channels would be lost
+    fn set_vss_url(&mut self, url: QString) {
+        let url = url.to_string().trim().to_string();
+        if url == self.vss_url.to_string() {
+            return;
+        }
+        if !url.is_empty() && !BdkWallet::vss_supported() {
+            log_error!("{}", tr("This build doesn't support the remote storage"));
+            return self.load();
+        }
+        let res = BdkWallet::channel_peers()
+            .map_err(String::from)
+            .and_then(|channels| {
+                if !channels.is_empty() {
+                    return Err(tr("Close the channels before changing the remote storage"));
+                }
+                BdkWallet::with_node_stopped(|| {
+                    let mut settings = Settings::load()?;
+                    settings.vss_url = url;
+                    settings.save()
+                })
+            });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// Only a server that serves snapshots is taken. The node restarts with it.
+    fn set_rgs_url(&mut self, url: QString) {
+        let url = match gossip::normalize_url(&url.to_string()) {
+            Ok(url) => url,
+            Err(e) => return self.show_rgs_status(e),
+        };
+        if url == self.rgs_url.to_string() {
+            return;
+        }
+        worker::run(
+            &*self,
+            move || {
+                if !url.is_empty() {
+                    gossip::probe(&url)?;
+                }
+                BdkWallet::with_node_stopped(|| {
+                    let mut settings = Settings::load()?;
+                    settings.rgs_url = url;
+                    settings.save()?;
+                    gossip::reset()
+                })
+            },
+            |model: &mut Self, res: Result<(), String>| {
+                model.show_rgs_status(res.err().unwrap_or_default());
+            },
+        );
+    }
+
+    fn show_rgs_status(&mut self, status: String) {
+        if !status.is_empty() {
+            log_error!("{}", status);
+        }
+        self.rgs_status = status.into();
+        self.rgs_checked();
+        self.load();
+    }
+
+    /// Takes effect right away, without restarting the node
+    fn set_verbose_logging(&mut self, verbose: bool) {
+        logging::set_verbose(verbose);
+        self.update(|s| s.verbose_logging = verbose);
+    }
+
+    /// Accepts an empty string to go back to esplora
+    fn set_electrum_url(&mut self, url: QString) {
+        let url = url.to_string().trim().to_string();
+        let res = if url.is_empty() {
+            Ok(url)
+        } else {
+            electrum::normalize_url(&url)
+        };
+        match res {
+            Ok(url) => self.update(|s| s.electrum_url = url),
+            Err(e) => {
+                log_error!("{}", e);
+                self.load();
+            }
+        }
+    }
+
+    /// Our own http clients use the new proxy right away, the node only picks it up when
+    /// the app starts again
+    fn set_proxy(&mut self, proxy: QString) {
+        let proxy = proxy.to_string().trim().to_string();
+        if proxy == self.proxy.to_string() {
+            return;
+        }
+        let res = proxy::validate(&proxy).and_then(|_| {
+            let mut settings = Settings::load()?;
+            settings.proxy = proxy.clone();
+            settings.save()
+        });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// The node is restarted, to connect or to stay disconnected
+    fn set_offline(&mut self, offline: bool) {
+        if offline == self.offline {
+            return;
+        }
+        let res = BdkWallet::with_node_stopped(|| {
+            let mut settings = Settings::load()?;
+            settings.offline = offline;
+            settings.save()
+        });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+
+    /// Modify and persist the settings, then reflect them in the properties
+    fn update<F: FnOnce(&mut Settings)>(&mut self, f: F) {
+        let res = Settings::load().and_then(|mut settings| {
+            f(&mut settings);
+            settings.save()
+        });
+        if let Err(e) = res {
+            log_error!("{}", e);
+        }
+        self.load();
+    }
+}
@@ -0,0 +1,90 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+use crate::worker;
+
+use utwallet_core::daemon;
+use utwallet_core::settings;
+use utwallet_core::wallet::{BdkWallet, StartupStage};
+
+use qmetaobject::*;
+
+/// Brings up the node in the background while the splash screen is shown
+#[derive(QObject, Default)]
+pub struct NodeStartup {
+    base: qt_base_class!(trait QObject),
+    /// probing, building, starting, ready or failed
+    stage: qt_property!(QString; NOTIFY changed),
+    /// why the node couldn't be started, empty if it didn't fail
+    error: qt_property!(QString; NOTIFY changed),
+    changed: qt_signal!(),
+    /// the node is up and the wallet can be used
+    ready: qt_signal!(),
+    running: bool,
+
+    start: qt_method!(fn(&mut self)),
+}
+
+impl NodeStartup {
+    fn start(&mut self) {
+        if self.running {
+            return;
+        }
+        self.running = true;
+        self.error = QString::default();
+        let qptr = QPointer::from(&*self);
+        let progress = queued_callback(move |stage: StartupStage| {
+            if let Some(startup) = qptr.as_pinned() {
+                startup.borrow_mut().set_stage(stage.name());
+            }
+        });
+        worker::run(
+            &*self,
+            move || {
+                // a daemon that runs the node in the background has to give it up first
+                match daemon::take_over() {
+                    Ok(true) => log_info!("the daemon handed the node over"),
+                    Ok(false) => {}
+                    Err(e) => log_error!("{}", e),
+                }
+                BdkWallet::init_node(progress)?;
+                if let Err(e) = settings::apply_retention() {
+                    log_error!("{}", e);
+                }
+                Ok(())
+            },
+            |startup: &mut Self, res: Result<(), String>| {
+                startup.running = false;
+                match res {
+                    Ok(()) => {
+                        startup.set_stage("ready");
+                        startup.ready();
+                    }
+                    Err(e) => {
+                        log_error!("{}", e);
+                        startup.error = e.into();
+                        startup.set_stage("failed");
+                    }
+                }
+            },
+        );
+    }
+
+    fn set_stage(&mut self, stage: &str) {
+        self.stage = stage.into();
+        self.changed();
+    }
+}
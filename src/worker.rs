@@ -0,0 +1,80 @@
+/*
+ * Copyright (C) 2022  Richard Ulrich
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation; version 3.
+ *
+ * utwallet is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! The wallet operations block on the network and on the node mutex, so they run on a
+//! background thread. One thread keeps them in the order the user triggered them.
+
+use qmetaobject::*;
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc::{channel, Sender},
+    sync::Mutex,
+    thread,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+static JOBS: Mutex<Option<Sender<Job>>> = Mutex::new(None);
+
+/// Queue a job for the worker thread, which is started with the first job
+pub fn spawn(job: impl FnOnce() + Send + 'static) {
+    let mut jobs = match JOBS.lock() {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            log_error!("Unable to get the mutex for the worker: {:?}", e);
+            return;
+        }
+    };
+    let sender = jobs.get_or_insert_with(|| {
+        let (sender, receiver) = channel::<Job>();
+        thread::spawn(move || {
+            // a panicking job must not take the queued and all later jobs with it
+            for job in receiver {
+                if let Err(e) = panic::catch_unwind(AssertUnwindSafe(job)) {
+                    let msg = e
+                        .downcast_ref::<&str>()
+                        .map(|s| s.to_string())
+                        .or_else(|| e.downcast_ref::<String>().cloned())
+                        .unwrap_or_default();
+                    log_error!("A job of the worker panicked: {}", msg);
+                }
+            }
+        });
+        sender
+    });
+    if let Err(e) = sender.send(Box::new(job)) {
+        log_error!("The worker thread is gone: {}", e);
+    }
+}
+
+/// Run the job on the worker thread, and hand its result to the object on the GUI thread.
+/// Nothing happens with the result if the object was destroyed in the meantime.
+pub fn run<T, R, F, D>(obj: &T, job: F, done: D)
+where
+    T: QObject + 'static,
+    R: Send + 'static,
+    F: FnOnce() -> R + Send + 'static,
+    D: FnOnce(&mut T, R) + 'static,
+{
+    let qptr = QPointer::from(obj);
+    let mut done = Some(done);
+    let deliver = queued_callback(move |result: R| {
+        if let (Some(obj), Some(done)) = (qptr.as_pinned(), done.take()) {
+            done(&mut obj.borrow_mut(), result);
+        }
+    });
+    spawn(move || deliver(job()));
+}